@@ -1,17 +1,20 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    to_json_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult,
+    Timestamp,
 };
+use cw_storage_plus::Bound;
 // use cw2::set_contract_version;
 
 use crate::error::ContractError;
-use crate::helpers::ValidStratId;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::queries::all_strat_ids;
+use crate::helpers::{schedule_next_run, unschedule_runs, ValidStratId};
+use crate::msg::{CompPrefStatus, DuePreference, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::queries::{all_strat_ids, authorized_keepers, collect_prefs, expired_prefs};
 use crate::state::{
-    CompPref, EndType, InactiveStatus, StoreSettings, ALLOWED_STRATEGY_IDS, COMP_PREFS,
-    PREFS_BY_PUBKEY, STORE_SETTINGS,
+    comp_prefs, CompPref, EndType, InactiveStatus, StoreSettings, ALLOWED_STRATEGY_IDS,
+    AUTHORIZED_KEEPERS, NEXT_RUN, PREFS_BY_PUBKEY, REINDEX_CURSOR, RUN_QUEUE, STATUS_CANCELLED,
+    STATUS_UNMARKED, STORE_SETTINGS,
 };
 
 // version info for migration info
@@ -54,7 +57,13 @@ pub fn instantiate(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
-    Ok(Response::new())
+
+    // prefs stored before the secondary indexes existed need to be re-saved so they get indexed.
+    // there can be too many to do in one transaction so the admin works through them in batches
+    // via `ReindexCompoundingPreferences`
+    REINDEX_CURSOR.save(deps.storage, &None)?;
+
+    Ok(Response::new().add_attribute("reindex_pending", "true"))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -69,7 +78,11 @@ pub fn execute(
     match msg {
         ExecuteMsg::AddAllowedStrategyId(_)
         | ExecuteMsg::RemoveAllowedStrategyId(_)
-        | ExecuteMsg::SetAdmin(_) => {
+        | ExecuteMsg::SetAdmin(_)
+        | ExecuteMsg::PruneInactiveCompoundingPreferences { .. }
+        | ExecuteMsg::ReindexCompoundingPreferences { .. }
+        | ExecuteMsg::AddAuthorizedKeeper(_)
+        | ExecuteMsg::RemoveAuthorizedKeeper(_) => {
             if STORE_SETTINGS.load(deps.storage)?.admin.ne(&info.sender) {
                 return Err(ContractError::Unauthorized);
             }
@@ -115,8 +128,28 @@ pub fn execute(
             let valid_comp_prefs =
                 unverified_comp_pref.verify(deps.storage, deps.api, &env.block.time)?;
 
+            // if the prefs are being updated with a new pubkey the old pubkey path is now stale
+            if let Some(prev_pref) =
+                comp_prefs().may_load(deps.storage, (valid_comp_prefs.strat_id, &target_address))?
+            {
+                if prev_pref
+                    .user_comp_pref
+                    .pub_key
+                    .ne(&valid_comp_prefs.pub_key)
+                {
+                    PREFS_BY_PUBKEY.remove(
+                        deps.storage,
+                        (
+                            &prev_pref.user_comp_pref.pub_key,
+                            valid_comp_prefs.strat_id,
+                            target_address.clone(),
+                        ),
+                    );
+                }
+            }
+
             // store the compounding preferences in state
-            COMP_PREFS.update(
+            comp_prefs().update(
                 deps.storage,
                 (valid_comp_prefs.strat_id, &target_address),
                 |prev_pref| -> Result<CompPref, ContractError> {
@@ -148,13 +181,23 @@ pub fn execute(
                 &(),
             )?;
 
+            // new or reactivated prefs are due to run right away
+            if !NEXT_RUN.has(deps.storage, (valid_comp_prefs.strat_id, &target_address)) {
+                schedule_next_run(
+                    deps.storage,
+                    valid_comp_prefs.strat_id,
+                    &target_address,
+                    env.block.time,
+                )?;
+            }
+
             Ok(Response::new().add_attribute(
                 "set compounding preferences",
                 format!("{}-{}", valid_comp_prefs.strat_id, target_address),
             ))
         }
         ExecuteMsg::CancelCompoundingPreferences(strat_id) => {
-            COMP_PREFS.update(
+            comp_prefs().update(
                 deps.storage,
                 (strat_id.u64(), &info.sender),
                 |prev_pref| -> Result<CompPref, ContractError> {
@@ -175,17 +218,193 @@ pub fn execute(
                 },
             )?;
 
+            unschedule_runs(deps.storage, strat_id.u64(), &info.sender)?;
+
             Ok(Response::new().add_attribute(
                 "cancel compounding preferences",
                 format!("{}-{}", strat_id, info.sender),
             ))
         }
-        ExecuteMsg::PruneInactiveCompoundingPreferences { .. } => {
-            if STORE_SETTINGS.load(deps.storage)?.admin.ne(&info.sender) {
+        ExecuteMsg::PruneInactiveCompoundingPreferences { limit, start_after } => {
+            let StoreSettings { days_to_prune, .. } = STORE_SETTINGS.load(deps.storage)?;
+
+            let range_min = start_after
+                .map(|(strat_id, user_address)| -> Result<_, ContractError> {
+                    let user_addr = deps
+                        .api
+                        .addr_validate(&user_address)
+                        .map_err(|_| ContractError::InvalidUserAddress(user_address))?;
+                    Ok((strat_id.u64(), user_addr))
+                })
+                .transpose()?;
+
+            // grab the batch up front so that we aren't mutating state while iterating over it.
+            // the batch is bounded by key rather than by position so that pruned entries
+            // don't shift the next batch
+            let batch: Vec<((u64, Addr), CompPref)> = comp_prefs()
+                .range(
+                    deps.storage,
+                    range_min
+                        .as_ref()
+                        .map(|(strat_id, user_addr)| Bound::exclusive((*strat_id, user_addr))),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.into())
+                .collect::<StdResult<_>>()?;
+
+            // the key to pass as `start_after` for the next batch
+            let (last_strat_id, last_address) = batch
+                .last()
+                .map(|((strat_id, user_addr), _)| (strat_id.to_string(), user_addr.to_string()))
+                .unwrap_or_default();
+
+            let mut expired_count: u32 = 0;
+            let mut pruned_count: u32 = 0;
+
+            for ((strat_id, user_addr), mut pref) in batch {
+                // prefs that have been inactive for long enough are removed along with their pubkey entry
+                if pref
+                    .prunable_at(&env.block.time, days_to_prune)
+                    .map_or(false, |prunable_at| prunable_at.lt(&env.block.time))
+                {
+                    comp_prefs().remove(deps.storage, (strat_id, &user_addr))?;
+                    unschedule_runs(deps.storage, strat_id, &user_addr)?;
+                    PREFS_BY_PUBKEY.remove(
+                        deps.storage,
+                        (&pref.user_comp_pref.pub_key, strat_id, user_addr),
+                    );
+                    pruned_count += 1;
+                    continue;
+                }
+
+                // prefs that have passed their expiration but haven't been marked yet get flagged as expired
+                if pref.is_inactive.is_none() {
+                    if let Some(expired_at) = pref.expired_timestamp(&env.block.time) {
+                        pref.is_inactive = Some(InactiveStatus {
+                            end_type: EndType::Expiration,
+                            ended_at: expired_at,
+                        });
+                        comp_prefs().save(deps.storage, (strat_id, &user_addr), &pref)?;
+                        unschedule_runs(deps.storage, strat_id, &user_addr)?;
+                        expired_count += 1;
+                    }
+                }
+            }
+
+            Ok(Response::new()
+                .add_attribute("action", "prune_inactive_compounding_preferences")
+                .add_attribute("expired_count", expired_count.to_string())
+                .add_attribute("pruned_count", pruned_count.to_string())
+                .add_attribute("last_strat_id", last_strat_id)
+                .add_attribute("last_address", last_address))
+        }
+        ExecuteMsg::ReindexCompoundingPreferences { limit } => {
+            let cursor = REINDEX_CURSOR
+                .may_load(deps.storage)?
+                .ok_or(ContractError::NoReindexPending)?;
+
+            let batch: Vec<((u64, Addr), CompPref)> = comp_prefs()
+                .range(
+                    deps.storage,
+                    cursor
+                        .as_ref()
+                        .map(|(strat_id, user_addr)| Bound::exclusive((*strat_id, user_addr))),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.into())
+                .collect::<StdResult<_>>()?;
+
+            for ((strat_id, user_addr), pref) in batch.iter() {
+                comp_prefs().replace(
+                    deps.storage,
+                    (*strat_id, user_addr),
+                    Some(pref),
+                    Some(pref),
+                )?;
+
+                // active prefs that have never been scheduled are due right away
+                if pref.is_active(&env.block.time)
+                    && !NEXT_RUN.has(deps.storage, (*strat_id, user_addr))
+                {
+                    schedule_next_run(deps.storage, *strat_id, user_addr, env.block.time)?;
+                }
+            }
+
+            // a short batch means we've reached the end of the stored prefs
+            let finished = batch.len() < usize::from(limit);
+            if finished {
+                REINDEX_CURSOR.remove(deps.storage);
+            } else if let Some(((strat_id, user_addr), _)) = batch.last() {
+                REINDEX_CURSOR.save(deps.storage, &Some((*strat_id, user_addr.clone())))?;
+            }
+
+            Ok(Response::new()
+                .add_attribute("action", "reindex_compounding_preferences")
+                .add_attribute("reindexed_count", batch.len().to_string())
+                .add_attribute("finished", finished.to_string()))
+        }
+        ExecuteMsg::AddAuthorizedKeeper(keeper) => {
+            let keeper_addr = deps
+                .api
+                .addr_validate(&keeper)
+                .map_err(|_| ContractError::InvalidKeeperAddress(keeper))?;
+            AUTHORIZED_KEEPERS.save(deps.storage, &keeper_addr, &())?;
+            Ok(Response::new().add_attribute("add authorized keeper", keeper_addr))
+        }
+        ExecuteMsg::RemoveAuthorizedKeeper(keeper) => {
+            let keeper_addr = deps
+                .api
+                .addr_validate(&keeper)
+                .map_err(|_| ContractError::InvalidKeeperAddress(keeper))?;
+            AUTHORIZED_KEEPERS.remove(deps.storage, &keeper_addr);
+            Ok(Response::new().add_attribute("remove authorized keeper", keeper_addr))
+        }
+        ExecuteMsg::AdvanceSchedule {
+            strat_id,
+            user_address,
+        } => {
+            let user_addr = deps
+                .api
+                .addr_validate(&user_address)
+                .map_err(|_| ContractError::InvalidUserAddress(user_address.clone()))?;
+
+            let pref = comp_prefs()
+                .may_load(deps.storage, (strat_id.u64(), &user_addr))?
+                .ok_or_else(|| ContractError::NoSettingsFound(user_address.clone(), strat_id))?;
+
+            // only the outpost that runs the strategy, a keeper or the admin can advance the schedule
+            if info.sender.ne(&pref.user_comp_pref.outpost_address)
+                && !AUTHORIZED_KEEPERS.has(deps.storage, &info.sender)
+                && STORE_SETTINGS.load(deps.storage)?.admin.ne(&info.sender)
+            {
                 return Err(ContractError::Unauthorized);
             }
 
-            Err(ContractError::Unimplemented)
+            if !pref.is_active(&env.block.time) {
+                return Err(ContractError::InactiveSettings(user_address, strat_id));
+            }
+
+            // the schedule can only be advanced once the scheduled run is due
+            if let Some(scheduled_run) =
+                NEXT_RUN.may_load(deps.storage, (strat_id.u64(), &user_addr))?
+            {
+                if scheduled_run.gt(&env.block.time) {
+                    return Err(ContractError::RunNotDue(scheduled_run));
+                }
+            }
+
+            let next_run = env
+                .block
+                .time
+                .plus_seconds(pref.user_comp_pref.comp_period.seconds());
+
+            schedule_next_run(deps.storage, strat_id.u64(), &user_addr, next_run)?;
+
+            Ok(Response::new()
+                .add_attribute("advance schedule", format!("{}-{}", strat_id, user_addr))
+                .add_attribute("next_run", next_run.to_string()))
         }
     }
 }
@@ -195,14 +414,75 @@ pub fn execute(
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
     match msg {
         QueryMsg::StoreSettings => Ok(to_json_binary(&STORE_SETTINGS.load(deps.storage)?)?),
-        QueryMsg::AllowedStrategyIds => Ok(to_json_binary(&all_strat_ids(deps.storage))?),
+        QueryMsg::AllowedStrategyIds => Ok(to_json_binary(&all_strat_ids(deps.storage)?)?),
+        QueryMsg::AuthorizedKeepers => Ok(to_json_binary(&authorized_keepers(deps.storage)?)?),
+        QueryMsg::DuePreferences {
+            strat_id,
+            now,
+            limit,
+            start_after,
+        } => {
+            strat_id.valid_strat_id(deps.storage)?;
+
+            let now = now.unwrap_or(env.block.time);
+
+            let range_min = start_after
+                .map(|(next_run, user_address)| -> Result<_, ContractError> {
+                    let user_addr = deps
+                        .api
+                        .addr_validate(&user_address)
+                        .map_err(|_| ContractError::InvalidUserAddress(user_address))?;
+                    Ok((next_run.seconds(), user_addr))
+                })
+                .transpose()?;
+
+            // the queue is ordered by next run so we can stop as soon as we hit the first run that isn't due
+            let due_prefs = RUN_QUEUE
+                .prefix(strat_id.u64())
+                .keys(
+                    deps.storage,
+                    range_min
+                        .as_ref()
+                        .map(|(next_run, user_addr)| Bound::exclusive((*next_run, user_addr))),
+                    None,
+                    Order::Ascending,
+                )
+                .take_while(|queued| match queued {
+                    Ok((next_run, _)) => *next_run <= now.seconds(),
+                    Err(_) => true,
+                })
+                .filter_map(|queued| -> Option<StdResult<DuePreference>> {
+                    let (next_run, user_addr) = match queued {
+                        Ok(queued) => queued,
+                        Err(err) => return Some(Err(err)),
+                    };
+
+                    match comp_prefs().may_load(deps.storage, (strat_id.u64(), &user_addr)) {
+                        Ok(Some(comp_pref)) if comp_pref.is_active(&now) => {
+                            Some(Ok(DuePreference {
+                                next_run: Timestamp::from_seconds(next_run),
+                                comp_pref,
+                            }))
+                        }
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                });
+
+            let due_prefs: Vec<DuePreference> = match limit {
+                Some(limit) => due_prefs.take(limit.into()).collect::<StdResult<_>>()?,
+                None => due_prefs.collect::<StdResult<_>>()?,
+            };
+
+            Ok(to_json_binary(&due_prefs)?)
+        }
         QueryMsg::StrategyPreferencesByUserAndStratId {
             user_address,
             strategy_id,
         } => {
             let user_addr = deps.api.addr_validate(&user_address)?;
 
-            Ok(to_json_binary(&COMP_PREFS.may_load(
+            Ok(to_json_binary(&comp_prefs().may_load(
                 deps.storage,
                 (strategy_id.into(), &user_addr),
             )?)?)
@@ -211,60 +491,129 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractErro
             strat_id,
             status,
             limit,
-            prev_address: _prev_address,
+            prev_address,
         } => {
             strat_id.valid_strat_id(deps.storage)?;
 
-            let mut matching_prefs: Vec<CompPref> = vec![];
-
-            // TODO: figure out how to do this bound
-            // let range_min = prev_address
-            //     .and_then(|prev_address| deps.api.addr_validate(&prev_address).ok())
-            //     .map(|addr| cw_storage_plus::Bound::Exclusive((strat_id.u64(), addr)));
-
-            let range_min = None;
-
-            let iterable_prefs = COMP_PREFS.prefix(strat_id.u64()).range(
-                deps.storage,
-                range_min,
-                None,
-                Order::Ascending,
-            );
-
-            if status.is_some() {
-                // if there's a status filter then we can use take wile to filter and take as we go
-                let taken = iterable_prefs.take_while(|possible_pref| {
-                    match possible_pref {
-                        Ok((_, pref)) if pref.matches_status_filter(&status, &env.block.time) => {
-                            deps.api.debug(&format!("matching pref: {:?}", pref));
-                            matching_prefs.push(pref.clone());
-                        }
+            let prev_addr = prev_address
+                .map(|prev_address| {
+                    deps.api
+                        .addr_validate(&prev_address)
+                        .map_err(|_| ContractError::InvalidUserAddress(prev_address))
+                })
+                .transpose()?;
+
+            let matching_prefs = match status {
+                // active and cancelled prefs can be found directly via the status index
+                Some(CompPrefStatus::Active) | Some(CompPrefStatus::Cancelled) => {
+                    let recorded_status = if let Some(CompPrefStatus::Active) = status {
+                        STATUS_UNMARKED
+                    } else {
+                        STATUS_CANCELLED
+                    };
 
-                        _ => (),
+                    collect_prefs(
+                        comp_prefs()
+                            .idx
+                            .status
+                            .prefix((strat_id.u64(), recorded_status))
+                            .range(
+                                deps.storage,
+                                prev_addr.map(|addr| Bound::exclusive((strat_id.u64(), addr))),
+                                None,
+                                Order::Ascending,
+                            ),
+                        &status,
+                        &env.block.time,
+                        limit,
+                    )?
+                }
+                // expirations aren't necessarily recorded yet so expired prefs are found by their expiry
+                Some(CompPrefStatus::Expired) | Some(CompPrefStatus::Inactive) => {
+                    // the previous pref tells us which of the indexes to resume from
+                    let prev_pref = prev_addr
+                        .as_ref()
+                        .map(|addr| {
+                            comp_prefs()
+                                .may_load(deps.storage, (strat_id.u64(), addr))?
+                                .map(|pref| (addr.clone(), pref))
+                                .ok_or_else(|| {
+                                    ContractError::NoSettingsFound(addr.to_string(), strat_id)
+                                })
+                        })
+                        .transpose()?;
+                    let prev_cancelled = prev_pref
+                        .as_ref()
+                        .map(|(_, pref)| pref.cancelled_timestamp().is_some());
+
+                    let cancelled_prefs: Box<
+                        dyn Iterator<Item = StdResult<((u64, Addr), CompPref)>> + '_,
+                    > = match (&status, prev_cancelled) {
+                        (Some(CompPrefStatus::Inactive), None | Some(true)) => Box::new(
+                            comp_prefs()
+                                .idx
+                                .status
+                                .prefix((strat_id.u64(), STATUS_CANCELLED))
+                                .range(
+                                    deps.storage,
+                                    prev_pref.as_ref().map(|(addr, _)| {
+                                        Bound::exclusive((strat_id.u64(), addr.clone()))
+                                    }),
+                                    None,
+                                    Order::Ascending,
+                                ),
+                        ),
+                        _ => Box::new(std::iter::empty()),
                     };
-                    limit.map_or(true, |limit| matching_prefs.len().lt(&limit.into()))
-                });
 
-                let _: Vec<Result<(Addr, CompPref), cosmwasm_std::StdError>> = taken.collect();
-            } else {
-                let results = if let Some(limit) = limit {
-                    // if there's no status filter but a limit then we can just use take to grab the next limit number of results
-                    Box::new(iterable_prefs.take(limit.into()))
-                } else {
-                    // otherwise just grab everything possible
-                    iterable_prefs
-                };
-
-                matching_prefs = results
-                    .filter_map(|possible_pref| possible_pref.map_or(None, |(_, pref)| Some(pref)))
-                    .collect();
-            }
+                    let expired_start = prev_pref
+                        .filter(|(_, pref)| pref.cancelled_timestamp().is_none())
+                        .map(|(addr, pref)| {
+                            Bound::exclusive((
+                                pref.user_comp_pref.expires.nanos(),
+                                (strat_id.u64(), addr),
+                            ))
+                        });
+
+                    collect_prefs(
+                        cancelled_prefs.chain(
+                            expired_prefs(
+                                deps.storage,
+                                strat_id.u64(),
+                                expired_start,
+                                &env.block.time,
+                            )
+                            // prefs that were cancelled before they expired were already listed above
+                            .filter(|p| {
+                                matches!(p, Ok((_, pref)) if pref.cancelled_timestamp().is_none())
+                                    || p.is_err()
+                            }),
+                        ),
+                        &status,
+                        &env.block.time,
+                        limit,
+                    )?
+                }
+                _ => collect_prefs(
+                    comp_prefs().prefix(strat_id.u64()).range(
+                        deps.storage,
+                        prev_addr.as_ref().map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    ),
+                    &status,
+                    &env.block.time,
+                    limit,
+                )?,
+            };
 
             Ok(to_json_binary(&matching_prefs)?)
         }
         QueryMsg::StrategyPreferencesByUser {
             user_address,
             status,
+            limit,
+            start_after,
         } => {
             // make sure we're searching for a realistic user address
             let user_addr = deps
@@ -272,43 +621,106 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractErro
                 .addr_validate(&user_address)
                 .map_err(|_| ContractError::InvalidUserAddress(user_address))?;
 
-            let strat_ids = all_strat_ids(deps.storage);
+            let user_prefs = collect_prefs(
+                comp_prefs()
+                    .idx
+                    .user
+                    .prefix(user_addr.clone())
+                    .range(
+                        deps.storage,
+                        start_after.map(|strat_id| Bound::exclusive((strat_id.u64(), user_addr))),
+                        None,
+                        Order::Ascending,
+                    )
+                    // prefs for strategies that are no longer allowed aren't returned
+                    .filter(|possible_pref| match possible_pref {
+                        Ok(((strat_id, _), _)) => ALLOWED_STRATEGY_IDS.has(deps.storage, *strat_id),
+                        Err(_) => true,
+                    }),
+                &status,
+                &env.block.time,
+                limit,
+            )?;
+
+            Ok(to_json_binary(&user_prefs)?)
+        }
+        QueryMsg::StrategyPreferencesByOutpost {
+            outpost_address,
+            status,
+            limit,
+            start_after,
+        } => {
+            let outpost_addr = deps
+                .api
+                .addr_validate(&outpost_address)
+                .map_err(|_| ContractError::InvalidOutpostAddress(outpost_address))?;
+
+            let range_min = start_after
+                .map(|(strat_id, user_address)| -> Result<_, ContractError> {
+                    let user_addr = deps
+                        .api
+                        .addr_validate(&user_address)
+                        .map_err(|_| ContractError::InvalidUserAddress(user_address))?;
+                    Ok(Bound::exclusive((strat_id.u64(), user_addr)))
+                })
+                .transpose()?;
+
+            let outpost_prefs = collect_prefs(
+                comp_prefs().idx.outpost.prefix(outpost_addr).range(
+                    deps.storage,
+                    range_min,
+                    None,
+                    Order::Ascending,
+                ),
+                &status,
+                &env.block.time,
+                limit,
+            )?;
 
-            // look up all the relevant pairs of strategy ids with our search address in state
-            let user_prefs: Vec<CompPref> = strat_ids
-                .into_iter()
-                .filter_map(|strat_id| -> Option<CompPref> {
-                    match COMP_PREFS.may_load(deps.storage, (strat_id.u64(), &user_addr)) {
+            Ok(to_json_binary(&outpost_prefs)?)
+        }
+        QueryMsg::StrategyPreferencesByPubkey {
+            pubkey,
+            status,
+            limit,
+            start_after,
+        } => {
+            let range_min = start_after
+                .map(|(strat_id, user_address)| -> Result<_, ContractError> {
+                    let user_addr = deps
+                        .api
+                        .addr_validate(&user_address)
+                        .map_err(|_| ContractError::InvalidUserAddress(user_address))?;
+                    Ok(Bound::exclusive((strat_id.u64(), user_addr)))
+                })
+                .transpose()?;
+
+            // look up the pubkey in state and then load each of the entries from comp_prefs
+            let matching_prefs = PREFS_BY_PUBKEY
+                .prefix(pubkey.as_str())
+                .keys(deps.storage, range_min, None, Order::Ascending)
+                .filter_map(|strat_keys| -> Option<StdResult<CompPref>> {
+                    let (strat_id, user_addr) = match strat_keys {
+                        Ok(strat_keys) => strat_keys,
+                        Err(err) => return Some(Err(err)),
+                    };
+
+                    match comp_prefs().may_load(deps.storage, (strat_id, &user_addr)) {
+                        // ensure we're abiding by the status filtering
                         Ok(Some(pref)) if pref.matches_status_filter(&status, &env.block.time) => {
-                            Some(pref)
+                            Some(Ok(pref))
                         }
-                        _ => None,
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
                     }
-                })
-                .collect();
+                });
 
-            Ok(to_json_binary(&user_prefs)?)
-        }
-        QueryMsg::StrategyPreferencesByPubkey { pubkey: _, status: _ } => {
-            let user_prefs: Vec<CompPref> = vec![];
-            // look up the pubkey in state
-            // PREFS_BY_PUBKEY.prefix(&pubkey.as_str()).range(deps.storage, None, None, Order::Ascending).filter_map(|strat_keys|
-            //     match strat_keys {
-            //         Ok(( strat_id, user_addr)) => {
-            //             // if the pubkey was in state we'll look up each entry from PREFS_BY_PUBKEY in COMP_PREFs
-            //             match COMP_PREFS.may_load(deps.storage, (strat_id, user_addr)) {
-            //                 Ok(Some(pref))
-            //                 // ensure we're abiding by the status filtering
-            //                     if pref.matches_status_filter(&status, &env.block.time) =>
-            //                 {
-            //                     Some(pref)
-            //                 }
-            //                 _ => None,
-            //             }
-            //         },
-            //         _ => None
-            //     }
-            // ).collect();
+            let user_prefs: Vec<CompPref> = match limit {
+                Some(limit) => matching_prefs
+                    .take(limit.into())
+                    .collect::<StdResult<_>>()?,
+                None => matching_prefs.collect::<StdResult<_>>()?,
+            };
 
             Ok(to_json_binary(&user_prefs)?)
         }
@@ -32,6 +32,18 @@ pub enum ContractError {
 
     #[error("No previous settings found. User: {0}, Strategy Id: {1}")]
     NoSettingsFound(String, Uint64),
+
+    #[error("Settings are no longer active. User: {0}, Strategy Id: {1}")]
+    InactiveSettings(String, Uint64),
+
+    #[error("Invalid keeper address {0}")]
+    InvalidKeeperAddress(String),
+
+    #[error("The next run is not due until {0}")]
+    RunNotDue(Timestamp),
+
+    #[error("There are no compounding preferences waiting to be re-indexed")]
+    NoReindexPending,
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 }
@@ -8,8 +8,9 @@ use cosmwasm_std::{
 use crate::{
     msg::{CompPrefStatus, ExecuteMsg},
     state::{
-        CompPref, EndType, InactiveStatus, UnverifiedUserCompPref, UserCompPref,
-        ALLOWED_STRATEGY_IDS,
+        CompPref, CompoundingFrequency, EndType, InactiveStatus, UnverifiedUserCompPref,
+        UserCompPref, ALLOWED_STRATEGY_IDS, NEXT_RUN, RUN_QUEUE, STATUS_CANCELLED, STATUS_EXPIRED,
+        STATUS_UNMARKED,
     },
     ContractError,
 };
@@ -36,6 +37,23 @@ impl CwTemplateContract {
 }
 
 impl CompPref {
+    /// The status key that the prefs are stored under in the status index.
+    /// This only reflects what has been recorded in state so prefs that
+    /// have passed their expiration without being marked are still `STATUS_UNMARKED`
+    pub fn recorded_status(&self) -> u8 {
+        match self.is_inactive {
+            None => STATUS_UNMARKED,
+            Some(InactiveStatus {
+                end_type: EndType::Cancellation,
+                ..
+            }) => STATUS_CANCELLED,
+            Some(InactiveStatus {
+                end_type: EndType::Expiration,
+                ..
+            }) => STATUS_EXPIRED,
+        }
+    }
+
     pub fn is_active(&self, current_timestamp: &Timestamp) -> bool {
         self.ended_timestamp(current_timestamp).is_none()
     }
@@ -70,6 +88,17 @@ impl CompPref {
         }
     }
 
+    /// The timestamp after which an inactive pref has been inactive for long enough
+    /// that it can be removed from state
+    pub fn prunable_at(
+        &self,
+        current_timestamp: &Timestamp,
+        days_to_prune: u16,
+    ) -> Option<Timestamp> {
+        self.ended_timestamp(current_timestamp)
+            .map(|ended_at| ended_at.plus_seconds(u64::from(days_to_prune) * 86_400))
+    }
+
     pub fn matches_status_filter(
         &self,
         status_filter: &Option<CompPrefStatus>,
@@ -145,3 +174,33 @@ impl CompPref {
         }
     }
 }
+
+impl CompoundingFrequency {
+    /// The number of seconds between each run of the strategy
+    pub fn seconds(&self) -> u64 {
+        self.clone() as u64
+    }
+}
+
+/// Sets the next time the user's strategy should run, replacing any previously scheduled run
+pub fn schedule_next_run(
+    store: &mut dyn Storage,
+    strat_id: u64,
+    user_addr: &Addr,
+    next_run: Timestamp,
+) -> StdResult<()> {
+    unschedule_runs(store, strat_id, user_addr)?;
+
+    NEXT_RUN.save(store, (strat_id, user_addr), &next_run)?;
+    RUN_QUEUE.save(store, (strat_id, next_run.seconds(), user_addr), &())
+}
+
+/// Removes the user's strategy from the run queue
+pub fn unschedule_runs(store: &mut dyn Storage, strat_id: u64, user_addr: &Addr) -> StdResult<()> {
+    if let Some(prev_run) = NEXT_RUN.may_load(store, (strat_id, user_addr))? {
+        RUN_QUEUE.remove(store, (strat_id, prev_run.seconds(), user_addr));
+        NEXT_RUN.remove(store, (strat_id, user_addr));
+    }
+
+    Ok(())
+}
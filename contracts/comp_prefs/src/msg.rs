@@ -1,5 +1,5 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Uint64;
+use cosmwasm_std::{Timestamp, Uint64};
 
 use crate::state::{CompPref, StoreSettings, UnverifiedUserCompPref};
 
@@ -45,8 +45,34 @@ pub enum ExecuteMsg {
 
     /// Updates compounding prefs to accurately store data on inactivity
     /// and removes settings that are more than `days_to_prune` days older than the
-    /// time they became inactive
-    PruneInactiveCompoundingPreferences { limit: u16, offset: u32 },
+    /// time they became inactive.
+    /// Batches are resumed from the last strategy id and user address of the previous batch,
+    /// which the response reports as `last_strat_id` and `last_address`
+    PruneInactiveCompoundingPreferences {
+        limit: u16,
+        start_after: Option<(Uint64, String)>,
+    },
+
+    /// Add an address that is allowed to advance compounding schedules
+    /// ADMIN ONLY ACTION
+    AddAuthorizedKeeper(String),
+
+    /// Remove an address from the authorized keepers
+    /// ADMIN ONLY ACTION
+    RemoveAuthorizedKeeper(String),
+
+    /// Re-saves the next `limit` stored prefs so that they're picked up by the indexes.
+    /// Resumes from where the previous batch left off until every pref has been re-indexed
+    /// ADMIN ONLY ACTION
+    ReindexCompoundingPreferences { limit: u16 },
+
+    /// Marks a strategy as having been run and schedules its next run
+    /// one `comp_period` from now.
+    /// Can be called by the prefs' outpost, an authorized keeper, or the admin
+    AdvanceSchedule {
+        strat_id: Uint64,
+        user_address: String,
+    },
 }
 
 #[cw_serde]
@@ -72,28 +98,66 @@ pub enum QueryMsg {
     StrategyPreferencesByUser {
         user_address: String,
         status: Option<CompPrefStatus>,
+        limit: Option<u16>,
+        /// strategy id to resume pagination from
+        start_after: Option<Uint64>,
+    },
+
+    /// Gets all of the stored strategy settings that run through a given outpost
+    #[returns(Vec<CompPref>)]
+    StrategyPreferencesByOutpost {
+        outpost_address: String,
+        status: Option<CompPrefStatus>,
+        limit: Option<u16>,
+        /// strategy id and user address to resume pagination from
+        start_after: Option<(Uint64, String)>,
     },
 
     #[returns(Vec<CompPref>)]
     StrategyPreferencesByStratId {
         strat_id: Uint64,
-        /// experimental status filter- maybe have unpredictable gas usage
-        /// buyer beware
+        /// status filter- active and cancelled prefs are looked up via the status index
+        /// and expired prefs via the expiry index. Inactive prefs list the cancelled prefs first
         status: Option<CompPrefStatus>,
         limit: Option<u16>,
-        /// address to resume pagination from
+        /// address to resume pagination from (exclusive)
         prev_address: Option<String>,
     },
 
+    #[returns(Vec<String>)]
+    AuthorizedKeepers,
+
+    /// Gets the active strategy settings for a strategy whose next run is due
+    /// in the order that they became due
+    #[returns(Vec<DuePreference>)]
+    DuePreferences {
+        strat_id: Uint64,
+        /// the time to check against- defaults to the current block time
+        now: Option<Timestamp>,
+        limit: Option<u16>,
+        /// next run time and user address to resume pagination from
+        start_after: Option<(Timestamp, String)>,
+    },
+
     /// Gets all of the stored strategy settings for the user with
     /// the given pubkey
     #[returns(Vec<CompPref>)]
     StrategyPreferencesByPubkey {
         pubkey: String,
         status: Option<CompPrefStatus>,
+        limit: Option<u16>,
+        /// strategy id and user address to resume pagination from
+        start_after: Option<(Uint64, String)>,
     },
 }
 
+#[cw_serde]
+pub struct DuePreference {
+    /// When the strategy was scheduled to run next
+    pub next_run: Timestamp,
+    pub comp_pref: CompPref,
+}
+
 #[cw_serde]
 pub enum CompPrefStatus {
     // Campaign is currently valid and not expired
@@ -1,12 +1,64 @@
-use cosmwasm_std::{Order, Storage, Uint64};
+use cosmwasm_std::{Addr, Order, StdResult, Storage, Timestamp, Uint64};
+use cw_storage_plus::Bound;
 
-use crate::state::ALLOWED_STRATEGY_IDS;
+use crate::{
+    msg::CompPrefStatus,
+    state::{comp_prefs, CompPref, ALLOWED_STRATEGY_IDS, AUTHORIZED_KEEPERS},
+};
 
 /// Queries the list of all strategy ids from state
-pub fn all_strat_ids(store: &dyn Storage) -> Vec<Uint64> {
+pub fn all_strat_ids(store: &dyn Storage) -> StdResult<Vec<Uint64>> {
     ALLOWED_STRATEGY_IDS
         .keys(store, None, None, Order::Ascending)
-        .filter_map(Result::ok)
-        .map(Uint64::from)
+        .map(|strat_id| strat_id.map(Uint64::from))
         .collect()
 }
+
+/// Queries the list of all authorized keepers from state
+pub fn authorized_keepers(store: &dyn Storage) -> StdResult<Vec<String>> {
+    AUTHORIZED_KEEPERS
+        .keys(store, None, None, Order::Ascending)
+        .map(|keeper| keeper.map(|keeper| keeper.to_string()))
+        .collect()
+}
+
+/// Collects the prefs from a range over the stored prefs that match the status filter.
+/// Non-matching prefs are skipped rather than ending the search so that
+/// a limited page is never cut short by an unrelated status
+pub fn collect_prefs<K>(
+    prefs: impl Iterator<Item = StdResult<(K, CompPref)>>,
+    status: &Option<CompPrefStatus>,
+    current_time: &Timestamp,
+    limit: Option<u16>,
+) -> StdResult<Vec<CompPref>> {
+    let matching_prefs = prefs
+        .filter(|possible_pref| match possible_pref {
+            Ok((_, pref)) => pref.matches_status_filter(status, current_time),
+            Err(_) => true,
+        })
+        .map(|possible_pref| possible_pref.map(|(_, pref)| pref));
+
+    match limit {
+        Some(limit) => matching_prefs.take(limit.into()).collect(),
+        None => matching_prefs.collect(),
+    }
+}
+
+/// Ranges over the strategy's prefs that have passed their expiration, whether or not
+/// the expiration has been recorded yet. The prefs are ordered by their expiration
+pub fn expired_prefs<'a>(
+    store: &'a dyn Storage,
+    strat_id: u64,
+    start_after: Option<Bound<'a, (u64, (u64, Addr))>>,
+    current_time: &'a Timestamp,
+) -> impl Iterator<Item = StdResult<((u64, Addr), CompPref)>> + 'a {
+    comp_prefs()
+        .idx
+        .expiry
+        .sub_prefix(strat_id)
+        .range(store, start_after, None, Order::Ascending)
+        .take_while(move |possible_pref| match possible_pref {
+            Ok((_, pref)) => current_time.gt(&pref.user_comp_pref.expires),
+            Err(_) => true,
+        })
+}
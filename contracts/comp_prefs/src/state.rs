@@ -1,6 +1,6 @@
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{Addr, Binary, Timestamp, Uint64};
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 
 // General settings for the whole contract
 pub const STORE_SETTINGS: Item<StoreSettings> = Item::new("store_settings");
@@ -8,15 +8,84 @@ pub const STORE_SETTINGS: Item<StoreSettings> = Item::new("store_settings");
 // Map of all the strategy ids that are allowed.
 pub const ALLOWED_STRATEGY_IDS: Map<u64, ()> = Map::new("allowed_strategy_ids");
 
+// Recorded status keys used by the status index.
+// Prefs that have passed their expiration but have not been pruned yet still sit under `STATUS_UNMARKED`
+pub const STATUS_UNMARKED: u8 = 0;
+pub const STATUS_CANCELLED: u8 = 1;
+pub const STATUS_EXPIRED: u8 = 2;
+
+pub struct CompPrefIndexes<'a> {
+    /// strat id and recorded status of the prefs
+    pub status: MultiIndex<'a, (u64, u8), CompPref, (u64, Addr)>,
+    /// strat id and the expiration (in nanos) of the prefs
+    pub expiry: MultiIndex<'a, (u64, u64), CompPref, (u64, Addr)>,
+    /// outpost address that the prefs are run through
+    pub outpost: MultiIndex<'a, Addr, CompPref, (u64, Addr)>,
+    /// user address that the prefs belong to
+    pub user: MultiIndex<'a, Addr, CompPref, (u64, Addr)>,
+}
+
+impl<'a> IndexList<CompPref> for CompPrefIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<CompPref>> + '_> {
+        let v: Vec<&dyn Index<CompPref>> =
+            vec![&self.status, &self.expiry, &self.outpost, &self.user];
+        Box::new(v.into_iter())
+    }
+}
+
 // Tuple of strat id and user address  that matches with that user's compounding preferences
-pub const COMP_PREFS: Map<(u64, &Addr), CompPref> = Map::new("compounding_preferences");
+pub fn comp_prefs<'a>() -> IndexedMap<'a, (u64, &'a Addr), CompPref, CompPrefIndexes<'a>> {
+    let indexes = CompPrefIndexes {
+        status: MultiIndex::new(
+            |_pk, pref| (pref.user_comp_pref.strat_id, pref.recorded_status()),
+            "compounding_preferences",
+            "compounding_preferences__status",
+        ),
+        expiry: MultiIndex::new(
+            |_pk, pref| {
+                (
+                    pref.user_comp_pref.strat_id,
+                    pref.user_comp_pref.expires.nanos(),
+                )
+            },
+            "compounding_preferences",
+            "compounding_preferences__expiry",
+        ),
+        outpost: MultiIndex::new(
+            |_pk, pref| pref.user_comp_pref.outpost_address.clone(),
+            "compounding_preferences",
+            "compounding_preferences__outpost",
+        ),
+        user: MultiIndex::new(
+            |_pk, pref| pref.user_comp_pref.address.clone(),
+            "compounding_preferences",
+            "compounding_preferences__user",
+        ),
+    };
+
+    IndexedMap::new("compounding_preferences", indexes)
+}
+
+// Set while the stored prefs are being re-indexed after a migration.
+// Holds the key of the last pref that was re-indexed or `None` if none have been yet
+pub const REINDEX_CURSOR: Item<Option<(u64, Addr)>> = Item::new("reindex_cursor");
 
 // structure for querying by pubkey.
 // returns the compound key that will be able to get the user's preferences from
-// the COMP_PREFS map
+// the comp_prefs map
 // note: this doesnt actually use a true pubkey but should be a unique identifier for the wallet
 pub const PREFS_BY_PUBKEY: Map<(&str, u64, Addr), ()> = Map::new("comp_prefs_by_pubkey");
 
+// Addresses other than the admin and the prefs' outpost that are allowed to advance compounding schedules
+pub const AUTHORIZED_KEEPERS: Map<&Addr, ()> = Map::new("authorized_keepers");
+
+// Tuple of strat id and user address that matches with the next time that user's strategy should run
+pub const NEXT_RUN: Map<(u64, &Addr), Timestamp> = Map::new("next_run");
+
+// Queue of strat id, next run (in seconds) and user address so that due prefs can be
+// looked up in the order they became due
+pub const RUN_QUEUE: Map<(u64, u64, &Addr), ()> = Map::new("run_queue");
+
 #[cw_serde]
 pub struct UnverifiedUserCompPref {
     /// address of the outpost contract
@@ -1,6 +1,6 @@
 use crate::{
     interface::YmosCompPrefsContract,
-    msg::{CompPrefStatus, ExecuteMsgFns, InstantiateMsg, QueryMsgFns},
+    msg::{CompPrefStatus, DuePreference, ExecuteMsgFns, InstantiateMsg, MigrateMsg, QueryMsgFns},
     state::{
         CompPref, CompoundingFrequency, EndType, InactiveStatus, StoreSettings,
         UnverifiedUserCompPref, UserCompPref,
@@ -267,5 +267,621 @@ pub fn test() -> anyhow::Result<()> {
         "should be able to filter out active compounding preferences"
     );
 
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_strat_id(
+            Uint64::from(1u64),
+            Some(1u16),
+            Some(user.to_string()),
+            None
+        )?,
+        vec![verified_prefs_2.clone()],
+        "should be able to resume pagination after the previous address"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_strat_id(
+            Uint64::from(1u64),
+            Some(1u16),
+            None,
+            Some(CompPrefStatus::Cancelled),
+        )?,
+        vec![verified_prefs_3.clone()],
+        "status filtering should not be cut short by non-matching prefs"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_user(
+            user_3.to_string(),
+            None,
+            None,
+            Some(CompPrefStatus::Cancelled)
+        )?,
+        vec![verified_prefs_3.clone()],
+        "should be able to get the compounding preferences by user"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_user(
+            user.to_string(),
+            None,
+            Some(Uint64::from(1u64)),
+            None
+        )?,
+        vec![] as Vec<CompPref>,
+        "should be able to paginate past the user's last strategy"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_outpost(
+            outpost_contract.to_string(),
+            None,
+            Some((Uint64::from(1u64), user_2.to_string())),
+            None
+        )?,
+        vec![verified_prefs_3.clone()],
+        "should be able to page through the compounding preferences by outpost"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_pubkey("123".to_string(), None, None, None)?,
+        vec![verified_prefs.clone()],
+        "should be able to get the compounding preferences by pubkey"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_pubkey(
+            "345".to_string(),
+            None,
+            None,
+            Some(CompPrefStatus::Active),
+        )?,
+        vec![] as Vec<CompPref>,
+        "should be able to filter pubkey results by status"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_pubkey(
+            "345".to_string(),
+            None,
+            None,
+            Some(CompPrefStatus::Cancelled),
+        )?,
+        vec![verified_prefs_3.clone()],
+        "should be able to get cancelled compounding preferences by pubkey"
+    );
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_pubkey(
+            "123".to_string(),
+            None,
+            Some((Uint64::from(1u64), user.to_string())),
+            None,
+        )?,
+        vec![] as Vec<CompPref>,
+        "should be able to paginate past the last pubkey result"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn test_prune_inactive_prefs() -> anyhow::Result<()> {
+    let admin = Addr::unchecked("tprl1adminf5846rxzp3fwlswy08fz8ccuwk03k57y");
+    let outpost_contract = Addr::unchecked("tprl1outpost15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user = Addr::unchecked("tprl1user15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user_2 = Addr::unchecked("tprl1user25846rxzp3fwlswy08fz8ccuwk03k57y");
+
+    let mock = Mock::new(&admin);
+
+    let comp_prefs_contract = YmosCompPrefsContract::new("mock:comp_pref_contract", mock.clone());
+
+    comp_prefs_contract.upload()?;
+
+    comp_prefs_contract.instantiate(
+        &InstantiateMsg {
+            admin: None,
+            chain_id: "temporal-1".to_string(),
+            days_to_prune: 1,
+        },
+        Some(&admin),
+        None,
+    )?;
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .add_allowed_strategy_id(Uint64::from(1u64))?;
+
+    let start_time = mock.block_info()?.time;
+
+    let prefs = |address: &Addr, pub_key: &str, expires: Timestamp| UnverifiedUserCompPref {
+        outpost_address: outpost_contract.to_string(),
+        address: address.to_string(),
+        strat_id: Uint64::from(1u64),
+        strategy_settings: to_json_binary(&ExampleCompoundPrefs {
+            user_address: address.to_string(),
+            tax_fee: Some(Decimal::percent(5)),
+        })
+        .unwrap(),
+        comp_period: CompoundingFrequency::Daily,
+        pub_key: pub_key.to_string(),
+        expires,
+    };
+
+    // user's prefs expire shortly while user_2's are cancelled long before they'd expire
+    comp_prefs_contract
+        .call_as(&user)
+        .set_compounding_preferences(prefs(&user, "123", start_time.plus_seconds(100)))?;
+    comp_prefs_contract
+        .call_as(&user_2)
+        .set_compounding_preferences(prefs(&user_2, "234", start_time.plus_seconds(1_000_000)))?;
+
+    mock.wait_seconds(200)?;
+
+    comp_prefs_contract
+        .call_as(&user_2)
+        .cancel_compounding_preferences(Uint64::from(1u64))?;
+
+    // only the admin can prune
+    comp_prefs_contract
+        .call_as(&user)
+        .prune_inactive_compounding_preferences(10u16, None)
+        .unwrap_err();
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .prune_inactive_compounding_preferences(10u16, None)?;
+
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_user_and_strat_id(Uint64::from(1u64), user.to_string())?
+            .and_then(|pref| pref.is_inactive),
+        Some(InactiveStatus {
+            end_type: EndType::Expiration,
+            ended_at: start_time.plus_seconds(100),
+        }),
+        "expired prefs should be marked as such"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(
+                Uint64::from(1u64),
+                None,
+                None,
+                Some(CompPrefStatus::Expired)
+            )?
+            .into_iter()
+            .map(|pref| pref.user_comp_pref.address)
+            .collect::<Vec<_>>(),
+        vec![user.clone()],
+        "expired prefs should be found via their expiry"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(
+                Uint64::from(1u64),
+                Some(1u16),
+                None,
+                Some(CompPrefStatus::Inactive)
+            )?
+            .into_iter()
+            .map(|pref| pref.user_comp_pref.address)
+            .collect::<Vec<_>>(),
+        vec![user_2.clone()],
+        "inactive prefs should list the cancelled prefs first"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(
+                Uint64::from(1u64),
+                Some(1u16),
+                Some(user_2.to_string()),
+                Some(CompPrefStatus::Inactive)
+            )?
+            .into_iter()
+            .map(|pref| pref.user_comp_pref.address)
+            .collect::<Vec<_>>(),
+        vec![user.clone()],
+        "inactive prefs should resume from the cancelled prefs into the expired prefs"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(Uint64::from(1u64), None, None, None)?
+            .len(),
+        2,
+        "no prefs should be pruned before days_to_prune has passed"
+    );
+
+    mock.wait_seconds(90_000)?;
+
+    // a batch of one should only prune the first pref
+    let res = comp_prefs_contract
+        .call_as(&admin)
+        .prune_inactive_compounding_preferences(1u16, None)?;
+    let last_strat_id = res.event_attr_value("wasm", "last_strat_id")?;
+    let last_address = res.event_attr_value("wasm", "last_address")?;
+    assert_eq!(
+        (last_strat_id.as_str(), last_address.as_str()),
+        ("1", user.as_str()),
+        "the batch should report the key to resume after"
+    );
+
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_user_and_strat_id(Uint64::from(1u64), user.to_string())?,
+        None,
+        "expired prefs should be pruned after days_to_prune"
+    );
+    assert!(
+        comp_prefs_contract
+            .strategy_preferences_by_user_and_strat_id(Uint64::from(1u64), user_2.to_string())?
+            .is_some(),
+        "prefs outside of the batch should be untouched"
+    );
+
+    // resuming after an entry that has since been pruned should pick up with the next entry
+    comp_prefs_contract
+        .call_as(&admin)
+        .prune_inactive_compounding_preferences(
+            1u16,
+            Some((Uint64::new(last_strat_id.parse()?), last_address)),
+        )?;
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_strat_id(
+            Uint64::from(1u64),
+            None,
+            None,
+            None
+        )?,
+        vec![] as Vec<CompPref>,
+        "cancelled prefs should be pruned after days_to_prune"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn test_inactive_prefs_listed_once() -> anyhow::Result<()> {
+    let admin = Addr::unchecked("tprl1adminf5846rxzp3fwlswy08fz8ccuwk03k57y");
+    let outpost_contract = Addr::unchecked("tprl1outpost15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user = Addr::unchecked("tprl1user15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user_2 = Addr::unchecked("tprl1user25846rxzp3fwlswy08fz8ccuwk03k57y");
+
+    let mock = Mock::new(&admin);
+
+    let comp_prefs_contract = YmosCompPrefsContract::new("mock:comp_pref_contract", mock.clone());
+
+    comp_prefs_contract.upload()?;
+
+    comp_prefs_contract.instantiate(
+        &InstantiateMsg {
+            admin: None,
+            chain_id: "temporal-1".to_string(),
+            days_to_prune: 180,
+        },
+        Some(&admin),
+        None,
+    )?;
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .add_allowed_strategy_id(Uint64::from(1u64))?;
+
+    let start_time = mock.block_info()?.time;
+
+    let prefs = |address: &Addr, pub_key: &str| UnverifiedUserCompPref {
+        outpost_address: outpost_contract.to_string(),
+        address: address.to_string(),
+        strat_id: Uint64::from(1u64),
+        strategy_settings: to_json_binary(&ExampleCompoundPrefs {
+            user_address: address.to_string(),
+            tax_fee: Some(Decimal::percent(5)),
+        })
+        .unwrap(),
+        comp_period: CompoundingFrequency::Daily,
+        pub_key: pub_key.to_string(),
+        expires: start_time.plus_seconds(100),
+    };
+
+    // user cancels before their prefs expire while user_2's prefs just expire
+    comp_prefs_contract
+        .call_as(&user)
+        .set_compounding_preferences(prefs(&user, "123"))?;
+    comp_prefs_contract
+        .call_as(&user_2)
+        .set_compounding_preferences(prefs(&user_2, "234"))?;
+
+    mock.wait_seconds(50)?;
+
+    comp_prefs_contract
+        .call_as(&user)
+        .cancel_compounding_preferences(Uint64::from(1u64))?;
+
+    mock.wait_seconds(100)?;
+
+    let inactive_addresses = |limit: Option<u16>, start_after: Option<String>| {
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(
+                Uint64::from(1u64),
+                limit,
+                start_after,
+                Some(CompPrefStatus::Inactive),
+            )
+            .map(|prefs| {
+                prefs
+                    .into_iter()
+                    .map(|pref| pref.user_comp_pref.address)
+                    .collect::<Vec<_>>()
+            })
+    };
+
+    // user's prefs are both cancelled and past their expiration but are only listed as cancelled
+    assert_eq!(
+        inactive_addresses(None, None)?,
+        vec![user.clone(), user_2.clone()],
+        "prefs that are cancelled and expired should only be listed once"
+    );
+    assert_eq!(
+        inactive_addresses(Some(1u16), Some(user.to_string()))?,
+        vec![user_2.clone()],
+        "resuming from a cancelled and expired pref should move on to the expired prefs"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_strat_id(
+                Uint64::from(1u64),
+                None,
+                None,
+                Some(CompPrefStatus::Expired)
+            )?
+            .into_iter()
+            .map(|pref| pref.user_comp_pref.address)
+            .collect::<Vec<_>>(),
+        vec![user_2],
+        "cancelled prefs shouldn't be listed as expired"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn test_due_preferences_schedule() -> anyhow::Result<()> {
+    let admin = Addr::unchecked("tprl1adminf5846rxzp3fwlswy08fz8ccuwk03k57y");
+    let outpost_contract = Addr::unchecked("tprl1outpost15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let keeper = Addr::unchecked("tprl1keeper5846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user = Addr::unchecked("tprl1user15846rxzp3fwlswy08fz8ccuwk03k57y");
+
+    let mock = Mock::new(&admin);
+
+    let comp_prefs_contract = YmosCompPrefsContract::new("mock:comp_pref_contract", mock.clone());
+
+    comp_prefs_contract.upload()?;
+
+    comp_prefs_contract.instantiate(
+        &InstantiateMsg {
+            admin: None,
+            chain_id: "temporal-1".to_string(),
+            days_to_prune: 180,
+        },
+        Some(&admin),
+        None,
+    )?;
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .add_allowed_strategy_id(Uint64::from(1u64))?;
+
+    let start_time = mock.block_info()?.time;
+
+    comp_prefs_contract
+        .call_as(&user)
+        .set_compounding_preferences(UnverifiedUserCompPref {
+            outpost_address: outpost_contract.to_string(),
+            address: user.to_string(),
+            strat_id: Uint64::from(1u64),
+            strategy_settings: to_json_binary(&ExampleCompoundPrefs {
+                user_address: user.to_string(),
+                tax_fee: Some(Decimal::percent(5)),
+            })?,
+            comp_period: CompoundingFrequency::Daily,
+            pub_key: "123".to_string(),
+            expires: start_time.plus_seconds(10 * 86_400),
+        })?;
+
+    let due = comp_prefs_contract.due_preferences(Uint64::from(1u64), None, None, None)?;
+    assert_eq!(due.len(), 1, "new prefs should be due right away");
+    assert_eq!(due[0].next_run, start_time);
+
+    // only the outpost, keepers and the admin can advance the schedule
+    comp_prefs_contract
+        .call_as(&user)
+        .advance_schedule(Uint64::from(1u64), user.to_string())
+        .unwrap_err();
+
+    comp_prefs_contract
+        .call_as(&outpost_contract)
+        .advance_schedule(Uint64::from(1u64), user.to_string())?;
+
+    // the schedule can't be advanced again before the next run is due
+    comp_prefs_contract
+        .call_as(&outpost_contract)
+        .advance_schedule(Uint64::from(1u64), user.to_string())
+        .unwrap_err();
+
+    assert_eq!(
+        comp_prefs_contract.due_preferences(Uint64::from(1u64), None, None, None)?,
+        vec![] as Vec<DuePreference>,
+        "prefs should not be due right after running"
+    );
+    assert_eq!(
+        comp_prefs_contract
+            .due_preferences(
+                Uint64::from(1u64),
+                None,
+                Some(start_time.plus_seconds(86_400)),
+                None
+            )?
+            .len(),
+        1,
+        "prefs should be due again one comp period later"
+    );
+    assert_eq!(
+        comp_prefs_contract.due_preferences(
+            Uint64::from(1u64),
+            None,
+            Some(start_time.plus_seconds(86_400)),
+            Some((start_time.plus_seconds(86_400), user.to_string()))
+        )?,
+        vec![] as Vec<DuePreference>,
+        "should be able to paginate past the last due prefs"
+    );
+
+    // non-admin cannot add keepers
+    comp_prefs_contract
+        .call_as(&keeper)
+        .add_authorized_keeper(keeper.to_string())
+        .unwrap_err();
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .add_authorized_keeper(keeper.to_string())?;
+
+    assert_eq!(
+        comp_prefs_contract.authorized_keepers()?,
+        vec![keeper.to_string()]
+    );
+
+    mock.wait_seconds(86_400)?;
+
+    comp_prefs_contract
+        .call_as(&keeper)
+        .advance_schedule(Uint64::from(1u64), user.to_string())?;
+
+    assert_eq!(
+        comp_prefs_contract
+            .due_preferences(
+                Uint64::from(1u64),
+                None,
+                Some(start_time.plus_seconds(2 * 86_400)),
+                None
+            )?
+            .len(),
+        1,
+        "keepers should be able to advance the schedule"
+    );
+
+    comp_prefs_contract
+        .call_as(&user)
+        .cancel_compounding_preferences(Uint64::from(1u64))?;
+
+    assert_eq!(
+        comp_prefs_contract.due_preferences(
+            Uint64::from(1u64),
+            None,
+            Some(start_time.plus_seconds(5 * 86_400)),
+            None
+        )?,
+        vec![] as Vec<DuePreference>,
+        "cancelled prefs should never be due"
+    );
+
+    comp_prefs_contract
+        .call_as(&keeper)
+        .advance_schedule(Uint64::from(1u64), user.to_string())
+        .unwrap_err();
+
+    Ok(())
+}
+
+#[test]
+pub fn test_reindex_after_migrate() -> anyhow::Result<()> {
+    let admin = Addr::unchecked("tprl1adminf5846rxzp3fwlswy08fz8ccuwk03k57y");
+    let outpost_contract = Addr::unchecked("tprl1outpost15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user = Addr::unchecked("tprl1user15846rxzp3fwlswy08fz8ccuwk03k57y");
+    let user_2 = Addr::unchecked("tprl1user25846rxzp3fwlswy08fz8ccuwk03k57y");
+
+    let mock = Mock::new(&admin);
+
+    let comp_prefs_contract = YmosCompPrefsContract::new("mock:comp_pref_contract", mock.clone());
+
+    comp_prefs_contract.upload()?;
+
+    comp_prefs_contract.instantiate(
+        &InstantiateMsg {
+            admin: None,
+            chain_id: "temporal-1".to_string(),
+            days_to_prune: 180,
+        },
+        Some(&admin),
+        None,
+    )?;
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .add_allowed_strategy_id(Uint64::from(1u64))?;
+
+    let start_time = mock.block_info()?.time;
+
+    for address in [&user, &user_2] {
+        comp_prefs_contract
+            .call_as(address)
+            .set_compounding_preferences(UnverifiedUserCompPref {
+                outpost_address: outpost_contract.to_string(),
+                address: address.to_string(),
+                strat_id: Uint64::from(1u64),
+                strategy_settings: to_json_binary(&ExampleCompoundPrefs {
+                    user_address: address.to_string(),
+                    tax_fee: Some(Decimal::percent(5)),
+                })?,
+                comp_period: CompoundingFrequency::Daily,
+                pub_key: address.to_string(),
+                expires: start_time.plus_seconds(10 * 86_400),
+            })?;
+    }
+
+    // nothing to reindex before a migration
+    comp_prefs_contract
+        .call_as(&admin)
+        .reindex_compounding_preferences(1u16)
+        .unwrap_err();
+
+    comp_prefs_contract.migrate(&MigrateMsg {}, comp_prefs_contract.code_id()?)?;
+
+    // only the admin can reindex
+    comp_prefs_contract
+        .call_as(&user)
+        .reindex_compounding_preferences(1u16)
+        .unwrap_err();
+
+    // each batch picks up where the last one ended until a short batch finishes the reindex
+    for _ in 0..3 {
+        comp_prefs_contract
+            .call_as(&admin)
+            .reindex_compounding_preferences(1u16)?;
+    }
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .reindex_compounding_preferences(1u16)
+        .unwrap_err();
+
+    assert_eq!(
+        comp_prefs_contract
+            .strategy_preferences_by_user(user.to_string(), None, None, None)?
+            .len(),
+        1,
+        "reindexed prefs should still be found by user"
+    );
+
+    comp_prefs_contract
+        .call_as(&admin)
+        .remove_allowed_strategy_id(Uint64::from(1u64))?;
+
+    assert_eq!(
+        comp_prefs_contract.strategy_preferences_by_user(user.to_string(), None, None, None)?,
+        vec![] as Vec<CompPref>,
+        "prefs for strategies that are no longer allowed should not be returned"
+    );
+
     Ok(())
 }
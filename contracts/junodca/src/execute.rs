@@ -1,37 +1,55 @@
-use std::iter;
-
-use cosmwasm_std::{Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128, Uint64,
+};
 use outpost_utils::{
-    comp_prefs::DestinationAction,
+    asset::OutpostAsset,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
     helpers::{
-        calc_additional_tax_split, calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, DestProjectMsgs,
-        TaxSplitResult,
+        below_min_compound, calc_additional_tax_split, enforce_compound_frequency, is_authorized_compounder,
+        min_out_after_slippage, prefs_sum_to_one, simulate_compound_response, CompoundPlan, DestProjectMsgs,
+        SimulateCompoundResponse, TaxSplitResult,
     },
-
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    stats::CompoundRun,
 };
 use terraswap_helpers::terraswap_swap::create_terraswap_swap_msg_with_simulation;
-use wynd_helpers::wynd_swap::{create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg};
+use wynd_helpers::wynd_swap::{
+    create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg, WyndexVenue,
+};
 use wyndex::asset::{Asset, AssetInfo};
 use juno_destinations::comp_prefs::{JunoCompPrefs, JunoDestinationProject, StakingDao};
-use juno_destinations::dest_project_gen::{balance_dao_msgs, gelotto_lottery_msgs, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs, wynd_staking_msgs};
+use juno_destinations::dest_project_gen::{
+    balance_dao_msgs, gelotto_lottery_msgs, juno_swap_router, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs,
+    wynd_staking_msgs, JunoSwapVenue,
+};
 use sail_destinations::dest_project_gen::{spark_ibc_msgs, white_whale_satellite_msgs};
-use universal_destinations::dest_project_gen::{daodao_cw20_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{
+    active_multi_staking_msgs, active_native_staking_msg, daodao_cw20_staking_msg, token_swap_msgs,
+};
 
 use crate::{
     msg::{ContractAddrs, DcaPrefs},
-    state::{ADMIN, AUTHORIZED_ADDRS, PROJECT_ADDRS},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS,
+        PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS, SWAP_SPLIT,
+    },
     ContractError,
 };
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     _project_addresses: ContractAddrs,
     user_address: String,
     comp_prefs: &DcaPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     let DcaPrefs {
         compound_token,
@@ -41,48 +59,94 @@ pub fn compound(
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(compound_preferences)?;
 
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
     // check that the delegator address is valid
     let user_addr: Addr = deps.api.addr_validate(&user_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &user_addr,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
     let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
 
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
     // calculate the total amount of rewards that will be compounded
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
     let TaxSplitResult {
         remaining_rewards,
         tax_amount,
+        referral_amount,
         claim_and_tax_msgs: tax_store_msg,
     } = calc_additional_tax_split(
         compound_token,
-        tax_fee.unwrap_or(Decimal::percent(1)),
+        tax_fee,
         user_address,
         project_addrs.take_rate_addr.to_string(),
+        referral.as_ref(),
     );
 
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
-        &project_addrs,
+    // amounts that aren't worth compounding are skipped rather than split into dust
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps.as_ref(), &project_addrs, &user_addr, amount)
+    })? {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &user_addr, compound_token.amount)?;
+
+    let comp_prefs = compound_preferences
+        .clone()
+        .with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (all_msgs, fallbacks) = isolate_destinations(&user_addr, &remaining_rewards, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(&project_addrs, &user_addr, amount, comp_prefs, deps.as_ref())
+    })?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(
+        deps.storage,
         &user_addr,
-        remaining_rewards.clone(),
-        compound_preferences.clone(),
-        deps.as_ref(),
+        &CompoundRun::new(env.block.time, vec![tax_amount.clone()], &fallbacks),
     )?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
 
-    let combined_msgs = all_msgs.iter().fold(
-        DestProjectMsgs {
-            msgs: tax_store_msg,
-            sub_msgs: vec![],
-            events: vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())],
-        },
-        |mut acc, msg| {
-            acc.msgs.append(&mut msg.msgs.clone());
-            acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-            acc.events.append(&mut msg.events.clone());
-            acc
-        },
-    );
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &user_addr,
+        &PipelineAsset::Native(remaining_rewards.denom.clone()),
+        all_msgs,
+    )?;
+
+    combined_msgs.prepend_msgs(tax_store_msg);
+    combined_msgs.prepend_events(vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())]);
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
         Event::new("amount_automated").add_attributes([remaining_rewards].iter().enumerate().map(|(i, coin)| Attribute {
@@ -91,13 +155,21 @@ pub fn compound(
         }));
 
     // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        vec![],
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         .add_attribute("compoundee", user_addr)
         .add_event(amount_automated_event)
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -125,6 +197,97 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    user_address: String,
+    comp_prefs: &DcaPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<JunoDestinationProject>, ContractError> {
+    let DcaPrefs {
+        compound_token,
+        compound_preferences,
+    } = comp_prefs;
+
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(compound_preferences)?;
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
+    let user_addr: Addr = deps.api.addr_validate(&user_address)?;
+
+    let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    let TaxSplitResult {
+        remaining_rewards,
+        tax_amount,
+        claim_and_tax_msgs,
+        ..
+    } = calc_additional_tax_split(
+        compound_token,
+        tax_fee,
+        user_address,
+        project_addrs.take_rate_addr.to_string(),
+        REFERRALS.referral(deps.storage, referrer.as_ref())?.as_ref(),
+    );
+
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps, &project_addrs, &user_addr, amount)
+    })? {
+        return Ok(CompoundPlan::skipped(compound_token.clone(), compound_preferences.clone(), skipped).simulate()?);
+    }
+
+    let dest_msgs = prefs_to_msgs(
+        &project_addrs,
+        &user_addr,
+        remaining_rewards.clone(),
+        compound_preferences.clone(),
+        deps,
+    )?;
+
+    Ok(simulate_compound_response(
+        compound_token.clone(),
+        vec![tax_amount],
+        remaining_rewards,
+        compound_preferences.clone(),
+        claim_and_tax_msgs,
+        dest_msgs,
+    )?)
+}
+
+/// What the amount would be worth in USDC going by its configured price source,
+/// or by a swap simulation through the wyndex router if it doesn't have one
+fn usdc_value(
+    deps: Deps,
+    project_addrs: &ContractAddrs,
+    user_addr: &Addr,
+    amount: &cosmwasm_std::Coin,
+) -> Result<Uint128, ContractError> {
+    if let Some(price_source) = PRICE_SOURCES.may_load(deps.storage, amount.denom.as_str())? {
+        return Ok(price_source.value(&deps.querier, &amount.into(), &project_addrs.usdc.clone().into())?);
+    }
+
+    let (_, usdc_value) = create_wyndex_swap_msg_with_simulation(
+        &deps.querier,
+        user_addr,
+        amount.amount,
+        AssetInfo::Native(amount.denom.clone()),
+        project_addrs.usdc.clone(),
+        project_addrs.destination_projects.wynd.multihop.to_string(),
+        None,
+        None,
+    )?;
+
+    Ok(usdc_value)
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -139,22 +302,20 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, JunoDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 let compounding_asset = Asset {
                     info: AssetInfo::Native(dca_denom.clone()),
                     amount: comp_token_amount,
                 };
 
                 match destination {
-                    JunoDestinationProject::JunoStaking { validator_address } => Ok(native_staking_msg(
+                    JunoDestinationProject::JunoStaking { validator_address } => Ok(active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         user_addr,
                         &cosmwasm_std::Coin {
@@ -162,6 +323,19 @@ pub fn prefs_to_msgs(
                             amount: comp_token_amount,
                         },
                     )?),
+                    JunoDestinationProject::JunoMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => Ok(active_multi_staking_msgs(
+                        &deps.querier,
+                        &validators,
+                        redelegate_inactive,
+                        user_addr,
+                        &cosmwasm_std::Coin {
+                            denom: dca_denom.clone(),
+                            amount: comp_token_amount,
+                        },
+                    )?),
 
                     JunoDestinationProject::DaoStaking(dao) => {
                         if let StakingDao::Kleomedes = dao {
@@ -176,6 +350,7 @@ pub fn prefs_to_msgs(
 
                         let dao_addresses = dao.get_daos_addresses(&project_addrs.destination_projects.daos);
 
+                        let mut impact_events = vec![];
                         let (swap_msgs, expected_dao_token_amount) = if let Some(pair_addr) = dao_addresses.juno_wyndex_pair
                         {
                             // if there's a direct juno & staking denom pair, then we can swap directly
@@ -183,10 +358,23 @@ pub fn prefs_to_msgs(
                                 &deps.querier,
                                 user_addr,
                                 pair_addr.as_ref(),
-                                compounding_asset,
+                                compounding_asset.clone(),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
+                                max_slippage,
                             )?;
 
+                            // dao pairs can be thin so the swap has to stay close to the pair's spot price
+                            if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                                impact_events.push(guard.check_against_spot(
+                                    &deps.querier,
+                                    &JunoSwapVenue::Wyndex(WyndexVenue::Pair(Addr::unchecked(&pair_addr))),
+                                    &pair_addr,
+                                    &compounding_asset.into(),
+                                    &AssetInfo::Token(dao_addresses.cw20.to_string()).into(),
+                                    swap_sim.return_amount,
+                                )?);
+                            }
+
                             (vec![swap_msg], swap_sim.return_amount)
                         } else {
                             // otherwise we need to use the wyndex router to swap
@@ -197,7 +385,8 @@ pub fn prefs_to_msgs(
                                 AssetInfo::Native(dca_denom.clone()),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
                                 project_addrs.destination_projects.wynd.multihop.to_string(),
-                                None
+                                None,
+                                max_slippage,
                             )?
                         };
 
@@ -209,7 +398,8 @@ pub fn prefs_to_msgs(
                             expected_dao_token_amount,
                         )?;
 
-                        stake_msgs.prepend_msgs(swap_msgs);
+                        stake_msgs.run_after_swap(swap_msgs);
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -227,28 +417,30 @@ pub fn prefs_to_msgs(
                             },
                             AssetInfo::Token(cw20.to_string()),
                             &juno_wynd_pair,
+                            max_slippage,
                         )?;
 
                         let mut staking_msg =
                             wynd_staking_msgs(&cw20, &user_addr.to_string(), comp_token_amount, bonding_period)?;
 
-                        staking_msg.prepend_msgs(vec![wynd_swap_msg]);
+                        staking_msg.run_after_swap(vec![wynd_swap_msg]);
 
                         Ok(staking_msg)
                     }
 
-                    JunoDestinationProject::TokenSwap { target_denom } => Ok(DestProjectMsgs {
-                        msgs: wynd_helpers::wynd_swap::create_wyndex_swap_msg(
+                    JunoDestinationProject::TokenSwap { target_denom } => {
+                        // the swap goes through whichever juno dex gives the best return
+                        let (swap_msgs, _) = token_swap_msgs(
+                            &juno_swap_router(&project_addrs.destination_projects, SWAP_SPLIT.may_load(deps.storage)?),
+                            &deps.querier,
                             user_addr,
-                            comp_token_amount,
-                            AssetInfo::Native(dca_denom.clone()),
-                            target_denom,
-                            project_addrs.destination_projects.wynd.multihop.to_string(),
-                        )
-                        .map_err(ContractError::Std)?,
-                        sub_msgs: vec![],
-                        events: vec![],
-                    }),
+                            &OutpostAsset::new(AssetInfo::Native(dca_denom.clone()), comp_token_amount),
+                            &target_denom.into(),
+                            max_slippage,
+                        )?;
+
+                        Ok(swap_msgs)
+                    },
                     JunoDestinationProject::WyndLp {
                         ..
                         // contract_address,
@@ -289,6 +481,7 @@ pub fn prefs_to_msgs(
                                 .white_whale
                                 .terraswap_multihop_router
                                 .to_string(),
+                            max_slippage,
                         )?;
 
                         let mut bond_msgs = white_whale_satellite_msgs(
@@ -297,7 +490,7 @@ pub fn prefs_to_msgs(
                             &project_addrs.destination_projects.white_whale.market.clone(),
                         )?;
 
-                        bond_msgs.prepend_msgs(swap_msgs);
+                        bond_msgs.run_after_swap(swap_msgs);
 
                         Ok(bond_msgs)
                     }
@@ -322,7 +515,8 @@ pub fn prefs_to_msgs(
                             comp_token_amount,
                             compounding_asset.info,
                             project_addrs.usdc.clone(),
-                            project_addrs.destination_projects.wynd.multihop.to_string(),None
+                            project_addrs.destination_projects.wynd.multihop.to_string(),None,
+                            max_slippage,
                         )?;
 
                         let mut spark_msgs = spark_ibc_msgs(
@@ -335,7 +529,7 @@ pub fn prefs_to_msgs(
                             fund,
                         )?;
 
-                        spark_msgs.prepend_msgs(swaps);
+                        spark_msgs.run_after_swap(swaps);
 
                         Ok(spark_msgs)
                     }
@@ -349,7 +543,8 @@ pub fn prefs_to_msgs(
                             comp_token_amount,
                             AssetInfo::Native(dca_denom.clone()),
                             target_asset.clone(),
-                            project_addrs.destination_projects.wynd.multihop.to_string(),None
+                            project_addrs.destination_projects.wynd.multihop.to_string(),None,
+                            max_slippage,
                         )
                         .map_err(ContractError::Std)?;
 
@@ -363,10 +558,40 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        send_msgs.append_msgs(swap_msgs);
+                        send_msgs.run_after_swap(swap_msgs);
 
                         Ok(send_msgs)
                     }
+                    JunoDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let (swap_msgs, sim) = create_wyndex_swap_msg_with_simulation(
+                            &deps.querier,
+                            user_addr,
+                            comp_token_amount,
+                            AssetInfo::Native(dca_denom.clone()),
+                            AssetInfo::Native(convert_to.clone()),
+                            project_addrs.destination_projects.wynd.multihop.to_string(),
+                            None,
+                            max_slippage,
+                        )
+                        .map_err(ContractError::Std)?;
+
+                        // the nested destinations only get what the swap is sure to return
+                        let intermediate_amount = if swap_msgs.is_empty() {
+                            sim
+                        } else {
+                            min_out_after_slippage(sim, max_slippage.unwrap_or(Decimal::percent(2)))
+                        };
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            user_addr,
+                            coin(intermediate_amount.u128(), convert_to),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
                     JunoDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
             
@@ -1,12 +1,14 @@
-use cosmwasm_std::{Addr, Coin, Decimal, Deps, QuerierWrapper, StdResult, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Coin, Deps, QuerierWrapper, StdError, StdResult, Timestamp, Uint128};
 use cw_grant_spec::grantable_trait::{dedupe_grant_reqs, GrantStructure, Grantable};
 use cw_grant_spec::grants::{
     AuthorizationType, ContractExecutionAuthorizationLimit, GrantBase, GrantRequirement, RevokeRequirement,
 };
-use juno_destinations::comp_prefs::{wyndex_asset_info_to_terraswap_asset_info, DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::comp_prefs::{DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::dest_project_gen::juno_swap_router;
 use juno_destinations::grants::{balance_dao_grant, gelotto_lottery_grant, wyndao_staking_grant};
+use outpost_utils::swap_venue::SwapVenue;
 use terraswap_helpers::terraswap_swap::terraswap_multihop_swap_grant;
-use universal_destinations::grants::{native_send_token, native_staking_grant};
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 use wynd_helpers::wynd_swap::{simulate_wynd_pool_swap, wynd_multihop_swap_grant, wynd_pool_swap_grant};
 use wyndex::{
     asset::{Asset, AssetInfo},
@@ -85,14 +87,21 @@ impl Grantable for QueryMsg {
             grant_data:
                 CompPrefsWithAddresses {
                     comp_frequency,
-                    comp_prefs: JunodcaCompoundPrefs { comp_prefs, tax_fee, .. },
+                    comp_prefs:
+                        JunodcaCompoundPrefs {
+                            comp_prefs,
+                            tax_fee,
+                            referrer,
+                            ..
+                        },
                     project_addresses,
                 },
             ..
         } = grant_structure.clone();
 
         let iteration_count: Uint128 = comp_frequency.iteration_count(current_timestamp, expiration).into();
-        let fee = tax_fee.unwrap_or(Decimal::percent(1));
+        // the grant spec query fills in the fee that the user is going to be charged
+        let fee = tax_fee.unwrap_or_default();
 
         let taxation_grants = vec![GrantRequirement::GrantSpec {
             grant_type: AuthorizationType::SendAuthorization {
@@ -108,7 +117,14 @@ impl Grantable for QueryMsg {
                         })
                         .collect(),
                 ),
-                allow_list: Some(vec![project_addresses.take_rate_addr.clone()]),
+                // the referrer's share of the tax is sent to them directly.
+                // the grant spec only gets this far for referrers that the admin has registered
+                allow_list: Some(
+                    [Some(project_addresses.take_rate_addr.clone()), referrer.map(Addr::unchecked)]
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                ),
             },
             granter,
             grantee: outpost_contract,
@@ -165,195 +181,198 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs =
-        comp_prefs
-            .first()
-            .unwrap()
-            .compound_preferences
-            .relative
-            .iter()
-            .flat_map(|action| -> Vec<GrantRequirement> {
-                let base = GrantBase {
-                    granter: granter.clone(),
-                    grantee: grantee.clone(),
-                    expiration,
-                };
+    let prefs = comp_prefs
+        .first()
+        .ok_or_else(|| StdError::generic_err(ContractError::NoDCACompoundPrefs.to_string()))?;
+    let destinations = prefs.compound_preferences.nested_destinations(&prefs.compound_token.denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
+        let base = GrantBase {
+            granter: granter.clone(),
+            grantee: grantee.clone(),
+            expiration,
+        };
 
-                match action.destination.clone() {
-                    JunoDestinationProject::Unallocated {} => vec![],
-                    JunoDestinationProject::JunoStaking { validator_address } => {
-                        native_staking_grant(base, None, Some(vec![validator_address]))
-                    }
+        match destination.clone() {
+            JunoDestinationProject::Unallocated {} => vec![],
+            JunoDestinationProject::Composite(_) => wynd_multihop_swap_grant(
+                base,
+                project_addresses.destination_projects.wynd.multihop.clone(),
+                AssetInfo::Native(source_denom.clone()),
+                Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
+            ),
+            JunoDestinationProject::JunoStaking { validator_address } => {
+                native_staking_grant(base, None, Some(vec![validator_address]))
+            }
+            JunoDestinationProject::JunoMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
 
-                    JunoDestinationProject::DaoStaking(dao) => {
-                        let DaoAddr {
-                            juno_wyndex_pair, cw20, ..
-                        } = dao.get_daos_addresses(&project_addresses.destination_projects.daos);
+            JunoDestinationProject::DaoStaking(dao) => {
+                let DaoAddr {
+                    juno_wyndex_pair, cw20, ..
+                } = dao.get_daos_addresses(&project_addresses.destination_projects.daos);
 
-                        let (swap_address, required_key) =
+                let (swap_address, required_key) =
                             // use the pair if possible
                             juno_wyndex_pair.map_or((
                                 project_addresses.destination_projects.wynd.multihop.to_string(),
                                 "execute_swap_operations".to_string(),
                             ),|pair_add| (pair_add.to_string(), "swap".to_string()));
 
-                        vec![
-                            // staking permission
-                            GrantRequirement::default_contract_exec_auth(base.clone(), cw20, vec!["send"], None),
-                            // swap permission
-                            GrantRequirement::default_contract_exec_auth(
-                                base,
-                                Addr::unchecked(swap_address),
-                                vec![required_key],
-                                Some("ujuno"),
-                            ),
-                        ]
-                    }
-                    JunoDestinationProject::BalanceDao {} => {
-                        balance_dao_grant(base, project_addresses.destination_projects.balance_dao.clone())
-                    }
-                    JunoDestinationProject::GelottoLottery {
-                        lottery,
-                        lucky_phrase: _lucky_phrase,
-                    } => gelotto_lottery_grant(
+                vec![
+                    // staking permission
+                    GrantRequirement::default_contract_exec_auth(base.clone(), cw20, vec!["send"], None),
+                    // swap permission
+                    GrantRequirement::default_contract_exec_auth(
                         base,
-                        lottery.get_lottery_address(&project_addresses.destination_projects.gelotto),
+                        Addr::unchecked(swap_address),
+                        vec![required_key],
+                        Some(&source_denom),
                     ),
-                    JunoDestinationProject::SendTokens { denom, address } => [
-                        // general multihop swap
-                        match denom.clone() {
-                            AssetInfo::Native(token_denom) if token_denom.eq("ujuno") => vec![],
-                            _ => wynd_multihop_swap_grant(
-                                base.clone(),
-                                project_addresses.destination_projects.wynd.multihop.clone(),
-                                AssetInfo::Native("ujuno".to_string()),
-                                Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                            ),
-                        },
-                        // send to the given user
-                        native_send_token(base, wyndex_asset_info_to_terraswap_asset_info(denom), address),
-                    ]
-                    .concat(),
-                    JunoDestinationProject::MintLsd { lsd_type } => vec![GrantRequirement::default_contract_exec_auth(
-                        base,
-                        lsd_type.get_mint_address(&project_addresses.destination_projects.juno_lsds),
-                        vec![match lsd_type {
-                            JunoLsd::StakeEasySe => "stake",
-                            JunoLsd::StakeEasyB => "stake_for_bjuno",
-                            JunoLsd::Wynd | JunoLsd::Backbone | JunoLsd::Eris => "bond",
-                        }],
-                        Some("ujuno"),
-                    )],
-                    JunoDestinationProject::WhiteWhaleSatellite { asset } => {
-                        let denom = match asset {
-                            AssetInfo::Native(denom) => denom,
-                            AssetInfo::Token(token) => token,
-                        };
+                ]
+            }
+            JunoDestinationProject::BalanceDao {} => {
+                balance_dao_grant(base, project_addresses.destination_projects.balance_dao.clone())
+            }
+            JunoDestinationProject::GelottoLottery {
+                lottery,
+                lucky_phrase: _lucky_phrase,
+            } => gelotto_lottery_grant(
+                base,
+                lottery.get_lottery_address(&project_addresses.destination_projects.gelotto),
+            ),
+            JunoDestinationProject::SendTokens { denom, address } => [
+                // general multihop swap
+                match denom.clone() {
+                    AssetInfo::Native(token_denom) if token_denom.eq(&source_denom) => vec![],
+                    _ => wynd_multihop_swap_grant(
+                        base.clone(),
+                        project_addresses.destination_projects.wynd.multihop.clone(),
+                        AssetInfo::Native(source_denom.clone()),
+                        Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
+                    ),
+                },
+                // send to the given user
+                native_send_token(base, denom, address),
+            ]
+            .concat(),
+            JunoDestinationProject::MintLsd { lsd_type } => vec![GrantRequirement::default_contract_exec_auth(
+                base,
+                lsd_type.get_mint_address(&project_addresses.destination_projects.juno_lsds),
+                vec![match lsd_type {
+                    JunoLsd::StakeEasySe => "stake",
+                    JunoLsd::StakeEasyB => "stake_for_bjuno",
+                    JunoLsd::Wynd | JunoLsd::Backbone | JunoLsd::Eris => "bond",
+                }],
+                Some(&source_denom),
+            )],
+            JunoDestinationProject::WhiteWhaleSatellite { asset } => {
+                let denom = match asset {
+                    AssetInfo::Native(denom) => denom,
+                    AssetInfo::Token(token) => token,
+                };
 
-                        vec![
-                            // general terraswap multihop swap
-                            terraswap_multihop_swap_grant(
-                                base.clone(),
-                                project_addresses
-                                    .destination_projects
-                                    .white_whale
-                                    .terraswap_multihop_router
-                                    .clone(),
-                                "ujuno",
-                            ),
-                            // bonding to the market
-                            vec![GrantRequirement::default_contract_exec_auth(
-                                base,
-                                project_addresses.destination_projects.white_whale.market.clone(),
-                                vec!["bond"],
-                                Some(&denom),
-                            )],
-                        ]
-                        .into_iter()
-                        .flatten()
-                        .collect()
-                    }
-                    JunoDestinationProject::WyndStaking {
-                        bonding_period: _bonding_period,
-                    } => vec![
-                        // pair swap for JUNO to WYND
-                        wynd_pool_swap_grant(
-                            base.clone(),
-                            project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
-                            AssetInfo::Native("ujuno".to_string()),
-                            Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                        ),
-                        // send wynd to the staking contract and stake the tokens
-                        // TODO: lock down the sending and the delegation further
-                        wyndao_staking_grant(base, project_addresses.destination_projects.wynd.cw20.clone()),
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect(),
-                    JunoDestinationProject::RacoonBet { .. } => vec![GrantRequirement::default_contract_exec_auth(
+                vec![
+                    // general terraswap multihop swap
+                    terraswap_multihop_swap_grant(
+                        base.clone(),
+                        project_addresses
+                            .destination_projects
+                            .white_whale
+                            .terraswap_multihop_router
+                            .clone(),
+                        &source_denom,
+                    ),
+                    // bonding to the market
+                    vec![GrantRequirement::default_contract_exec_auth(
                         base,
-                        project_addresses.destination_projects.racoon_bet.game.clone(),
-                        vec!["place_bet"],
-                        Some("ujuno"),
+                        project_addresses.destination_projects.white_whale.market.clone(),
+                        vec!["bond"],
+                        Some(&denom),
                     )],
-                    JunoDestinationProject::TokenSwap {
-                        target_denom: _target_denom,
-                    } => wynd_multihop_swap_grant(
-                        base,
-                        project_addresses.destination_projects.wynd.multihop.clone(),
-                        AssetInfo::Native("ujuno".to_string()),
-                        None,
-                    ),
+                ]
+                .into_iter()
+                .flatten()
+                .collect()
+            }
+            JunoDestinationProject::WyndStaking {
+                bonding_period: _bonding_period,
+            } => vec![
+                // pair swap for JUNO to WYND
+                wynd_pool_swap_grant(
+                    base.clone(),
+                    project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
+                    AssetInfo::Native(source_denom.clone()),
+                    Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
+                ),
+                // send wynd to the staking contract and stake the tokens
+                // TODO: lock down the sending and the delegation further
+                wyndao_staking_grant(base, project_addresses.destination_projects.wynd.cw20.clone()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            JunoDestinationProject::RacoonBet { .. } => vec![GrantRequirement::default_contract_exec_auth(
+                base,
+                project_addresses.destination_projects.racoon_bet.game.clone(),
+                vec!["place_bet"],
+                Some(&source_denom),
+            )],
+            JunoDestinationProject::TokenSwap {
+                target_denom: _target_denom,
+            } => juno_swap_router(&project_addresses.destination_projects, None)
+                .grants(base, &AssetInfo::Native(source_denom.clone()).into()),
 
-                    JunoDestinationProject::WyndLp { .. } => vec![
-                        // // general multihop swap
-                        // GrantRequirement::GrantSpec {
-                        //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
-                        //         contract_addr: project_addresses.destination_projects.wynd.multihop.clone(),
-                        //         limit: ContractExecutionAuthorizationLimit::single_fund_limit("ujuno"),
-                        //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
-                        //             keys: vec!["execute_swap_operations".to_string()],
-                        //         },
-                        //     }]),
-                        //     granter: granter.clone(),
-                        //     grantee: grantee.clone(),
-                        //     expiration,
-                        // },
-                        // // bonding to the pool
-                        // GrantRequirement::GrantSpec {
-                        //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
-                        //         contract_addr: Addr::unchecked(contract_address),
-                        //         limit: ContractExecutionAuthorizationLimit::default(),
-                        //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
-                        //             // might need a bond key as well
-                        //             keys: vec!["send".to_string()],
-                        //         },
-                        //     }]),
-                        //     granter: granter.clone(),
-                        //     grantee: grantee.clone(),
-                        //     expiration,
-                        // },
-                    ],
-                    JunoDestinationProject::SparkIbcCampaign { fund: _fund } => vec![
-                        wynd_multihop_swap_grant(
-                            base.clone(),
-                            project_addresses.destination_projects.wynd.multihop.clone(),
-                            AssetInfo::Native("ujuno".to_string()),
-                            Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                        ),
-                        // funding campaign
-                        vec![GrantRequirement::default_contract_exec_auth(
-                            base,
-                            project_addresses.destination_projects.spark_ibc.fund.clone(),
-                            vec!["fund"],
-                            Some(&project_addresses.usdc.to_string()),
-                        )],
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect(),
-                }
-            });
+            JunoDestinationProject::WyndLp { .. } => vec![
+                // // general multihop swap
+                // GrantRequirement::GrantSpec {
+                //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
+                //         contract_addr: project_addresses.destination_projects.wynd.multihop.clone(),
+                //         limit: ContractExecutionAuthorizationLimit::single_fund_limit("ujuno"),
+                //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
+                //             keys: vec!["execute_swap_operations".to_string()],
+                //         },
+                //     }]),
+                //     granter: granter.clone(),
+                //     grantee: grantee.clone(),
+                //     expiration,
+                // },
+                // // bonding to the pool
+                // GrantRequirement::GrantSpec {
+                //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
+                //         contract_addr: Addr::unchecked(contract_address),
+                //         limit: ContractExecutionAuthorizationLimit::default(),
+                //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
+                //             // might need a bond key as well
+                //             keys: vec!["send".to_string()],
+                //         },
+                //     }]),
+                //     granter: granter.clone(),
+                //     grantee: grantee.clone(),
+                //     expiration,
+                // },
+            ],
+            JunoDestinationProject::SparkIbcCampaign { fund: _fund } => vec![
+                wynd_multihop_swap_grant(
+                    base.clone(),
+                    project_addresses.destination_projects.wynd.multihop.clone(),
+                    AssetInfo::Native(source_denom.clone()),
+                    Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
+                ),
+                // funding campaign
+                vec![GrantRequirement::default_contract_exec_auth(
+                    base,
+                    project_addresses.destination_projects.spark_ibc.fund.clone(),
+                    vec!["fund"],
+                    Some(&project_addresses.usdc.to_string()),
+                )],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        }
+    });
 
     Ok(dedupe_grant_reqs(grant_specs.collect()))
 }
@@ -1,14 +1,26 @@
 use crate::error::ContractError;
 use crate::msg::{CompPrefsWithAddresses, ExecuteMsg, InstantiateMsg, JunostakeCompoundPrefs, MigrateMsg, QueryMsg};
-use crate::state::{ADMIN, AUTHORIZED_ADDRS, PROJECT_ADDRS};
+use crate::state::{
+    ADMIN, AUTHORIZED_ADDRS, BATCH_COMPOUND, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE,
+    PENDING_FALLBACKS, PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS, SWAP_SPLIT,
+};
 use crate::{execute, queries};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdError, StdResult, Timestamp,
+    to_json_binary, Binary, Deps, DepsMut, Env, Event, MessageInfo, Reply, Response, StdError, StdResult, Timestamp, Uint64,
 };
 use cw2::{get_contract_version, set_contract_version};
 use cw_grant_spec::grantable_trait::{GrantStructure, Grantable};
+use outpost_utils::batch::{batch_compound_submsgs, handle_batch_reply, is_batch_reply};
+use outpost_utils::fallback::{handle_destination_reply, is_fallback_reply};
+use outpost_utils::pipeline::{handle_pipeline_reply, PIPELINE_NEXT_STAGE_REPLY_ID, PIPELINE_SWAP_REPLY_ID};
+use outpost_utils::{
+    errors::OutpostError,
+    helpers::{compound_eligibility, compound_was_skipped, is_authorized_compounder},
+    msg_gen::create_advance_schedule_msg,
+    queries::query_stored_comp_prefs,
+};
 use semver::Version;
 
 // version info for migration info
@@ -16,11 +28,35 @@ const CONTRACT_NAME: &str = "crates.io:ac-outpost-junostake";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg {
         // an id of 0 means we don't care about the response
         Reply { id: 0, .. } => Ok(Response::default()),
-        // TODO handle non-zero ids
+        // a compound's swap has landed or the pipeline is ready for its next swap
+        Reply {
+            id: id @ (PIPELINE_SWAP_REPLY_ID | PIPELINE_NEXT_STAGE_REPLY_ID),
+            result,
+        } => Ok(handle_pipeline_reply(
+            deps.storage,
+            &deps.querier,
+            &env,
+            id,
+            result,
+            &PIPELINE,
+            &PENDING_FALLBACKS,
+            &STATS,
+        )?),
+        // one of the compound's destinations has finished. failed ones are rerouted to their fallback
+        Reply { id, result } if is_fallback_reply(id) => Ok(handle_destination_reply(
+            deps.storage,
+            &env,
+            id,
+            result,
+            &PENDING_FALLBACKS,
+            &STATS,
+        )?),
+        // one of the users in a batch compound has finished
+        Reply { id, result } if is_batch_reply(id) => Ok(handle_batch_reply(deps.storage, &BATCH_COMPOUND, id, result)?),
         _ => Err(ContractError::Unauthorized {}),
     }
 }
@@ -32,6 +68,7 @@ pub fn instantiate(deps: DepsMut, _env: Env, info: MessageInfo, msg: Instantiate
     let InstantiateMsg {
         admin,
         project_addresses,
+        max_tax_fee,
     } = msg;
 
     let admin_addr = match admin {
@@ -45,6 +82,7 @@ pub fn instantiate(deps: DepsMut, _env: Env, info: MessageInfo, msg: Instantiate
     ADMIN.save(deps.storage, &admin_addr)?;
     AUTHORIZED_ADDRS.save(deps.storage, &vec![])?;
     PROJECT_ADDRS.save(deps.storage, &project_addresses.validate_addrs(deps.api)?)?;
+    MAX_TAX_FEE.save(deps.storage, &max_tax_fee)?;
 
     Ok(Response::default())
 }
@@ -58,13 +96,19 @@ pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, Co
         set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     }
 
-    if let MigrateMsg {
-        project_addresses: Some(addresses),
-    } = msg
-    {
+    let MigrateMsg {
+        project_addresses,
+        max_tax_fee,
+    } = msg;
+
+    if let Some(addresses) = project_addresses {
         PROJECT_ADDRS.save(deps.storage, &addresses.validate_addrs(deps.api)?)?
     }
 
+    if let Some(max_tax_fee) = max_tax_fee {
+        MAX_TAX_FEE.save(deps.storage, &max_tax_fee)?
+    }
+
     Ok(Response::default())
 }
 
@@ -115,29 +159,262 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> R
 
             Ok(Response::default())
         }
-        ExecuteMsg::Compound(JunostakeCompoundPrefs {
-            user_address,
-            comp_prefs,
-            tax_fee,
-        }) => {
-            let addresses = PROJECT_ADDRS.load(deps.storage)?;
+        ExecuteMsg::UpdateCompPrefsAddress(address) => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            COMP_PREFS_ADDR.save(deps.storage, &deps.api.addr_validate(&address)?)?;
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::UpdateDefaultFallback(fallback) => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            match fallback {
+                Some(fallback) => DEFAULT_FALLBACK.save(deps.storage, &fallback)?,
+                None => DEFAULT_FALLBACK.remove(deps.storage),
+            }
 
-            execute::compound(deps, env, info, addresses, user_address, comp_prefs, tax_fee)
+            Ok(Response::default())
         }
+        ExecuteMsg::UpdateSwapSplit(split) => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            match split {
+                Some(split) => SWAP_SPLIT.save(deps.storage, &split)?,
+                None => SWAP_SPLIT.remove(deps.storage),
+            }
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::UpdatePriceSource { denom, source } => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            match source {
+                Some(source) => PRICE_SOURCES.save(deps.storage, &denom, &source)?,
+                None => PRICE_SOURCES.remove(deps.storage, &denom),
+            }
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::UpdatePriceImpactGuard(guard) => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            match guard {
+                Some(guard) => PRICE_IMPACT_GUARD.save(deps.storage, &guard)?,
+                None => PRICE_IMPACT_GUARD.remove(deps.storage),
+            }
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::UpdateFeeSchedule(schedule) => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            FEES.update_schedule(deps.storage, schedule, MAX_TAX_FEE.load(deps.storage)?)?;
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::UpdateFeeOverride { user, fee } => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            let user = deps.api.addr_validate(&user)?;
+            FEES.update_override(deps.storage, &user, fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::AgreeToFee { max_fee } => {
+            let fee = FEES.agree(deps.storage, &info.sender, max_fee)?;
+
+            Ok(Response::default().add_event(
+                Event::new("agreed_fee")
+                    .add_attribute("user", info.sender)
+                    .add_attribute("fee", fee.to_string()),
+            ))
+        }
+        ExecuteMsg::UpdateReferrer { referrer, share } => {
+            if info.sender != ADMIN.load(deps.storage)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            REFERRALS.update_referrer(deps.storage, &deps.api.addr_validate(&referrer)?, share)?;
+
+            Ok(Response::default())
+        }
+        ExecuteMsg::CompoundStored { user_address, strat_id } => {
+            let comp_prefs_addr = COMP_PREFS_ADDR
+                .may_load(deps.storage)?
+                .ok_or(OutpostError::CompPrefsAddressNotSet)?;
+
+            let stored_prefs: JunostakeCompoundPrefs = query_stored_comp_prefs(
+                &deps.querier,
+                &comp_prefs_addr,
+                &env.contract.address,
+                &user_address,
+                strat_id,
+                &env.block.time,
+            )?;
+
+            // the stored settings have to be for the user that they're stored under
+            if stored_prefs.user_address.ne(&user_address) {
+                return Err(OutpostError::StoredCompPrefsUserMismatch {
+                    user_address: stored_prefs.user_address,
+                }
+                .into());
+            }
+
+            // run the stored prefs like any other compound, an error here reverts the whole tx
+            // so the schedule is only advanced for compounds that actually ran
+            let response = compound_prefs(deps, env, info, stored_prefs, Some(strat_id))?;
+
+            // compounds skipped for being below the user's minimum leave the strategy due as well
+            if compound_was_skipped(&response) {
+                return Ok(response);
+            }
+
+            Ok(response.add_message(create_advance_schedule_msg(&comp_prefs_addr, strat_id, &user_address)?))
+        }
+        ExecuteMsg::BatchCompound(batch) => {
+            // users that the sender isn't allowed to compound for are skipped before anything runs
+            let users = batch
+                .into_iter()
+                .map(|prefs| {
+                    let authorized = deps
+                        .api
+                        .addr_validate(&prefs.user_address)
+                        .map_err(OutpostError::from)
+                        .and_then(|user| {
+                            is_authorized_compounder(deps.as_ref(), &info.sender, &user, ADMIN, AUTHORIZED_ADDRS)
+                        })
+                        .map_err(|err| err.to_string());
+
+                    (prefs.user_address.clone(), authorized.map(|_| ExecuteMsg::Compound(prefs)))
+                })
+                .collect();
+
+            Ok(batch_compound_submsgs(
+                deps.storage,
+                &BATCH_COMPOUND,
+                &env.contract.address,
+                users,
+            )?)
+        }
+        ExecuteMsg::Compound(prefs) => compound_prefs(deps, env, info, prefs, None),
     }
 }
 
+/// Compounds the user's prefs, limited by the frequency of the stored strategy they came from if there is one
+fn compound_prefs(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    prefs: JunostakeCompoundPrefs,
+    strat_id: Option<Uint64>,
+) -> Result<Response, ContractError> {
+    // destinations without a slippage tolerance of their own use the compound's
+    let JunostakeCompoundPrefs {
+        user_address,
+        comp_prefs,
+        tax_fee,
+        referrer,
+        min_compound,
+        ..
+    } = prefs.with_max_slippage()?;
+
+    let addresses = PROJECT_ADDRS.load(deps.storage)?;
+    let referrer = referrer.map(|referrer| deps.api.addr_validate(&referrer)).transpose()?;
+
+    execute::compound(
+        deps,
+        env,
+        info,
+        addresses,
+        user_address,
+        comp_prefs,
+        tax_fee,
+        referrer,
+        min_compound,
+        strat_id,
+    )
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Version {} => to_json_binary(&queries::query_version()),
         QueryMsg::AuthorizedCompounders {} => to_json_binary(&queries::query_authorized_compounders(deps)),
+        QueryMsg::CompoundEligibility { user, strat_id } => to_json_binary(
+            &compound_eligibility(
+                deps,
+                &env.contract.address,
+                &deps.api.addr_validate(&user)?,
+                strat_id,
+                env.block.time,
+                &LAST_COMPOUNDED,
+                &COMP_PREFS_ADDR,
+            )
+            .map_err(|err| StdError::generic_err(err.to_string()))?,
+        ),
+        QueryMsg::SimulateCompound(prefs) => {
+            // destinations without a slippage tolerance of their own use the compound's
+            let JunostakeCompoundPrefs {
+                user_address,
+                comp_prefs,
+                tax_fee,
+                referrer,
+                min_compound,
+                ..
+            } = prefs
+                .with_max_slippage()
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+            let project_addresses = PROJECT_ADDRS.load(deps.storage)?;
+            let referrer = referrer.map(|referrer| deps.api.addr_validate(&referrer)).transpose()?;
+
+            to_json_binary(
+                &execute::simulate_compound(
+                    deps,
+                    project_addresses,
+                    user_address,
+                    comp_prefs,
+                    tax_fee,
+                    referrer,
+                    min_compound,
+                )
+                .map_err(|err| StdError::generic_err(err.to_string()))?,
+            )
+        }
         QueryMsg::GrantSpec { comp_prefs, expiration } => {
             let project_addresses = PROJECT_ADDRS.load(deps.storage)?;
+            let granter = deps.api.addr_validate(&comp_prefs.user_address)?;
+            // the withdraw tax grant caps the fee at the user's current quote which they agree to alongside their grants
+            let tax_fee = FEES
+                .grant_fee(deps.storage, &granter, comp_prefs.tax_fee, MAX_TAX_FEE.load(deps.storage)?)
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            let comp_prefs = JunostakeCompoundPrefs {
+                tax_fee: Some(tax_fee),
+                ..comp_prefs
+            };
+            // shares can end up at the default fallback so it needs grants as well
+            let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+            // the referrer is added to the send grant so only the ones the admin has registered can be granted
+            let referrer = comp_prefs
+                .referrer
+                .as_deref()
+                .map(|referrer| deps.api.addr_validate(referrer))
+                .transpose()?;
+            REFERRALS
+                .check_registered(deps.storage, referrer.as_ref())
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+
             to_json_binary(&QueryMsg::query_grants(
                 GrantStructure {
                     grantee: env.contract.address.clone(),
-                    granter: deps.api.addr_validate(&comp_prefs.user_address)?,
+                    granter,
                     expiration,
                     grant_contract: env.contract.address,
                     grant_data: CompPrefsWithAddresses {
@@ -150,6 +427,8 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         }
         QueryMsg::RevokeSpec { comp_prefs } => {
             let project_addresses = PROJECT_ADDRS.load(deps.storage)?;
+            // shares can end up at the default fallback so it needs grants as well
+            let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
             to_json_binary(&QueryMsg::query_revokes(GrantStructure {
                 grantee: env.contract.address.clone(),
                 granter: deps.api.addr_validate(&comp_prefs.user_address)?,
@@ -161,5 +440,23 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
                 },
             })?)
         }
+        QueryMsg::PriceSource { denom } => to_json_binary(&PRICE_SOURCES.may_load(deps.storage, &denom)?),
+        QueryMsg::FeeSchedule {} => to_json_binary(&FEES.schedule.may_load(deps.storage)?),
+        QueryMsg::UserFee { user } => to_json_binary(
+            &FEES
+                .fee(
+                    deps.storage,
+                    &deps.api.addr_validate(&user)?,
+                    None,
+                    MAX_TAX_FEE.load(deps.storage)?,
+                )
+                .map_err(|err| StdError::generic_err(err.to_string()))?,
+        ),
+        QueryMsg::ReferralEarnings { referrer } => {
+            to_json_binary(&REFERRALS.earnings(deps.storage, &deps.api.addr_validate(&referrer)?)?)
+        }
+        QueryMsg::Referrers {} => to_json_binary(&REFERRALS.referrers(deps.storage)?),
+        QueryMsg::UserStats { user } => to_json_binary(&STATS.user_stats(deps.storage, &deps.api.addr_validate(&user)?)?),
+        QueryMsg::OutpostStats {} => to_json_binary(&STATS.outpost_stats(deps.storage)?),
     }
 }
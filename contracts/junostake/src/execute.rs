@@ -1,35 +1,59 @@
-use cosmwasm_std::{Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128,
+    Uint64,
+};
 use outpost_utils::{
-    comp_prefs::DestinationAction,
-    helpers::{calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, sum_coins, DestProjectMsgs},
-
+    asset::OutpostAsset,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
+    helpers::{
+        below_min_compound, enforce_compound_frequency, is_authorized_compounder, min_out_after_slippage, prefs_sum_to_one,
+        sum_coins, CompoundPlan, DestProjectMsgs, SimulateCompoundResponse,
+    },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    queries::query_pending_rewards,
+    referrals::Referral,
+    stats::CompoundRun,
 };
-use std::iter;
 use terraswap_helpers::terraswap_swap::create_terraswap_swap_msg_with_simulation;
 
 use withdraw_rewards_tax_grant::{client::WithdrawRewardsTaxClient, msg::SimulateExecuteResponse};
 
 use crate::{
     msg::ContractAddrs,
-    state::{ADMIN, AUTHORIZED_ADDRS},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS,
+        PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, REFERRALS, STATS, SWAP_SPLIT,
+    },
     ContractError,
 };
-use wynd_helpers::wynd_swap::{create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg};
+use wynd_helpers::wynd_swap::{
+    create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg, WyndexVenue,
+};
 use wyndex::asset::{Asset, AssetInfo};
 use juno_destinations::comp_prefs::{JunoCompPrefs, JunoDestinationProject, StakingDao};
-use juno_destinations::dest_project_gen::{balance_dao_msgs, gelotto_lottery_msgs, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs, wynd_staking_msgs};
+use juno_destinations::dest_project_gen::{
+    balance_dao_msgs, gelotto_lottery_msgs, juno_swap_router, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs,
+    wynd_staking_msgs, JunoSwapVenue,
+};
 use sail_destinations::dest_project_gen::{spark_ibc_msgs, white_whale_satellite_msgs};
-use universal_destinations::dest_project_gen::{daodao_cw20_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{
+    active_multi_staking_msgs, active_native_staking_msg, daodao_cw20_staking_msg, token_swap_msgs,
+};
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     project_addresses: ContractAddrs,
     delegator_address: String,
     comp_prefs: JunoCompPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(&comp_prefs)?;
@@ -37,60 +61,104 @@ pub fn compound(
     // check that the delegator address is valid
     let delegator: Addr = deps.api.addr_validate(&delegator_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &delegator,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
-    // get the denom of the staking token. this should be "ujuno"
-    let staking_denom = project_addresses.staking_denom.clone();
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
 
-    // prepare the withdraw rewards message and simulation from the authzpp grant
     let (
-        SimulateExecuteResponse {
-            // the rewards that the delegator is due to recieve
-            delegator_rewards,
+        CompoundPlan {
+            tax,
+            compounded,
+            claim_msgs,
+            dest_msgs,
+            fallbacks,
+            skipped,
             ..
         },
-        // withdraw delegator rewards wasm message
         withdraw_msg,
-    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, &delegator)
-        .simulate_with_contract_execute(deps.querier, tax_fee)?;
-
-    let total_rewards = sum_coins(&staking_denom, &delegator_rewards);
-
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
+        referral_amount,
+    ) = plan_compound(
+        deps.as_ref(),
         &project_addresses,
-        // &env.block,
-        // staking_denom,
         &delegator,
-        total_rewards.clone(),
         comp_prefs,
-        deps.as_ref(),
+        tax_fee,
+        referral.as_ref(),
+        &min_compound,
     )?;
 
-    let combined_msgs = all_msgs.iter().fold(DestProjectMsgs::default(), |mut acc, msg| {
-        acc.msgs.append(&mut msg.msgs.clone());
-        acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-        acc.events.append(&mut msg.events.clone());
-        acc
-    });
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = skipped {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &delegator, compounded.amount)?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(deps.storage, &delegator, &CompoundRun::new(env.block.time, tax, &fallbacks))?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
+
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &delegator,
+        &PipelineAsset::Native(project_addresses.staking_denom.clone()),
+        dest_msgs,
+    )?;
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
-        Event::new("amount_automated").add_attributes([total_rewards].iter().enumerate().map(|(i, coin)| Attribute {
+        Event::new("amount_automated").add_attributes([compounded].iter().enumerate().map(|(i, coin)| Attribute {
             key: format!("amount_{}", i),
             value: coin.to_string(),
         }));
 
-    // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    // the referrer is paid out of the withdrawn rewards before anything is compounded
+    let claim_msgs = match claim_msgs.is_empty() {
+        true => vec![withdraw_msg],
+        false => vec![withdraw_msg, create_exec_msg(&env.contract.address, claim_msgs)?],
+    };
+
+    // the final exec message that will be broadcast and contains all the sub msgs.
+    // the withdraw runs first so that the pipeline's balance snapshot includes the rewards
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        claim_msgs,
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
-        .add_message(withdraw_msg)
         .add_attribute("subaction", "withdraw rewards")
         .add_event(amount_automated_event)
         // .add_attribute("amount_automated", to_json_binary(&[total_rewards])?.to_string())
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -118,6 +186,148 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    project_addresses: ContractAddrs,
+    user_address: String,
+    comp_prefs: JunoCompPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<JunoDestinationProject>, ContractError> {
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(&comp_prefs)?;
+
+    let delegator: Addr = deps.api.addr_validate(&user_address)?;
+
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
+
+    let (plan, ..) = plan_compound(
+        deps,
+        &project_addresses,
+        &delegator,
+        comp_prefs,
+        tax_fee,
+        referral.as_ref(),
+        &min_compound,
+    )?;
+
+    Ok(plan.simulate()?)
+}
+
+/// Works out everything a compound would do for the delegator without saving anything
+/// so that compounds and simulations go through the same steps.
+/// Also returns the msg that withdraws the delegator's rewards through the authzpp grant
+/// and the referrer's cut of the tax
+fn plan_compound(
+    deps: Deps,
+    project_addresses: &ContractAddrs,
+    delegator: &Addr,
+    comp_prefs: JunoCompPrefs,
+    tax_fee: Option<Decimal>,
+    referral: Option<&Referral>,
+    min_compound: &Option<MinCompound>,
+) -> Result<(CompoundPlan<JunoDestinationProject>, CosmosMsg, Coin), ContractError> {
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, delegator, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    // get the denom of the staking token. this should be "ujuno"
+    let staking_denom = project_addresses.staking_denom.clone();
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    comp_prefs.check_native_only(&staking_denom)?;
+
+    let total_rewards = query_pending_rewards(&deps.querier, delegator, staking_denom.clone())?.total;
+
+    // the authzpp grant only pays the take rate so the referrer's cut is taken off of its fee
+    // and sent separately. the user pays the same fee either way
+    let (tax_fee, referral_amount) = match referral {
+        Some(referral) => referral.split_fee(tax_fee, total_rewards.amount),
+        None => (tax_fee, Uint128::zero()),
+    };
+    let referral_amount = coin(referral_amount.u128(), &staking_denom);
+
+    // prepare the withdraw rewards message and simulation from the authzpp grant
+    let (
+        SimulateExecuteResponse {
+            // the rewards that the delegator is due to recieve
+            delegator_rewards,
+            ..
+        },
+        // withdraw delegator rewards wasm message
+        withdraw_msg,
+    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, delegator)
+        .simulate_with_contract_execute(deps.querier, Some(tax_fee))?;
+
+    // the authzpp grant takes its tax before the rewards reach the delegator
+    // so the tax is whatever is missing from their pending rewards once the referrer is paid
+    let compounded = coin(
+        sum_coins(&staking_denom, &delegator_rewards)
+            .amount
+            .saturating_sub(referral_amount.amount)
+            .u128(),
+        &staking_denom,
+    );
+    let tax = coin(total_rewards.amount.saturating_sub(compounded.amount).u128(), &staking_denom);
+
+    let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = below_min_compound(delegator, &compounded, min_compound, |rewards| {
+        match PRICE_SOURCES.may_load(deps.storage, rewards.denom.as_str())? {
+            // an admin configured price source takes precedence over simulating the swap to usdc
+            Some(price_source) => {
+                Ok(price_source.value(&deps.querier, &rewards.into(), &project_addresses.usdc.clone().into())?)
+            }
+            None => Ok::<_, ContractError>(
+                create_wyndex_swap_msg_with_simulation(
+                    &deps.querier,
+                    delegator,
+                    rewards.amount,
+                    AssetInfo::Native(rewards.denom.clone()),
+                    project_addresses.usdc.clone(),
+                    project_addresses.destination_projects.wynd.multihop.to_string(),
+                    None,
+                    None,
+                )?
+                .1,
+            ),
+        }
+    })? {
+        return Ok((
+            CompoundPlan::skipped(total_rewards, comp_prefs, skipped),
+            withdraw_msg.into(),
+            referral_amount,
+        ));
+    }
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (dest_msgs, fallbacks) = isolate_destinations(delegator, &compounded, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(project_addresses, delegator, amount, comp_prefs, deps)
+    })?;
+
+    Ok((
+        CompoundPlan {
+            total_rewards,
+            tax: vec![tax],
+            compounded,
+            comp_prefs,
+            claim_msgs: referral
+                .and_then(|referral| referral.payout_msg(delegator, &referral_amount))
+                .into_iter()
+                .collect(),
+            dest_msgs,
+            fallbacks,
+            skipped: None,
+        },
+        withdraw_msg.into(),
+        referral_amount,
+    ))
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -130,26 +340,37 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, JunoDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 let compounding_asset = Asset {
-                    info: AssetInfo::Native("ujuno".to_string()),
+                    info: AssetInfo::Native(total_rewards.denom.clone()),
                     amount: comp_token_amount,
                 };
 
                 match destination {
-                    JunoDestinationProject::JunoStaking { validator_address } => Ok(native_staking_msg(
+                    JunoDestinationProject::JunoStaking { validator_address } => Ok(active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         delegator_addr,
                         &cosmwasm_std::Coin {
-                            denom: "ujuno".to_string(),
+                            denom: total_rewards.denom.clone(),
+                            amount: comp_token_amount,
+                        },
+                    )?),
+                    JunoDestinationProject::JunoMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => Ok(active_multi_staking_msgs(
+                        &deps.querier,
+                        &validators,
+                        redelegate_inactive,
+                        delegator_addr,
+                        &cosmwasm_std::Coin {
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                     )?),
@@ -167,6 +388,7 @@ pub fn prefs_to_msgs(
                         
                         let dao_addresses = dao.get_daos_addresses(&project_addrs.destination_projects.daos);
 
+                        let mut impact_events = vec![];
                         let (swap_msgs, expected_dao_token_amount) = if let Some(pair_addr) = dao_addresses.juno_wyndex_pair
                         {
                             // if there's a direct juno & staking denom pair, then we can swap directly
@@ -174,10 +396,23 @@ pub fn prefs_to_msgs(
                                 &deps.querier,
                                 delegator_addr,
                                 pair_addr.as_ref(),
-                                compounding_asset,
+                                compounding_asset.clone(),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
+                                max_slippage,
                             )?;
 
+                            // dao pairs can be thin so the swap has to stay close to the pair's spot price
+                            if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                                impact_events.push(guard.check_against_spot(
+                                    &deps.querier,
+                                    &JunoSwapVenue::Wyndex(WyndexVenue::Pair(Addr::unchecked(&pair_addr))),
+                                    &pair_addr,
+                                    &compounding_asset.into(),
+                                    &AssetInfo::Token(dao_addresses.cw20.to_string()).into(),
+                                    swap_sim.return_amount,
+                                )?);
+                            }
+
                             (vec![swap_msg], swap_sim.return_amount)
                         } else {
                             // otherwise we need to use the wyndex router to swap
@@ -185,10 +420,11 @@ pub fn prefs_to_msgs(
                                 &deps.querier,
                                 delegator_addr,
                                 comp_token_amount,
-                                AssetInfo::Native("ujuno".to_string()),
+                                AssetInfo::Native(total_rewards.denom.clone()),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
                                 project_addrs.destination_projects.wynd.multihop.to_string(),
-                                None
+                                None,
+                                max_slippage,
                             )?
                         };
 
@@ -200,7 +436,8 @@ pub fn prefs_to_msgs(
                             expected_dao_token_amount,
                         )?;
 
-                        stake_msgs.prepend_msgs(swap_msgs);
+                        stake_msgs.run_after_swap(swap_msgs);
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -213,33 +450,35 @@ pub fn prefs_to_msgs(
                         let wynd_swap_msg = wynd_pair_swap_msg(
                             delegator_addr,
                             Asset {
-                                info: AssetInfo::Native("ujuno".to_string()),
+                                info: AssetInfo::Native(total_rewards.denom.clone()),
                                 amount: comp_token_amount,
                             },
                             AssetInfo::Token(cw20.to_string()),
                             &juno_wynd_pair,
+                            max_slippage,
                         )?;
 
                         let mut staking_msg =
                             wynd_staking_msgs(&cw20, &delegator_addr.to_string(), comp_token_amount, bonding_period)?;
 
-                        staking_msg.prepend_msgs(vec![wynd_swap_msg]);
+                        staking_msg.run_after_swap(vec![wynd_swap_msg]);
 
                         Ok(staking_msg)
                     }
 
-                    JunoDestinationProject::TokenSwap { target_denom } => Ok(DestProjectMsgs {
-                        msgs: wynd_helpers::wynd_swap::create_wyndex_swap_msg(
+                    JunoDestinationProject::TokenSwap { target_denom } => {
+                        // the swap goes through whichever juno dex gives the best return
+                        let (swap_msgs, _) = token_swap_msgs(
+                            &juno_swap_router(&project_addrs.destination_projects, SWAP_SPLIT.may_load(deps.storage)?),
+                            &deps.querier,
                             delegator_addr,
-                            comp_token_amount,
-                            AssetInfo::Native("ujuno".to_string()),
-                            target_denom,
-                            project_addrs.destination_projects.wynd.multihop.to_string(),
-                        )
-                        .map_err(ContractError::Std)?,
-                        sub_msgs: vec![],
-                        events: vec![],
-                    }),
+                            &OutpostAsset::new(AssetInfo::Native(total_rewards.denom.clone()), comp_token_amount),
+                            &target_denom.into(),
+                            max_slippage,
+                        )?;
+
+                        Ok(swap_msgs)
+                    },
                     JunoDestinationProject::WyndLp {
                         ..
                         // contract_address,
@@ -261,7 +500,7 @@ pub fn prefs_to_msgs(
                         delegator_addr,
                         Some(&project_addrs.destination_projects.racoon_bet.juno_usdc_wynd_pair),
                         cosmwasm_std::Coin {
-                            denom: "ujuno".to_string(),
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                         game,
@@ -280,6 +519,7 @@ pub fn prefs_to_msgs(
                                 .white_whale
                                 .terraswap_multihop_router
                                 .to_string(),
+                            max_slippage,
                         )?;
 
                         let mut bond_msgs = white_whale_satellite_msgs(
@@ -288,7 +528,7 @@ pub fn prefs_to_msgs(
                             &project_addrs.destination_projects.white_whale.market.clone(),
                         )?;
 
-                        bond_msgs.prepend_msgs(swap_msgs);
+                        bond_msgs.run_after_swap(swap_msgs);
 
                         Ok(bond_msgs)
                     }
@@ -315,6 +555,7 @@ pub fn prefs_to_msgs(
                             project_addrs.usdc.clone(),
                             project_addrs.destination_projects.wynd.multihop.to_string(),
                             None,
+                            max_slippage,
                         )?;
 
                         let mut spark_msgs = spark_ibc_msgs(
@@ -327,7 +568,7 @@ pub fn prefs_to_msgs(
                             fund,
                         )?;
 
-                        spark_msgs.prepend_msgs(swaps);
+                        spark_msgs.run_after_swap(swaps);
 
                         Ok(spark_msgs)
                     }
@@ -339,10 +580,11 @@ pub fn prefs_to_msgs(
                             &deps.querier,
                             delegator_addr,
                             comp_token_amount,
-                            AssetInfo::Native("ujuno".to_string()),
+                            AssetInfo::Native(total_rewards.denom.clone()),
                             target_asset.clone(),
                             project_addrs.destination_projects.wynd.multihop.to_string(),
-                            None
+                            None,
+                            max_slippage,
                         )
                         .map_err(ContractError::Std)?;
 
@@ -356,10 +598,40 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        send_msgs.append_msgs(swap_msgs);
+                        send_msgs.run_after_swap(swap_msgs);
 
                         Ok(send_msgs)
                     }
+                    JunoDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let (swap_msgs, sim) = create_wyndex_swap_msg_with_simulation(
+                            &deps.querier,
+                            delegator_addr,
+                            comp_token_amount,
+                            AssetInfo::Native(total_rewards.denom.clone()),
+                            AssetInfo::Native(convert_to.clone()),
+                            project_addrs.destination_projects.wynd.multihop.to_string(),
+                            None,
+                            max_slippage,
+                        )
+                        .map_err(ContractError::Std)?;
+
+                        // the nested destinations only get what the swap is sure to return
+                        let intermediate_amount = if swap_msgs.is_empty() {
+                            sim
+                        } else {
+                            min_out_after_slippage(sim, max_slippage.unwrap_or(Decimal::percent(2)))
+                        };
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            delegator_addr,
+                            coin(intermediate_amount.u128(), convert_to),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
                     JunoDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
             
@@ -4,10 +4,12 @@ use cw_grant_spec::grants::{
     ContractExecutionAuthorizationLimit, GrantBase, GrantRequirement, RevokeRequirement,
 };
 
-use juno_destinations::comp_prefs::{wyndex_asset_info_to_terraswap_asset_info, DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::comp_prefs::{DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::dest_project_gen::juno_swap_router;
 use juno_destinations::grants::{balance_dao_grant, gelotto_lottery_grant, wyndao_staking_grant};
+use outpost_utils::swap_venue::SwapVenue;
 use terraswap_helpers::terraswap_swap::terraswap_multihop_swap_grant;
-use universal_destinations::grants::{native_send_token, native_staking_grant};
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 use withdraw_rewards_tax_grant::msg::GrantSpecData;
 use wynd_helpers::wynd_swap::{simulate_wynd_pool_swap, wynd_multihop_swap_grant, wynd_pool_swap_grant};
 use wyndex::{
@@ -104,7 +106,24 @@ impl Grantable for QueryMsg {
             current_timestamp,
         )?;
 
-        Ok([withdraw_tax_grants, gen_comp_pref_grants(grant_structure)?].concat())
+        // the referrer's cut of the tax is sent to them straight from the user's wallet.
+        // the grant spec only gets this far for referrers that the admin has registered
+        let referral_grants = comp_prefs
+            .referrer
+            .map(|referrer| {
+                native_send_token(
+                    GrantBase {
+                        granter: grant_structure.granter.clone(),
+                        grantee: grant_structure.grantee.clone(),
+                        expiration,
+                    },
+                    AssetInfo::Native(project_addresses.staking_denom.clone()),
+                    referrer,
+                )
+            })
+            .unwrap_or_default();
+
+        Ok([withdraw_tax_grants, referral_grants, gen_comp_pref_grants(grant_structure)?].concat())
     }
 
     fn query_revokes(
@@ -155,18 +174,29 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs = comp_prefs.relative.iter().flat_map(|action| -> Vec<GrantRequirement> {
+    let destinations = comp_prefs.nested_destinations(&project_addresses.staking_denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
         let base = GrantBase {
             granter: granter.clone(),
             grantee: grantee.clone(),
             expiration,
         };
 
-        match action.destination.clone() {
+        match destination.clone() {
             JunoDestinationProject::Unallocated {} => vec![],
+            JunoDestinationProject::Composite(_) => wynd_multihop_swap_grant(
+                base,
+                project_addresses.destination_projects.wynd.multihop.clone(),
+                AssetInfo::Native(source_denom.clone()),
+                Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
+            ),
             JunoDestinationProject::JunoStaking { validator_address } => {
                 native_staking_grant(base, None, Some(vec![validator_address]))
             }
+            JunoDestinationProject::JunoMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
 
             JunoDestinationProject::DaoStaking(dao) => {
                 let DaoAddr {
@@ -188,7 +218,7 @@ pub fn gen_comp_pref_grants(
                         base,
                         Addr::unchecked(swap_address),
                         vec![required_key],
-                        Some("ujuno"),
+                        Some(&source_denom),
                     ),
                 ]
             }
@@ -205,16 +235,16 @@ pub fn gen_comp_pref_grants(
             JunoDestinationProject::SendTokens { denom, address } => [
                 // general multihop swap
                 match denom.clone() {
-                    AssetInfo::Native(token_denom) if token_denom.eq("ujuno") => vec![],
+                    AssetInfo::Native(token_denom) if token_denom.eq(&source_denom) => vec![],
                     _ => wynd_multihop_swap_grant(
                         base.clone(),
                         project_addresses.destination_projects.wynd.multihop.clone(),
-                        AssetInfo::Native("ujuno".to_string()),
-                        Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+                        AssetInfo::Native(source_denom.clone()),
+                        Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
                     ),
                 },
                 // send to the given user
-                native_send_token(base, wyndex_asset_info_to_terraswap_asset_info(denom), address),
+                native_send_token(base, denom, address),
             ]
             .concat(),
             JunoDestinationProject::MintLsd { lsd_type } => vec![GrantRequirement::default_contract_exec_auth(
@@ -225,7 +255,7 @@ pub fn gen_comp_pref_grants(
                     JunoLsd::StakeEasyB => "stake_for_bjuno",
                     JunoLsd::Wynd | JunoLsd::Backbone | JunoLsd::Eris => "bond",
                 }],
-                Some("ujuno"),
+                Some(&source_denom),
             )],
             JunoDestinationProject::WhiteWhaleSatellite { asset } => {
                 let denom = match asset {
@@ -242,7 +272,7 @@ pub fn gen_comp_pref_grants(
                             .white_whale
                             .terraswap_multihop_router
                             .clone(),
-                        "ujuno",
+                        &source_denom,
                     ),
                     // bonding to the market
                     vec![GrantRequirement::default_contract_exec_auth(
@@ -263,8 +293,8 @@ pub fn gen_comp_pref_grants(
                 wynd_pool_swap_grant(
                     base.clone(),
                     project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
-                    AssetInfo::Native("ujuno".to_string()),
-                    Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+                    AssetInfo::Native(source_denom.clone()),
+                    Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
                 ),
                 // send wynd to the staking contract and stake the tokens
                 // TODO: lock down the sending and the delegation further
@@ -277,16 +307,12 @@ pub fn gen_comp_pref_grants(
                 base,
                 project_addresses.destination_projects.racoon_bet.game.clone(),
                 vec!["place_bet"],
-                Some("ujuno"),
+                Some(&source_denom),
             )],
             JunoDestinationProject::TokenSwap {
                 target_denom: _target_denom,
-            } => wynd_multihop_swap_grant(
-                base,
-                project_addresses.destination_projects.wynd.multihop.clone(),
-                AssetInfo::Native("ujuno".to_string()),
-                None,
-            ),
+            } => juno_swap_router(&project_addresses.destination_projects, None)
+                .grants(base, &AssetInfo::Native(source_denom.clone()).into()),
 
             JunoDestinationProject::WyndLp { .. } => vec![
                 // // general multihop swap
@@ -321,8 +347,8 @@ pub fn gen_comp_pref_grants(
                 wynd_multihop_swap_grant(
                     base.clone(),
                     project_addresses.destination_projects.wynd.multihop.clone(),
-                    AssetInfo::Native("ujuno".to_string()),
-                    Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+                    AssetInfo::Native(source_denom.clone()),
+                    Some(ContractExecutionAuthorizationLimit::single_fund_limit(&source_denom)),
                 ),
                 // funding campaign
                 vec![GrantRequirement::default_contract_exec_auth(
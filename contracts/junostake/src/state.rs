@@ -1,8 +1,27 @@
-use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Timestamp};
+use cw_storage_plus::{Item, Map};
+use juno_destinations::comp_prefs::JunoDestinationProject;
+use juno_destinations::dest_project_gen::JunoPriceSource;
+use outpost_utils::{
+    batch::BatchProgress, fallback::PendingFallback, fee_schedule::FeeStore, pipeline::Pipeline,
+    price_source::PriceImpactGuard, referrals::ReferralStore, stats::StatsStore, swap_venue::SplitOrder,
+};
 
 use crate::msg::ContractAddrs;
 
 pub const ADMIN: Item<Addr> = Item::new("admin");
 pub const AUTHORIZED_ADDRS: Item<Vec<Addr>> = Item::new("allowed_addrs");
 pub const PROJECT_ADDRS: Item<ContractAddrs> = Item::new("project_addrs");
+pub const COMP_PREFS_ADDR: Item<Addr> = Item::new("comp_prefs_addr");
+pub const LAST_COMPOUNDED: Map<&Addr, Timestamp> = Map::new("last_compounded");
+pub const PIPELINE: Item<Pipeline> = Item::new("pipeline");
+pub const DEFAULT_FALLBACK: Item<JunoDestinationProject> = Item::new("default_fallback");
+pub const PENDING_FALLBACKS: Map<u64, PendingFallback> = Map::new("pending_fallbacks");
+pub const BATCH_COMPOUND: Item<BatchProgress> = Item::new("batch_compound");
+pub const SWAP_SPLIT: Item<SplitOrder> = Item::new("swap_split");
+pub const PRICE_SOURCES: Map<&str, JunoPriceSource> = Map::new("price_sources");
+pub const PRICE_IMPACT_GUARD: Item<PriceImpactGuard> = Item::new("price_impact_guard");
+pub const FEES: FeeStore = FeeStore::new("fee_schedule", "fee_overrides", "agreed_fees", "fee_volume");
+pub const STATS: StatsStore = StatsStore::new("user_stats", "outpost_stats");
+pub const REFERRALS: ReferralStore = ReferralStore::new("referrers", "referral_earnings");
+pub const MAX_TAX_FEE: Item<Decimal> = Item::new("max_tax_fee");
@@ -1,34 +1,50 @@
-use cosmwasm_std::{Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg};
+use cosmwasm_std::{Addr, Attribute, Coin, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint64};
 use outpost_utils::{
-    comp_prefs::DestinationAction,
-    helpers::{calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, DestProjectMsgs, TaxSplitResult},
+    asset::OutpostAsset,
+    comp_prefs::{DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
+    helpers::{
+        below_min_compound, enforce_compound_frequency, is_authorized_compounder, prefs_sum_to_one,
+        simulate_compound_response, DestProjectMsgs, SimulateCompoundResponse, TaxSplitResult,
+    },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    stats::CompoundRun,
 };
-use std::iter;
 
 
 
 
 use crate::{
+    helpers::query_and_generate_ww_market_reward_msgs,
     msg::ContractAddrs,
-    state::{ADMIN, AUTHORIZED_ADDRS},
-    ContractError, helpers::{query_and_generate_ww_market_reward_msgs, asset_to_coin, terraswap_assetinfo_to_wyndex_assetinfo},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS,
+        PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, REFERRALS, STATS,
+    },
+    ContractError,
+};
+use wynd_helpers::wynd_swap::{
+    create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg, WyndexVenue,
 };
-use wynd_helpers::wynd_swap::{create_wyndex_swap_msg_with_simulation, simulate_and_swap_wynd_pair, wynd_pair_swap_msg};
 use wyndex::asset::{Asset, AssetInfo};
 use juno_destinations::comp_prefs::{JunoCompPrefs, JunoDestinationProject, StakingDao};
-use juno_destinations::dest_project_gen::{balance_dao_msgs, gelotto_lottery_msgs, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs, wynd_staking_msgs};
+use juno_destinations::dest_project_gen::{balance_dao_msgs, gelotto_lottery_msgs, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs, wynd_staking_msgs, JunoSwapVenue};
 use sail_destinations::dest_project_gen::{spark_ibc_msgs, white_whale_satellite_msgs};
-use universal_destinations::dest_project_gen::{daodao_cw20_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{active_multi_staking_msgs, active_native_staking_msg, daodao_cw20_staking_msg};
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     project_addresses: ContractAddrs,
     delegator_address: String,
     comp_prefs: JunoCompPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(&comp_prefs)?;
@@ -36,40 +52,104 @@ pub fn compound(
     // check that the delegator address is valid
     let delegator: Addr = deps.api.addr_validate(&delegator_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &delegator,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &delegator, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
 
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
    let TaxSplitResult {
         remaining_rewards,
         tax_amount,
+        referral_amount,
         claim_and_tax_msgs,
     } = query_and_generate_ww_market_reward_msgs(
-        tax_fee.unwrap_or(Decimal::percent(5)), 
+        tax_fee, 
         &delegator, &project_addresses.take_rate_addr.clone(), 
         &project_addresses.destination_projects.white_whale.rewards.clone(), 
         &project_addresses.destination_projects.white_whale.market.clone(),
         &project_addresses.terraswap_routes.whale_asset.to_string(),
+        referral.as_ref(),
         &deps.querier)?;
 
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
-        &project_addresses,
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = below_min_compound(&delegator, &remaining_rewards, &min_compound, |rewards| {
+        match PRICE_SOURCES.may_load(deps.storage, rewards.denom.as_str())? {
+            // an admin configured price source takes precedence over simulating the swap to usdc
+            Some(price_source) => {
+                Ok(price_source.value(&deps.querier, &rewards.into(), &project_addresses.usdc.clone().into())?)
+            }
+            None => Ok::<_, ContractError>(
+                project_addresses
+                    .terraswap_routes
+                    .gen_whale_swap_with_sim(
+                        &delegator,
+                        rewards.amount,
+                        project_addresses.usdc.to_string().as_str(),
+                        &project_addresses.destination_projects.white_whale.terraswap_multihop_router,
+                        &deps.querier,
+                        None,
+                    )?
+                    .1
+                    .amount,
+            ),
+        }
+    })? {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &delegator, remaining_rewards.amount)?;
+
+    let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (all_msgs, fallbacks) = isolate_destinations(&delegator, &remaining_rewards, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(&project_addresses, &delegator, amount, comp_prefs, deps.as_ref())
+    })?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(
+        deps.storage,
         &delegator,
-        remaining_rewards.clone(),
-        comp_prefs,
-        deps.as_ref(),
+        &CompoundRun::new(env.block.time, vec![tax_amount.clone()], &fallbacks),
     )?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
 
-    let mut combined_msgs = all_msgs.iter().fold(DestProjectMsgs::default(), |mut acc, msg| {
-        acc.msgs.append(&mut msg.msgs.clone());
-        acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-        acc.events.append(&mut msg.events.clone());
-        acc
-    });
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &delegator,
+        &PipelineAsset::Native(remaining_rewards.denom.clone()),
+        all_msgs,
+    )?;
 
     // add the claim and tax msgs to the list of msgs to be broadcast. do them first so all the funds are in place for compounding
     combined_msgs.prepend_msgs(claim_and_tax_msgs);
     combined_msgs.prepend_events(vec![Event::new("tax").add_attribute("amount", tax_amount.to_string())]);
+    combined_msgs.append_events(referral_events);
 
     // amount_automated is standardized and emitted across all outposts for record keeping purposes
     let amount_automated_event =
@@ -79,14 +159,22 @@ pub fn compound(
         }));
 
     // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        vec![],
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         
         .add_event(amount_automated_event)
         // .add_attribute("amount_automated", to_json_binary(&[total_rewards])?.to_string())
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -114,6 +202,61 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    project_addresses: ContractAddrs,
+    user_address: String,
+    comp_prefs: JunoCompPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+) -> Result<SimulateCompoundResponse<JunoDestinationProject>, ContractError> {
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(&comp_prefs)?;
+
+    let delegator: Addr = deps.api.addr_validate(&user_address)?;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &delegator, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    let TaxSplitResult {
+        remaining_rewards,
+        tax_amount,
+        claim_and_tax_msgs,
+        ..
+    } = query_and_generate_ww_market_reward_msgs(
+        tax_fee,
+        &delegator,
+        &project_addresses.take_rate_addr.clone(),
+        &project_addresses.destination_projects.white_whale.rewards.clone(),
+        &project_addresses.destination_projects.white_whale.market.clone(),
+        &project_addresses.terraswap_routes.whale_asset.to_string(),
+        REFERRALS.referral(deps.storage, referrer.as_ref())?.as_ref(),
+        &deps.querier,
+    )?;
+
+    let dest_msgs = prefs_to_msgs(
+        &project_addresses,
+        &delegator,
+        remaining_rewards.clone(),
+        comp_prefs.clone(),
+        deps,
+    )?;
+
+    Ok(simulate_compound_response(
+        Coin {
+            denom: remaining_rewards.denom.clone(),
+            amount: remaining_rewards.amount + tax_amount.amount,
+        },
+        vec![tax_amount],
+        remaining_rewards,
+        comp_prefs,
+        claim_and_tax_msgs,
+        dest_msgs,
+    )?)
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -126,17 +269,14 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, JunoDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     let terraswap_multihop_addr = project_addrs.destination_projects.white_whale.terraswap_multihop_router.clone();
    
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 
 
                 match destination {
@@ -146,20 +286,45 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 "ujuno", 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
                         
-                        let mut staking_msgs = native_staking_msg(
+                        let mut staking_msgs = active_native_staking_msg(
+                            &deps.querier,
                             &validator_address,
                             delegator_addr,
-                            &asset_to_coin(simulated_juno)?,
+                            &OutpostAsset::from(simulated_juno).to_coin()?,
                         )?;
 
                     
 
-                    staking_msgs.append_msgs(vec![swap_msg]);
+                    staking_msgs.run_after_swap(vec![swap_msg]);
                     
                     Ok(staking_msgs)
                 },
+                    JunoDestinationProject::JunoMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => {
+                        let (swap_msg, simulated_juno) = project_addrs.terraswap_routes.gen_whale_swap_with_sim(
+                            delegator_addr,
+                            comp_token_amount,
+                            "ujuno",
+                            &terraswap_multihop_addr,
+                            &deps.querier,
+                            max_slippage,
+                        )?;
+
+                        let mut staking_msgs = active_multi_staking_msgs(
+                            &deps.querier,
+                            &validators,
+                            redelegate_inactive,
+                            delegator_addr,
+                            &OutpostAsset::from(simulated_juno).to_coin()?,
+                        )?;
+                        staking_msgs.run_after_swap(vec![swap_msg]);
+
+                        Ok(staking_msgs)
+                    }
 
                     JunoDestinationProject::DaoStaking(dao) => {
                         if let StakingDao::Kleomedes = dao {
@@ -180,22 +345,35 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 "ujuno", 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
 
+                        let mut impact_events = vec![];
                         let (wyndex_swap_msgs, expected_dao_token_amount) = if let Some(pair_addr) = dao_addresses.juno_wyndex_pair
                         {
+                            let juno = OutpostAsset::from(simulated_juno);
+
                             // if there's a direct juno & staking denom pair, then we can swap directly
                             let (swap_msg, swap_sim) = simulate_and_swap_wynd_pair(
                                 &deps.querier,
                                 delegator_addr,
                                 pair_addr.as_ref(),
-                                wyndex::asset::Asset {
-                                    info: terraswap_assetinfo_to_wyndex_assetinfo(simulated_juno.info),
-                                    amount: simulated_juno.amount,
-                                },
+                                juno.clone().into(),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
+                                max_slippage,
                             )?;
 
+                            // dao pairs can be thin so the swap has to stay close to the pair's spot price
+                            if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                                impact_events.push(guard.check_against_spot(
+                                    &deps.querier,
+                                    &JunoSwapVenue::Wyndex(WyndexVenue::Pair(Addr::unchecked(&pair_addr))),
+                                    &pair_addr,
+                                    &juno,
+                                    &AssetInfo::Token(dao_addresses.cw20.to_string()).into(),
+                                    swap_sim.return_amount,
+                                )?);
+                            }
+
                             (vec![swap_msg], swap_sim.return_amount)
                         } else {
                             // otherwise we need to use the wyndex router to swap
@@ -206,7 +384,8 @@ pub fn prefs_to_msgs(
                                 AssetInfo::Native("ujuno".to_string()),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
                                 project_addrs.destination_projects.wynd.multihop.to_string(),
-                                None
+                                None,
+                                max_slippage,
                             )?
                         };
 
@@ -218,10 +397,9 @@ pub fn prefs_to_msgs(
                             expected_dao_token_amount,
                         )?;
 
-                        // order is important here
-                        stake_msgs.prepend_msgs(wyndex_swap_msgs);
-                        // we need to do the terraswap swap before the wyndex one
-                        stake_msgs.prepend_msgs(vec![terraswap_swap_msg]);
+                        // order is important here. we need to do the terraswap swap before the wyndex one
+                        stake_msgs.run_after_swap([vec![terraswap_swap_msg], wyndex_swap_msgs].concat());
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -236,7 +414,7 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 "ujuno", 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
 
                         // swap juno for wynd
                         let wynd_swap_msg = wynd_pair_swap_msg(
@@ -247,13 +425,13 @@ pub fn prefs_to_msgs(
                             },
                             AssetInfo::Token(cw20.to_string()),
                             &juno_wynd_pair,
+                            max_slippage,
                         )?;
 
                         let mut staking_msg =
                             wynd_staking_msgs(&cw20, &delegator_addr.to_string(), comp_token_amount, bonding_period)?;
 
-                        staking_msg.prepend_msgs(vec![wynd_swap_msg]);
-                        staking_msg.prepend_msgs(vec![juno_swap_msg]);
+                        staking_msg.run_after_swap(vec![juno_swap_msg, wynd_swap_msg]);
 
                         Ok(staking_msg)
                     }
@@ -264,6 +442,7 @@ pub fn prefs_to_msgs(
                             comp_token_amount,
                             &target_denom.to_string(),
                             &terraswap_multihop_addr,
+                            max_slippage,
                         )?],                        
                         sub_msgs: vec![],
                         events: vec![],
@@ -281,7 +460,7 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 "ujuno", 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
 
                             let mut lottery_msgs = gelotto_lottery_msgs(
                             delegator_addr,
@@ -292,7 +471,7 @@ pub fn prefs_to_msgs(
                             simulated_juno.amount,
                         )?;
 
-                        lottery_msgs.prepend_msgs(vec![juno_swap_msg]);
+                        lottery_msgs.run_after_swap(vec![juno_swap_msg]);
 
                         Ok(lottery_msgs)
                     },
@@ -303,7 +482,7 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 project_addrs.usdc.to_string().as_str(), 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
                         
                         
                         let mut game_msgs = racoon_bet_msgs(
@@ -311,12 +490,12 @@ pub fn prefs_to_msgs(
                         delegator_addr,
                         // dont pass the wyndex pair in because we are passing in usdc and can skip the query
                         None,
-                        asset_to_coin(simulated_usdc)?,
+                        OutpostAsset::from(simulated_usdc).to_coin()?,
                         game,
                         &project_addrs.destination_projects.racoon_bet.game,
                     )?;
 
-                    game_msgs.prepend_msgs(vec![usdc_swap_msg]);
+                    game_msgs.run_after_swap(vec![usdc_swap_msg]);
                     
                     Ok(game_msgs)
                     },
@@ -327,16 +506,16 @@ pub fn prefs_to_msgs(
                             comp_token_amount, 
                             asset.to_string().as_str(), 
                             &terraswap_multihop_addr, 
-                            &deps.querier)?;   
+                            &deps.querier, max_slippage)?;   
 
                         // now just bond it to the satellite
                         let mut bond_msgs = white_whale_satellite_msgs(
                             delegator_addr,
-                            asset_to_coin(simulated_asset)?,
+                            OutpostAsset::from(simulated_asset).to_coin()?,
                             &project_addrs.destination_projects.white_whale.market.clone(),
                         )?;
 
-                        bond_msgs.prepend_msgs(vec![lsd_swap_msg]);
+                        bond_msgs.run_after_swap(vec![lsd_swap_msg]);
 
                         Ok(bond_msgs)
                     }
@@ -347,7 +526,7 @@ pub fn prefs_to_msgs(
                              comp_token_amount, 
                              "ujuno", 
                              &terraswap_multihop_addr, 
-                             &deps.querier)?;
+                             &deps.querier, max_slippage)?;
                         
                         let mut  balance_msgs = balance_dao_msgs(
                         delegator_addr,
@@ -355,7 +534,7 @@ pub fn prefs_to_msgs(
                         simulated_juno.amount,
                     )?;
 
-                    balance_msgs.prepend_msgs(vec![juno_swap_msg]);
+                    balance_msgs.run_after_swap(vec![juno_swap_msg]);
                 
                     Ok(balance_msgs)
                     },
@@ -367,7 +546,7 @@ pub fn prefs_to_msgs(
                            comp_token_amount, 
                            "ujuno", 
                            &terraswap_multihop_addr, 
-                           &deps.querier)?;
+                           &deps.querier, max_slippage)?;
 
                         let mut mint_msgs = mint_juno_lsd_msgs(
                         delegator_addr,
@@ -376,7 +555,7 @@ pub fn prefs_to_msgs(
                         project_addrs.destination_projects.juno_lsds.clone(),
                     )?;
 
-                    mint_msgs.prepend_msgs(vec![juno_swap_msg]);
+                    mint_msgs.run_after_swap(vec![juno_swap_msg]);
                 
                     Ok(mint_msgs)
                     },
@@ -389,16 +568,16 @@ pub fn prefs_to_msgs(
                                 comp_token_amount, 
                                 project_addrs.usdc.to_string().as_str(), 
                                 &terraswap_multihop_addr, 
-                                &deps.querier)?;
+                                &deps.querier, max_slippage)?;
 
                         let mut spark_msgs = spark_ibc_msgs(
                             delegator_addr,
                             &spark_addr,
-                            asset_to_coin(simulated_usdc)?,
+                            OutpostAsset::from(simulated_usdc).to_coin()?,
                             fund,
                         )?;
 
-                        spark_msgs.prepend_msgs(vec![usdc_swap_msg]);
+                        spark_msgs.run_after_swap(vec![usdc_swap_msg]);
 
                         Ok(spark_msgs)
                     }
@@ -414,6 +593,7 @@ pub fn prefs_to_msgs(
                             &target_asset.to_string(),
                             &terraswap_multihop_addr,
                             &deps.querier,
+                            max_slippage,
                         )?;
 
 
@@ -428,10 +608,11 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        send_msgs.append_msgs(vec![swap_msg]);
+                        send_msgs.run_after_swap(vec![swap_msg]);
 
                         Ok(send_msgs)
                     }
+                    JunoDestinationProject::Composite(_) => Err(ContractError::NotImplemented {}),
                     JunoDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
             
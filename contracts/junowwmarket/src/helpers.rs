@@ -4,6 +4,7 @@ use cw_grant_spec::grants::{GrantBase, GrantRequirement};
 use outpost_utils::{
     helpers::{calc_tax_split, TaxSplitResult},
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    referrals::Referral,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -111,6 +112,7 @@ pub fn query_and_generate_ww_market_reward_msgs(
     ww_rewards_addr: &Addr,
     ww_lair_addr: &Addr,
     whale_denom: &str,
+    referral: Option<&Referral>,
     querier: &QuerierWrapper,
 ) -> Result<TaxSplitResult, ContractError> {
     // query the pending ww sat market rewards
@@ -125,7 +127,7 @@ pub fn query_and_generate_ww_market_reward_msgs(
     };
 
     // calculate the tax split
-    let mut tax_split = calc_tax_split(whale_rewards, tax_percent, user_addr, tax_addr);
+    let mut tax_split = calc_tax_split(whale_rewards, tax_percent, user_addr, tax_addr, referral);
 
     // add the claim msg to the result so that it can be executed
     // at the beginning of the compounding
@@ -139,7 +141,11 @@ pub fn query_and_generate_ww_market_reward_msgs(
     Ok(tax_split)
 }
 
-pub fn ww_market_rewards_split_grants(base: GrantBase, project_addresses: ContractAddrs) -> Vec<GrantRequirement> {
+pub fn ww_market_rewards_split_grants(
+    base: GrantBase,
+    project_addresses: ContractAddrs,
+    referrer: Option<String>,
+) -> Vec<GrantRequirement> {
     vec![
         GrantRequirement::default_contract_exec_auth(
             base.clone(),
@@ -150,7 +156,13 @@ pub fn ww_market_rewards_split_grants(base: GrantBase, project_addresses: Contra
         GrantRequirement::GrantSpec {
             grant_type: cw_grant_spec::grants::AuthorizationType::SendAuthorization {
                 spend_limit: Some(coins(u128::MAX, project_addresses.terraswap_routes.whale_asset.to_string())),
-                allow_list: Some(vec![project_addresses.take_rate_addr]),
+                // the referrer's share of the tax is sent to them directly
+                allow_list: Some(
+                    [Some(project_addresses.take_rate_addr), referrer.map(Addr::unchecked)]
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                ),
             },
             granter: base.granter,
             grantee: base.grantee,
@@ -186,6 +198,7 @@ impl TerraswapRouteAddrs {
         ask_denom: &str,
         multihop_addr: &Addr,
         querier: &QuerierWrapper,
+        max_slippage: Option<Decimal>,
     ) -> Result<(CosmosProtoMsg, Asset), ContractError> {
         // TODO: if we returned an array of cosmos proto msgs instead we could retun an empty array when swapping from whale to whale
         if self.whale_asset.to_string().eq(ask_denom) {
@@ -203,7 +216,7 @@ impl TerraswapRouteAddrs {
         if let Some(pool_addr) = self.get_whale_pool_addr(ask_denom) {
             // Ok(create_terraswap_pool_swap_msg(sender, offer_asset, &pool_addr)?)
             let (swap_msg, amount) =
-                create_terraswap_pool_swap_msg_with_simulation(querier, sender, offer_asset, &pool_addr)?;
+                create_terraswap_pool_swap_msg_with_simulation(querier, sender, offer_asset, &pool_addr, max_slippage)?;
 
             Ok((
                 swap_msg,
@@ -222,6 +235,7 @@ impl TerraswapRouteAddrs {
                 offer_amount,
                 swap_ops,
                 multihop_addr.to_string(),
+                max_slippage,
             )?;
 
             Ok((
@@ -247,6 +261,7 @@ impl TerraswapRouteAddrs {
         offer_amount: Uint128,
         ask_denom: &str,
         multihop_addr: &Addr,
+        max_slippage: Option<Decimal>,
     ) -> Result<CosmosProtoMsg, ContractError> {
         // TODO: if we returned an array of cosmos proto msgs instead we could retun an empty array when swapping from whale to whale
         if self.whale_asset.to_string().eq(ask_denom) {
@@ -262,12 +277,15 @@ impl TerraswapRouteAddrs {
         };
 
         if let Some(pool_addr) = self.get_whale_pool_addr(ask_denom) {
-            Ok(create_terraswap_pool_swap_msg(sender, offer_asset, &pool_addr)?)
+            Ok(create_terraswap_pool_swap_msg(sender, offer_asset, &pool_addr, max_slippage)?)
         } else if let Some(swap_ops) = self.get_whale_swap_routes(ask_denom) {
-            Ok(create_swap_msg(sender, offer_amount, swap_ops, multihop_addr.to_string())?
-                .first()
-                .unwrap()
-                .clone())
+            // without a simulation there's nothing to base a minimum receive amount on
+            Ok(
+                create_swap_msg(sender, offer_amount, swap_ops, multihop_addr.to_string(), None)?
+                    .first()
+                    .unwrap()
+                    .clone(),
+            )
         } else {
             Err(ContractError::TerraswapNoSwapPath {
                 from: self.whale_asset.to_string(),
@@ -304,34 +322,3 @@ impl TerraswapRouteAddrs {
         }
     }
 }
-
-/// converts from a Wyndex AssetInfo to a Terraswap AssetInfo
-pub fn wyndex_assetinfo_to_terraswap_assetinfo(
-    asset_info: wyndex::asset::AssetInfo,
-) -> white_whale::pool_network::asset::AssetInfo {
-    match asset_info {
-        wyndex::asset::AssetInfo::Native(denom) => AssetInfo::NativeToken { denom },
-        wyndex::asset::AssetInfo::Token(contract_addr) => AssetInfo::Token { contract_addr },
-    }
-}
-
-/// fn to convert from a terraswap asset info to a wyndex asset info
-pub fn terraswap_assetinfo_to_wyndex_assetinfo(
-    asset_info: white_whale::pool_network::asset::AssetInfo,
-) -> wyndex::asset::AssetInfo {
-    match asset_info {
-        AssetInfo::NativeToken { denom } => wyndex::asset::AssetInfo::Native(denom),
-        AssetInfo::Token { contract_addr } => wyndex::asset::AssetInfo::Token(contract_addr),
-    }
-}
-
-/// converts a terraswap asset into a coin or errors (if it's a cw20)
-pub fn asset_to_coin(asset: Asset) -> Result<Coin, ContractError> {
-    match asset.info {
-        AssetInfo::NativeToken { denom } => Ok(Coin {
-            denom,
-            amount: asset.amount,
-        }),
-        AssetInfo::Token { contract_addr } => Err(ContractError::AssetIsNotCoinable(contract_addr)),
-    }
-}
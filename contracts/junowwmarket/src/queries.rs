@@ -4,10 +4,10 @@ use cw_grant_spec::grants::{
     ContractExecutionAuthorizationLimit, GrantBase, GrantRequirement, RevokeRequirement,
 };
 
-use juno_destinations::comp_prefs::{wyndex_asset_info_to_terraswap_asset_info, DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::comp_prefs::{DaoAddr, JunoDestinationProject, JunoLsd};
 use juno_destinations::grants::{balance_dao_grant, gelotto_lottery_grant, wyndao_staking_grant};
 
-use universal_destinations::grants::{native_send_token, native_staking_grant};
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 
 use wynd_helpers::wynd_swap::{simulate_wynd_pool_swap, wynd_pool_swap_grant};
 use wyndex::{
@@ -87,7 +87,7 @@ impl Grantable for QueryMsg {
             grant_contract: outpost_contract,
             grant_data:
                 CompPrefsWithAddresses {
-                    comp_prefs: _comp_prefs,
+                    comp_prefs: JunoWhiteWhaleMarketCompoundPrefs { referrer, .. },
                     project_addresses,
                 },
             ..
@@ -99,6 +99,7 @@ impl Grantable for QueryMsg {
                 expiration,
             },
             project_addresses,
+            referrer,
         );
 
         Ok(dedupe_grant_reqs(
@@ -115,7 +116,7 @@ impl Grantable for QueryMsg {
             grant_contract: outpost_contract,
             grant_data:
                 CompPrefsWithAddresses {
-                    comp_prefs: _comp_prefs,
+                    comp_prefs: JunoWhiteWhaleMarketCompoundPrefs { referrer, .. },
                     project_addresses,
                 },
             ..
@@ -128,6 +129,7 @@ impl Grantable for QueryMsg {
                 expiration,
             },
             project_addresses,
+            referrer,
         );
 
         Ok(
@@ -152,15 +154,16 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs = comp_prefs.relative.iter().flat_map(|action| -> Vec<GrantRequirement> {
+    let grant_specs = comp_prefs.destinations().flat_map(|destination| -> Vec<GrantRequirement> {
         let base = GrantBase {
             granter: granter.clone(),
             grantee: grantee.clone(),
             expiration,
         };
 
-        match action.destination.clone() {
+        match destination.clone() {
             JunoDestinationProject::Unallocated {} => vec![],
+            JunoDestinationProject::Composite(_) => vec![],
             JunoDestinationProject::JunoStaking { validator_address } => {
                 [
                     vec![
@@ -182,6 +185,30 @@ pub fn gen_comp_pref_grants(
                 ]
                 .concat()
             }
+            JunoDestinationProject::JunoMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => {
+                [
+                    vec![
+                        // terraswap swap to get the juno to hand off to wyndex
+                        project_addresses
+                            .terraswap_routes
+                            .gen_terraswap_whale_swap_grant(
+                                base.clone(),
+                                "ujuno".to_string(),
+                                project_addresses
+                                    .destination_projects
+                                    .white_whale
+                                    .terraswap_multihop_router
+                                    .clone(),
+                            )
+                            .unwrap(),
+                    ],
+                    native_multi_staking_grant(base, validators, redelegate_inactive),
+                ]
+                .concat()
+            }
 
             JunoDestinationProject::DaoStaking(dao) => {
                 let DaoAddr {
@@ -288,7 +315,7 @@ pub fn gen_comp_pref_grants(
                         .unwrap()],
                 },
                 // send to the given user
-                native_send_token(base, wyndex_asset_info_to_terraswap_asset_info(denom), address),
+                native_send_token(base, denom, address),
             ]
             .concat(),
             JunoDestinationProject::MintLsd { lsd_type } => vec![
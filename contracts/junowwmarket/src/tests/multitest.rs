@@ -59,6 +59,9 @@ impl OutpostContract {
                 comp_prefs,
                 user_address: delegator_address,
                 tax_fee: None,
+                referrer: None,
+                max_slippage: None,
+                min_compound: None,
             }),
             &[],
         )
@@ -1,41 +1,61 @@
-use cosmwasm_std::{coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128, Uint64,
+};
 use migaloo_destinations::{
-    comp_prefs::{DaoDaoStakingInfo, LsdMintAction, MUsdcAction, MigalooCompPrefs, MigalooDestinationProject, MigalooVault},
+    comp_prefs::{
+        route_ask_denom, DaoDaoStakingInfo, LsdMintAction, MUsdcAction, MigalooCompPrefs, MigalooDestinationProject,
+        MigalooVault,
+    },
     dest_project_gen::{
         burn_whale_msgs, deposit_ginkou_usdc_msgs, ecosystem_stake_msgs, eris_amp_vault_msgs, eris_arb_vault_msgs,
         mint_or_buy_whale_lsd_msgs, query_ginkou_musdc_mint,
     },
+    errors::MigalooDestinationError,
 };
 use outpost_utils::{
-    comp_prefs::DestinationAction,
+    asset::OutpostAssetInfo,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
     helpers::{
-        calc_additional_tax_split, calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one,
-        DestProjectMsgs, TaxSplitResult,
+        below_min_compound, calc_additional_tax_split, enforce_compound_frequency, is_authorized_compounder,
+        min_out_after_slippage, prefs_sum_to_one, simulate_compound_response, CompoundPlan, DestProjectMsgs,
+        SimulateCompoundResponse, TaxSplitResult,
     },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    stats::CompoundRun,
+};
+use terraswap_helpers::terraswap_swap::{
+    create_terraswap_pool_swap_msg_with_simulation, create_terraswap_swap_msg_with_simulation, simulate_pool_swap,
+    TerraswapVenue,
 };
-use std::iter;
-use terraswap_helpers::terraswap_swap::create_terraswap_pool_swap_msg_with_simulation;
 use white_whale::pool_network::asset::{Asset, AssetInfo};
 
 
 
 use crate::{
     msg::{ContractAddrs, DcaPrefs},
-    state::{ADMIN, AUTHORIZED_ADDRS, PROJECT_ADDRS},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS,
+        PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS,
+    },
     ContractError,
 };
 use sail_destinations::dest_project_gen::{racoon_bet_msgs, spark_ibc_msgs, white_whale_satellite_msgs};
-use universal_destinations::dest_project_gen::{daodao_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{active_multi_staking_msgs, active_native_staking_msg, daodao_staking_msg};
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     _project_addresses: ContractAddrs,
     user_address: String,
     comp_prefs: &DcaPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     let DcaPrefs {
         compound_token,
@@ -45,48 +65,94 @@ pub fn compound(
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(compound_preferences)?;
 
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
     // check that the delegator address is valid
     let user_addr: Addr = deps.api.addr_validate(&user_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &user_addr,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
     let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
 
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
     // calculate the total amount of rewards that will be compounded
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
     let TaxSplitResult {
         remaining_rewards,
         tax_amount,
+        referral_amount,
         claim_and_tax_msgs: tax_store_msg,
     } = calc_additional_tax_split(
         compound_token,
-        tax_fee.unwrap_or(Decimal::percent(1)),
+        tax_fee,
         user_address.clone(),
         project_addrs.take_rate_addr.to_string(),
+        referral.as_ref(),
     );
 
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
-        &project_addrs,
+    // amounts that aren't worth compounding are skipped rather than split into dust
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps.as_ref(), &project_addrs, amount)
+    })? {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &user_addr, compound_token.amount)?;
+
+    let comp_prefs = compound_preferences
+        .clone()
+        .with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (all_msgs, fallbacks) = isolate_destinations(&user_addr, &remaining_rewards, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(&project_addrs, &user_addr, amount, comp_prefs, deps.as_ref())
+    })?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(
+        deps.storage,
         &user_addr,
-        remaining_rewards.clone(),
-        compound_preferences.clone(),
-        deps.as_ref(),
+        &CompoundRun::new(env.block.time, vec![tax_amount.clone()], &fallbacks),
     )?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
 
-    let combined_msgs = all_msgs.iter().fold(
-        DestProjectMsgs {
-            msgs: tax_store_msg,
-            sub_msgs: vec![],
-            events: vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())],
-        },
-        |mut acc, msg| {
-            acc.msgs.append(&mut msg.msgs.clone());
-            acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-            acc.events.append(&mut msg.events.clone());
-            acc
-        },
-    );
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &user_addr,
+        &PipelineAsset::Native(remaining_rewards.denom.clone()),
+        all_msgs,
+    )?;
+
+    combined_msgs.prepend_msgs(tax_store_msg);
+    combined_msgs.prepend_events(vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())]);
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
         Event::new("amount_automated").add_attributes([remaining_rewards].iter().enumerate().map(|(i, coin)| Attribute {
@@ -95,13 +161,21 @@ pub fn compound(
         }));
 
     // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        vec![],
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         .add_attribute("compoundee", user_address.clone())
         .add_event(amount_automated_event)
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -129,6 +203,96 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    user_address: String,
+    comp_prefs: &DcaPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<MigalooDestinationProject>, ContractError> {
+    let DcaPrefs {
+        compound_token,
+        compound_preferences,
+    } = comp_prefs;
+
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(compound_preferences)?;
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
+    let user_addr: Addr = deps.api.addr_validate(&user_address)?;
+
+    let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    let TaxSplitResult {
+        remaining_rewards,
+        tax_amount,
+        claim_and_tax_msgs,
+        ..
+    } = calc_additional_tax_split(
+        compound_token,
+        tax_fee,
+        user_address,
+        project_addrs.take_rate_addr.to_string(),
+        REFERRALS.referral(deps.storage, referrer.as_ref())?.as_ref(),
+    );
+
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps, &project_addrs, amount)
+    })? {
+        return Ok(CompoundPlan::skipped(compound_token.clone(), compound_preferences.clone(), skipped).simulate()?);
+    }
+
+    let dest_msgs = prefs_to_msgs(
+        &project_addrs,
+        &user_addr,
+        remaining_rewards.clone(),
+        compound_preferences.clone(),
+        deps,
+    )?;
+
+    Ok(simulate_compound_response(
+        compound_token.clone(),
+        vec![tax_amount],
+        remaining_rewards,
+        compound_preferences.clone(),
+        claim_and_tax_msgs,
+        dest_msgs,
+    )?)
+}
+
+/// What the amount would be worth in USDC going by its configured price source,
+/// or by a swap simulation on the WHALE-USDC pool if it doesn't have one
+fn usdc_value(deps: Deps, project_addrs: &ContractAddrs, amount: &cosmwasm_std::Coin) -> Result<Uint128, ContractError> {
+    if let Some(price_source) = PRICE_SOURCES.may_load(deps.storage, amount.denom.as_str())? {
+        return Ok(price_source.value(
+            &deps.querier,
+            &amount.into(),
+            &OutpostAssetInfo::from_denom(&project_addrs.destination_projects.denoms.usdc),
+        )?);
+    }
+
+    let simulation = simulate_pool_swap(
+        &deps.querier,
+        project_addrs.destination_projects.swap_routes.whale_usdc_pool.as_str(),
+        &Asset {
+            info: AssetInfo::NativeToken {
+                denom: amount.denom.clone(),
+            },
+            amount: amount.amount,
+        },
+    )?;
+
+    Ok(simulation.return_amount)
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -141,28 +305,39 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, MigalooDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 let compounding_asset = Asset {
                     info: AssetInfo::NativeToken {
-                        denom: "uwhale".to_string(),
+                        denom: total_rewards.denom.clone(),
                     },
                     amount: comp_token_amount,
                 };
 
                 match destination {
-                    MigalooDestinationProject::MigalooStaking { validator_address } => Ok(native_staking_msg(
+                    MigalooDestinationProject::MigalooStaking { validator_address } => Ok(active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         delegator_addr,
                         &cosmwasm_std::Coin {
-                            denom: "uwhale".to_string(),
+                            denom: total_rewards.denom.clone(),
+                            amount: comp_token_amount,
+                        },
+                    )?),
+                    MigalooDestinationProject::MigalooMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => Ok(active_multi_staking_msgs(
+                        &deps.querier,
+                        &validators,
+                        redelegate_inactive,
+                        delegator_addr,
+                        &cosmwasm_std::Coin {
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                     )?),
@@ -178,10 +353,24 @@ pub fn prefs_to_msgs(
                         let (swap_msg, swap_sim) = create_terraswap_pool_swap_msg_with_simulation(
                             &deps.querier,
                             delegator_addr,
-                            compounding_asset,
+                            compounding_asset.clone(),
                             &swap_pair_addr,
+                            max_slippage,
                         )?;
 
+                        // dao pools can be thin so the swap has to stay close to the pool's spot price
+                        let mut impact_events = vec![];
+                        if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                            impact_events.push(guard.check_against_spot(
+                                &deps.querier,
+                                &TerraswapVenue::Pool(swap_pair_addr.clone()),
+                                swap_pair_addr.as_str(),
+                                &compounding_asset.into(),
+                                &asset_info.clone().into(),
+                                swap_sim,
+                            )?);
+                        }
+
                         let mut stake_msgs = daodao_staking_msg(
                             dao_name.to_string(),
                             delegator_addr,
@@ -192,7 +381,8 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        stake_msgs.prepend_msgs(vec![swap_msg]);
+                        stake_msgs.run_after_swap(vec![swap_msg]);
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -213,7 +403,7 @@ pub fn prefs_to_msgs(
                         //     AllianceAsset::AmpLuna =>
                         //         project_addrs.destination_projects.swap_routes.whale.amp_luna
                         // };
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
 
                     MigalooDestinationProject::SparkIbcCampaign { fund } => {
@@ -222,6 +412,7 @@ pub fn prefs_to_msgs(
                             delegator_addr,
                             compounding_asset,
                             &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                            max_slippage,
                         )?;
 
                         let mut donate_msgs = spark_ibc_msgs(
@@ -231,7 +422,7 @@ pub fn prefs_to_msgs(
                             fund,
                         )?;
 
-                        donate_msgs.prepend_msgs(vec![swap_msg]);
+                        donate_msgs.run_after_swap(vec![swap_msg]);
 
                         Ok(donate_msgs)
                     }
@@ -241,7 +432,7 @@ pub fn prefs_to_msgs(
                         delegator_addr,
                         Some(&project_addrs.destination_projects.swap_routes.whale_usdc_pool),
                         cosmwasm_std::Coin {
-                            denom: "uwhale".to_string(),
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                         game,
@@ -292,7 +483,7 @@ pub fn prefs_to_msgs(
 
                         // Ok(bond_msgs)
 
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
 
                     MigalooDestinationProject::GinkouDepositUSDC { and_then } => {
@@ -301,6 +492,7 @@ pub fn prefs_to_msgs(
                             delegator_addr,
                             compounding_asset,
                             &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                            max_slippage,
                         )?;
 
                         let mut deposit_msgs = deposit_ginkou_usdc_msgs(
@@ -379,6 +571,7 @@ pub fn prefs_to_msgs(
                                     delegator_addr,
                                     compounding_asset,
                                     &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                                    max_slippage,
                                 )?;
 
                                 (
@@ -399,6 +592,7 @@ pub fn prefs_to_msgs(
                                     delegator_addr,
                                     compounding_asset,
                                     &project_addrs.destination_projects.swap_routes.whale_ash_pool,
+                                    max_slippage,
                                 )?;
 
                                 (
@@ -417,7 +611,7 @@ pub fn prefs_to_msgs(
 
                         let mut bond_msgs = eris_amp_vault_msgs(delegator_addr, bonding_asset, vault_addr)?;
 
-                        bond_msgs.prepend_msgs(vec![swap_msg]);
+                        bond_msgs.run_after_swap(vec![swap_msg]);
 
                         Ok(bond_msgs)
                     }
@@ -431,6 +625,7 @@ pub fn prefs_to_msgs(
                             &project_addrs.destination_projects.projects.whale_lsd,
                             &project_addrs.destination_projects.swap_routes,
                             &project_addrs.destination_projects.denoms,
+                            max_slippage,
                         )?;
 
                         match and_then {
@@ -452,13 +647,13 @@ pub fn prefs_to_msgs(
                     }
 
                     MigalooDestinationProject::TokenSwap { target_denom: _ } => {
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
                     MigalooDestinationProject::SendTokens {
                         denom: _target_asset,
                         address: _to_address,
                     } => {
-                        unimplemented!();
+                        Err(ContractError::NotImplemented {})
                         // let (swap_msgs, sim) = create_wyndex_swap_msg_with_simulation(
                         //     &deps.querier,
                         //     delegator_addr,
@@ -484,8 +679,34 @@ pub fn prefs_to_msgs(
 
                         // Ok(send_msgs)
                     }
-                    MigalooDestinationProject::GinkouRepayLoan {} => unimplemented!(),
-                    MigalooDestinationProject::GinkouProvideLiquidity { asset: _, and_then: _ } => unimplemented!(),
+                    MigalooDestinationProject::GinkouRepayLoan {} => Err(ContractError::NotImplemented {}),
+                    MigalooDestinationProject::GinkouProvideLiquidity { asset: _, and_then: _ } => Err(ContractError::NotImplemented {}),
+
+                    MigalooDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let intermediate_denom =
+                            route_ask_denom(&convert_to).ok_or(MigalooDestinationError::InvalidCompositeRoute {})?;
+
+                        // the route always gets a minimum receive so the nested destinations can be sized off of it
+                        let swap_slippage = max_slippage.unwrap_or(Decimal::percent(2));
+                        let (swap_msgs, sim) = create_terraswap_swap_msg_with_simulation(
+                            &deps.querier,
+                            delegator_addr,
+                            comp_token_amount,
+                            convert_to,
+                            project_addrs.destination_projects.projects.terraswap_multihop_router.to_string(),
+                            Some(swap_slippage),
+                        )?;
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            delegator_addr,
+                            coin(min_out_after_slippage(sim, swap_slippage).u128(), intermediate_denom),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
 
                     MigalooDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
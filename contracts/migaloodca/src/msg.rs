@@ -1,10 +1,17 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Api, Coin, Decimal, Timestamp};
+use cosmwasm_std::{Addr, Api, Coin, Decimal, Timestamp, Uint64};
 use cw_grant_spec::grants::{GrantRequirement, RevokeRequirement};
 use migaloo_destinations::comp_prefs::{
-    MigalooCompPrefs, MigalooDestinationProjectAddresses, MigalooDestinationProjectAddrs,
+    MigalooCompPrefs, MigalooDestinationProject, MigalooDestinationProjectAddresses, MigalooDestinationProjectAddrs,
 };
+use migaloo_destinations::dest_project_gen::MigalooPriceSource;
+use outpost_utils::comp_prefs::MinCompound;
+use outpost_utils::errors::OutpostError;
+use outpost_utils::fee_schedule::FeeSchedule;
 use outpost_utils::helpers::CompoundingFrequency;
+use outpost_utils::helpers::{CompoundEligibilityResponse, SimulateCompoundResponse};
+use outpost_utils::price_source::PriceImpactGuard;
+use outpost_utils::stats::CompoundStats;
 use white_whale::pool_network::asset::AssetInfo;
 
 use crate::ContractError;
@@ -23,11 +30,16 @@ pub struct InstantiateMsg {
 
     /// All of the addresses that the compounder can interact with
     pub project_addresses: ContractAddresses,
+
+    /// The most that users can be charged. Neither the fee schedule nor the compound's `tax_fee` can go above it
+    pub max_tax_fee: Decimal,
 }
 
 #[cw_serde]
 pub struct MigrateMsg {
     pub project_addresses: Option<ContractAddresses>,
+    /// Required when migrating from a version that didn't store the max tax fee
+    pub max_tax_fee: Option<Decimal>,
 }
 
 #[cw_serde]
@@ -40,6 +52,15 @@ pub enum QueryMsg {
     #[returns(AuthorizedCompoundersResponse)]
     AuthorizedCompounders {},
 
+    /// Explains whether or not the user can currently be compounded,
+    /// either for one of their stored strategies or at the default frequency
+    #[returns(CompoundEligibilityResponse)]
+    CompoundEligibility { user: String, strat_id: Option<Uint64> },
+
+    /// Simulates compounding the given prefs without executing anything
+    #[returns(SimulateCompoundResponse<MigalooDestinationProject>)]
+    SimulateCompound(MigaloodcaCompoundPrefs),
+
     #[returns(Vec<GrantRequirement>)]
     GrantSpec {
         frequency: CompoundingFrequency,
@@ -49,6 +70,34 @@ pub enum QueryMsg {
 
     #[returns(Vec<RevokeRequirement>)]
     RevokeSpec { comp_prefs: MigaloodcaCompoundPrefs },
+
+    /// The price source used to value the given denom, if one has been configured
+    #[returns(Option<MigalooPriceSource>)]
+    PriceSource { denom: String },
+
+    #[returns(Option<FeeSchedule>)]
+    FeeSchedule {},
+
+    /// The fee the user would be charged if they were compounded now.
+    /// Errors if the outpost quotes the user a fee that they haven't agreed to yet
+    #[returns(Decimal)]
+    UserFee { user: String },
+
+    /// The total amount of each denom that has been paid out to the referrer
+    #[returns(Vec<Coin>)]
+    ReferralEarnings { referrer: String },
+
+    /// Every registered referrer along with the portion of the tax they are paid
+    #[returns(Vec<(Addr, Decimal)>)]
+    Referrers {},
+
+    /// Running totals of everything that has been automated for the user
+    #[returns(CompoundStats)]
+    UserStats { user: String },
+
+    /// Running totals of everything the outpost has automated
+    #[returns(CompoundStats)]
+    OutpostStats {},
 }
 
 #[cw_serde]
@@ -68,7 +117,50 @@ pub enum ExecuteMsg {
     AddAuthorizedCompounder(String),
     RemoveAuthorizedCompounder(String),
     Compound(MigaloodcaCompoundPrefs),
+    /// Compound many users at once. Each user runs in its own submessage so one user failing
+    /// doesn't revert the rest of the batch
+    BatchCompound(Vec<MigaloodcaCompoundPrefs>),
     UpdateProjectAddresses(ContractAddresses),
+    /// Update the comp prefs contract that stored prefs are loaded from
+    UpdateCompPrefsAddress(String),
+    /// Set the destination that a failed destination's share goes to when the user hasn't picked a fallback
+    UpdateDefaultFallback(Option<MigalooDestinationProject>),
+    /// Set where the price of the given denom comes from when valuing it in usdc.
+    /// Without a price source the value is simulated by swapping on the WHALE-USDC pool
+    UpdatePriceSource {
+        denom: String,
+        source: Option<MigalooPriceSource>,
+    },
+    /// Set the max price impact for swaps into thin pools such as the dao token pairs.
+    /// Destinations whose swap goes over it are rerouted to their fallback
+    UpdatePriceImpactGuard(Option<PriceImpactGuard>),
+    /// Set the fees that users are charged. Once there is a schedule the fee is looked up on chain
+    /// and the compound's `tax_fee` has to match it. None of the fees can be above the max tax fee
+    UpdateFeeSchedule(Option<FeeSchedule>),
+    /// Set a fee for the user that replaces the schedule, for example zero for partner wallets.
+    /// It can't be above the max tax fee either
+    UpdateFeeOverride {
+        user: String,
+        fee: Option<Decimal>,
+    },
+    /// Sent by the user alongside their grants to lock in their current fee.
+    /// They can't be compounded until they have and are never charged more than it afterwards.
+    /// Errors if the fee is above `max_fee`
+    AgreeToFee {
+        max_fee: Decimal,
+    },
+    /// Register a referrer along with the portion of the tax they are paid or remove them if there's no share.
+    /// Users can only be referred by a registered referrer
+    UpdateReferrer {
+        referrer: String,
+        share: Option<Decimal>,
+    },
+    /// Compound using the user's prefs that are stored in the comp prefs contract
+    /// The strategy's schedule is only advanced if the compound runs, a failed or skipped compound leaves it due
+    CompoundStored {
+        user_address: String,
+        strat_id: Uint64,
+    },
 }
 
 #[cw_serde]
@@ -76,6 +168,49 @@ pub struct MigaloodcaCompoundPrefs {
     pub comp_prefs: Vec<DcaPrefs>,
     pub user_address: String,
     pub tax_fee: Option<Decimal>,
+    /// the partner frontend that onboarded the user. they're paid their share of the tax
+    /// as long as the admin has registered them as a referrer
+    pub referrer: Option<String>,
+    /// the most slippage that the compound's swaps will accept, for example "0.01" is 1%.
+    /// destinations can set their own to override it
+    pub max_slippage: Option<Decimal>,
+    /// the least that the compound token has to be worth for the compound to go ahead.
+    /// smaller amounts are skipped rather than split into destinations
+    pub min_compound: Option<MinCompound>,
+}
+
+impl MigaloodcaCompoundPrefs {
+    /// Fills in the outpost's default fallback for every destination that doesn't have one
+    pub fn with_default_fallback(self, default_fallback: Option<MigalooDestinationProject>) -> Self {
+        MigaloodcaCompoundPrefs {
+            comp_prefs: self
+                .comp_prefs
+                .into_iter()
+                .map(|prefs| DcaPrefs {
+                    compound_preferences: prefs.compound_preferences.with_default_fallback(default_fallback.clone()),
+                    ..prefs
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Applies the compound's slippage tolerance to every destination that doesn't have its own
+    pub fn with_max_slippage(self) -> Result<Self, OutpostError> {
+        Ok(MigaloodcaCompoundPrefs {
+            comp_prefs: self
+                .comp_prefs
+                .into_iter()
+                .map(|prefs| {
+                    Ok(DcaPrefs {
+                        compound_preferences: prefs.compound_preferences.with_default_slippage(self.max_slippage)?,
+                        ..prefs
+                    })
+                })
+                .collect::<Result<_, OutpostError>>()?,
+            ..self
+        })
+    }
 }
 
 #[cw_serde]
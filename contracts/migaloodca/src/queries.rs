@@ -1,16 +1,18 @@
-use cosmwasm_std::{Addr, Coin, Decimal, Deps, StdResult, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Coin, Deps, StdError, StdResult, Timestamp, Uint128};
 use cw_grant_spec::grantable_trait::{dedupe_grant_reqs, GrantStructure, Grantable};
 use cw_grant_spec::grants::{AuthorizationType, GrantBase, GrantRequirement, RevokeRequirement};
 use migaloo_destinations::comp_prefs::MigalooDestinationProject;
 use migaloo_destinations::grants::furnace_grant;
 
-use universal_destinations::grants::native_staking_grant;
+use terraswap_helpers::terraswap_swap::terraswap_multihop_swap_grant;
+use universal_destinations::grants::{native_multi_staking_grant, native_staking_grant};
 use white_whale::pool_network::asset::AssetInfo;
 
 use crate::msg::{CompPrefsWithAddresses, DcaPrefs, MigaloodcaCompoundPrefs, QueryMsg};
 use crate::{
     msg::{AuthorizedCompoundersResponse, VersionResponse},
     state::{ADMIN, AUTHORIZED_ADDRS},
+    ContractError,
 };
 
 pub fn query_version() -> VersionResponse {
@@ -42,14 +44,21 @@ impl Grantable for QueryMsg {
             grant_data:
                 CompPrefsWithAddresses {
                     comp_frequency,
-                    comp_prefs: MigaloodcaCompoundPrefs { comp_prefs, tax_fee, .. },
+                    comp_prefs:
+                        MigaloodcaCompoundPrefs {
+                            comp_prefs,
+                            tax_fee,
+                            referrer,
+                            ..
+                        },
                     project_addresses,
                 },
             ..
         } = grant_structure.clone();
 
         let iteration_count: Uint128 = comp_frequency.iteration_count(current_timestamp, expiration).into();
-        let fee = tax_fee.unwrap_or(Decimal::percent(1));
+        // the grant spec query fills in the fee that the user is going to be charged
+        let fee = tax_fee.unwrap_or_default();
 
         let taxation_grants = vec![GrantRequirement::GrantSpec {
             grant_type: AuthorizationType::SendAuthorization {
@@ -65,7 +74,14 @@ impl Grantable for QueryMsg {
                         })
                         .collect(),
                 ),
-                allow_list: Some(vec![project_addresses.take_rate_addr.clone()]),
+                // the referrer's share of the tax is sent to them directly.
+                // the grant spec only gets this far for referrers that the admin has registered
+                allow_list: Some(
+                    [Some(project_addresses.take_rate_addr.clone()), referrer.map(Addr::unchecked)]
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                ),
             },
             granter,
             grantee: outpost_contract,
@@ -129,221 +145,231 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs =
-        comp_prefs
-            .first()
-            .unwrap()
-            .compound_preferences
-            .relative
-            .iter()
-            .flat_map(|action| -> Vec<GrantRequirement> {
-                let base = GrantBase {
-                    granter: granter.clone(),
-                    grantee: grantee.clone(),
-                    expiration,
-                };
+    let prefs = comp_prefs
+        .first()
+        .ok_or_else(|| StdError::generic_err(ContractError::NoDCACompoundPrefs.to_string()))?;
+    let destinations = prefs.compound_preferences.nested_destinations(&prefs.compound_token.denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
+        let base = GrantBase {
+            granter: granter.clone(),
+            grantee: grantee.clone(),
+            expiration,
+        };
 
-                match action.destination.clone() {
-                    MigalooDestinationProject::Unallocated {} => vec![],
-                    MigalooDestinationProject::MigalooStaking { validator_address } => {
-                        native_staking_grant(base, None, Some(vec![validator_address]))
-                    }
-                    MigalooDestinationProject::Furnace { and_then } => furnace_grant(
-                        base,
-                        project_addresses.destination_projects.projects.clone(),
-                        and_then,
-                        AssetInfo::NativeToken {
-                            denom: project_addresses.destination_projects.denoms.ash.clone(),
-                        },
-                    ),
-                    _ => vec![],
-                    // MigalooDestinationProject::DaoStaking(dao) => {
-                    //     let DaoAddr {
-                    //         juno_wyndex_pair, cw20, ..
-                    //     } = dao.get_daos_addresses(&project_addresses.destination_projects.daos);
+        match destination.clone() {
+            MigalooDestinationProject::Unallocated {} => vec![],
+            MigalooDestinationProject::Composite(_) => terraswap_multihop_swap_grant(
+                base,
+                project_addresses
+                    .destination_projects
+                    .projects
+                    .terraswap_multihop_router
+                    .clone(),
+                &source_denom,
+            ),
+            MigalooDestinationProject::MigalooStaking { validator_address } => {
+                native_staking_grant(base, None, Some(vec![validator_address]))
+            }
+            MigalooDestinationProject::MigalooMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
+            MigalooDestinationProject::Furnace { and_then } => furnace_grant(
+                base,
+                project_addresses.destination_projects.projects.clone(),
+                and_then,
+                AssetInfo::NativeToken {
+                    denom: project_addresses.destination_projects.denoms.ash.clone(),
+                },
+            ),
+            _ => vec![],
+            // MigalooDestinationProject::DaoStaking(dao) => {
+            //     let DaoAddr {
+            //         juno_wyndex_pair, cw20, ..
+            //     } = dao.get_daos_addresses(&project_addresses.destination_projects.daos);
 
-                    //     let (swap_address, required_key) =
-                    //         // use the pair if possible
-                    //         juno_wyndex_pair.map_or((
-                    //             project_addresses.destination_projects.wynd.multihop.to_string(),
-                    //             "execute_swap_operations".to_string(),
-                    //         ),|pair_add| (pair_add.to_string(), "swap".to_string()));
+            //     let (swap_address, required_key) =
+            //         // use the pair if possible
+            //         juno_wyndex_pair.map_or((
+            //             project_addresses.destination_projects.wynd.multihop.to_string(),
+            //             "execute_swap_operations".to_string(),
+            //         ),|pair_add| (pair_add.to_string(), "swap".to_string()));
 
-                    //     vec![
-                    //         // staking permission
-                    //         GrantRequirement::default_contract_exec_auth(base.clone(), cw20, vec!["send"], None),
-                    //         // swap permission
-                    //         GrantRequirement::default_contract_exec_auth(
-                    //             base,
-                    //             Addr::unchecked(swap_address),
-                    //             vec![required_key],
-                    //             Some("ujuno"),
-                    //         ),
-                    //     ]
-                    // }
-                    // MigalooDestinationProject::BalanceDao {} => {
-                    //     balance_dao_grant(base, project_addresses.destination_projects.balance_dao.clone())
-                    // }
-                    // MigalooDestinationProject::GelottoLottery {
-                    //     lottery,
-                    //     lucky_phrase: _lucky_phrase,
-                    // } => gelotto_lottery_grant(
-                    //     base,
-                    //     lottery.get_lottery_address(&project_addresses.destination_projects.gelotto),
-                    // ),
-                    // MigalooDestinationProject::SendTokens { denom, address } => [
-                    //     // general multihop swap
-                    //     match denom.clone() {
-                    //         AssetInfo::Native(token_denom) if token_denom.eq("ujuno") => vec![],
-                    //         _ => wynd_multihop_swap_grant(
-                    //             base.clone(),
-                    //             project_addresses.destination_projects.wynd.multihop.clone(),
-                    //             AssetInfo::Native("ujuno".to_string()),
-                    //             Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                    //         ),
-                    //     },
-                    //     // send to the given user
-                    //     vec![match denom {
-                    //         // if it's a native denom we need a send authorization
-                    //         AssetInfo::Native(denom) => GrantRequirement::GrantSpec {
-                    //             grant_type: AuthorizationType::SendAuthorization {
-                    //                 spend_limit: Some(vec![coin(u128::MAX, denom)]),
-                    //                 allow_list: Some(vec![Addr::unchecked(address)]),
-                    //             },
-                    //             granter: granter.clone(),
-                    //             grantee: grantee.clone(),
-                    //             expiration,
-                    //         },
-                    //         // if it's a cw20 then we need a contract execution authorization on the cw20 contract
-                    //         AssetInfo::Token(contract_addr) => GrantRequirement::default_contract_exec_auth(
-                    //             base,
-                    //             Addr::unchecked(contract_addr),
-                    //             vec!["transfer"],
-                    //             None,
-                    //         ),
-                    //     }],
-                    // ]
-                    // .concat(),
-                    // MigalooDestinationProject::MintLsd { lsd_type } => vec![GrantRequirement::default_contract_exec_auth(
-                    //     base,
-                    //     lsd_type.get_mint_address(&project_addresses.destination_projects.juno_lsds),
-                    //     vec![match lsd_type {
-                    //         JunoLsd::StakeEasySe => "stake",
-                    //         JunoLsd::StakeEasyB => "stake_for_bjuno",
-                    //         JunoLsd::Wynd | JunoLsd::Backbone | JunoLsd::Eris => "bond",
-                    //     }],
-                    //     Some("ujuno"),
-                    // )],
-                    // MigalooDestinationProject::WhiteWhaleSatellite { asset } => {
-                    //     let denom = match asset {
-                    //         AssetInfo::Native(denom) => denom,
-                    //         AssetInfo::Token(token) => token,
-                    //     };
+            //     vec![
+            //         // staking permission
+            //         GrantRequirement::default_contract_exec_auth(base.clone(), cw20, vec!["send"], None),
+            //         // swap permission
+            //         GrantRequirement::default_contract_exec_auth(
+            //             base,
+            //             Addr::unchecked(swap_address),
+            //             vec![required_key],
+            //             Some("ujuno"),
+            //         ),
+            //     ]
+            // }
+            // MigalooDestinationProject::BalanceDao {} => {
+            //     balance_dao_grant(base, project_addresses.destination_projects.balance_dao.clone())
+            // }
+            // MigalooDestinationProject::GelottoLottery {
+            //     lottery,
+            //     lucky_phrase: _lucky_phrase,
+            // } => gelotto_lottery_grant(
+            //     base,
+            //     lottery.get_lottery_address(&project_addresses.destination_projects.gelotto),
+            // ),
+            // MigalooDestinationProject::SendTokens { denom, address } => [
+            //     // general multihop swap
+            //     match denom.clone() {
+            //         AssetInfo::Native(token_denom) if token_denom.eq("ujuno") => vec![],
+            //         _ => wynd_multihop_swap_grant(
+            //             base.clone(),
+            //             project_addresses.destination_projects.wynd.multihop.clone(),
+            //             AssetInfo::Native("ujuno".to_string()),
+            //             Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+            //         ),
+            //     },
+            //     // send to the given user
+            //     vec![match denom {
+            //         // if it's a native denom we need a send authorization
+            //         AssetInfo::Native(denom) => GrantRequirement::GrantSpec {
+            //             grant_type: AuthorizationType::SendAuthorization {
+            //                 spend_limit: Some(vec![coin(u128::MAX, denom)]),
+            //                 allow_list: Some(vec![Addr::unchecked(address)]),
+            //             },
+            //             granter: granter.clone(),
+            //             grantee: grantee.clone(),
+            //             expiration,
+            //         },
+            //         // if it's a cw20 then we need a contract execution authorization on the cw20 contract
+            //         AssetInfo::Token(contract_addr) => GrantRequirement::default_contract_exec_auth(
+            //             base,
+            //             Addr::unchecked(contract_addr),
+            //             vec!["transfer"],
+            //             None,
+            //         ),
+            //     }],
+            // ]
+            // .concat(),
+            // MigalooDestinationProject::MintLsd { lsd_type } => vec![GrantRequirement::default_contract_exec_auth(
+            //     base,
+            //     lsd_type.get_mint_address(&project_addresses.destination_projects.juno_lsds),
+            //     vec![match lsd_type {
+            //         JunoLsd::StakeEasySe => "stake",
+            //         JunoLsd::StakeEasyB => "stake_for_bjuno",
+            //         JunoLsd::Wynd | JunoLsd::Backbone | JunoLsd::Eris => "bond",
+            //     }],
+            //     Some("ujuno"),
+            // )],
+            // MigalooDestinationProject::WhiteWhaleSatellite { asset } => {
+            //     let denom = match asset {
+            //         AssetInfo::Native(denom) => denom,
+            //         AssetInfo::Token(token) => token,
+            //     };
 
-                    //     vec![
-                    //         // general terraswap multihop swap
-                    //         terraswap_multihop_swap_grant(
-                    //             base.clone(),
-                    //             project_addresses
-                    //                 .destination_projects
-                    //                 .white_whale
-                    //                 .terraswap_multihop_router
-                    //                 .clone(),
-                    //             "ujuno",
-                    //         ),
-                    //         // bonding to the market
-                    //         vec![GrantRequirement::default_contract_exec_auth(
-                    //             base,
-                    //             project_addresses.destination_projects.white_whale.market.clone(),
-                    //             vec!["bond"],
-                    //             Some(&denom),
-                    //         )],
-                    //     ]
-                    //     .into_iter()
-                    //     .flatten()
-                    //     .collect()
-                    // }
-                    // MigalooDestinationProject::WyndStaking {
-                    //     bonding_period: _bonding_period,
-                    // } => vec![
-                    //     // pair swap for JUNO to WYND
-                    //     wynd_pool_swap_grant(
-                    //         base.clone(),
-                    //         project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
-                    //         AssetInfo::Native("ujuno".to_string()),
-                    //         Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                    //     ),
-                    //     // send wynd to the staking contract and stake the tokens
-                    //     // TODO: lock down the sending and the delegation further
-                    //     wyndao_staking_grant(base, project_addresses.destination_projects.wynd.cw20.clone()),
-                    // ]
-                    // .into_iter()
-                    // .flatten()
-                    // .collect(),
-                    // MigalooDestinationProject::RacoonBet { .. } => vec![GrantRequirement::default_contract_exec_auth(
-                    //     base,
-                    //     project_addresses.destination_projects.racoon_bet.game.clone(),
-                    //     vec!["place_bet"],
-                    //     Some("ujuno"),
-                    // )],
-                    // MigalooDestinationProject::TokenSwap {
-                    //     target_denom: _target_denom,
-                    // } => wynd_multihop_swap_grant(
-                    //     base,
-                    //     project_addresses.destination_projects.wynd.multihop.clone(),
-                    //     AssetInfo::Native("ujuno".to_string()),
-                    //     None,
-                    // ),
+            //     vec![
+            //         // general terraswap multihop swap
+            //         terraswap_multihop_swap_grant(
+            //             base.clone(),
+            //             project_addresses
+            //                 .destination_projects
+            //                 .white_whale
+            //                 .terraswap_multihop_router
+            //                 .clone(),
+            //             "ujuno",
+            //         ),
+            //         // bonding to the market
+            //         vec![GrantRequirement::default_contract_exec_auth(
+            //             base,
+            //             project_addresses.destination_projects.white_whale.market.clone(),
+            //             vec!["bond"],
+            //             Some(&denom),
+            //         )],
+            //     ]
+            //     .into_iter()
+            //     .flatten()
+            //     .collect()
+            // }
+            // MigalooDestinationProject::WyndStaking {
+            //     bonding_period: _bonding_period,
+            // } => vec![
+            //     // pair swap for JUNO to WYND
+            //     wynd_pool_swap_grant(
+            //         base.clone(),
+            //         project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
+            //         AssetInfo::Native("ujuno".to_string()),
+            //         Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+            //     ),
+            //     // send wynd to the staking contract and stake the tokens
+            //     // TODO: lock down the sending and the delegation further
+            //     wyndao_staking_grant(base, project_addresses.destination_projects.wynd.cw20.clone()),
+            // ]
+            // .into_iter()
+            // .flatten()
+            // .collect(),
+            // MigalooDestinationProject::RacoonBet { .. } => vec![GrantRequirement::default_contract_exec_auth(
+            //     base,
+            //     project_addresses.destination_projects.racoon_bet.game.clone(),
+            //     vec!["place_bet"],
+            //     Some("ujuno"),
+            // )],
+            // MigalooDestinationProject::TokenSwap {
+            //     target_denom: _target_denom,
+            // } => wynd_multihop_swap_grant(
+            //     base,
+            //     project_addresses.destination_projects.wynd.multihop.clone(),
+            //     AssetInfo::Native("ujuno".to_string()),
+            //     None,
+            // ),
 
-                    // MigalooDestinationProject::WyndLp { .. } => vec![
-                    //     // // general multihop swap
-                    //     // GrantRequirement::GrantSpec {
-                    //     //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
-                    //     //         contract_addr: project_addresses.destination_projects.wynd.multihop.clone(),
-                    //     //         limit: ContractExecutionAuthorizationLimit::single_fund_limit("ujuno"),
-                    //     //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
-                    //     //             keys: vec!["execute_swap_operations".to_string()],
-                    //     //         },
-                    //     //     }]),
-                    //     //     granter: granter.clone(),
-                    //     //     grantee: grantee.clone(),
-                    //     //     expiration,
-                    //     // },
-                    //     // // bonding to the pool
-                    //     // GrantRequirement::GrantSpec {
-                    //     //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
-                    //     //         contract_addr: Addr::unchecked(contract_address),
-                    //     //         limit: ContractExecutionAuthorizationLimit::default(),
-                    //     //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
-                    //     //             // might need a bond key as well
-                    //     //             keys: vec!["send".to_string()],
-                    //     //         },
-                    //     //     }]),
-                    //     //     granter: granter.clone(),
-                    //     //     grantee: grantee.clone(),
-                    //     //     expiration,
-                    //     // },
-                    // ],
-                    // MigalooDestinationProject::SparkIbcCampaign { fund: _fund } => vec![
-                    //     wynd_multihop_swap_grant(
-                    //         base.clone(),
-                    //         project_addresses.destination_projects.wynd.multihop.clone(),
-                    //         AssetInfo::Native("ujuno".to_string()),
-                    //         Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
-                    //     ),
-                    //     // funding campaign
-                    //     vec![GrantRequirement::default_contract_exec_auth(
-                    //         base,
-                    //         project_addresses.destination_projects.spark_ibc.fund.clone(),
-                    //         vec!["fund"],
-                    //         Some(&project_addresses.usdc.to_string()),
-                    //     )],
-                    // ]
-                    // .into_iter()
-                    // .flatten()
-                    // .collect(),
-                }
-            });
+            // MigalooDestinationProject::WyndLp { .. } => vec![
+            //     // // general multihop swap
+            //     // GrantRequirement::GrantSpec {
+            //     //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
+            //     //         contract_addr: project_addresses.destination_projects.wynd.multihop.clone(),
+            //     //         limit: ContractExecutionAuthorizationLimit::single_fund_limit("ujuno"),
+            //     //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
+            //     //             keys: vec!["execute_swap_operations".to_string()],
+            //     //         },
+            //     //     }]),
+            //     //     granter: granter.clone(),
+            //     //     grantee: grantee.clone(),
+            //     //     expiration,
+            //     // },
+            //     // // bonding to the pool
+            //     // GrantRequirement::GrantSpec {
+            //     //     grant_type: AuthorizationType::ContractExecutionAuthorization(vec![ContractExecutionSetting {
+            //     //         contract_addr: Addr::unchecked(contract_address),
+            //     //         limit: ContractExecutionAuthorizationLimit::default(),
+            //     //         filter: ContractExecutionAuthorizationFilter::AcceptedMessageKeysFilter {
+            //     //             // might need a bond key as well
+            //     //             keys: vec!["send".to_string()],
+            //     //         },
+            //     //     }]),
+            //     //     granter: granter.clone(),
+            //     //     grantee: grantee.clone(),
+            //     //     expiration,
+            //     // },
+            // ],
+            // MigalooDestinationProject::SparkIbcCampaign { fund: _fund } => vec![
+            //     wynd_multihop_swap_grant(
+            //         base.clone(),
+            //         project_addresses.destination_projects.wynd.multihop.clone(),
+            //         AssetInfo::Native("ujuno".to_string()),
+            //         Some(ContractExecutionAuthorizationLimit::single_fund_limit("ujuno")),
+            //     ),
+            //     // funding campaign
+            //     vec![GrantRequirement::default_contract_exec_auth(
+            //         base,
+            //         project_addresses.destination_projects.spark_ibc.fund.clone(),
+            //         vec!["fund"],
+            //         Some(&project_addresses.usdc.to_string()),
+            //     )],
+            // ]
+            // .into_iter()
+            // .flatten()
+            // .collect(),
+        }
+    });
 
     Ok(dedupe_grant_reqs(grant_specs.collect()))
 }
@@ -15,6 +15,9 @@ pub enum ContractError {
     #[error("Juno Destinations Error: &{0}")]
     MigalooDestinationError(#[from] migaloo_destinations::errors::MigalooDestinationError),
 
+    #[error("Terraswap Helper Error: &{0}")]
+    TerraswapHelperError(#[from] terraswap_helpers::errors::TerraswapHelperError),
+
     #[error("Sail Destinations Error: &{0}")]
     SailDestinationError(#[from] sail_destinations::errors::SailDestinationError),
 
@@ -1,22 +1,36 @@
-use cosmwasm_std::{coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128,
+    Uint64,
+};
 use migaloo_destinations::{
     comp_prefs::{
-        DaoDaoStakingInfo, LsdMintAction, MUsdcAction, MigalooCompPrefs,
+        route_ask_denom, DaoDaoStakingInfo, LsdMintAction, MUsdcAction, MigalooCompPrefs,
         MigalooDestinationProject, MigalooVault,
     },
     dest_project_gen::{
         burn_whale_msgs, deposit_ginkou_usdc_msgs, ecosystem_stake_msgs, eris_amp_vault_msgs, eris_arb_vault_msgs,
         mint_or_buy_whale_lsd_msgs, query_ginkou_musdc_mint,
     },
+    errors::MigalooDestinationError,
 };
 use outpost_utils::{
-    comp_prefs::DestinationAction,
-    helpers::{calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, sum_coins, DestProjectMsgs},
-    msg_gen::{create_exec_msg},
+    asset::OutpostAssetInfo,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
+    helpers::{
+        below_min_compound, enforce_compound_frequency, is_authorized_compounder, min_out_after_slippage, prefs_sum_to_one,
+        sum_coins, CompoundPlan, DestProjectMsgs, SimulateCompoundResponse,
+    },
+    msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    queries::query_pending_rewards,
+    referrals::Referral,
+    stats::CompoundRun,
 };
-use std::iter;
 use terraswap_helpers::terraswap_swap::{
-    create_terraswap_pool_swap_msg_with_simulation,
+    create_terraswap_pool_swap_msg_with_simulation, create_terraswap_swap_msg_with_simulation, simulate_pool_swap,
+    TerraswapVenue,
 };
 use white_whale::pool_network::asset::{Asset, AssetInfo};
 
@@ -24,22 +38,28 @@ use withdraw_rewards_tax_grant::{client::WithdrawRewardsTaxClient, msg::Simulate
 
 use crate::{
     msg::ContractAddrs,
-    state::{ADMIN, AUTHORIZED_ADDRS},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS,
+        PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, REFERRALS, STATS,
+    },
     ContractError,
 };
 use sail_destinations::{
     dest_project_gen::{racoon_bet_msgs, spark_ibc_msgs, white_whale_satellite_msgs},
 };
-use universal_destinations::dest_project_gen::{daodao_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{active_multi_staking_msgs, active_native_staking_msg, daodao_staking_msg};
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     project_addresses: ContractAddrs,
     delegator_address: String,
     comp_prefs: MigalooCompPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(&comp_prefs)?;
@@ -47,61 +67,105 @@ pub fn compound(
     // check that the delegator address is valid
     let delegator: Addr = deps.api.addr_validate(&delegator_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &delegator, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &delegator,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
-    // get the denom of the staking token. this should be "ujuno"
-    let staking_denom = project_addresses.staking_denom.clone();
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
 
-    // prepare the withdraw rewards message and simulation from the authzpp grant
     let (
-        SimulateExecuteResponse {
-            // the rewards that the delegator is due to recieve
-            delegator_rewards,
+        CompoundPlan {
+            tax,
+            compounded,
+            claim_msgs,
+            dest_msgs,
+            fallbacks,
+            skipped,
             ..
         },
-        // withdraw delegator rewards wasm message
         withdraw_msg,
-    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, &delegator)
-        .simulate_with_contract_execute(deps.querier, tax_fee)?;
-
-    let total_rewards = sum_coins(&staking_denom, &delegator_rewards);
-
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
+        referral_amount,
+    ) = plan_compound(
+        deps.as_ref(),
         &project_addresses,
-        // &env.block,
-        // staking_denom,
         &delegator,
-        total_rewards.clone(),
         comp_prefs,
-        deps.as_ref(),
+        tax_fee,
+        referral.as_ref(),
+        &min_compound,
     )?;
 
-    let combined_msgs = all_msgs.iter().fold(DestProjectMsgs::default(), |mut acc, msg| {
-        acc.msgs.append(&mut msg.msgs.clone());
-        acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-        acc.events.append(&mut msg.events.clone());
-        acc
-    });
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = skipped {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &delegator, compounded.amount)?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(deps.storage, &delegator, &CompoundRun::new(env.block.time, tax, &fallbacks))?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
+
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &delegator,
+        &PipelineAsset::Native(project_addresses.staking_denom.clone()),
+        dest_msgs,
+    )?;
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
-        Event::new("amount_automated").add_attributes([total_rewards].iter().enumerate().map(|(i, coin)| Attribute {
+        Event::new("amount_automated").add_attributes([compounded].iter().enumerate().map(|(i, coin)| Attribute {
             key: format!("amount_{}", i),
             value: coin.to_string(),
         }));
 
-    // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    // the referrer is paid out of the withdrawn rewards before anything is compounded
+    let claim_msgs = match claim_msgs.is_empty() {
+        true => vec![withdraw_msg],
+        false => vec![withdraw_msg, create_exec_msg(&env.contract.address, claim_msgs)?],
+    };
+
+    // the final exec message that will be broadcast and contains all the sub msgs.
+    // the withdraw runs first so that the pipeline's balance snapshot includes the rewards
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        claim_msgs,
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         .add_attribute("compoundee", delegator_address)
-        .add_message(withdraw_msg)
         .add_attribute("subaction", "withdraw rewards")
         .add_event(amount_automated_event)
         // .add_attribute("amount_automated", to_json_binary(&[total_rewards])?.to_string())
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -129,6 +193,150 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    project_addresses: ContractAddrs,
+    user_address: String,
+    comp_prefs: MigalooCompPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<MigalooDestinationProject>, ContractError> {
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(&comp_prefs)?;
+
+    let delegator: Addr = deps.api.addr_validate(&user_address)?;
+
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
+
+    let (plan, ..) = plan_compound(
+        deps,
+        &project_addresses,
+        &delegator,
+        comp_prefs,
+        tax_fee,
+        referral.as_ref(),
+        &min_compound,
+    )?;
+
+    Ok(plan.simulate()?)
+}
+
+/// Works out everything a compound would do for the delegator without saving anything
+/// so that compounds and simulations go through the same steps.
+/// Also returns the msg that withdraws the delegator's rewards through the authzpp grant
+/// and the referrer's cut of the tax
+fn plan_compound(
+    deps: Deps,
+    project_addresses: &ContractAddrs,
+    delegator: &Addr,
+    comp_prefs: MigalooCompPrefs,
+    tax_fee: Option<Decimal>,
+    referral: Option<&Referral>,
+    min_compound: &Option<MinCompound>,
+) -> Result<(CompoundPlan<MigalooDestinationProject>, CosmosMsg, Coin), ContractError> {
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, delegator, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    // get the denom of the staking token. this should be "uwhale"
+    let staking_denom = project_addresses.staking_denom.clone();
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    comp_prefs.check_native_only(&staking_denom)?;
+
+    let total_rewards = query_pending_rewards(&deps.querier, delegator, staking_denom.clone())?.total;
+
+    // the authzpp grant only pays the take rate so the referrer's cut is taken off of its fee
+    // and sent separately. the user pays the same fee either way
+    let (tax_fee, referral_amount) = match referral {
+        Some(referral) => referral.split_fee(tax_fee, total_rewards.amount),
+        None => (tax_fee, Uint128::zero()),
+    };
+    let referral_amount = coin(referral_amount.u128(), &staking_denom);
+
+    // prepare the withdraw rewards message and simulation from the authzpp grant
+    let (
+        SimulateExecuteResponse {
+            // the rewards that the delegator is due to recieve
+            delegator_rewards,
+            ..
+        },
+        // withdraw delegator rewards wasm message
+        withdraw_msg,
+    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, delegator)
+        .simulate_with_contract_execute(deps.querier, Some(tax_fee))?;
+
+    // the authzpp grant takes its tax before the rewards reach the delegator
+    // so the tax is whatever is missing from their pending rewards once the referrer is paid
+    let compounded = coin(
+        sum_coins(&staking_denom, &delegator_rewards)
+            .amount
+            .saturating_sub(referral_amount.amount)
+            .u128(),
+        &staking_denom,
+    );
+    let tax = coin(total_rewards.amount.saturating_sub(compounded.amount).u128(), &staking_denom);
+
+    let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = below_min_compound(delegator, &compounded, min_compound, |rewards| {
+        match PRICE_SOURCES.may_load(deps.storage, rewards.denom.as_str())? {
+            // an admin configured price source takes precedence over simulating the swap to usdc
+            Some(price_source) => Ok(price_source.value(
+                &deps.querier,
+                &rewards.into(),
+                &OutpostAssetInfo::from_denom(&project_addresses.destination_projects.denoms.usdc),
+            )?),
+            None => Ok::<_, ContractError>(
+                simulate_pool_swap(
+                    &deps.querier,
+                    project_addresses.destination_projects.swap_routes.whale_usdc_pool.as_str(),
+                    &Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: rewards.denom.clone(),
+                        },
+                        amount: rewards.amount,
+                    },
+                )?
+                .return_amount,
+            ),
+        }
+    })? {
+        return Ok((
+            CompoundPlan::skipped(total_rewards, comp_prefs, skipped),
+            withdraw_msg.into(),
+            referral_amount,
+        ));
+    }
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (dest_msgs, fallbacks) = isolate_destinations(delegator, &compounded, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(project_addresses, delegator, amount, comp_prefs, deps)
+    })?;
+
+    Ok((
+        CompoundPlan {
+            total_rewards,
+            tax: vec![tax],
+            compounded,
+            comp_prefs,
+            claim_msgs: referral
+                .and_then(|referral| referral.payout_msg(delegator, &referral_amount))
+                .into_iter()
+                .collect(),
+            dest_msgs,
+            fallbacks,
+            skipped: None,
+        },
+        withdraw_msg.into(),
+        referral_amount,
+    ))
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -141,28 +349,39 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, MigalooDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 let compounding_asset = Asset {
                     info: AssetInfo::NativeToken {
-                        denom: "uwhale".to_string(),
+                        denom: total_rewards.denom.clone(),
                     },
                     amount: comp_token_amount,
                 };
 
                 match destination {
-                    MigalooDestinationProject::MigalooStaking { validator_address } => Ok(native_staking_msg(
+                    MigalooDestinationProject::MigalooStaking { validator_address } => Ok(active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         delegator_addr,
                         &cosmwasm_std::Coin {
-                            denom: "uwhale".to_string(),
+                            denom: total_rewards.denom.clone(),
+                            amount: comp_token_amount,
+                        },
+                    )?),
+                    MigalooDestinationProject::MigalooMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => Ok(active_multi_staking_msgs(
+                        &deps.querier,
+                        &validators,
+                        redelegate_inactive,
+                        delegator_addr,
+                        &cosmwasm_std::Coin {
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                     )?),
@@ -178,10 +397,24 @@ pub fn prefs_to_msgs(
                         let (swap_msg, swap_sim) = create_terraswap_pool_swap_msg_with_simulation(
                             &deps.querier,
                             delegator_addr,
-                            compounding_asset,
+                            compounding_asset.clone(),
                             &swap_pair_addr,
+                            max_slippage,
                         )?;
 
+                        // dao pools can be thin so the swap has to stay close to the pool's spot price
+                        let mut impact_events = vec![];
+                        if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                            impact_events.push(guard.check_against_spot(
+                                &deps.querier,
+                                &TerraswapVenue::Pool(swap_pair_addr.clone()),
+                                swap_pair_addr.as_str(),
+                                &compounding_asset.into(),
+                                &asset_info.clone().into(),
+                                swap_sim,
+                            )?);
+                        }
+
                         let mut stake_msgs = daodao_staking_msg(
                             dao_name.to_string(),
                             delegator_addr,
@@ -192,7 +425,8 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        stake_msgs.prepend_msgs(vec![swap_msg]);
+                        stake_msgs.run_after_swap(vec![swap_msg]);
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -213,7 +447,7 @@ pub fn prefs_to_msgs(
                         //     AllianceAsset::AmpLuna =>
                         //         project_addrs.destination_projects.swap_routes.whale.amp_luna
                         // };
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
 
                     MigalooDestinationProject::SparkIbcCampaign { fund } => {
@@ -222,6 +456,7 @@ pub fn prefs_to_msgs(
                             delegator_addr,
                             compounding_asset,
                             &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                            max_slippage,
                         )?;
 
                         let mut donate_msgs = spark_ibc_msgs(
@@ -231,7 +466,7 @@ pub fn prefs_to_msgs(
                             fund,
                         )?;
 
-                        donate_msgs.prepend_msgs(vec![swap_msg]);
+                        donate_msgs.run_after_swap(vec![swap_msg]);
 
                         Ok(donate_msgs)
                     }
@@ -241,7 +476,7 @@ pub fn prefs_to_msgs(
                         delegator_addr,
                         Some(&project_addrs.destination_projects.swap_routes.whale_usdc_pool),
                         cosmwasm_std::Coin {
-                            denom: "uwhale".to_string(),
+                            denom: total_rewards.denom.clone(),
                             amount: comp_token_amount,
                         },
                         game,
@@ -292,7 +527,7 @@ pub fn prefs_to_msgs(
 
                         // Ok(bond_msgs)
 
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
 
                     MigalooDestinationProject::GinkouDepositUSDC { and_then } => {
@@ -301,6 +536,7 @@ pub fn prefs_to_msgs(
                             delegator_addr,
                             compounding_asset,
                             &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                            max_slippage,
                         )?;
 
                         let mut deposit_msgs = deposit_ginkou_usdc_msgs(
@@ -379,6 +615,7 @@ pub fn prefs_to_msgs(
                                     delegator_addr,
                                     compounding_asset,
                                     &project_addrs.destination_projects.swap_routes.whale_usdc_pool,
+                                    max_slippage,
                                 )?;
 
                                 (
@@ -399,6 +636,7 @@ pub fn prefs_to_msgs(
                                     delegator_addr,
                                     compounding_asset,
                                     &project_addrs.destination_projects.swap_routes.whale_ash_pool,
+                                    max_slippage,
                                 )?;
 
                                 (
@@ -417,7 +655,7 @@ pub fn prefs_to_msgs(
 
                         let mut bond_msgs = eris_amp_vault_msgs(delegator_addr, bonding_asset, vault_addr)?;
 
-                        bond_msgs.prepend_msgs(vec![swap_msg]);
+                        bond_msgs.run_after_swap(vec![swap_msg]);
 
                         Ok(bond_msgs)
                     }
@@ -431,6 +669,7 @@ pub fn prefs_to_msgs(
                             &project_addrs.destination_projects.projects.whale_lsd,
                             &project_addrs.destination_projects.swap_routes,
                             &project_addrs.destination_projects.denoms,
+                            max_slippage,
                         )?;
 
                         match and_then {
@@ -452,13 +691,13 @@ pub fn prefs_to_msgs(
                     }
 
                     MigalooDestinationProject::TokenSwap { target_denom: _ } => {
-                        unimplemented!()
+                        Err(ContractError::NotImplemented {})
                     }
                     MigalooDestinationProject::SendTokens {
                         denom: _target_asset,
                         address: _to_address,
                     } => {
-                        unimplemented!();
+                        Err(ContractError::NotImplemented {})
                         // let (swap_msgs, sim) = create_wyndex_swap_msg_with_simulation(
                         //     &deps.querier,
                         //     delegator_addr,
@@ -484,8 +723,34 @@ pub fn prefs_to_msgs(
 
                         // Ok(send_msgs)
                     }
-                    MigalooDestinationProject::GinkouRepayLoan {} => unimplemented!(),
-                    MigalooDestinationProject::GinkouProvideLiquidity { asset: _, and_then: _ } => unimplemented!(),
+                    MigalooDestinationProject::GinkouRepayLoan {} => Err(ContractError::NotImplemented {}),
+                    MigalooDestinationProject::GinkouProvideLiquidity { asset: _, and_then: _ } => Err(ContractError::NotImplemented {}),
+
+                    MigalooDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let intermediate_denom =
+                            route_ask_denom(&convert_to).ok_or(MigalooDestinationError::InvalidCompositeRoute {})?;
+
+                        // the route always gets a minimum receive so the nested destinations can be sized off of it
+                        let swap_slippage = max_slippage.unwrap_or(Decimal::percent(2));
+                        let (swap_msgs, sim) = create_terraswap_swap_msg_with_simulation(
+                            &deps.querier,
+                            delegator_addr,
+                            comp_token_amount,
+                            convert_to,
+                            project_addrs.destination_projects.projects.terraswap_multihop_router.to_string(),
+                            Some(swap_slippage),
+                        )?;
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            delegator_addr,
+                            coin(min_out_after_slippage(sim, swap_slippage).u128(), intermediate_denom),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
 
                     MigalooDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
@@ -6,7 +6,9 @@ use cw_grant_spec::grants::{
 use migaloo_destinations::comp_prefs::MigalooDestinationProject;
 use migaloo_destinations::grants::furnace_grant;
 
-use universal_destinations::grants::native_staking_grant;
+use terraswap_helpers::terraswap_swap::terraswap_multihop_swap_grant;
+use outpost_utils::asset::OutpostAssetInfo;
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 use white_whale::pool_network::asset::{AssetInfo};
 use withdraw_rewards_tax_grant::msg::GrantSpecData;
 
@@ -62,7 +64,24 @@ impl Grantable for QueryMsg {
             current_timestamp,
         )?;
 
-        Ok([withdraw_tax_grants, gen_comp_pref_grants(grant_structure)?].concat())
+        // the referrer's cut of the tax is sent to them straight from the user's wallet.
+        // the grant spec only gets this far for referrers that the admin has registered
+        let referral_grants = comp_prefs
+            .referrer
+            .map(|referrer| {
+                native_send_token(
+                    GrantBase {
+                        granter: grant_structure.granter.clone(),
+                        grantee: grant_structure.grantee.clone(),
+                        expiration,
+                    },
+                    OutpostAssetInfo::from_denom(project_addresses.staking_denom.clone()),
+                    referrer,
+                )
+            })
+            .unwrap_or_default();
+
+        Ok([withdraw_tax_grants, referral_grants, gen_comp_pref_grants(grant_structure)?].concat())
     }
 
     fn query_revokes(
@@ -113,18 +132,28 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs = comp_prefs.relative.iter().flat_map(|action| -> Vec<GrantRequirement> {
+    let destinations = comp_prefs.nested_destinations(&project_addresses.staking_denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
         let base = GrantBase {
             granter: granter.clone(),
             grantee: grantee.clone(),
             expiration,
         };
 
-        match action.destination.clone() {
+        match destination.clone() {
             MigalooDestinationProject::Unallocated {} => vec![],
+            MigalooDestinationProject::Composite(_) => terraswap_multihop_swap_grant(
+                base,
+                project_addresses.destination_projects.projects.terraswap_multihop_router.clone(),
+                &source_denom,
+            ),
             MigalooDestinationProject::MigalooStaking { validator_address } => {
                 native_staking_grant(base, None, Some(vec![validator_address]))
             }
+            MigalooDestinationProject::MigalooMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
             MigalooDestinationProject::Furnace { and_then } => furnace_grant(
                 base,
                 project_addresses.destination_projects.projects.clone(),
@@ -1,6 +1,8 @@
-use std::iter;
+use std::str::FromStr;
 
-use cosmwasm_std::{coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Timestamp};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Timestamp, Uint128, Uint64,
+};
 use osmosis_destinations::{
     comp_prefs::{OsmosisCompPrefs, OsmosisDestinationProject, OsmosisLsd, OsmosisPoolSettings},
     dest_project_gen::{mint_milk_tia_msgs, stake_ion_msgs, stake_mbrn_msgs},
@@ -9,41 +11,53 @@ use osmosis_destinations::{
 use osmosis_helpers::{
     osmosis_lp::{gen_join_cl_pool_single_sided_msgs, gen_join_classic_pool_single_sided_msgs},
     osmosis_swap::{
-        estimate_token_out_min_amount, generate_known_to_known_swap_and_sim_msg, generate_known_to_unknown_route,
-        generate_known_to_unknown_swap_and_sim_msg, generate_swap, OsmosisRoutePools,
+        check_route_price_impact, estimate_token_out_min_amount, generate_known_to_known_route,
+        generate_known_to_known_swap_and_sim_msg, generate_known_to_unknown_route,
+        generate_known_to_unknown_swap_and_sim_msg, generate_swap, generate_swap_and_sim_msg, simulate_swap,
+        OsmosisRoutePools,
     },
 };
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
 use outpost_utils::{
-    comp_prefs::{DestinationAction, TakeRate},
+    asset::OutpostAssetInfo,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound, TakeRate},
+    fallback::{isolate_destinations, save_pending_fallbacks},
     helpers::{
-        calc_additional_tax_split, calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, DestProjectMsgs,
-        TaxSplitResult,
+        below_min_compound, calc_additional_tax_split, enforce_compound_frequency, is_authorized_compounder,
+        min_out_after_slippage, prefs_sum_to_one, simulate_compound_response, CompoundPlan, DestProjectMsgs,
+        SimulateCompoundResponse, TaxSplitResult,
     },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    stats::CompoundRun,
 };
 use sail_destinations::dest_project_gen::mint_eris_lsd_msgs;
 
 use universal_destinations::dest_project_gen::send_tokens_msgs;
-use white_whale::pool_network::asset::{Asset, AssetInfo};
 
 use crate::{
     msg::{ContractAddrs, DcaPrefs},
-    state::{ADMIN, AUTHORIZED_ADDRS, KNOWN_DENOMS, KNOWN_OSMO_POOLS, KNOWN_USDC_POOLS, PROJECT_ADDRS, TWAP_DURATION},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, KNOWN_DENOMS, KNOWN_OSMO_POOLS, KNOWN_USDC_POOLS,
+        LAST_COMPOUNDED, PENDING_FALLBACKS, PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS,
+        TWAP_DURATION, TWAP_FALLBACK,
+    },
     ContractError,
 };
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     _project_addresses: ContractAddrs,
     user_address: String,
     comp_prefs: &DcaPrefs,
     tax_fee: Option<Decimal>,
-    TakeRate {
-        max_tax_fee,
-        take_rate_addr,
-    }: TakeRate,
+    take_rate: TakeRate,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     let DcaPrefs {
         compound_token,
@@ -53,49 +67,94 @@ pub fn compound(
     // validate that the preference quantites sum to 1
     let _ = prefs_sum_to_one(compound_preferences)?;
 
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
     // check that the delegator address is valid
     let user_addr: Addr = deps.api.addr_validate(&user_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &user_addr,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
     let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
 
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, take_rate.max_tax_fee)?;
+
     // calculate the total amount of rewards that will be compounded
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
     let TaxSplitResult {
         remaining_rewards,
         tax_amount,
+        referral_amount,
         claim_and_tax_msgs: tax_store_msg,
     } = calc_additional_tax_split(
         compound_token,
-        tax_fee.unwrap_or(max_tax_fee),
+        tax_fee,
         user_address,
-        take_rate_addr.to_string(),
+        take_rate.take_rate_addr.to_string(),
+        referral.as_ref(),
     );
 
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
-        &project_addrs,
+    // amounts that aren't worth compounding are skipped rather than split into dust
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps.as_ref(), &project_addrs, amount, env.block.time)
+    })? {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &user_addr, compound_token.amount)?;
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    let comp_prefs = compound_preferences
+        .clone()
+        .with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (all_msgs, fallbacks) = isolate_destinations(&user_addr, &remaining_rewards, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(&project_addrs, &user_addr, amount, comp_prefs, deps.as_ref(), env.block.time)
+    })?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(
+        deps.storage,
         &user_addr,
-        remaining_rewards.clone(),
-        compound_preferences.clone(),
-        deps.as_ref(),
-        env.block.time,
+        &CompoundRun::new(env.block.time, vec![tax_amount.clone()], &fallbacks),
     )?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
 
-    let combined_msgs = all_msgs.iter().fold(
-        DestProjectMsgs {
-            msgs: tax_store_msg,
-            sub_msgs: vec![],
-            events: vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())],
-        },
-        |mut acc, msg| {
-            acc.msgs.append(&mut msg.msgs.clone());
-            acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-            acc.events.append(&mut msg.events.clone());
-            acc
-        },
-    );
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &user_addr,
+        &PipelineAsset::Native(remaining_rewards.denom.clone()),
+        all_msgs,
+    )?;
+
+    combined_msgs.prepend_msgs(tax_store_msg);
+    combined_msgs.prepend_events(vec![Event::new("dca_tax").add_attribute("amount", tax_amount.to_string())]);
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
         Event::new("amount_automated").add_attributes([remaining_rewards].iter().enumerate().map(|(i, coin)| Attribute {
@@ -104,12 +163,20 @@ pub fn compound(
         }));
 
     // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        vec![],
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         .add_event(amount_automated_event)
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -137,6 +204,116 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    current_timestamp: Timestamp,
+    user_address: String,
+    comp_prefs: &DcaPrefs,
+    tax_fee: Option<Decimal>,
+    take_rate: TakeRate,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<OsmosisDestinationProject>, ContractError> {
+    let DcaPrefs {
+        compound_token,
+        compound_preferences,
+    } = comp_prefs;
+
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(compound_preferences)?;
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    compound_preferences.check_native_only(&compound_token.denom)?;
+
+    let user_addr: Addr = deps.api.addr_validate(&user_address)?;
+
+    let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, &user_addr, tax_fee, take_rate.max_tax_fee)?;
+
+    let TaxSplitResult {
+        remaining_rewards,
+        tax_amount,
+        claim_and_tax_msgs,
+        ..
+    } = calc_additional_tax_split(
+        compound_token,
+        tax_fee,
+        user_address,
+        take_rate.take_rate_addr.to_string(),
+        REFERRALS.referral(deps.storage, referrer.as_ref())?.as_ref(),
+    );
+
+    if let Some(skipped) = below_min_compound(&user_addr, &remaining_rewards, &min_compound, |amount| {
+        usdc_value(deps, &project_addrs, amount, current_timestamp)
+    })? {
+        return Ok(CompoundPlan::skipped(compound_token.clone(), compound_preferences.clone(), skipped).simulate()?);
+    }
+
+    let dest_msgs = prefs_to_msgs(
+        &project_addrs,
+        &user_addr,
+        remaining_rewards.clone(),
+        compound_preferences.clone(),
+        deps,
+        current_timestamp,
+    )?;
+
+    Ok(simulate_compound_response(
+        compound_token.clone(),
+        vec![tax_amount],
+        remaining_rewards,
+        compound_preferences.clone(),
+        claim_and_tax_msgs,
+        dest_msgs,
+    )?)
+}
+
+/// What the amount would be worth in USDC going by its configured price source,
+/// or by a swap simulation along the known route to USDC if it doesn't have one
+fn usdc_value(
+    deps: Deps,
+    project_addrs: &ContractAddrs,
+    amount: &cosmwasm_std::Coin,
+    current_time: Timestamp,
+) -> Result<Uint128, ContractError> {
+    let usdc_denom = &project_addrs.destination_projects.denoms.usdc;
+    let usdc_route = || {
+        generate_known_to_known_route(
+            deps.storage,
+            OsmosisRoutePools {
+                stored_denoms: KNOWN_DENOMS,
+                stored_pools: MultipleStoredPools {
+                    osmo: KNOWN_OSMO_POOLS,
+                    usdc: KNOWN_USDC_POOLS,
+                },
+                pools: project_addrs.destination_projects.swap_routes.clone(),
+                denoms: project_addrs.destination_projects.denoms.clone(),
+            },
+            &amount.denom,
+            usdc_denom,
+        )
+    };
+
+    if let Some(price_source) = PRICE_SOURCES.may_load(deps.storage, amount.denom.as_str())? {
+        return Ok(price_source
+            .resolve(
+                usdc_route,
+                TWAP_DURATION.load(deps.storage)?,
+                current_time,
+                TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+            )?
+            .value(&deps.querier, &amount.into(), &OutpostAssetInfo::from_denom(usdc_denom))?);
+    }
+
+    let (simulation, _) = simulate_swap(&deps.querier, amount, usdc_route()?)?;
+
+    Ok(Uint128::from_str(&simulation.token_out_amount)?)
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -152,21 +329,13 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, OsmosisDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
-                let compounding_asset = Asset {
-                    info: AssetInfo::NativeToken {
-                        denom: dca_denom.clone(),
-                    },
-                    amount: comp_token_amount,
-                };
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
+                let compounding_asset = OutpostAssetInfo::from_denom(dca_denom.clone()).with_amount(comp_token_amount);
 
                 match destination {
                     OsmosisDestinationProject::TokenSwap { target_asset } => {
@@ -181,36 +350,45 @@ pub fn prefs_to_msgs(
                                 pools: project_addrs.destination_projects.swap_routes.clone(),
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
-                            "uosmo",
+                            &dca_denom,
                             target_asset.clone(),
                         )?;
 
+                        // target assets can exit through thin pools so the swap has to stay close to the route's twap
+                        let mut events = guard_price_impact(
+                            deps,
+                            &route,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            current_timestamp,
+                        )?;
+                        events.push(Event::new("token_swap").add_attribute("target_asset", target_asset.to_string()));
+
                         Ok(DestProjectMsgs {
                             msgs: vec![generate_swap(
-                                &coin(comp_token_amount.u128(), "uosmo"),
+                                &coin(comp_token_amount.u128(), &dca_denom),
                                 user_addr,
                                 route.clone(),
                                 estimate_token_out_min_amount(
                                     &deps.querier,
                                     &TWAP_DURATION.load(deps.storage)?,
                                     &route,
-                                    "uosmo".to_string(),
+                                    dca_denom.clone(),
                                     comp_token_amount,
                                     current_timestamp,
+                                    max_slippage,
+                                    &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                                 )?,
                             )],
                             sub_msgs: vec![],
-                            events: vec![Event::new("token_swap").add_attribute("target_asset", target_asset.to_string())],
+                            events,
                         })
                     }
                     OsmosisDestinationProject::SendTokens {
                         address: to_address,
                         target_asset,
                     } => {
-                        let (sim, swap_msgs) = generate_known_to_unknown_swap_and_sim_msg(
-                            &deps.querier,
+                        let route = generate_known_to_unknown_route(
                             deps.storage,
-                            &TWAP_DURATION.load(deps.storage)?,
                             OsmosisRoutePools {
                                 stored_denoms: KNOWN_DENOMS,
                                 stored_pools: MultipleStoredPools {
@@ -220,25 +398,38 @@ pub fn prefs_to_msgs(
                                 pools: project_addrs.destination_projects.swap_routes.clone(),
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
-                            user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &dca_denom,
                             target_asset.clone(),
-                            current_timestamp.clone(),
+                        )?;
+                        // target assets can exit through thin pools so the swap has to stay close to the route's twap
+                        let impact_events = guard_price_impact(
+                            deps,
+                            &route,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            current_timestamp,
+                        )?;
+
+                        let (sim, swap_msgs) = generate_swap_and_sim_msg(
+                            &deps.querier,
+                            &TWAP_DURATION.load(deps.storage)?,
+                            user_addr,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            target_asset.denom.clone(),
+                            route,
+                            current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // after the swap we can send the estimated funds to the target address
                         let mut send_msgs = send_tokens_msgs(
                             user_addr,
                             &deps.api.addr_validate(&to_address)?,
-                            Asset {
-                                info: AssetInfo::NativeToken {
-                                    denom: target_asset.denom,
-                                },
-                                amount: sim,
-                            },
+                            OutpostAssetInfo::from_denom(target_asset.denom).with_amount(sim),
                         )?;
 
-                        send_msgs.prepend_msgs(swap_msgs);
+                        send_msgs.run_after_swap(swap_msgs);
+                        send_msgs.append_events(impact_events);
 
                         Ok(send_msgs)
                     }
@@ -266,9 +457,11 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.tia,
                             current_timestamp.clone(),
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // Mint milkTIA
@@ -278,7 +471,7 @@ pub fn prefs_to_msgs(
                             coin(est_tia.u128(), project_addrs.destination_projects.denoms.tia.clone()),
                         )?;
 
-                        mint_milk_tia.prepend_msgs(swap_to_tia_msgs);
+                        mint_milk_tia.run_after_swap(swap_to_tia_msgs);
 
                         Ok(mint_milk_tia)
                     }
@@ -298,15 +491,17 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.ion,
                             current_timestamp.clone(),
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         let mut staking_msg =
                             stake_ion_msgs(user_addr, &project_addrs.destination_projects.projects.ion_dao, est_ion)?;
 
-                        staking_msg.prepend_msgs(swap_to_ion_msgs);
+                        staking_msg.run_after_swap(swap_to_ion_msgs);
 
                         Ok(staking_msg)
                     }
@@ -326,9 +521,11 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.mbrn,
                             current_timestamp.clone(),
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // can't stake less than 1 MBRN
@@ -348,7 +545,7 @@ pub fn prefs_to_msgs(
                             coin(est_mbrn.u128(), project_addrs.destination_projects.denoms.mbrn.clone()),
                         )?;
 
-                        staking_msg.prepend_msgs(swap_to_mbrn_msgs);
+                        staking_msg.run_after_swap(swap_to_mbrn_msgs);
 
                         Ok(staking_msg)
                     }
@@ -371,9 +568,11 @@ pub fn prefs_to_msgs(
                         },
                         user_addr,
                         pool_id,
-                        &coin(comp_token_amount.u128(), "uosmo"),
+                        &coin(comp_token_amount.u128(), &dca_denom),
                         bond_tokens,
                         current_timestamp.clone(),
+                        max_slippage,
+                        &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                     )?),
                     // Entering a CL pool
                     OsmosisDestinationProject::OsmosisLiquidityPool {
@@ -390,15 +589,58 @@ pub fn prefs_to_msgs(
                         &TWAP_DURATION.load(deps.storage)?,
                         user_addr,
                         pool_id,
-                        &coin(comp_token_amount.u128(), "uosmo"),
+                        &coin(comp_token_amount.u128(), &dca_denom),
                         lower_tick,
                         upper_tick,
                         token_min_amount_0,
                         token_min_amount_1,
                         current_timestamp.clone(),
+                        max_slippage,
+                        &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                     )?),
+                    OsmosisDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let intermediate_denom = convert_to.denom.clone();
+                        let (sim, swap_msgs) = generate_known_to_unknown_swap_and_sim_msg(
+                            &deps.querier,
+                            deps.storage,
+                            &TWAP_DURATION.load(deps.storage)?,
+                            OsmosisRoutePools {
+                                stored_denoms: KNOWN_DENOMS,
+                                stored_pools: MultipleStoredPools {
+                                    osmo: KNOWN_OSMO_POOLS,
+                                    usdc: KNOWN_USDC_POOLS,
+                                },
+                                pools: project_addrs.destination_projects.swap_routes.clone(),
+                                denoms: project_addrs.destination_projects.denoms.clone(),
+                            },
+                            user_addr,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            convert_to,
+                            current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+                        )?;
+
+                        // the nested destinations only get what the swap is sure to return
+                        let intermediate_amount = if swap_msgs.is_empty() {
+                            sim
+                        } else {
+                            min_out_after_slippage(sim, max_slippage.unwrap_or(Decimal::percent(1)))
+                        };
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            user_addr,
+                            coin(intermediate_amount.u128(), intermediate_denom),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                            current_timestamp,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
                     OsmosisDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
-                    _ => unimplemented!(),
+                    _ => Err(ContractError::NotImplemented {}),
                 }
             },
         )
@@ -406,3 +648,26 @@ pub fn prefs_to_msgs(
 
     Ok(compounding_msgs)
 }
+
+/// Checks a swap along the route against the price impact guard when the admin has set one
+fn guard_price_impact(
+    deps: Deps,
+    route: &[SwapAmountInRoute],
+    offer: &cosmwasm_std::Coin,
+    current_timestamp: Timestamp,
+) -> Result<Vec<Event>, ContractError> {
+    match PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+        Some(guard) => Ok(check_route_price_impact(
+            &deps.querier,
+            &guard,
+            route,
+            offer,
+            TWAP_DURATION.load(deps.storage)?,
+            current_timestamp,
+            TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+        )?
+        .into_iter()
+        .collect()),
+        None => Ok(vec![]),
+    }
+}
@@ -2,18 +2,19 @@ use crate::msg::{CompPrefsWithAddresses, DcaPrefs, OsmodcaCompoundPrefs, QueryMs
 use crate::{
     msg::{AuthorizedCompoundersResponse, VersionResponse},
     state::{ADMIN, AUTHORIZED_ADDRS},
+    ContractError,
 };
-use cosmwasm_std::{Addr, Coin, Decimal, Deps, StdResult, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Coin, Decimal, Deps, StdError, StdResult, Timestamp, Uint128};
 use cw_grant_spec::grantable_trait::{dedupe_grant_reqs, GrantStructure, Grantable};
 use cw_grant_spec::grants::{AuthorizationType, GrantBase, GrantRequirement, RevokeRequirement};
 use osmosis_destinations::comp_prefs::{OsmosisDestinationProject, OsmosisLsd, OsmosisPoolSettings};
 use osmosis_destinations::grants::{membrane_stake_grant, mint_milk_tia_grant, stake_ion_grants};
 use osmosis_helpers::osmosis_lp::{join_cl_pool_grants, join_classic_pool_grants};
 use osmosis_helpers::osmosis_swap::osmosis_swap_grants;
+use outpost_utils::asset::OutpostAssetInfo;
 use outpost_utils::comp_prefs::TakeRate;
 use sail_destinations::grants::eris_lsd_grant;
-use universal_destinations::grants::{native_send_token, native_staking_grant};
-use white_whale::pool_network::asset::AssetInfo;
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 
 pub fn query_version() -> VersionResponse {
     VersionResponse {
@@ -44,12 +45,15 @@ impl Grantable for QueryMsg {
             grant_data:
                 CompPrefsWithAddresses {
                     comp_frequency,
-                    comp_prefs: OsmodcaCompoundPrefs { comp_prefs, .. },
+                    comp_prefs: OsmodcaCompoundPrefs {
+                        comp_prefs, referrer, ..
+                    },
                     project_addresses,
                     take_rate:
                         TakeRate {
                             max_tax_fee,
                             take_rate_addr,
+                            ..
                         },
                 },
             ..
@@ -71,7 +75,14 @@ impl Grantable for QueryMsg {
                         })
                         .collect(),
                 ),
-                allow_list: Some(vec![take_rate_addr.clone()]),
+                // the referrer's share of the tax is sent to them directly.
+                // the grant spec only gets this far for referrers that the admin has registered
+                allow_list: Some(
+                    [Some(take_rate_addr.clone()), referrer.map(Addr::unchecked)]
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                ),
             },
             granter,
             grantee: outpost_contract,
@@ -128,87 +139,81 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs =
-        comp_prefs
-            .first()
-            .unwrap()
-            .compound_preferences
-            .relative
-            .iter()
-            .flat_map(|action| -> Vec<GrantRequirement> {
-                let base = GrantBase {
-                    granter: granter.clone(),
-                    grantee: grantee.clone(),
-                    expiration,
-                };
-
-                match action.destination.clone() {
-                    OsmosisDestinationProject::Unallocated {} => vec![],
-                    OsmosisDestinationProject::OsmosisStaking { validator_address } => {
-                        native_staking_grant(base, None, Some(vec![validator_address]))
-                    }
-                    OsmosisDestinationProject::TokenSwap { target_asset: _ } => osmosis_swap_grants(base),
-                    OsmosisDestinationProject::SendTokens { address, target_asset } => vec![
-                        osmosis_swap_grants(base.clone()),
-                        native_send_token(
-                            base,
-                            AssetInfo::NativeToken {
-                                denom: target_asset.denom,
-                            },
-                            address,
-                        ),
-                    ]
-                    .concat(),
-
-                    OsmosisDestinationProject::MintLsd { lsd: OsmosisLsd::Eris } => eris_lsd_grant(
-                        base,
-                        project_addresses.destination_projects.projects.eris_amposmo_bonding.clone(),
-                        AssetInfo::NativeToken {
-                            denom: "uosmo".to_string(),
-                        },
-                    ),
-                    OsmosisDestinationProject::MintLsd {
-                        lsd: OsmosisLsd::MilkyWay,
-                    } => vec![
-                        osmosis_swap_grants(base.clone()),
-                        mint_milk_tia_grant(
-                            base,
-                            project_addresses.destination_projects.projects.milky_way_bonding.clone(),
-                            &project_addresses.destination_projects.denoms.tia,
-                        ),
-                    ]
-                    .concat(),
-
-                    OsmosisDestinationProject::IonStaking {} => vec![
-                        osmosis_swap_grants(base.clone()),
-                        stake_ion_grants(
-                            base,
-                            project_addresses.destination_projects.projects.ion_dao.clone(),
-                            &project_addresses.destination_projects.denoms.ion,
-                        ),
-                    ]
-                    .concat(),
-
-                    OsmosisDestinationProject::MembraneStake {} => vec![
-                        osmosis_swap_grants(base.clone()),
-                        membrane_stake_grant(
-                            base.clone(),
-                            project_addresses.destination_projects.projects.membrane.staking.clone(),
-                            &project_addresses.destination_projects.denoms.mbrn,
-                        ),
-                    ]
-                    .concat(),
-
-                    OsmosisDestinationProject::OsmosisLiquidityPool {
-                        pool_id: _,
-                        pool_settings: OsmosisPoolSettings::Standard { bond_tokens },
-                    } => join_classic_pool_grants(base, bond_tokens),
-                    OsmosisDestinationProject::OsmosisLiquidityPool {
-                        pool_id: _,
-                        pool_settings: OsmosisPoolSettings::ConcentratedLiquidity { .. },
-                    } => join_cl_pool_grants(base),
-                }
-            });
+    let prefs = comp_prefs
+        .first()
+        .ok_or_else(|| StdError::generic_err(ContractError::NoDCACompoundPrefs.to_string()))?;
+    let destinations = prefs.compound_preferences.nested_destinations(&prefs.compound_token.denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
+        let base = GrantBase {
+            granter: granter.clone(),
+            grantee: grantee.clone(),
+            expiration,
+        };
+
+        match destination.clone() {
+            OsmosisDestinationProject::Unallocated {} => vec![],
+            OsmosisDestinationProject::Composite(_) => osmosis_swap_grants(base),
+            OsmosisDestinationProject::OsmosisStaking { validator_address } => {
+                native_staking_grant(base, None, Some(vec![validator_address]))
+            }
+            OsmosisDestinationProject::OsmosisMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
+            OsmosisDestinationProject::TokenSwap { target_asset: _ } => osmosis_swap_grants(base),
+            OsmosisDestinationProject::SendTokens { address, target_asset } => vec![
+                osmosis_swap_grants(base.clone()),
+                native_send_token(base, OutpostAssetInfo::from_denom(target_asset.denom), address),
+            ]
+            .concat(),
+
+            OsmosisDestinationProject::MintLsd { lsd: OsmosisLsd::Eris } => eris_lsd_grant(
+                base,
+                project_addresses.destination_projects.projects.eris_amposmo_bonding.clone(),
+                OutpostAssetInfo::from_denom(source_denom.clone()),
+            ),
+            OsmosisDestinationProject::MintLsd {
+                lsd: OsmosisLsd::MilkyWay,
+            } => vec![
+                osmosis_swap_grants(base.clone()),
+                mint_milk_tia_grant(
+                    base,
+                    project_addresses.destination_projects.projects.milky_way_bonding.clone(),
+                    &project_addresses.destination_projects.denoms.tia,
+                ),
+            ]
+            .concat(),
+
+            OsmosisDestinationProject::IonStaking {} => vec![
+                osmosis_swap_grants(base.clone()),
+                stake_ion_grants(
+                    base,
+                    project_addresses.destination_projects.projects.ion_dao.clone(),
+                    &project_addresses.destination_projects.denoms.ion,
+                ),
+            ]
+            .concat(),
+
+            OsmosisDestinationProject::MembraneStake {} => vec![
+                osmosis_swap_grants(base.clone()),
+                membrane_stake_grant(
+                    base.clone(),
+                    project_addresses.destination_projects.projects.membrane.staking.clone(),
+                    &project_addresses.destination_projects.denoms.mbrn,
+                ),
+            ]
+            .concat(),
+
+            OsmosisDestinationProject::OsmosisLiquidityPool {
+                pool_id: _,
+                pool_settings: OsmosisPoolSettings::Standard { bond_tokens },
+            } => join_classic_pool_grants(base, bond_tokens),
+            OsmosisDestinationProject::OsmosisLiquidityPool {
+                pool_id: _,
+                pool_settings: OsmosisPoolSettings::ConcentratedLiquidity { .. },
+            } => join_cl_pool_grants(base),
+        }
+    });
 
     Ok(dedupe_grant_reqs(grant_specs.collect()))
 }
@@ -1,17 +1,34 @@
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, Timestamp};
 use cw_storage_plus::{Item, Map};
+use osmosis_destinations::comp_prefs::OsmosisDestinationProject;
 use osmosis_destinations::pools::{StoredDenoms, StoredPools};
-use outpost_utils::comp_prefs::TakeRate;
+use osmosis_helpers::osmosis_swap::{OsmosisPriceSource, TwapFallback};
+use outpost_utils::{
+    batch::BatchProgress, comp_prefs::TakeRate, fallback::PendingFallback, fee_schedule::FeeStore, pipeline::Pipeline,
+    price_source::PriceImpactGuard, referrals::ReferralStore, stats::StatsStore,
+};
 
 use crate::msg::ContractAddrs;
 
 pub const ADMIN: Item<Addr> = Item::new("admin");
 pub const AUTHORIZED_ADDRS: Item<Vec<Addr>> = Item::new("allowed_addrs");
 pub const PROJECT_ADDRS: Item<ContractAddrs> = Item::new("project_addrs");
+pub const COMP_PREFS_ADDR: Item<Addr> = Item::new("comp_prefs_addr");
+pub const LAST_COMPOUNDED: Map<&Addr, Timestamp> = Map::new("last_compounded");
+pub const PIPELINE: Item<Pipeline> = Item::new("pipeline");
+pub const DEFAULT_FALLBACK: Item<OsmosisDestinationProject> = Item::new("default_fallback");
+pub const PENDING_FALLBACKS: Map<u64, PendingFallback> = Map::new("pending_fallbacks");
 pub const TAKE_RATE: Item<TakeRate> = Item::new("take_rate");
+pub const REFERRALS: ReferralStore = ReferralStore::new("referrers", "referral_earnings");
+pub const STATS: StatsStore = StatsStore::new("user_stats", "outpost_stats");
+pub const FEES: FeeStore = FeeStore::new("fee_schedule", "fee_overrides", "agreed_fees", "fee_volume");
 
 pub const KNOWN_OSMO_POOLS: StoredPools = Map::new("known_osmo_pools");
 pub const KNOWN_USDC_POOLS: StoredPools = Map::new("known_usdc_pools");
 pub const KNOWN_DENOMS: StoredDenoms = Map::new("known_denoms");
 
 pub const TWAP_DURATION: Item<u64> = Item::new("twap_duration");
+pub const TWAP_FALLBACK: Item<TwapFallback> = Item::new("twap_fallback");
+pub const PRICE_SOURCES: Map<&str, OsmosisPriceSource> = Map::new("price_sources");
+pub const PRICE_IMPACT_GUARD: Item<PriceImpactGuard> = Item::new("price_impact_guard");
+pub const BATCH_COMPOUND: Item<BatchProgress> = Item::new("batch_compound");
@@ -1,6 +1,9 @@
-use std::iter;
+use std::str::FromStr;
 
-use cosmwasm_std::{coin, Addr, Attribute, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Timestamp};
+use cosmwasm_std::{
+    coin, Addr, Attribute, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Timestamp,
+    Uint128, Uint64,
+};
 use osmosis_destinations::{
     comp_prefs::{OsmosisCompPrefs, OsmosisDestinationProject, OsmosisLsd, OsmosisPoolSettings},
     dest_project_gen::{mint_milk_tia_msgs, stake_ion_msgs, stake_mbrn_msgs},
@@ -9,40 +12,55 @@ use osmosis_destinations::{
 use osmosis_helpers::{
     osmosis_lp::{gen_join_cl_pool_single_sided_msgs, gen_join_classic_pool_single_sided_msgs},
     osmosis_swap::{
-        estimate_token_out_min_amount, generate_known_to_known_swap_and_sim_msg, generate_known_to_unknown_route,
-        generate_known_to_unknown_swap_and_sim_msg, generate_swap, OsmosisRoutePools,
+        check_route_price_impact, estimate_token_out_min_amount, generate_known_to_known_route,
+        generate_known_to_known_swap_and_sim_msg, generate_known_to_unknown_route,
+        generate_known_to_unknown_swap_and_sim_msg, generate_swap, generate_swap_and_sim_msg, simulate_swap,
+        OsmosisRoutePools,
     },
 };
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
 
 use outpost_utils::{
-    comp_prefs::{DestinationAction, TakeRate},
-    helpers::{calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, sum_coins, DestProjectMsgs},
+    asset::OutpostAssetInfo,
+    comp_prefs::{CompositeDestination, DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
+    helpers::{
+        below_min_compound, enforce_compound_frequency, is_authorized_compounder, min_out_after_slippage, prefs_sum_to_one,
+        sum_coins, CompoundPlan, DestProjectMsgs, SimulateCompoundResponse,
+    },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    queries::query_pending_rewards,
+    referrals::Referral,
+    stats::CompoundRun,
 };
 use sail_destinations::dest_project_gen::mint_eris_lsd_msgs;
 
-use universal_destinations::dest_project_gen::{native_staking_msg, send_tokens_msgs};
-use white_whale::pool_network::asset::{Asset, AssetInfo};
+use universal_destinations::dest_project_gen::{active_multi_staking_msgs, active_native_staking_msg, send_tokens_msgs};
 use withdraw_rewards_tax_grant::{client::WithdrawRewardsTaxClient, msg::SimulateExecuteResponse};
 
 use crate::{
     msg::ContractAddrs,
-    state::{ADMIN, AUTHORIZED_ADDRS, KNOWN_DENOMS, KNOWN_OSMO_POOLS, KNOWN_USDC_POOLS, PROJECT_ADDRS, TWAP_DURATION},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, KNOWN_DENOMS, KNOWN_OSMO_POOLS, KNOWN_USDC_POOLS,
+        LAST_COMPOUNDED, PENDING_FALLBACKS, PIPELINE, PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS,
+        TAKE_RATE, TWAP_DURATION, TWAP_FALLBACK,
+    },
     ContractError,
 };
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     project_addresses: ContractAddrs,
     user_address: String,
     comp_prefs: OsmosisCompPrefs,
     fee_to_charge: Option<Decimal>,
-    TakeRate {
-        max_tax_fee,
-        take_rate_addr,
-    }: TakeRate,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     // validate that the preference quantites sum to 1
     let _ = prefs_sum_to_one(&comp_prefs)?;
@@ -50,61 +68,105 @@ pub fn compound(
     // check that the delegator address is valid
     let user_addr: Addr = deps.api.addr_validate(&user_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &user_addr,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
-    // get the denom of the staking token. this should be "uosmo"
-    let staking_denom = project_addresses.staking_denom.clone();
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
 
-    // prepare the withdraw rewards message and simulation from the authzpp grant
     let (
-        SimulateExecuteResponse {
-            // the rewards that the delegator is due to recieve
-            delegator_rewards,
+        CompoundPlan {
+            tax,
+            compounded,
+            claim_msgs,
+            dest_msgs,
+            fallbacks,
+            skipped,
             ..
         },
-        // withdraw delegator rewards wasm message
         withdraw_msg,
-    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, &user_addr)
-        .simulate_with_contract_execute(deps.querier, fee_to_charge)?;
-
-    let total_rewards = sum_coins(&staking_denom, &delegator_rewards);
-
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(
+        referral_amount,
+    ) = plan_compound(
+        deps.as_ref(),
+        env.block.time,
         &project_addresses,
-        // &env.block,
-        // staking_denom,
         &user_addr,
-        total_rewards.clone(),
         comp_prefs,
-        deps.as_ref(),
-        env.block.time,
+        fee_to_charge,
+        referral.as_ref(),
+        &min_compound,
     )?;
 
-    let combined_msgs = all_msgs.iter().fold(DestProjectMsgs::default(), |mut acc, msg| {
-        acc.msgs.append(&mut msg.msgs.clone());
-        acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-        acc.events.append(&mut msg.events.clone());
-        acc
-    });
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = skipped {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &user_addr, compounded.amount)?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(deps.storage, &user_addr, &CompoundRun::new(env.block.time, tax, &fallbacks))?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
+
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
+        &user_addr,
+        &PipelineAsset::Native(project_addresses.staking_denom.clone()),
+        dest_msgs,
+    )?;
+    combined_msgs.append_events(referral_events);
 
     let amount_automated_event =
-        Event::new("amount_automated").add_attributes([total_rewards].iter().enumerate().map(|(i, coin)| Attribute {
+        Event::new("amount_automated").add_attributes([compounded].iter().enumerate().map(|(i, coin)| Attribute {
             key: format!("amount_{}", i),
             value: coin.to_string(),
         }));
 
-    // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    // the referrer is paid out of the withdrawn rewards before anything is compounded
+    let claim_msgs = match claim_msgs.is_empty() {
+        true => vec![withdraw_msg],
+        false => vec![withdraw_msg, create_exec_msg(&env.contract.address, claim_msgs)?],
+    };
+
+    // the final exec message that will be broadcast and contains all the sub msgs.
+    // the withdraw runs first so that the pipeline's balance snapshot includes the rewards
+    let exec_msgs = pipeline_start_submsgs(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &PIPELINE,
+        claim_msgs,
+        combined_msgs.msgs,
+        pipelined,
+    )?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
-        .add_message(withdraw_msg)
         .add_attribute("subaction", "withdraw rewards")
         .add_event(amount_automated_event)
         // .add_attribute("amount_automated", to_json_binary(&[total_rewards])?.to_string())
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -132,6 +194,171 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    current_timestamp: Timestamp,
+    project_addresses: ContractAddrs,
+    user_address: String,
+    comp_prefs: OsmosisCompPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<OsmosisDestinationProject>, ContractError> {
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(&comp_prefs)?;
+
+    let user_addr: Addr = deps.api.addr_validate(&user_address)?;
+
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
+
+    let (plan, ..) = plan_compound(
+        deps,
+        current_timestamp,
+        &project_addresses,
+        &user_addr,
+        comp_prefs,
+        tax_fee,
+        referral.as_ref(),
+        &min_compound,
+    )?;
+
+    Ok(plan.simulate()?)
+}
+
+/// Works out everything a compound would do for the user without saving anything
+/// so that compounds and simulations go through the same steps.
+/// Also returns the msg that withdraws the user's rewards through the authzpp grant
+/// and the referrer's cut of the tax
+fn plan_compound(
+    deps: Deps,
+    block_time: Timestamp,
+    project_addresses: &ContractAddrs,
+    user_addr: &Addr,
+    comp_prefs: OsmosisCompPrefs,
+    fee_to_charge: Option<Decimal>,
+    referral: Option<&Referral>,
+    min_compound: &Option<MinCompound>,
+) -> Result<(CompoundPlan<OsmosisDestinationProject>, CosmosMsg, Coin), ContractError> {
+    // get the denom of the staking token. this should be "uosmo"
+    let staking_denom = project_addresses.staking_denom.clone();
+
+    // composite destinations can't hand their intermediate asset to destinations that need the native token
+    comp_prefs.check_native_only(&staking_denom)?;
+
+    let total_rewards = query_pending_rewards(&deps.querier, user_addr, staking_denom.clone())?.total;
+
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let fee = FEES.fee(
+        deps.storage,
+        user_addr,
+        fee_to_charge,
+        TAKE_RATE.load(deps.storage)?.max_tax_fee,
+    )?;
+
+    // the authzpp grant only pays the take rate so the referrer's cut is taken off of its fee
+    // and sent separately. the user pays the same fee either way
+    let (fee_to_charge, referral_amount) = match referral {
+        Some(referral) => referral.split_fee(fee, total_rewards.amount),
+        None => (fee, Uint128::zero()),
+    };
+    let referral_amount = coin(referral_amount.u128(), &staking_denom);
+
+    // prepare the withdraw rewards message and simulation from the authzpp grant
+    let (
+        SimulateExecuteResponse {
+            // the rewards that the delegator is due to recieve
+            delegator_rewards,
+            ..
+        },
+        // withdraw delegator rewards wasm message
+        withdraw_msg,
+    ) = WithdrawRewardsTaxClient::new(&project_addresses.authzpp.withdraw_tax, user_addr)
+        .simulate_with_contract_execute(deps.querier, Some(fee_to_charge))?;
+
+    // the authzpp grant takes its tax before the rewards reach the user
+    // so the tax is whatever is missing from their pending rewards once the referrer is paid
+    let compounded = coin(
+        sum_coins(&staking_denom, &delegator_rewards)
+            .amount
+            .saturating_sub(referral_amount.amount)
+            .u128(),
+        &staking_denom,
+    );
+    let tax = coin(total_rewards.amount.saturating_sub(compounded.amount).u128(), &staking_denom);
+
+    let comp_prefs = comp_prefs.with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = below_min_compound(user_addr, &compounded, min_compound, |rewards| {
+        let usdc_denom = &project_addresses.destination_projects.denoms.usdc;
+        let usdc_route = || {
+            generate_known_to_known_route(
+                deps.storage,
+                OsmosisRoutePools {
+                    stored_denoms: KNOWN_DENOMS,
+                    stored_pools: MultipleStoredPools {
+                        osmo: KNOWN_OSMO_POOLS,
+                        usdc: KNOWN_USDC_POOLS,
+                    },
+                    pools: project_addresses.destination_projects.swap_routes.clone(),
+                    denoms: project_addresses.destination_projects.denoms.clone(),
+                },
+                &rewards.denom,
+                usdc_denom,
+            )
+        };
+
+        match PRICE_SOURCES.may_load(deps.storage, rewards.denom.as_str())? {
+            // an admin configured price source takes precedence over simulating the swap to usdc
+            Some(price_source) => Ok(price_source
+                .resolve(
+                    usdc_route,
+                    TWAP_DURATION.load(deps.storage)?,
+                    block_time,
+                    TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+                )?
+                .value(&deps.querier, &rewards.into(), &OutpostAssetInfo::from_denom(usdc_denom))?),
+            None => {
+                let (simulation, _) = simulate_swap(&deps.querier, rewards, usdc_route()?)?;
+
+                Ok::<_, ContractError>(Uint128::from_str(&simulation.token_out_amount)?)
+            }
+        }
+    })? {
+        return Ok((
+            CompoundPlan::skipped(total_rewards, comp_prefs, skipped),
+            withdraw_msg.into(),
+            referral_amount,
+        ));
+    }
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (dest_msgs, fallbacks) = isolate_destinations(user_addr, &compounded, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(project_addresses, user_addr, amount, comp_prefs, deps, block_time)
+    })?;
+
+    Ok((
+        CompoundPlan {
+            total_rewards,
+            tax: vec![tax],
+            compounded,
+            comp_prefs,
+            claim_msgs: referral
+                .and_then(|referral| referral.payout_msg(user_addr, &referral_amount))
+                .into_iter()
+                .collect(),
+            dest_msgs,
+            fallbacks,
+            skipped: None,
+        },
+        withdraw_msg.into(),
+        referral_amount,
+    ))
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -147,24 +374,17 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, OsmosisDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards.amount)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards.amount)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
-                let compounding_asset = Asset {
-                    info: AssetInfo::NativeToken {
-                        denom: dca_denom.clone(),
-                    },
-                    amount: comp_token_amount,
-                };
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
+                let compounding_asset = OutpostAssetInfo::from_denom(dca_denom.clone()).with_amount(comp_token_amount);
 
                 match destination {
-                    OsmosisDestinationProject::OsmosisStaking { validator_address } => Ok(native_staking_msg(
+                    OsmosisDestinationProject::OsmosisStaking { validator_address } => Ok(active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         user_addr,
                         &cosmwasm_std::Coin {
@@ -172,6 +392,19 @@ pub fn prefs_to_msgs(
                             amount: comp_token_amount,
                         },
                     )?),
+                    OsmosisDestinationProject::OsmosisMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => Ok(active_multi_staking_msgs(
+                        &deps.querier,
+                        &validators,
+                        redelegate_inactive,
+                        user_addr,
+                        &cosmwasm_std::Coin {
+                            denom: dca_denom.clone(),
+                            amount: comp_token_amount,
+                        },
+                    )?),
                     OsmosisDestinationProject::TokenSwap { target_asset } => {
                         let route = generate_known_to_unknown_route(
                             deps.storage,
@@ -184,36 +417,45 @@ pub fn prefs_to_msgs(
                                 pools: project_addrs.destination_projects.swap_routes.clone(),
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
-                            "uosmo",
+                            &dca_denom,
                             target_asset.clone(),
                         )?;
 
+                        // target assets can exit through thin pools so the swap has to stay close to the route's twap
+                        let mut events = guard_price_impact(
+                            deps,
+                            &route,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            current_timestamp,
+                        )?;
+                        events.push(Event::new("token_swap").add_attribute("target_asset", target_asset.to_string()));
+
                         Ok(DestProjectMsgs {
                             msgs: vec![generate_swap(
-                                &coin(comp_token_amount.u128(), "uosmo"),
+                                &coin(comp_token_amount.u128(), &dca_denom),
                                 user_addr,
                                 route.clone(),
                                 estimate_token_out_min_amount(
                                     &deps.querier,
                                     &TWAP_DURATION.load(deps.storage)?,
                                     &route,
-                                    "uosmo".to_string(),
+                                    dca_denom.clone(),
                                     comp_token_amount,
                                     current_timestamp,
+                                    max_slippage,
+                                    &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                                 )?,
                             )],
                             sub_msgs: vec![],
-                            events: vec![Event::new("token_swap").add_attribute("target_asset", target_asset.to_string())],
+                            events,
                         })
                     }
                     OsmosisDestinationProject::SendTokens {
                         address: to_address,
                         target_asset,
                     } => {
-                        let (sim, swap_msgs) = generate_known_to_unknown_swap_and_sim_msg(
-                            &deps.querier,
+                        let route = generate_known_to_unknown_route(
                             deps.storage,
-                            &TWAP_DURATION.load(deps.storage)?,
                             OsmosisRoutePools {
                                 stored_denoms: KNOWN_DENOMS,
                                 stored_pools: MultipleStoredPools {
@@ -223,25 +465,38 @@ pub fn prefs_to_msgs(
                                 pools: project_addrs.destination_projects.swap_routes.clone(),
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
-                            user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &dca_denom,
                             target_asset.clone(),
+                        )?;
+                        // target assets can exit through thin pools so the swap has to stay close to the route's twap
+                        let impact_events = guard_price_impact(
+                            deps,
+                            &route,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            current_timestamp,
+                        )?;
+
+                        let (sim, swap_msgs) = generate_swap_and_sim_msg(
+                            &deps.querier,
+                            &TWAP_DURATION.load(deps.storage)?,
+                            user_addr,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            target_asset.denom.clone(),
+                            route,
                             current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // after the swap we can send the estimated funds to the target address
                         let mut send_msgs = send_tokens_msgs(
                             user_addr,
                             &deps.api.addr_validate(&to_address)?,
-                            Asset {
-                                info: AssetInfo::NativeToken {
-                                    denom: target_asset.denom,
-                                },
-                                amount: sim,
-                            },
+                            OutpostAssetInfo::from_denom(target_asset.denom).with_amount(sim),
                         )?;
 
-                        send_msgs.prepend_msgs(swap_msgs);
+                        send_msgs.run_after_swap(swap_msgs);
+                        send_msgs.append_events(impact_events);
 
                         Ok(send_msgs)
                     }
@@ -269,9 +524,11 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.tia,
                             current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // Mint milkTIA
@@ -281,7 +538,7 @@ pub fn prefs_to_msgs(
                             coin(est_tia.u128(), project_addrs.destination_projects.denoms.tia.clone()),
                         )?;
 
-                        mint_milk_tia.prepend_msgs(swap_to_tia_msgs);
+                        mint_milk_tia.run_after_swap(swap_to_tia_msgs);
 
                         Ok(mint_milk_tia)
                     }
@@ -301,15 +558,17 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.ion,
                             current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         let mut staking_msg =
                             stake_ion_msgs(user_addr, &project_addrs.destination_projects.projects.ion_dao, est_ion)?;
 
-                        staking_msg.prepend_msgs(swap_to_ion_msgs);
+                        staking_msg.run_after_swap(swap_to_ion_msgs);
 
                         Ok(staking_msg)
                     }
@@ -329,9 +588,11 @@ pub fn prefs_to_msgs(
                                 denoms: project_addrs.destination_projects.denoms.clone(),
                             },
                             user_addr,
-                            &coin(comp_token_amount.u128(), "uosmo"),
+                            &coin(comp_token_amount.u128(), &dca_denom),
                             &project_addrs.destination_projects.denoms.mbrn,
                             current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                         )?;
 
                         // can't stake less than 1 MBRN
@@ -351,7 +612,7 @@ pub fn prefs_to_msgs(
                             coin(est_mbrn.u128(), project_addrs.destination_projects.denoms.mbrn.clone()),
                         )?;
 
-                        staking_msg.prepend_msgs(swap_to_mbrn_msgs);
+                        staking_msg.run_after_swap(swap_to_mbrn_msgs);
 
                         Ok(staking_msg)
                     }
@@ -374,9 +635,11 @@ pub fn prefs_to_msgs(
                         },
                         user_addr,
                         pool_id,
-                        &coin(comp_token_amount.u128(), "uosmo"),
+                        &coin(comp_token_amount.u128(), &dca_denom),
                         bond_tokens,
                         current_timestamp.clone(),
+                        max_slippage,
+                        &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                     )?),
                     // Entering a CL pool
                     OsmosisDestinationProject::OsmosisLiquidityPool {
@@ -393,12 +656,14 @@ pub fn prefs_to_msgs(
                         &TWAP_DURATION.load(deps.storage)?,
                         user_addr,
                         pool_id,
-                        &coin(comp_token_amount.u128(), "uosmo"),
+                        &coin(comp_token_amount.u128(), &dca_denom),
                         lower_tick,
                         upper_tick,
                         token_min_amount_0,
                         token_min_amount_1,
                         current_timestamp.clone(),
+                        max_slippage,
+                        &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
                     )?),
 
                     // OsmosisDestinationProject::RedBankLendAsset {
@@ -421,6 +686,47 @@ pub fn prefs_to_msgs(
                     // OsmosisDestinationProject::MembraneDeposit { position_id, asset } => Ok(DestProjectMsgs::default()),
 
                     // OsmosisDestinationProject::DaoDaoStake { dao } => Ok(DestProjectMsgs::default()),
+                    OsmosisDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                        let intermediate_denom = convert_to.denom.clone();
+                        let (sim, swap_msgs) = generate_known_to_unknown_swap_and_sim_msg(
+                            &deps.querier,
+                            deps.storage,
+                            &TWAP_DURATION.load(deps.storage)?,
+                            OsmosisRoutePools {
+                                stored_denoms: KNOWN_DENOMS,
+                                stored_pools: MultipleStoredPools {
+                                    osmo: KNOWN_OSMO_POOLS,
+                                    usdc: KNOWN_USDC_POOLS,
+                                },
+                                pools: project_addrs.destination_projects.swap_routes.clone(),
+                                denoms: project_addrs.destination_projects.denoms.clone(),
+                            },
+                            user_addr,
+                            &coin(comp_token_amount.u128(), &dca_denom),
+                            convert_to,
+                            current_timestamp,
+                            max_slippage,
+                            &TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+                        )?;
+
+                        // the nested destinations only get what the swap is sure to return
+                        let intermediate_amount = if swap_msgs.is_empty() {
+                            sim
+                        } else {
+                            min_out_after_slippage(sim, max_slippage.unwrap_or(Decimal::percent(1)))
+                        };
+
+                        let nested_msgs = prefs_to_msgs(
+                            project_addrs,
+                            user_addr,
+                            coin(intermediate_amount.u128(), intermediate_denom),
+                            then.with_default_slippage(max_slippage)?,
+                            deps,
+                            current_timestamp,
+                        )?;
+
+                        Ok(DestProjectMsgs::composite(swap_msgs, nested_msgs))
+                    }
                     OsmosisDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
             },
@@ -429,3 +735,26 @@ pub fn prefs_to_msgs(
 
     Ok(compounding_msgs)
 }
+
+/// Checks a swap along the route against the price impact guard when the admin has set one
+fn guard_price_impact(
+    deps: Deps,
+    route: &[SwapAmountInRoute],
+    offer: &cosmwasm_std::Coin,
+    current_timestamp: Timestamp,
+) -> Result<Vec<Event>, ContractError> {
+    match PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+        Some(guard) => Ok(check_route_price_impact(
+            &deps.querier,
+            &guard,
+            route,
+            offer,
+            TWAP_DURATION.load(deps.storage)?,
+            current_timestamp,
+            TWAP_FALLBACK.may_load(deps.storage)?.unwrap_or_default(),
+        )?
+        .into_iter()
+        .collect()),
+        None => Ok(vec![]),
+    }
+}
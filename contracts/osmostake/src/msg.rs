@@ -1,11 +1,17 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Api, Decimal, Timestamp, Uint64};
+use cosmwasm_std::{Addr, Api, Coin, Decimal, Timestamp, Uint64};
 use cw_grant_spec::grants::{GrantRequirement, RevokeRequirement};
+use outpost_utils::helpers::{CompoundEligibilityResponse, SimulateCompoundResponse};
 
 use osmosis_destinations::comp_prefs::{
-    OsmosisCompPrefs, OsmosisDestinationProjectAddresses, OsmosisDestinationProjectAddrs,
+    OsmosisCompPrefs, OsmosisDestinationProject, OsmosisDestinationProjectAddresses, OsmosisDestinationProjectAddrs,
 };
-use outpost_utils::comp_prefs::TakeRate;
+use osmosis_helpers::osmosis_swap::{OsmosisPriceSource, TwapFallback};
+use outpost_utils::comp_prefs::{MinCompound, TakeRate};
+use outpost_utils::errors::OutpostError;
+use outpost_utils::fee_schedule::FeeSchedule;
+use outpost_utils::price_source::PriceImpactGuard;
+use outpost_utils::stats::CompoundStats;
 
 use crate::ContractError;
 
@@ -46,6 +52,15 @@ pub enum QueryMsg {
     #[returns(AuthorizedCompoundersResponse)]
     AuthorizedCompounders {},
 
+    /// Explains whether or not the user can currently be compounded,
+    /// either for one of their stored strategies or at the default frequency
+    #[returns(CompoundEligibilityResponse)]
+    CompoundEligibility { user: String, strat_id: Option<Uint64> },
+
+    /// Simulates compounding the given prefs without executing anything
+    #[returns(SimulateCompoundResponse<OsmosisDestinationProject>)]
+    SimulateCompound(OsmostakeCompoundPrefs),
+
     #[returns(Vec<GrantRequirement>)]
     GrantSpec {
         expiration: Timestamp,
@@ -55,8 +70,39 @@ pub enum QueryMsg {
     #[returns(Vec<RevokeRequirement>)]
     RevokeSpec { comp_prefs: OsmostakeCompoundPrefs },
 
+    #[returns(Option<FeeSchedule>)]
+    FeeSchedule {},
+
+    /// The fee the user would be charged if they were compounded now.
+    /// Errors if the outpost quotes the user a fee that they haven't agreed to yet
+    #[returns(Decimal)]
+    UserFee { user: String },
+
+    /// The total amount of each denom that has been paid out to the referrer
+    #[returns(Vec<Coin>)]
+    ReferralEarnings { referrer: String },
+
+    /// Every registered referrer along with the portion of the tax they are paid
+    #[returns(Vec<(Addr, Decimal)>)]
+    Referrers {},
+
+    /// Running totals of everything that has been automated for the user
+    #[returns(CompoundStats)]
+    UserStats { user: String },
+
+    /// Running totals of everything the outpost has automated
+    #[returns(CompoundStats)]
+    OutpostStats {},
+
     #[returns(Uint64)]
     TwapDuration,
+
+    #[returns(TwapFallback)]
+    TwapFallback,
+
+    /// The price source used to value the given denom, if one has been configured
+    #[returns(Option<OsmosisPriceSource>)]
+    PriceSource { denom: String },
 }
 
 #[cw_serde]
@@ -76,9 +122,54 @@ pub enum ExecuteMsg {
     AddAuthorizedCompounder(String),
     RemoveAuthorizedCompounder(String),
     Compound(OsmostakeCompoundPrefs),
+    /// Compound many users at once. Each user runs in its own submessage so one user failing
+    /// doesn't revert the rest of the batch
+    BatchCompound(Vec<OsmostakeCompoundPrefs>),
     UpdateProjectAddresses(Box<ContractAddresses>),
     /// Update the number of seconds used for twap based osmosis swap estimates
     ChangeTwapDuration(Uint64),
+    /// Update what osmosis swap estimates do when a pool in the route doesn't have a twap yet
+    ChangeTwapFallback(TwapFallback),
+    /// Set where the price of the given denom comes from when valuing it in usdc.
+    /// Without a price source the value is simulated by swapping along the known route to usdc
+    UpdatePriceSource {
+        denom: String,
+        source: Option<OsmosisPriceSource>,
+    },
+    /// Set the max price impact for swaps into target assets that exit through thin pools.
+    /// Destinations whose swap goes over it are rerouted to their fallback
+    UpdatePriceImpactGuard(Option<PriceImpactGuard>),
+    /// Update the comp prefs contract that stored prefs are loaded from
+    UpdateCompPrefsAddress(String),
+    /// Set the destination that a failed destination's share goes to when the user hasn't picked a fallback
+    UpdateDefaultFallback(Option<OsmosisDestinationProject>),
+    /// Set the fees that users are charged. Once there is a schedule the fee is looked up on chain
+    /// and the compound's `tax_fee` has to match it. None of the fees can be above the max tax fee
+    UpdateFeeSchedule(Option<FeeSchedule>),
+    /// Set a fee for the user that replaces the schedule, for example zero for partner wallets.
+    /// It can't be above the max tax fee either
+    UpdateFeeOverride {
+        user: String,
+        fee: Option<Decimal>,
+    },
+    /// Sent by the user alongside their grants to lock in their current fee.
+    /// They can't be compounded until they have and are never charged more than it afterwards.
+    /// Errors if the fee is above `max_fee`
+    AgreeToFee {
+        max_fee: Decimal,
+    },
+    /// Register a referrer along with the portion of the tax they are paid or remove them if there's no share.
+    /// Users can only be referred by a registered referrer
+    UpdateReferrer {
+        referrer: String,
+        share: Option<Decimal>,
+    },
+    /// Compound using the user's prefs that are stored in the comp prefs contract
+    /// The strategy's schedule is only advanced if the compound runs, a failed or skipped compound leaves it due
+    CompoundStored {
+        user_address: String,
+        strat_id: Uint64,
+    },
 }
 
 #[cw_serde]
@@ -87,6 +178,33 @@ pub struct OsmostakeCompoundPrefs {
     pub comp_prefs: OsmosisCompPrefs,
     pub user_address: String,
     pub tax_fee: Option<Decimal>,
+    /// the partner frontend that onboarded the user. they're paid their share of the tax
+    /// as long as the admin has registered them as a referrer
+    pub referrer: Option<String>,
+    /// the most slippage that the compound's swaps will accept, for example "0.01" is 1%.
+    /// destinations can set their own to override it
+    pub max_slippage: Option<Decimal>,
+    /// the least that the rewards have to be worth for the compound to go ahead.
+    /// smaller rewards are left to build up until a later compound
+    pub min_compound: Option<MinCompound>,
+}
+
+impl OsmostakeCompoundPrefs {
+    /// Fills in the outpost's default fallback for every destination that doesn't have one
+    pub fn with_default_fallback(self, default_fallback: Option<OsmosisDestinationProject>) -> Self {
+        OsmostakeCompoundPrefs {
+            comp_prefs: self.comp_prefs.with_default_fallback(default_fallback),
+            ..self
+        }
+    }
+
+    /// Applies the compound's slippage tolerance to every destination that doesn't have its own
+    pub fn with_max_slippage(self) -> Result<Self, OutpostError> {
+        Ok(OsmostakeCompoundPrefs {
+            comp_prefs: self.comp_prefs.with_default_slippage(self.max_slippage)?,
+            ..self
+        })
+    }
 }
 
 #[cw_serde]
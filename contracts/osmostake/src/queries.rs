@@ -11,9 +11,9 @@ use osmosis_destinations::comp_prefs::{OsmosisDestinationProject, OsmosisLsd, Os
 use osmosis_destinations::grants::{membrane_stake_grant, mint_milk_tia_grant, stake_ion_grants};
 use osmosis_helpers::osmosis_lp::{join_cl_pool_grants, join_classic_pool_grants};
 use osmosis_helpers::osmosis_swap::osmosis_swap_grants;
+use outpost_utils::asset::OutpostAssetInfo;
 use sail_destinations::grants::eris_lsd_grant;
-use universal_destinations::grants::{native_send_token, native_staking_grant};
-use white_whale::pool_network::asset::AssetInfo;
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 use withdraw_rewards_tax_grant::msg::GrantSpecData;
 
 pub fn query_version() -> VersionResponse {
@@ -44,7 +44,7 @@ impl Grantable for QueryMsg {
             grant_contract: outpost_contract,
             grant_data:
                 CompPrefsWithAddresses {
-                    comp_prefs: OsmostakeCompoundPrefs { comp_prefs: _, .. },
+                    comp_prefs: OsmostakeCompoundPrefs { referrer, .. },
                     project_addresses,
                     take_rate,
                 },
@@ -65,7 +65,23 @@ impl Grantable for QueryMsg {
             current_timestamp,
         )?;
 
-        Ok([withdraw_tax_grants, gen_comp_pref_grants(grant_structure)?].concat())
+        // the referrer's cut of the tax is sent to them straight from the user's wallet.
+        // the grant spec only gets this far for referrers that the admin has registered
+        let referral_grants = referrer
+            .map(|referrer| {
+                native_send_token(
+                    GrantBase {
+                        granter: grant_structure.granter.clone(),
+                        grantee: grant_structure.grantee.clone(),
+                        expiration,
+                    },
+                    OutpostAssetInfo::from_denom(project_addresses.staking_denom.clone()),
+                    referrer,
+                )
+            })
+            .unwrap_or_default();
+
+        Ok([withdraw_tax_grants, referral_grants, gen_comp_pref_grants(grant_structure)?].concat())
     }
 
     fn query_revokes(
@@ -120,37 +136,35 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs = comp_prefs.relative.iter().flat_map(|action| -> Vec<GrantRequirement> {
+    let destinations = comp_prefs.nested_destinations(&project_addresses.staking_denom);
+    let grant_specs = destinations.flat_map(|(source_denom, destination)| -> Vec<GrantRequirement> {
         let base = GrantBase {
             granter: granter.clone(),
             grantee: grantee.clone(),
             expiration,
         };
 
-        match action.destination.clone() {
+        match destination.clone() {
             OsmosisDestinationProject::Unallocated {} => vec![],
+            OsmosisDestinationProject::Composite(_) => osmosis_swap_grants(base),
             OsmosisDestinationProject::OsmosisStaking { validator_address } => {
                 native_staking_grant(base, None, Some(vec![validator_address]))
             }
+            OsmosisDestinationProject::OsmosisMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => native_multi_staking_grant(base, validators, redelegate_inactive),
             OsmosisDestinationProject::TokenSwap { target_asset: _ } => osmosis_swap_grants(base),
             OsmosisDestinationProject::SendTokens { address, target_asset } => vec![
                 osmosis_swap_grants(base.clone()),
-                native_send_token(
-                    base,
-                    AssetInfo::NativeToken {
-                        denom: target_asset.denom,
-                    },
-                    address,
-                ),
+                native_send_token(base, OutpostAssetInfo::from_denom(target_asset.denom), address),
             ]
             .concat(),
 
             OsmosisDestinationProject::MintLsd { lsd: OsmosisLsd::Eris } => eris_lsd_grant(
                 base,
                 project_addresses.destination_projects.projects.eris_amposmo_bonding.clone(),
-                AssetInfo::NativeToken {
-                    denom: "uosmo".to_string(),
-                },
+                OutpostAssetInfo::from_denom(source_denom.clone()),
             ),
             OsmosisDestinationProject::MintLsd {
                 lsd: OsmosisLsd::MilkyWay,
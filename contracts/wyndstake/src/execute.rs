@@ -1,34 +1,51 @@
-use std::iter;
-
-use cosmwasm_std::{Addr, Attribute, Coin, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128};
+use cosmwasm_std::{Addr, Attribute, Coin, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Response, SubMsg, Uint128, Uint64};
 use outpost_utils::{
-    comp_prefs::DestinationAction,
-    helpers::{calculate_compound_amounts, is_authorized_compounder, prefs_sum_to_one, DestProjectMsgs, RewardSplit},
+    asset::OutpostAsset,
+    comp_prefs::{DestinationAction, MinCompound},
+    fallback::{isolate_destinations, save_pending_fallbacks},
+    helpers::{
+        below_min_compound, enforce_compound_frequency, is_authorized_compounder, prefs_sum_to_one, CompoundPlan, DestProjectMsgs, RewardSplit,
+        SimulateCompoundResponse,
+    },
     msg_gen::create_exec_msg,
+    pipeline::{pipeline_start_submsgs, prepare_pipeline, PipelineAsset},
+    price_source::PriceSource,
+    referrals::Referral,
+    stats::CompoundRun,
+    swap_venue::SwapVenue,
 };
 use terraswap_helpers::terraswap_swap::create_terraswap_swap_msg_with_simulation;
 use wynd_helpers::wynd_swap::simulate_and_swap_wynd_pair;
 use wyndex::asset::{Asset, AssetInfo};
 use juno_destinations::comp_prefs::{JunoCompPrefs, JunoDestinationProject, StakingDao};
-use juno_destinations::dest_project_gen::{balance_dao_msgs, gelotto_lottery_msgs, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs, wynd_staking_msgs};
+use juno_destinations::dest_project_gen::{
+    balance_dao_msgs, gelotto_lottery_msgs, juno_swap_router, mint_juno_lsd_msgs, racoon_bet_msgs, send_tokens_msgs,
+    wynd_staking_msgs,
+};
 use sail_destinations::dest_project_gen::{spark_ibc_msgs, white_whale_satellite_msgs};
-use universal_destinations::dest_project_gen::{daodao_cw20_staking_msg, native_staking_msg};
+use universal_destinations::dest_project_gen::{active_multi_staking_msgs, active_native_staking_msg, daodao_cw20_staking_msg};
 
 use crate::{
-    helpers::{query_and_generate_wynd_reward_msgs, wynd_wyndex_multihop_swap, },
+    helpers::{query_and_generate_wynd_reward_msgs, wynd_wyndex_multihop_swap},
     msg::ContractAddrs,
-    state::{ADMIN, AUTHORIZED_ADDRS, PROJECT_ADDRS},
+    state::{
+        ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, DEFAULT_FALLBACK, FEES, LAST_COMPOUNDED, MAX_TAX_FEE, PENDING_FALLBACKS, PIPELINE,
+        PRICE_IMPACT_GUARD, PRICE_SOURCES, PROJECT_ADDRS, REFERRALS, STATS, SWAP_SPLIT,
+    },
     ContractError,
 };
 
 pub fn compound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     _project_addresses: ContractAddrs,
     user_address: String,
     comp_prefs: &JunoCompPrefs,
     tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+    strat_id: Option<Uint64>,
 ) -> Result<Response, ContractError> {
     // validate that the preference quantites sum to 1
     let _ = !prefs_sum_to_one(comp_prefs)?;
@@ -36,62 +53,85 @@ pub fn compound(
     // check that the delegator address is valid
     let user_addr: Addr = deps.api.addr_validate(&user_address)?;
 
-    // validate that the user is authorized to compound
-    is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    // validate that the user is authorized to compound.
+    // batches are split up by user by the outpost itself once the batch's sender has been checked
+    if info.sender.ne(&env.contract.address) {
+        is_authorized_compounder(deps.as_ref(), &info.sender, &user_addr, ADMIN, AUTHORIZED_ADDRS)?;
+    }
+
+    // make sure the user isn't being compounded more often than their frequency allows
+    enforce_compound_frequency(
+        deps.branch(),
+        &info.sender,
+        &env.contract.address,
+        &user_addr,
+        strat_id,
+        env.block.time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )?;
 
     let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
+
+    let (
+        CompoundPlan {
+            tax,
+            compounded,
+            claim_msgs,
+            dest_msgs,
+            fallbacks,
+            skipped,
+            ..
+        },
+        referral_amount,
+    ) = plan_compound(deps.as_ref(), &project_addrs, &user_addr, comp_prefs, tax_fee, referral.as_ref(), &min_compound)?;
 
-    // calculate the total amount of rewards that will be compounded
-    let RewardSplit {
-        user_rewards,
-        tax_amount,
-        claim_msgs,
-    } = query_and_generate_wynd_reward_msgs(
-        tax_fee.unwrap_or(Decimal::percent(5)),
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = skipped {
+        return Ok(Response::default().add_attribute("action", "outpost compound").add_event(skipped));
+    }
+
+    // keep a running total of what each referrer has been paid
+    let referral_events = REFERRALS.record(deps.storage, referral.as_ref(), &referral_amount)?;
+
+    // count the compound towards the user's fee tier
+    FEES.record_volume(deps.storage, &user_addr, compounded.amount)?;
+
+    // keep running totals so the outpost's activity can be queried without an indexer.
+    // only the destinations that are actually running are counted
+    STATS.record(deps.storage, &user_addr, &CompoundRun::new(env.block.time, tax.clone(), &fallbacks))?;
+    save_pending_fallbacks(deps.storage, &PENDING_FALLBACKS, fallbacks)?;
+
+    // swaps whose follow up msgs need the actual swap output are queued up to run from the reply handler
+    let (mut combined_msgs, pipelined) = prepare_pipeline(
+        deps.storage,
+        &PIPELINE,
         &user_addr,
-        &project_addrs.take_rate_addr,
-        &project_addrs.wynd_stake_addr,
-        &project_addrs.destination_projects.wynd.cw20,
-        &deps.querier,
+        &PipelineAsset::Cw20(project_addrs.destination_projects.wynd.cw20.to_string()),
+        dest_msgs,
     )?;
 
-    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs
-    let all_msgs = prefs_to_msgs(&project_addrs, &user_addr, user_rewards, comp_prefs.clone(), deps.as_ref())?;
-
-    let combined_msgs = all_msgs.iter().fold(
-        DestProjectMsgs {
-            msgs: claim_msgs,
-            sub_msgs: vec![],
-            events: vec![Event::new("wyndstake_tax").add_attribute("amount", format!("{}{}", tax_amount, "uwynd"))],
-        },
-        |mut acc, msg| {
-            acc.msgs.append(&mut msg.msgs.clone());
-            acc.sub_msgs.append(&mut msg.sub_msgs.clone());
-            acc.events.append(&mut msg.events.clone());
-            acc
-        },
+    combined_msgs.prepend_msgs(claim_msgs);
+    combined_msgs.prepend_events(
+        tax.iter()
+            .map(|tax| Event::new("wyndstake_tax").add_attribute("amount", tax.to_string()))
+            .collect(),
     );
+    combined_msgs.append_events(referral_events);
 
-    let amount_automated_event = Event::new("amount_automated").add_attributes(
-        [Coin {
-            amount: user_rewards,
-            denom: "uwynd".to_string(),
-        }]
-        .iter()
-        .enumerate()
-        .map(|(i, coin)| Attribute {
-            key: format!("amount_{}", i),
-            value: coin.to_string(),
-        }),
-    );
+    let amount_automated_event = Event::new("amount_automated").add_attributes([compounded].iter().enumerate().map(|(i, coin)| Attribute {
+        key: format!("amount_{}", i),
+        value: coin.to_string(),
+    }));
 
     // the final exec message that will be broadcast and contains all the sub msgs
-    let exec_msg = create_exec_msg(&env.contract.address, combined_msgs.msgs)?;
+    let exec_msgs = pipeline_start_submsgs(deps.storage, &deps.querier, &env, &PIPELINE, vec![], combined_msgs.msgs, pipelined)?;
 
     let resp = Response::default()
         .add_attribute("action", "outpost compound")
         .add_event(amount_automated_event)
-        .add_message(exec_msg)
+        .add_submessages(exec_msgs)
         .add_submessages(
             combined_msgs
                 .sub_msgs
@@ -116,6 +156,117 @@ pub fn compound(
     Ok(resp)
 }
 
+/// Runs through everything a compound would do for the user without broadcasting
+/// anything so that the outcome of their prefs can be previewed
+pub fn simulate_compound(
+    deps: Deps,
+    user_address: String,
+    comp_prefs: &JunoCompPrefs,
+    tax_fee: Option<Decimal>,
+    referrer: Option<Addr>,
+    min_compound: Option<MinCompound>,
+) -> Result<SimulateCompoundResponse<JunoDestinationProject>, ContractError> {
+    // validate that the preference quantites sum to 1
+    let _ = prefs_sum_to_one(comp_prefs)?;
+
+    let user_addr: Addr = deps.api.addr_validate(&user_address)?;
+
+    let project_addrs = PROJECT_ADDRS.load(deps.storage)?;
+
+    let referral = REFERRALS.referral(deps.storage, referrer.as_ref())?;
+
+    Ok(plan_compound(deps, &project_addrs, &user_addr, comp_prefs, tax_fee, referral.as_ref(), &min_compound)?
+        .0
+        .simulate()?)
+}
+
+/// Works out everything a compound would do for the user without saving anything
+/// so that compounds and simulations go through the same steps.
+/// Also returns how much of the tax goes to the user's referrer
+fn plan_compound(
+    deps: Deps,
+    project_addrs: &ContractAddrs,
+    user_addr: &Addr,
+    comp_prefs: &JunoCompPrefs,
+    tax_fee: Option<Decimal>,
+    referral: Option<&Referral>,
+    min_compound: &Option<MinCompound>,
+) -> Result<(CompoundPlan<JunoDestinationProject>, Coin), ContractError> {
+    // once the outpost has a fee schedule the fee is looked up on chain rather than trusting the caller
+    let tax_fee = FEES.fee(deps.storage, user_addr, tax_fee, MAX_TAX_FEE.load(deps.storage)?)?;
+
+    // calculate the total amount of rewards that will be compounded
+    let RewardSplit {
+        user_rewards,
+        tax_amount,
+        referral_amount,
+        claim_msgs,
+    } = query_and_generate_wynd_reward_msgs(
+        tax_fee,
+        user_addr,
+        &project_addrs.take_rate_addr,
+        &project_addrs.wynd_stake_addr,
+        &project_addrs.destination_projects.wynd.cw20,
+        referral,
+        &deps.querier,
+    )?;
+    let referral_amount = Coin::new(referral_amount.u128(), "uwynd");
+
+    let total_rewards = Coin::new((user_rewards + tax_amount).u128(), "uwynd");
+    let compounded = Coin::new(user_rewards.u128(), "uwynd");
+
+    let comp_prefs = comp_prefs.clone().with_default_fallback(DEFAULT_FALLBACK.may_load(deps.storage)?);
+
+    // rewards that aren't worth compounding yet are left to build up for a later compound
+    if let Some(skipped) = below_min_compound(user_addr, &compounded, min_compound, |rewards| {
+        let wynd = AssetInfo::Token(project_addrs.destination_projects.wynd.cw20.to_string());
+
+        // wynd is a cw20 so its price source is stored under the token contract's address
+        match PRICE_SOURCES.may_load(deps.storage, project_addrs.destination_projects.wynd.cw20.as_str())? {
+            // an admin configured price source takes precedence over simulating the swap to usdc
+            Some(price_source) => Ok(price_source.value(
+                &deps.querier,
+                &OutpostAsset::new(wynd, rewards.amount),
+                &project_addrs.usdc.clone().into(),
+            )?),
+            None => Ok::<_, ContractError>(
+                wynd_wyndex_multihop_swap(
+                    &deps.querier,
+                    user_addr,
+                    rewards.amount,
+                    wynd,
+                    project_addrs.usdc.clone(),
+                    project_addrs.destination_projects.wynd.multihop.to_string(),
+                    None,
+                )?
+                .1,
+            ),
+        }
+    })? {
+        return Ok((CompoundPlan::skipped(total_rewards, comp_prefs, skipped), referral_amount));
+    }
+
+    // the list of all the compounding msgs to broadcast on behalf of the user based on their comp prefs.
+    // each destination is compounded on its own so that a failing destination falls back instead of failing the rest
+    let (dest_msgs, fallbacks) = isolate_destinations(user_addr, &compounded, &comp_prefs, |amount, comp_prefs| {
+        prefs_to_msgs(project_addrs, user_addr, amount.amount, comp_prefs, deps)
+    })?;
+
+    Ok((
+        CompoundPlan {
+            total_rewards,
+            tax: vec![Coin::new(tax_amount.u128(), "uwynd")],
+            compounded,
+            comp_prefs,
+            claim_msgs,
+            dest_msgs,
+            fallbacks,
+            skipped: None,
+        },
+        referral_amount,
+    ))
+}
+
 /// Converts the user's compound preferences into a list of
 /// CosmosProtoMsgs that will be broadcast on their behalf
 pub fn prefs_to_msgs(
@@ -132,15 +283,12 @@ pub fn prefs_to_msgs(
     // calculates the amount of ujuno that will be used for each target project accurately.
     // these amounts are paired with the associated destination action
     // for example (1000, JunoDestinationProject::JunoStaking { validator_address: "juno1..." })
-    let compound_token_amounts = iter::zip(
-        calculate_compound_amounts(&comp_prefs.clone().try_into()?, &total_rewards)?,
-        comp_prefs.relative,
-    );
+    let compound_token_amounts = comp_prefs.split_rewards(&total_rewards)?.into_iter();
 
     // generate the list of individual msgs to compound the user's rewards
     let compounding_msgs: Vec<DestProjectMsgs> = compound_token_amounts
         .map(
-            |(comp_token_amount, DestinationAction { destination, .. })| -> Result<DestProjectMsgs, ContractError> {
+            |(comp_token_amount, DestinationAction { destination, max_slippage, .. })| -> Result<DestProjectMsgs, ContractError> {
                 let compounding_asset = Asset {
                     info: AssetInfo::Token(wynd_addr.to_string()),
                     amount: comp_token_amount,
@@ -154,9 +302,10 @@ pub fn prefs_to_msgs(
                             simulate_and_swap_wynd_pair(&deps.querier,
                         user_addr, 
                         project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(), 
-                        compounding_asset, juno_asset_info.clone())?;
+                        compounding_asset, juno_asset_info.clone(), max_slippage)?;
 
-                      let mut staking_msgs =  native_staking_msg(
+                      let mut staking_msgs =  active_native_staking_msg(
+                        &deps.querier,
                         &validator_address,
                         user_addr,
                         &cosmwasm_std::Coin {
@@ -164,10 +313,43 @@ pub fn prefs_to_msgs(
                             amount: expected_juno,
                         },
                     )?;
-                    staking_msgs.append_msgs(vec![swap_msg]);
+                    staking_msgs.run_after_swap(vec![swap_msg]);
 
                     Ok(staking_msgs)   
                 },
+                    JunoDestinationProject::JunoMultiStaking {
+                        validators,
+                        redelegate_inactive,
+                    } => {
+                        let (
+                            swap_msg,
+                            wyndex::pair::SimulationResponse {
+                                return_amount: expected_juno,
+                                ..
+                            },
+                        ) = simulate_and_swap_wynd_pair(
+                            &deps.querier,
+                            user_addr,
+                            project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(),
+                            compounding_asset,
+                            juno_asset_info.clone(),
+                            max_slippage,
+                        )?;
+
+                        let mut staking_msgs = active_multi_staking_msgs(
+                            &deps.querier,
+                            &validators,
+                            redelegate_inactive,
+                            user_addr,
+                            &cosmwasm_std::Coin {
+                                denom: "ujuno".to_string(),
+                                amount: expected_juno,
+                            },
+                        )?;
+                        staking_msgs.run_after_swap(vec![swap_msg]);
+
+                        Ok(staking_msgs)
+                    }
 
                     JunoDestinationProject::DaoStaking(dao) => {
                         if let StakingDao::Kleomedes = dao {
@@ -190,9 +372,33 @@ pub fn prefs_to_msgs(
                                 comp_token_amount,
                                 wynd_asset_info.clone(),
                                 AssetInfo::Token(dao_addresses.cw20.to_string()),
-                                project_addrs.destination_projects.wynd.multihop.to_string(),)?;
-                            
-                        
+                                project_addrs.destination_projects.wynd.multihop.to_string(),
+                                max_slippage,)?;
+
+                        // the route ends in the dao's juno pair which can be thin so the swap has to stay close to the route's spot price
+                        let mut impact_events = vec![];
+                        if let Some(guard) = PRICE_IMPACT_GUARD.may_load(deps.storage)? {
+                            impact_events.push(guard.check_against_probe(
+                                &project_addrs.destination_projects.wynd.multihop.to_string(),
+                                &OutpostAsset::new(wynd_asset_info.clone(), comp_token_amount),
+                                &AssetInfo::Token(dao_addresses.cw20.to_string()).into(),
+                                expected_dao_token_amount,
+                                |probe_amount| {
+                                    Ok::<_, ContractError>(
+                                        wynd_wyndex_multihop_swap(
+                                            &deps.querier,
+                                            user_addr,
+                                            probe_amount,
+                                            wynd_asset_info.clone(),
+                                            AssetInfo::Token(dao_addresses.cw20.to_string()),
+                                            project_addrs.destination_projects.wynd.multihop.to_string(),
+                                            None,
+                                        )?
+                                        .1,
+                                    )
+                                },
+                            )?);
+                        }
 
                         let mut stake_msgs = daodao_cw20_staking_msg(
                             dao.to_string(),
@@ -202,7 +408,8 @@ pub fn prefs_to_msgs(
                             expected_dao_token_amount,
                         )?;
 
-                        stake_msgs.prepend_msgs(swap_msgs);
+                        stake_msgs.run_after_swap(swap_msgs);
+                        stake_msgs.append_events(impact_events);
 
                         Ok(stake_msgs)
                     }
@@ -220,15 +427,19 @@ pub fn prefs_to_msgs(
                                 let (swap, sim) = simulate_and_swap_wynd_pair(
                                     &deps.querier, user_addr
                                     , project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(), 
-                                    Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone())?;
+                                    Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone(), max_slippage)?;
 
                                 (vec![swap], sim.return_amount)
                             }
-                            _ => wynd_wyndex_multihop_swap(&deps.querier,                                
-                                 user_addr, comp_token_amount,
-                                  wynd_asset_info.clone(),
-                                   target_denom.clone(), 
-                                   project_addrs.destination_projects.wynd.multihop.to_string())?
+                            // otherwise the swap goes through whichever juno dex gives the best return
+                            _ => juno_swap_router(&project_addrs.destination_projects, SWAP_SPLIT.may_load(deps.storage)?)
+                                .swap_msgs(
+                                    &deps.querier,
+                                    user_addr,
+                                    &OutpostAsset::new(wynd_asset_info.clone(), comp_token_amount),
+                                    &target_denom.clone().into(),
+                                    max_slippage,
+                                )?,
                         };
                     
                     
@@ -249,7 +460,7 @@ pub fn prefs_to_msgs(
                         let (juno_swap, juno_sim) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone(), max_slippage)?;
 
                         let mut lottery_msgs = gelotto_lottery_msgs(
                         user_addr,
@@ -260,7 +471,7 @@ pub fn prefs_to_msgs(
                         juno_sim.return_amount,
                     )?;
 
-                    lottery_msgs.append_msgs(vec![juno_swap]);                
+                    lottery_msgs.run_after_swap(vec![juno_swap]);                
                 
                 Ok(lottery_msgs)},
                     JunoDestinationProject::RacoonBet { game } => {
@@ -268,7 +479,7 @@ pub fn prefs_to_msgs(
                         let (swap, usdc_sim) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.wynd_usdc_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone(), max_slippage)?;
                             
                        let mut rac_msgs =  racoon_bet_msgs(
                         &deps.querier,
@@ -283,7 +494,7 @@ pub fn prefs_to_msgs(
                         &project_addrs.destination_projects.racoon_bet.game,
                     )?;
 
-                    rac_msgs.append_msgs(vec![swap]);
+                    rac_msgs.run_after_swap(vec![swap]);
 
                     Ok(rac_msgs)
                 },
@@ -292,7 +503,7 @@ pub fn prefs_to_msgs(
                         let (usdc_swap,  wyndex::pair::SimulationResponse {return_amount: est_usdc,..}) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.wynd_usdc_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone(), max_slippage)?;
                             
                         let (swap_ops, denom) = project_addrs.destination_projects.white_whale.get_usdc_swap_operations(asset)?;
 
@@ -306,6 +517,7 @@ pub fn prefs_to_msgs(
                                 .white_whale
                                 .terraswap_multihop_router
                                 .to_string(),
+                            max_slippage,
                         )?;
 
                         let mut bond_msgs = white_whale_satellite_msgs(
@@ -314,9 +526,7 @@ pub fn prefs_to_msgs(
                             &project_addrs.destination_projects.white_whale.market.clone(),
                         )?;
 
-                        bond_msgs.prepend_msgs(lsd_swap_msgs);
-
-                        bond_msgs.append_msgs(vec![usdc_swap]);
+                        bond_msgs.run_after_swap([vec![usdc_swap], lsd_swap_msgs].concat());
 
                         Ok(bond_msgs)
                     }
@@ -324,7 +534,7 @@ pub fn prefs_to_msgs(
                         let (juno_swap, wyndex::pair::SimulationResponse {return_amount: juno_sim, ..}) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone(), max_slippage)?;
 
                         let mut balance_msgs = balance_dao_msgs(
                         user_addr,
@@ -332,7 +542,7 @@ pub fn prefs_to_msgs(
                         juno_sim,
                     )?;
 
-                    balance_msgs.append_msgs(vec![juno_swap]);
+                    balance_msgs.run_after_swap(vec![juno_swap]);
                 
                 Ok(balance_msgs)},
 
@@ -341,7 +551,7 @@ pub fn prefs_to_msgs(
                         let (juno_swap, wyndex::pair::SimulationResponse {return_amount: juno_sim, ..}) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.juno_wynd_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, juno_asset_info.clone(), max_slippage)?;
 
 
                         let mut msgs = mint_juno_lsd_msgs(
@@ -350,7 +560,7 @@ pub fn prefs_to_msgs(
                         juno_sim,
                         project_addrs.destination_projects.juno_lsds.clone(),
                     )?; 
-                    msgs.append_msgs(vec![juno_swap]);
+                    msgs.run_after_swap(vec![juno_swap]);
                     
                     Ok(msgs)
                 },
@@ -361,7 +571,7 @@ pub fn prefs_to_msgs(
                         let (usdc_swap,  wyndex::pair::SimulationResponse {return_amount: est_usdc,..}) = simulate_and_swap_wynd_pair(
                             &deps.querier, user_addr
                             , project_addrs.destination_projects.wynd.wynd_usdc_pair.as_ref(), 
-                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone())?;
+                            Asset { info: wynd_asset_info.clone(), amount: comp_token_amount }, project_addrs.usdc.clone(), max_slippage)?;
                             
                         // the helper method will validate that we have enough usdc and what not
                         let mut spark_msgs = spark_ibc_msgs(
@@ -374,7 +584,7 @@ pub fn prefs_to_msgs(
                             fund,
                         )?;
 
-                        spark_msgs.prepend_msgs(vec![usdc_swap]);
+                        spark_msgs.run_after_swap(vec![usdc_swap]);
 
                         Ok(spark_msgs)
                     }
@@ -389,7 +599,7 @@ pub fn prefs_to_msgs(
                             wynd_asset_info.clone(),                            
                             target_asset.clone(),
                             project_addrs.destination_projects.wynd.multihop.to_string(),
-
+                            max_slippage,
                         )
                         .map_err(ContractError::Std)?;
 
@@ -403,10 +613,11 @@ pub fn prefs_to_msgs(
                             },
                         )?;
 
-                        send_msgs.append_msgs(swap_msgs);
+                        send_msgs.run_after_swap(swap_msgs);
 
                         Ok(send_msgs)
                     }
+                    JunoDestinationProject::Composite(_) => Err(ContractError::NotImplemented {}),
                     JunoDestinationProject::Unallocated {} => Ok(DestProjectMsgs::default()),
                 }
             },
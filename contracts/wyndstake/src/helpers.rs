@@ -1,6 +1,7 @@
 use outpost_utils::{
     helpers::RewardSplit,
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    referrals::Referral,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -43,6 +44,7 @@ pub fn query_and_generate_wynd_reward_msgs(
     tax_addr: &Addr,
     wynd_staking_addr: &Addr,
     wynd_cw20_addr: &Addr,
+    referral: Option<&Referral>,
     querier: &QuerierWrapper,
 ) -> Result<RewardSplit, ContractError> {
     gen_wynd_claim_rewards_msg(
@@ -51,6 +53,7 @@ pub fn query_and_generate_wynd_reward_msgs(
         tax_addr,
         wynd_staking_addr,
         wynd_cw20_addr,
+        referral,
         querier
             .query_wasm_smart(
                 wynd_staking_addr,
@@ -68,12 +71,14 @@ pub fn gen_wynd_claim_rewards_msg(
     tax_addr: &Addr,
     wynd_staking_addr: &Addr,
     wynd_cw20_addr: &Addr,
+    referral: Option<&Referral>,
     WithdrawableRewardsResponse { rewards }: WithdrawableRewardsResponse,
 ) -> Result<RewardSplit, ContractError> {
     if tax_percent.is_zero() {
         return Ok(RewardSplit {
             user_rewards: rewards,
             tax_amount: Uint128::zero(),
+            referral_amount: Uint128::zero(),
             claim_msgs: vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
                 wynd_staking_addr.to_string(),
                 &delegator_addr,
@@ -84,29 +89,44 @@ pub fn gen_wynd_claim_rewards_msg(
     }
     let user_rewards = rewards * (Decimal::one() - tax_percent);
     let tax_amount = rewards - user_rewards;
+    // the referrer's cut comes out of the tax so the user pays the same either way
+    let referral_amount = referral.map_or(Uint128::zero(), |Referral { share, .. }| tax_amount.mul_floor(*share).min(tax_amount));
 
-    let claim_msgs: Vec<CosmosProtoMsg> = vec![
-        CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
-            wynd_staking_addr.to_string(),
-            &delegator_addr,
-            &wynd_stake::msg::ExecuteMsg::WithdrawRewards { owner: None, receiver: None },
-            None,
-        )?),
-        CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
+    let claim_msgs: Vec<CosmosProtoMsg> = [
+        Some((tax_addr, tax_amount - referral_amount)),
+        referral.map(|Referral { referrer, .. }| (referrer, referral_amount)),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|(_, amount)| !amount.is_zero())
+    .map(|(recipient, amount)| {
+        create_exec_contract_msg(
             wynd_cw20_addr,
             delegator_addr,
             &cw20::Cw20ExecuteMsg::Transfer {
-                recipient: tax_addr.to_string(),
-                amount: tax_amount,
+                recipient: recipient.to_string(),
+                amount,
             },
             None,
-        )?),
-    ];
+        )
+        .map(CosmosProtoMsg::ExecuteContract)
+    })
+    .collect::<Result<Vec<_>, _>>()?;
 
     Ok(RewardSplit {
         user_rewards,
         tax_amount,
-        claim_msgs,
+        referral_amount,
+        claim_msgs: [
+            vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
+                wynd_staking_addr.to_string(),
+                &delegator_addr,
+                &wynd_stake::msg::ExecuteMsg::WithdrawRewards { owner: None, receiver: None },
+                None,
+            )?)],
+            claim_msgs,
+        ]
+        .concat(),
     })
 }
 
@@ -117,6 +137,7 @@ pub fn wynd_wyndex_multihop_swap(
     wynd_asset_info: AssetInfo,
     ask_asset_info: AssetInfo,
     multihop_address: String,
+    max_slippage: Option<Decimal>,
 ) -> Result<(Vec<CosmosProtoMsg>, Uint128), StdError> {
     let juno_asset_info = AssetInfo::Native("ujuno".to_string());
 
@@ -140,9 +161,10 @@ pub fn wynd_wyndex_multihop_swap(
             ],
             minimum_receive: None,
             receiver: None,
-            max_spread: Some(Decimal::percent(2)),
+            max_spread: Some(max_slippage.unwrap_or(Decimal::percent(2))),
             referral_address: None,
             referral_commission: None,
         }),
+        max_slippage,
     )
 }
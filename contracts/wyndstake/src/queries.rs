@@ -2,10 +2,12 @@ use cosmwasm_std::{Addr, Deps, QuerierWrapper, StdResult, Timestamp, Uint128};
 use cw_grant_spec::grantable_trait::{dedupe_grant_reqs, GrantStructure, Grantable};
 use cw_grant_spec::grants::{AuthorizationType, GrantBase, GrantRequirement, RevokeRequirement};
 
-use juno_destinations::comp_prefs::{wyndex_asset_info_to_terraswap_asset_info, DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::comp_prefs::{DaoAddr, JunoDestinationProject, JunoLsd};
+use juno_destinations::dest_project_gen::juno_swap_router;
 use juno_destinations::grants::{balance_dao_grant, gelotto_lottery_grant, wyndao_staking_grant};
+use outpost_utils::swap_venue::SwapVenue;
 use terraswap_helpers::terraswap_swap::terraswap_multihop_grant;
-use universal_destinations::grants::{native_send_token, native_staking_grant};
+use universal_destinations::grants::{native_multi_staking_grant, native_send_token, native_staking_grant};
 use wynd_helpers::wynd_swap::{simulate_wynd_pool_swap, wynd_multihop_swap_grant, wynd_pool_swap_grant};
 use wyndex::{
     asset::{Asset, AssetInfo},
@@ -151,7 +153,7 @@ pub fn gen_comp_pref_grants(
             },
     }: GrantStructure<CompPrefsWithAddresses>,
 ) -> StdResult<Vec<GrantRequirement>> {
-    let grant_specs = comp_prefs.relative.iter().flat_map(|action| -> Vec<GrantRequirement> {
+    let grant_specs = comp_prefs.destinations().flat_map(|destination| -> Vec<GrantRequirement> {
         let base = GrantBase {
             granter: granter.clone(),
             grantee: grantee.clone(),
@@ -161,8 +163,9 @@ pub fn gen_comp_pref_grants(
         let wynd_asset_info = AssetInfo::Token(wynd_addr.to_string());
         let _juno_asset_info = AssetInfo::Native("ujuno".to_string());
 
-        match action.destination.clone() {
+        match destination.clone() {
             JunoDestinationProject::Unallocated {} => vec![],
+            JunoDestinationProject::Composite(_) => vec![],
             JunoDestinationProject::JunoStaking { validator_address } => vec![
                 wynd_pool_swap_grant(
                     base.clone(),
@@ -175,6 +178,21 @@ pub fn gen_comp_pref_grants(
             .into_iter()
             .flatten()
             .collect(),
+            JunoDestinationProject::JunoMultiStaking {
+                validators,
+                redelegate_inactive,
+            } => vec![
+                wynd_pool_swap_grant(
+                    base.clone(),
+                    project_addresses.destination_projects.wynd.juno_wynd_pair.clone(),
+                    wynd_asset_info,
+                    None,
+                ),
+                native_multi_staking_grant(base, validators, redelegate_inactive),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
 
             JunoDestinationProject::DaoStaking(dao) => {
                 let DaoAddr { cw20, .. } = dao.get_daos_addresses(&project_addresses.destination_projects.daos);
@@ -229,7 +247,7 @@ pub fn gen_comp_pref_grants(
                     ),
                 },
                 // send to the given user
-                native_send_token(base, wyndex_asset_info_to_terraswap_asset_info(denom), address),
+                native_send_token(base, denom, address),
             ]
             .concat(),
             JunoDestinationProject::MintLsd { lsd_type } => [
@@ -310,7 +328,7 @@ pub fn gen_comp_pref_grants(
             ]
             .concat(),
             JunoDestinationProject::TokenSwap { target_denom: _target_denom } => {
-                wynd_multihop_swap_grant(base, project_addresses.destination_projects.wynd.multihop.clone(), wynd_asset_info, None)
+                juno_swap_router(&project_addresses.destination_projects, None).grants(base, &wynd_asset_info.into())
             }
 
             JunoDestinationProject::WyndLp { .. } => vec![
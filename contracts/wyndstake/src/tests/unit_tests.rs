@@ -1,11 +1,32 @@
-use cosmwasm_std::{Addr, Decimal};
+use cosmwasm_std::{
+    from_json,
+    testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage},
+    to_json_binary, Addr, ContractResult, Decimal, OwnedDeps, QuerierResult, SystemResult, Timestamp, Uint64, WasmQuery,
+};
+use juno_destinations::comp_prefs::{DestinationProjectAddresses, JunoCompPrefs, JunoDestinationProject, WyndStakingBondingPeriod};
+use juno_destinations::dest_project_gen::JunoPriceSource;
 use outpost_utils::{
+    comp_prefs::{DestinationAction, MinCompound},
+    errors::OutpostError,
     helpers::RewardSplit,
-    msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    msg_gen::{create_advance_schedule_msg, create_exec_contract_msg, CosmosProtoMsg},
+    price_source::{OraclePrice, OraclePriceResponse},
+    referrals::Referral,
 };
 use wynd_stake::msg::WithdrawableRewardsResponse;
+use wyndex::asset::AssetInfo;
+use ymos_comp_prefs::{
+    msg::QueryMsg as CompPrefsQueryMsg,
+    state::{CompPref, CompoundingFrequency, UserCompPref},
+};
 
-use crate::helpers::gen_wynd_claim_rewards_msg;
+use crate::{
+    contract::execute,
+    helpers::gen_wynd_claim_rewards_msg,
+    msg::{AuthzppAddresses, ContractAddresses, ContractAddrs, ExecuteMsg, WyndstakeCompoundPrefs},
+    state::{ADMIN, AUTHORIZED_ADDRS, COMP_PREFS_ADDR, MAX_TAX_FEE, PROJECT_ADDRS},
+    ContractError,
+};
 
 #[test]
 pub fn validate_wynd_rewards_split() {
@@ -21,6 +42,7 @@ pub fn validate_wynd_rewards_split() {
             &tax_addr,
             &wynd_staking_addr,
             &wynd_cw20_addr,
+            None,
             WithdrawableRewardsResponse {
                 rewards: 1_000_000u128.into()
             }
@@ -29,6 +51,7 @@ pub fn validate_wynd_rewards_split() {
         RewardSplit {
             user_rewards: 950_000u128.into(),
             tax_amount: 50_000u128.into(),
+            referral_amount: 0u128.into(),
             claim_msgs: vec![
                 CosmosProtoMsg::ExecuteContract(
                     create_exec_contract_msg(
@@ -63,6 +86,7 @@ pub fn validate_wynd_rewards_split() {
             &tax_addr,
             &wynd_staking_addr,
             &wynd_cw20_addr,
+            None,
             WithdrawableRewardsResponse {
                 rewards: 1_000_000u128.into()
             }
@@ -71,6 +95,7 @@ pub fn validate_wynd_rewards_split() {
         RewardSplit {
             user_rewards: 1_000_000u128.into(),
             tax_amount: 0u128.into(),
+            referral_amount: 0u128.into(),
             claim_msgs: vec![CosmosProtoMsg::ExecuteContract(
                 create_exec_contract_msg(
                     wynd_staking_addr.to_string(),
@@ -82,5 +107,318 @@ pub fn validate_wynd_rewards_split() {
             ),]
         },
         "Wynd rewards split shortcuts when 0 tax rate"
+    );
+
+    let referrer_addr = Addr::unchecked("referrer1");
+    let transfer_msg = |recipient: &Addr, amount: u128| {
+        CosmosProtoMsg::ExecuteContract(
+            create_exec_contract_msg(
+                wynd_cw20_addr.clone(),
+                &delegator_addr.clone(),
+                &cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: amount.into(),
+                },
+                None,
+            )
+            .unwrap(),
+        )
+    };
+
+    assert_eq!(
+        gen_wynd_claim_rewards_msg(
+            Decimal::percent(5),
+            &delegator_addr,
+            &tax_addr,
+            &wynd_staking_addr,
+            &wynd_cw20_addr,
+            Some(&Referral {
+                referrer: referrer_addr.clone(),
+                share: Decimal::percent(20),
+            }),
+            WithdrawableRewardsResponse {
+                rewards: 1_000_000u128.into()
+            }
+        )
+        .unwrap(),
+        RewardSplit {
+            user_rewards: 950_000u128.into(),
+            tax_amount: 50_000u128.into(),
+            referral_amount: 10_000u128.into(),
+            claim_msgs: vec![
+                CosmosProtoMsg::ExecuteContract(
+                    create_exec_contract_msg(
+                        wynd_staking_addr.to_string(),
+                        &delegator_addr.clone(),
+                        &wynd_stake::msg::ExecuteMsg::WithdrawRewards { owner: None, receiver: None },
+                        None,
+                    )
+                    .unwrap()
+                ),
+                transfer_msg(&tax_addr, 40_000),
+                transfer_msg(&referrer_addr, 10_000),
+            ]
+        },
+        "Referrer is paid their share out of the tax"
     )
 }
+
+const COMP_PREFS: &str = "comp_prefs";
+const WYND_STAKE: &str = "wynd_stake";
+const ORACLE: &str = "oracle";
+
+/// An outpost with its comp prefs contract set whose queries are answered with `stored_pref`
+fn stored_prefs_outpost(stored_pref: Option<CompPref>) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies();
+
+    let mut destination_projects = DestinationProjectAddresses::default();
+    destination_projects.wynd.cw20 = "wynd_cw20".to_string();
+
+    // the mock api won't validate the unused empty addresses so the validated addresses are built from json
+    let project_addrs: ContractAddrs = from_json(
+        to_json_binary(&ContractAddresses {
+            take_rate_addr: "take_rate".to_string(),
+            usdc: AssetInfo::Native("uusdc".to_string()),
+            authzpp: AuthzppAddresses::default(),
+            destination_projects,
+            wynd_stake_addr: WYND_STAKE.to_string(),
+        })
+        .unwrap(),
+    )
+    .unwrap();
+
+    ADMIN.save(deps.as_mut().storage, &Addr::unchecked("admin")).unwrap();
+    AUTHORIZED_ADDRS.save(deps.as_mut().storage, &vec![]).unwrap();
+    PROJECT_ADDRS.save(deps.as_mut().storage, &project_addrs).unwrap();
+    COMP_PREFS_ADDR.save(deps.as_mut().storage, &Addr::unchecked(COMP_PREFS)).unwrap();
+    MAX_TAX_FEE.save(deps.as_mut().storage, &Decimal::percent(10)).unwrap();
+
+    deps.querier.update_wasm(move |query| -> QuerierResult {
+        let response = match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr.eq(COMP_PREFS) => match from_json(msg).unwrap() {
+                CompPrefsQueryMsg::StrategyPreferencesByUserAndStratId { .. } => to_json_binary(&stored_pref),
+                query => panic!("unexpected comp prefs query {:?}", query),
+            },
+            WasmQuery::Smart { contract_addr, .. } if contract_addr.eq(WYND_STAKE) => to_json_binary(&WithdrawableRewardsResponse {
+                rewards: 1_000_000u128.into(),
+            }),
+            // an oracle that prices everything at half a usdc
+            WasmQuery::Smart { contract_addr, .. } if contract_addr.eq(ORACLE) => {
+                to_json_binary(&OraclePriceResponse { price: Decimal::percent(50) })
+            }
+            query => panic!("unexpected query {:?}", query),
+        };
+
+        SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+    });
+
+    deps
+}
+
+fn stored_pref(outpost_address: &str, user_address: &str) -> CompPref {
+    CompPref {
+        user_comp_pref: UserCompPref {
+            outpost_address: Addr::unchecked(outpost_address),
+            strat_id: 1,
+            strategy_settings: to_json_binary(&WyndstakeCompoundPrefs {
+                comp_prefs: JunoCompPrefs {
+                    relative: vec![DestinationAction {
+                        destination: JunoDestinationProject::WyndStaking {
+                            bonding_period: WyndStakingBondingPeriod::ThirtyDays,
+                        },
+                        amount: 1_000_000_000_000_000_000u128,
+                        fallback: None,
+                        max_slippage: None,
+                    }],
+                    absolute: vec![],
+                    capped: vec![],
+                },
+                user_address: user_address.to_string(),
+                tax_fee: Some(Decimal::percent(5)),
+                referrer: None,
+                max_slippage: None,
+                min_compound: None,
+            })
+            .unwrap(),
+            address: Addr::unchecked(user_address),
+            comp_period: CompoundingFrequency::Daily,
+            pub_key: "123".to_string(),
+            expires: mock_env().block.time.plus_seconds(86_400),
+        },
+        chain_id: "juno-1".to_string(),
+        created_at: Timestamp::default(),
+        updated_at: Timestamp::default(),
+        is_inactive: None,
+    }
+}
+
+#[test]
+pub fn compound_stored_prefs() {
+    let env = mock_env();
+    let mut deps = stored_prefs_outpost(Some(stored_pref(env.contract.address.as_str(), "user")));
+
+    let response = execute(
+        deps.as_mut(),
+        env,
+        mock_info("admin", &[]),
+        ExecuteMsg::CompoundStored {
+            user_address: "user".to_string(),
+            strat_id: Uint64::from(1u64),
+        },
+    )
+    .unwrap();
+
+    assert!(
+        response.events.iter().any(|event| event.ty.eq("amount_automated")),
+        "the stored prefs should be compounded"
+    );
+    assert_eq!(
+        response.messages.last().map(|sub_msg| sub_msg.msg.clone()),
+        Some(create_advance_schedule_msg(&Addr::unchecked(COMP_PREFS), Uint64::from(1u64), "user").unwrap()),
+        "the comp prefs contract should be told that the strategy ran"
+    );
+}
+
+#[test]
+pub fn compound_stored_prefs_failed_compound_not_advanced() {
+    let env = mock_env();
+
+    // stored prefs that only allocate half of the rewards can't be compounded
+    let mut pref = stored_pref(env.contract.address.as_str(), "user");
+    let mut settings: WyndstakeCompoundPrefs = from_json(&pref.user_comp_pref.strategy_settings).unwrap();
+    settings.comp_prefs.relative[0].amount = 500_000_000_000_000_000u128;
+    pref.user_comp_pref.strategy_settings = to_json_binary(&settings).unwrap();
+
+    let mut deps = stored_prefs_outpost(Some(pref));
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info("admin", &[]),
+        ExecuteMsg::CompoundStored {
+            user_address: "user".to_string(),
+            strat_id: Uint64::from(1u64),
+        },
+    )
+    .unwrap_err();
+
+    // the error reverts the advance message along with the compound so the strategy stays due
+    assert!(
+        matches!(err, ContractError::OutpostError(OutpostError::InvalidPrefQtys { .. })),
+        "a failed compound should not advance the schedule"
+    );
+}
+
+#[test]
+pub fn compound_stored_prefs_skipped_compound_not_advanced() {
+    let env = mock_env();
+
+    // the user's minimum is more than the 1 WYND of pending rewards
+    let mut pref = stored_pref(env.contract.address.as_str(), "user");
+    let mut settings: WyndstakeCompoundPrefs = from_json(&pref.user_comp_pref.strategy_settings).unwrap();
+    settings.min_compound = Some(MinCompound::Native(2_000_000u128.into()));
+    pref.user_comp_pref.strategy_settings = to_json_binary(&settings).unwrap();
+
+    let mut deps = stored_prefs_outpost(Some(pref));
+    let response = execute(
+        deps.as_mut(),
+        env,
+        mock_info("admin", &[]),
+        ExecuteMsg::CompoundStored {
+            user_address: "user".to_string(),
+            strat_id: Uint64::from(1u64),
+        },
+    )
+    .unwrap();
+
+    assert!(
+        response.events.iter().any(|event| event.ty.eq("compound_skipped")),
+        "rewards below the minimum should be skipped"
+    );
+    assert!(
+        response.messages.is_empty(),
+        "a skipped compound should neither run nor advance the schedule"
+    );
+}
+
+#[test]
+pub fn compound_valued_by_price_source() {
+    let env = mock_env();
+
+    // 0.95 WYND of rewards after tax is only worth 0.475 USDC going by the oracle
+    let mut pref = stored_pref(env.contract.address.as_str(), "user");
+    let mut settings: WyndstakeCompoundPrefs = from_json(&pref.user_comp_pref.strategy_settings).unwrap();
+    settings.min_compound = Some(MinCompound::Usdc(600_000u128.into()));
+    pref.user_comp_pref.strategy_settings = to_json_binary(&settings).unwrap();
+
+    let mut deps = stored_prefs_outpost(Some(pref));
+    let update_price_source = ExecuteMsg::UpdatePriceSource {
+        denom: "wynd_cw20".to_string(),
+        source: Some(JunoPriceSource::Oracle(OraclePrice {
+            contract: Addr::unchecked(ORACLE),
+        })),
+    };
+
+    // only the admin can pick where prices come from
+    let err = execute(deps.as_mut(), env.clone(), mock_info("user", &[]), update_price_source.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}), "only the admin should set price sources");
+
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), update_price_source).unwrap();
+
+    let response = execute(
+        deps.as_mut(),
+        env,
+        mock_info("admin", &[]),
+        ExecuteMsg::CompoundStored {
+            user_address: "user".to_string(),
+            strat_id: Uint64::from(1u64),
+        },
+    )
+    .unwrap();
+
+    let skipped = response
+        .events
+        .iter()
+        .find(|event| event.ty.eq("compound_skipped"))
+        .expect("rewards worth less than the minimum should be skipped");
+    assert!(
+        skipped.attributes.iter().any(|attr| attr.key.eq("value") && attr.value.eq("475000usdc")),
+        "the rewards should be valued by the configured oracle"
+    );
+}
+
+#[test]
+pub fn compound_stored_prefs_missing_or_unauthorized() {
+    let env = mock_env();
+    let compound_stored = ExecuteMsg::CompoundStored {
+        user_address: "user".to_string(),
+        strat_id: Uint64::from(1u64),
+    };
+
+    // nothing stored for the strategy
+    let mut deps = stored_prefs_outpost(None);
+    let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), compound_stored.clone()).unwrap_err();
+    assert!(
+        matches!(err, ContractError::OutpostError(OutpostError::NoStoredCompPrefs { .. })),
+        "missing stored prefs should not be compounded"
+    );
+
+    // the strategy is stored for a different outpost
+    let mut deps = stored_prefs_outpost(Some(stored_pref("other_outpost", "user")));
+    let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), compound_stored.clone()).unwrap_err();
+    assert!(
+        matches!(err, ContractError::OutpostError(OutpostError::StoredCompPrefsOutpostMismatch { .. })),
+        "prefs stored for another outpost should not be compounded"
+    );
+
+    // the stored settings belong to a different user
+    let mut deps = stored_prefs_outpost(Some(stored_pref(env.contract.address.as_str(), "other_user")));
+    let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), compound_stored.clone()).unwrap_err();
+    assert!(
+        matches!(err, ContractError::OutpostError(OutpostError::StoredCompPrefsUserMismatch { .. })),
+        "settings for another user should not be compounded"
+    );
+
+    // only authorized compounders can run the stored prefs
+    let mut deps = stored_prefs_outpost(Some(stored_pref(env.contract.address.as_str(), "user")));
+    execute(deps.as_mut(), env, mock_info("stranger", &[]), compound_stored).unwrap_err();
+}
@@ -1,7 +1,10 @@
 use crate::errors::JunoDestinationError;
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{Addr, Api};
-use outpost_utils::comp_prefs::CompoundPrefs;
+use outpost_utils::comp_prefs::{
+    CompositeDestination, CompoundPrefs, NestedDestinations, ValidatorSelection,
+};
+use outpost_utils::stats::DestinationLabel;
 use sail_destinations::comp_prefs::{FundMsg, RacoonBetGame};
 use wyndex::asset::AssetInfo;
 
@@ -11,6 +14,12 @@ pub type JunoCompPrefs = CompoundPrefs<JunoDestinationProject>;
 pub enum JunoDestinationProject {
     /// Native Staking on juno
     JunoStaking { validator_address: String },
+    /// Native Staking on juno split across several validators
+    JunoMultiStaking {
+        validators: Vec<ValidatorSelection>,
+        /// move any stake left on validators that have left the active set to the remaining ones
+        redelegate_inactive: bool,
+    },
     /// Send tokens to a specific address
     SendTokens { denom: AssetInfo, address: String },
     /// Swapping to an arbitrary token via Wyndex
@@ -51,10 +60,55 @@ pub enum JunoDestinationProject {
     RacoonBet { game: RacoonBetGame },
     /// Mint one of many Juno LSDs
     MintLsd { lsd_type: JunoLsd },
+    /// Swap to the given native denom via Wyndex and then
+    /// split what comes back between another set of prefs
+    Composite(CompositeDestination<JunoDestinationProject, String>),
     /// Do nothing with the funds
     Unallocated {},
 }
 
+impl NestedDestinations for JunoDestinationProject {
+    fn nested_prefs(&self) -> Option<(String, &CompoundPrefs<Self>)> {
+        match self {
+            JunoDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                Some((convert_to.clone(), then))
+            }
+            _ => None,
+        }
+    }
+
+    fn native_only(&self) -> bool {
+        matches!(
+            self,
+            JunoDestinationProject::JunoStaking { .. }
+                | JunoDestinationProject::JunoMultiStaking { .. }
+                | JunoDestinationProject::MintLsd { .. }
+        )
+    }
+}
+
+impl DestinationLabel for JunoDestinationProject {
+    fn label(&self) -> &str {
+        match self {
+            JunoDestinationProject::JunoStaking { .. } => "juno_staking",
+            JunoDestinationProject::JunoMultiStaking { .. } => "juno_multi_staking",
+            JunoDestinationProject::SendTokens { .. } => "send_tokens",
+            JunoDestinationProject::TokenSwap { .. } => "token_swap",
+            JunoDestinationProject::WyndStaking { .. } => "wynd_staking",
+            JunoDestinationProject::WyndLp { .. } => "wynd_lp",
+            JunoDestinationProject::GelottoLottery { .. } => "gelotto_lottery",
+            JunoDestinationProject::SparkIbcCampaign { .. } => "spark_ibc_campaign",
+            JunoDestinationProject::BalanceDao {} => "balance_dao",
+            JunoDestinationProject::WhiteWhaleSatellite { .. } => "white_whale_satellite",
+            JunoDestinationProject::DaoStaking(_) => "dao_staking",
+            JunoDestinationProject::RacoonBet { .. } => "racoon_bet",
+            JunoDestinationProject::MintLsd { .. } => "mint_lsd",
+            JunoDestinationProject::Composite(_) => "composite",
+            JunoDestinationProject::Unallocated {} => "unallocated",
+        }
+    }
+}
+
 #[cw_serde]
 pub enum StakingDao {
     /// Neta Dao
@@ -619,16 +673,3 @@ pub enum GelottoExecute {
         seed: u32,
     },
 }
-
-pub fn wyndex_asset_info_to_terraswap_asset_info(
-    asset_info: AssetInfo,
-) -> white_whale::pool_network::asset::AssetInfo {
-    match asset_info {
-        AssetInfo::Native(denom) => {
-            white_whale::pool_network::asset::AssetInfo::NativeToken { denom }
-        }
-        AssetInfo::Token(contract_addr) => {
-            white_whale::pool_network::asset::AssetInfo::Token { contract_addr }
-        }
-    }
-}
@@ -1,21 +1,29 @@
 use crate::{
     comp_prefs::{
-        GelottoAddrs, GelottoExecute, GelottoLottery, JunoLsd, JunoLsdAddrs, StakeEasyMsgs,
-        WyndStakingBondingPeriod,
+        DestinationProjectAddrs, GelottoAddrs, GelottoExecute, GelottoLottery, JunoLsd,
+        JunoLsdAddrs, StakeEasyMsgs, WyndStakingBondingPeriod,
     },
     errors::JunoDestinationError,
 };
 
 use cosmos_sdk_proto::cosmos::{bank::v1beta1::MsgSend, base::v1beta1::Coin as CsdkCoin};
-use cosmwasm_std::{to_json_binary, Addr, Attribute, Coin, Event, QuerierWrapper, Uint128};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Attribute, Coin, Decimal, Event, QuerierWrapper, Uint128,
+};
+use cw_grant_spec::grants::{GrantBase, GrantRequirement};
 use outpost_utils::{
+    asset::{OutpostAsset, OutpostAssetInfo},
     helpers::DestProjectMsgs,
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    price_source::{OraclePrice, PriceSource, SimulatedPrice},
+    swap_venue::{BestExecutionRouter, SplitOrder, SwapVenue},
 };
 use sail_destinations::comp_prefs::{RacoonBetExec, RacoonBetGame};
 
 use std::fmt::Display;
-use wynd_helpers::wynd_swap::simulate_wynd_pool_swap;
+use terraswap_helpers::terraswap_swap::TerraswapVenue;
+use wynd_helpers::wynd_swap::{simulate_wynd_pool_swap, WyndexVenue};
 use wyndex::asset::{Asset, AssetInfo};
 
 pub type DestinationResult = Result<DestProjectMsgs, JunoDestinationError>;
@@ -168,22 +176,16 @@ where
 pub fn send_tokens_msgs<T>(
     sender_addr: &T,
     recipient_addr: &T,
-    asset_to_send: Asset,
+    asset_to_send: impl Into<OutpostAsset>,
 ) -> DestinationResult
 where
     T: Into<String> + Display,
 {
+    let asset_to_send: OutpostAsset = asset_to_send.into();
+
     Ok(DestProjectMsgs {
         msgs: vec![match &asset_to_send.info {
-            AssetInfo::Native(denom) => CosmosProtoMsg::Send(MsgSend {
-                amount: vec![CsdkCoin {
-                    denom: denom.clone(),
-                    amount: asset_to_send.amount.into(),
-                }],
-                from_address: sender_addr.to_string(),
-                to_address: recipient_addr.to_string(),
-            }),
-            AssetInfo::Token(cw20_addr) => {
+            OutpostAssetInfo::Cw20(cw20_addr) => {
                 CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
                     cw20_addr.clone(),
                     sender_addr,
@@ -194,6 +196,11 @@ where
                     None,
                 )?)
             }
+            _ => CosmosProtoMsg::Send(MsgSend {
+                amount: vec![asset_to_send.to_csdk_coin()?],
+                from_address: sender_addr.to_string(),
+                to_address: recipient_addr.to_string(),
+            }),
         }],
         sub_msgs: vec![],
         events: vec![Event::new("send_tokens")
@@ -312,3 +319,107 @@ where
         events: vec![Event::new("racoon_bet").add_attributes(attributes)],
     })
 }
+
+/// The dexes that the juno outposts can swap through
+#[cw_serde]
+pub enum JunoSwapVenue {
+    Wyndex(WyndexVenue),
+    WhiteWhale(TerraswapVenue),
+}
+
+impl SwapVenue for JunoSwapVenue {
+    type Error = JunoDestinationError;
+
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, JunoDestinationError> {
+        Ok(match self {
+            JunoSwapVenue::Wyndex(venue) => venue.simulate(querier, offer, ask)?,
+            JunoSwapVenue::WhiteWhale(venue) => venue.simulate(querier, offer, ask)?,
+        })
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), JunoDestinationError> {
+        Ok(match self {
+            JunoSwapVenue::Wyndex(venue) => {
+                venue.swap_msgs(querier, sender, offer, ask, max_slippage)?
+            }
+            JunoSwapVenue::WhiteWhale(venue) => {
+                venue.swap_msgs(querier, sender, offer, ask, max_slippage)?
+            }
+        })
+    }
+
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        match self {
+            JunoSwapVenue::Wyndex(venue) => venue.grants(base, offer),
+            JunoSwapVenue::WhiteWhale(venue) => venue.grants(base, offer),
+        }
+    }
+}
+
+/// Routes swaps through whichever of the Wyndex and White Whale routers gives the best return
+pub fn juno_swap_router(
+    destination_projects: &DestinationProjectAddrs,
+    split: Option<SplitOrder>,
+) -> BestExecutionRouter<JunoSwapVenue> {
+    BestExecutionRouter::new(
+        vec![
+            JunoSwapVenue::Wyndex(WyndexVenue::Multihop(
+                destination_projects.wynd.multihop.clone(),
+            )),
+            JunoSwapVenue::WhiteWhale(TerraswapVenue::Multihop(
+                destination_projects
+                    .white_whale
+                    .terraswap_multihop_router
+                    .clone(),
+            )),
+        ],
+        split,
+    )
+}
+
+/// How a juno outpost prices a given asset
+#[cw_serde]
+pub enum JunoPriceSource {
+    /// A simulated swap of `probe_amount` on the given venue
+    PoolSimulation {
+        venue: JunoSwapVenue,
+        probe_amount: Uint128,
+    },
+    /// An oracle contract that answers `OracleQueryMsg::Price`
+    Oracle(OraclePrice),
+}
+
+impl PriceSource for JunoPriceSource {
+    type Error = JunoDestinationError;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, JunoDestinationError> {
+        match self {
+            JunoPriceSource::PoolSimulation {
+                venue,
+                probe_amount,
+            } => SimulatedPrice {
+                venue: venue.clone(),
+                probe_amount: *probe_amount,
+            }
+            .price(querier, base, quote),
+            JunoPriceSource::Oracle(oracle) => Ok(oracle.price(querier, base, quote)?),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use cosmwasm_std::StdError;
+use outpost_utils::errors::OutpostError;
 use sail_destinations::errors::SailDestinationError;
 use thiserror::Error;
 
@@ -7,12 +8,18 @@ pub enum JunoDestinationError {
     #[error("Outpost StdError: {0}")]
     Std(#[from] StdError),
 
+    #[error("Outpost Error: {0}")]
+    OutpostError(#[from] OutpostError),
+
     #[error("Juno Dest - Sail Destinations Error: &{0}")]
     SailDestinationsError(#[from] SailDestinationError),
 
     #[error("WyndHelper Error: {0}")]
     WyndHelperError(#[from] wynd_helpers::errors::WyndHelperError),
 
+    #[error("TerraswapHelper Error: {0}")]
+    TerraswapHelperError(#[from] terraswap_helpers::errors::TerraswapHelperError),
+
     #[error("Parsing invalid wynd pool bonding period: {0}")]
     InvalidBondingPeriod(String),
 
@@ -2,7 +2,10 @@ use crate::errors::MigalooDestinationError;
 
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{Addr, Api, Decimal, Uint128, Uint64};
-use outpost_utils::comp_prefs::CompoundPrefs;
+use outpost_utils::comp_prefs::{
+    CompositeDestination, CompoundPrefs, NestedDestinations, ValidatorSelection,
+};
+use outpost_utils::stats::DestinationLabel;
 use sail_destinations::comp_prefs::{FundMsg, RacoonBetGame};
 use white_whale::pool_network::{
     asset::{Asset, AssetInfo},
@@ -18,6 +21,13 @@ pub enum MigalooDestinationProject {
         validator_address: String,
     },
 
+    /// Native staking split across several validators
+    MigalooMultiStaking {
+        validators: Vec<ValidatorSelection>,
+        /// move any stake left on validators that have left the active set to the remaining ones
+        redelegate_inactive: bool,
+    },
+
     /// Send tokens to a specific address
     SendTokens {
         denom: AssetInfo,
@@ -105,12 +115,71 @@ pub enum MigalooDestinationProject {
     //     // this is basically only for the whale usdc pool that can be used for ecosystem staking
     //     and_then: Option<ProvideLiquidityAction>,
     // },
+    /// Swap along the given TerraSwap route and then
+    /// split what comes back between another set of prefs
+    Composite(CompositeDestination<MigalooDestinationProject, Vec<SwapOperation>>),
 
     // TODO: whale usdc alliance
     /// Do nothing with the funds
     Unallocated {},
 }
 
+impl NestedDestinations for MigalooDestinationProject {
+    fn nested_prefs(&self) -> Option<(String, &CompoundPrefs<Self>)> {
+        match self {
+            MigalooDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                route_ask_denom(convert_to).map(|denom| (denom, then))
+            }
+            _ => None,
+        }
+    }
+
+    fn native_only(&self) -> bool {
+        matches!(
+            self,
+            MigalooDestinationProject::MigalooStaking { .. }
+                | MigalooDestinationProject::MigalooMultiStaking { .. }
+                | MigalooDestinationProject::MintLsd { .. }
+                | MigalooDestinationProject::Furnace { .. }
+        )
+    }
+}
+
+impl DestinationLabel for MigalooDestinationProject {
+    fn label(&self) -> &str {
+        match self {
+            MigalooDestinationProject::MigalooStaking { .. } => "migaloo_staking",
+            MigalooDestinationProject::MigalooMultiStaking { .. } => "migaloo_multi_staking",
+            MigalooDestinationProject::SendTokens { .. } => "send_tokens",
+            MigalooDestinationProject::TokenSwap { .. } => "token_swap",
+            MigalooDestinationProject::AllianceStake { .. } => "alliance_stake",
+            MigalooDestinationProject::DaoDaoStake { .. } => "dao_dao_stake",
+            MigalooDestinationProject::SparkIbcCampaign { .. } => "spark_ibc_campaign",
+            MigalooDestinationProject::WhiteWhaleSatellite { .. } => "white_whale_satellite",
+            MigalooDestinationProject::RacoonBet { .. } => "racoon_bet",
+            MigalooDestinationProject::MintLsd { .. } => "mint_lsd",
+            MigalooDestinationProject::Furnace { .. } => "furnace",
+            MigalooDestinationProject::Vault { .. } => "vault",
+            MigalooDestinationProject::GinkouDepositUSDC { .. } => "ginkou_deposit_usdc",
+            MigalooDestinationProject::GinkouProvideLiquidity { .. } => "ginkou_provide_liquidity",
+            MigalooDestinationProject::GinkouRepayLoan {} => "ginkou_repay_loan",
+            MigalooDestinationProject::Composite(_) => "composite",
+            MigalooDestinationProject::Unallocated {} => "unallocated",
+        }
+    }
+}
+
+/// The native denom that the route ends up swapping into
+pub fn route_ask_denom(route: &[SwapOperation]) -> Option<String> {
+    match route.last() {
+        Some(SwapOperation::TerraSwap {
+            ask_asset_info: AssetInfo::NativeToken { denom },
+            ..
+        }) => Some(denom.clone()),
+        _ => None,
+    }
+}
+
 // native staking rewards, alliance rewards, lp rewards, sat market rewards
 
 // #[cw_serde]
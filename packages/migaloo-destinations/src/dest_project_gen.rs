@@ -5,16 +5,19 @@ use crate::comp_prefs::{
 use crate::errors::MigalooDestinationError;
 use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as CsdkCoin;
 use cosmos_sdk_proto::cosmos::staking::v1beta1::MsgDelegate;
-use cosmwasm_std::{to_json_binary, Addr, Event, QuerierWrapper, Uint128};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_json_binary, Addr, Decimal, Event, QuerierWrapper, Uint128};
 use outpost_utils::helpers::csdk_coins;
 use outpost_utils::{
+    asset::OutpostAssetInfo,
     helpers::DestProjectMsgs,
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    price_source::{OraclePrice, PriceSource, SimulatedPrice},
 };
 use sail_destinations::comp_prefs::{LsdQueryMsg, LsdStateResponse};
 use sail_destinations::dest_project_gen::{mint_eris_lsd_msgs, terraswap_pool_swap_msgs};
 use std::fmt::Display;
-use terraswap_helpers::terraswap_swap::simulate_pool_swap;
+use terraswap_helpers::terraswap_swap::{simulate_pool_swap, TerraswapVenue};
 use white_whale::pool_network::asset::{Asset, AssetInfo};
 
 pub type DestinationResult = Result<DestProjectMsgs, MigalooDestinationError>;
@@ -314,6 +317,7 @@ pub fn mint_or_buy_whale_lsd_msgs(
     lsd_addrs: &WhaleLsdAddrs,
     swap_routes: &DestProjectVerifiedSwapRoutes,
     denoms: &Denoms,
+    max_slippage: Option<Decimal>,
 ) -> Result<(Asset, DestProjectMsgs), MigalooDestinationError> {
     let whale_asset = Asset {
         info: AssetInfo::NativeToken {
@@ -343,6 +347,7 @@ pub fn mint_or_buy_whale_lsd_msgs(
                 user_addr,
                 &lsd.get_whale_pool_addr(swap_routes),
                 whale_asset,
+                max_slippage,
             )?,
         ))
     }
@@ -375,3 +380,38 @@ pub fn query_ginkou_musdc_mint(
         },
     })
 }
+
+/// How a migaloo outpost prices a given asset
+#[cw_serde]
+pub enum MigalooPriceSource {
+    /// A simulated swap of `probe_amount` on the given White Whale pool or router
+    PoolSimulation {
+        venue: TerraswapVenue,
+        probe_amount: Uint128,
+    },
+    /// An oracle contract that answers `OracleQueryMsg::Price`
+    Oracle(OraclePrice),
+}
+
+impl PriceSource for MigalooPriceSource {
+    type Error = MigalooDestinationError;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, MigalooDestinationError> {
+        match self {
+            MigalooPriceSource::PoolSimulation {
+                venue,
+                probe_amount,
+            } => Ok(SimulatedPrice {
+                venue: venue.clone(),
+                probe_amount: *probe_amount,
+            }
+            .price(querier, base, quote)?),
+            MigalooPriceSource::Oracle(oracle) => Ok(oracle.price(querier, base, quote)?),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use cosmwasm_std::StdError;
+use outpost_utils::errors::OutpostError;
 use sail_destinations::errors::SailDestinationError;
+use terraswap_helpers::errors::TerraswapHelperError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,6 +9,12 @@ pub enum MigalooDestinationError {
     #[error("Outpost StdError: {0}")]
     Std(#[from] StdError),
 
+    #[error("Outpost Error: {0}")]
+    OutpostError(#[from] OutpostError),
+
+    #[error("TerraswapHelper Error: {0}")]
+    TerraswapHelperError(#[from] TerraswapHelperError),
+
     #[error("Sail Destination Error in Migaloo Destinations: {0}")]
     SailDestinationError(#[from] SailDestinationError),
 
@@ -18,4 +26,7 @@ pub enum MigalooDestinationError {
 
     #[error("Invalid asset: {denom} for project: {project}")]
     InvalidAsset { denom: String, project: String },
+
+    #[error("Composite destination routes must end in a native token")]
+    InvalidCompositeRoute {},
 }
@@ -1,6 +1,9 @@
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{Addr, Api, Uint128};
-use outpost_utils::comp_prefs::CompoundPrefs;
+use outpost_utils::comp_prefs::{
+    CompositeDestination, CompoundPrefs, NestedDestinations, ValidatorSelection,
+};
+use outpost_utils::stats::DestinationLabel;
 
 use crate::{
     errors::OsmosisDestinationError,
@@ -40,6 +43,13 @@ pub enum OsmosisDestinationProject {
         validator_address: String,
     },
 
+    /// Stake the tokens split across several validators
+    OsmosisMultiStaking {
+        validators: Vec<ValidatorSelection>,
+        /// move any stake left on validators that have left the active set to the remaining ones
+        redelegate_inactive: bool,
+    },
+
     /// Swap the given denom for the target denom and leave that token liquid.
     TokenSwap {
         target_asset: TargetAsset,
@@ -155,9 +165,51 @@ pub enum OsmosisDestinationProject {
     // WhiteWhaleSatellite {
     //     asset: String,
     // },
+    /// Swap to the target asset and then split what comes back between another set of prefs
+    Composite(CompositeDestination<OsmosisDestinationProject, TargetAsset>),
+
     Unallocated {},
 }
 
+impl NestedDestinations for OsmosisDestinationProject {
+    fn nested_prefs(&self) -> Option<(String, &CompoundPrefs<Self>)> {
+        match self {
+            OsmosisDestinationProject::Composite(CompositeDestination { convert_to, then }) => {
+                Some((convert_to.denom.clone(), then))
+            }
+            _ => None,
+        }
+    }
+
+    fn native_only(&self) -> bool {
+        matches!(
+            self,
+            OsmosisDestinationProject::OsmosisStaking { .. }
+                | OsmosisDestinationProject::OsmosisMultiStaking { .. }
+                | OsmosisDestinationProject::MintLsd {
+                    lsd: OsmosisLsd::Eris
+                }
+        )
+    }
+}
+
+impl DestinationLabel for OsmosisDestinationProject {
+    fn label(&self) -> &str {
+        match self {
+            OsmosisDestinationProject::OsmosisStaking { .. } => "osmosis_staking",
+            OsmosisDestinationProject::OsmosisMultiStaking { .. } => "osmosis_multi_staking",
+            OsmosisDestinationProject::TokenSwap { .. } => "token_swap",
+            OsmosisDestinationProject::SendTokens { .. } => "send_tokens",
+            OsmosisDestinationProject::MembraneStake {} => "membrane_stake",
+            OsmosisDestinationProject::IonStaking {} => "ion_staking",
+            OsmosisDestinationProject::OsmosisLiquidityPool { .. } => "osmosis_liquidity_pool",
+            OsmosisDestinationProject::MintLsd { .. } => "mint_lsd",
+            OsmosisDestinationProject::Composite(_) => "composite",
+            OsmosisDestinationProject::Unallocated {} => "unallocated",
+        }
+    }
+}
+
 #[cw_serde]
 pub enum KnownPairedPoolAsset {
     OSMO,
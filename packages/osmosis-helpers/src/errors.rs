@@ -1,4 +1,5 @@
 use cosmwasm_std::{DivideByZeroError, StdError};
+use outpost_utils::errors::OutpostError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +7,9 @@ pub enum OsmosisHelperError {
     #[error("Outpost StdError: {0}")]
     Std(#[from] StdError),
 
+    #[error("Outpost Error: {0}")]
+    OutpostError(#[from] OutpostError),
+
     #[error("Divide by zero error: {0}")]
     DivideByZeroError(#[from] DivideByZeroError),
 
@@ -33,6 +37,9 @@ pub enum OsmosisHelperError {
     #[error("Cannot enter pool- incorrect assets")]
     InvalidAssets,
 
+    #[error("Could not get the twap for pool {pool_id}: {error}")]
+    TwapUnavailable { pool_id: u64, error: String },
+
     #[error("Could not simulate swap of {from} to {to}")]
     SwapSimulationError { from: String, to: String },
 }
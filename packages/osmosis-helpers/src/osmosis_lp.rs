@@ -1,5 +1,5 @@
 use cosmwasm_std::{
-    coin, Addr, Coin, Event, QuerierWrapper, StdError, Storage, Timestamp, Uint128,
+    coin, Addr, Coin, Decimal, Event, QuerierWrapper, StdError, Storage, Timestamp, Uint128,
 };
 use cw_grant_spec::grants::{GrantBase, GrantRequirement};
 
@@ -19,7 +19,7 @@ use crate::errors::OsmosisHelperError;
 use crate::osmosis_swap::estimate_token_out_min_amount;
 use crate::osmosis_swap::{
     generate_known_to_known_swap_and_sim_msg, osmosis_swap_grants, pool_swap_with_sim,
-    OsmosisRoutePools,
+    OsmosisRoutePools, TwapFallback,
 };
 
 pub fn query_pool_info(
@@ -54,6 +54,8 @@ pub fn classic_pool_join_single_side_prepratory_swap(
     offer_asset: &Coin,
     pool_routes: OsmosisRoutePools,
     current_timestamp: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<SingleSidedJoinSwap, OsmosisHelperError> {
     let pool_tokens = query_pool_info(PoolmanagerQuerier::new(querier), pool_id)?
         .pool_assets
@@ -91,6 +93,8 @@ pub fn classic_pool_join_single_side_prepratory_swap(
                 offer_asset,
                 &target_pool_target.denom,
                 current_timestamp,
+                max_slippage,
+                twap_fallback,
             )?;
             return Ok(SingleSidedJoinSwap {
                 join_asset: coin(sim.u128(), target_pool_target.denom.clone()),
@@ -147,6 +151,8 @@ pub fn join_osmosis_cl_pool_single_side(
     token_min_amount0: Uint128,
     token_min_amount1: Uint128,
     current_timestamp: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<Vec<CosmosProtoMsg>, OsmosisHelperError> {
     let pool_querier = PoolmanagerQuerier::new(querier);
 
@@ -178,14 +184,13 @@ pub fn join_osmosis_cl_pool_single_side(
 
     // the offer asset exists in the pool and is coin_a
     if let Some((_, coin_b)) = pool_assets {
+        // only half of the offer asset is swapped so the min out is based on that half
+        let swap_amount = offer_asset.amount.checked_div(2u128.into())?;
         let (mut pre_swap, est_token_b) = pool_swap_with_sim(
             querier,
             user_addr,
             &pool_id,
-            &coin(
-                offer_asset.amount.checked_div(2u128.into())?.u128(),
-                offer_asset.denom.clone(),
-            ),
+            &coin(swap_amount.u128(), offer_asset.denom.clone()),
             &coin_b.denom,
             estimate_token_out_min_amount(
                 querier,
@@ -195,8 +200,10 @@ pub fn join_osmosis_cl_pool_single_side(
                     token_out_denom: coin_b.denom.clone(),
                 }],
                 offer_asset.denom.clone(),
-                offer_asset.amount,
+                swap_amount,
                 current_timestamp,
+                max_slippage,
+                twap_fallback,
             )?,
         )?;
 
@@ -207,8 +214,7 @@ pub fn join_osmosis_cl_pool_single_side(
             upper_tick,
             tokens_provided: vec![
                 OsmosisCoin {
-                    amount: (offer_asset.amount - offer_asset.amount.checked_div(2u128.into())?)
-                        .to_string(),
+                    amount: (offer_asset.amount - swap_amount).to_string(),
                     denom: offer_asset.denom.clone(),
                 },
                 OsmosisCoin {
@@ -240,6 +246,8 @@ pub fn gen_join_cl_pool_single_sided_msgs(
     token_min_amount0: Uint128,
     token_min_amount1: Uint128,
     current_time: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<DestProjectMsgs, OsmosisHelperError> {
     let join_pool_msgs = join_osmosis_cl_pool_single_side(
         querier,
@@ -252,6 +260,8 @@ pub fn gen_join_cl_pool_single_sided_msgs(
         token_min_amount0,
         token_min_amount1,
         current_time,
+        max_slippage,
+        twap_fallback,
     )?;
 
     Ok(DestProjectMsgs {
@@ -273,6 +283,8 @@ pub fn gen_join_classic_pool_single_sided_msgs(
     offer_token: &Coin,
     bond_tokens: bool,
     current_timestamp: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<DestProjectMsgs, OsmosisHelperError> {
     let SingleSidedJoinSwap {
         join_asset,
@@ -286,6 +298,8 @@ pub fn gen_join_classic_pool_single_sided_msgs(
         offer_token,
         route_pools,
         current_timestamp,
+        max_slippage,
+        twap_fallback,
     )?;
 
     let join_pool_msgs =
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    Addr, BlockInfo, Coin as CWCoin, Decimal, QuerierWrapper, StdResult, Storage, Timestamp,
+    Addr, BlockInfo, Coin as CWCoin, Decimal, Event, QuerierWrapper, StdResult, Storage, Timestamp,
     Uint128,
 };
 
@@ -18,12 +18,18 @@ use osmosis_std::{
         poolmanager::v1beta1::{
             EstimateSwapExactAmountInRequest, EstimateSwapExactAmountInResponse,
             EstimateSwapExactAmountOutResponse, MsgSwapExactAmountIn, MsgSwapExactAmountOut,
-            SwapAmountInRoute, SwapAmountOutRoute,
+            SpotPriceRequest, SwapAmountInRoute, SwapAmountOutRoute,
         },
         twap::v1beta1::ArithmeticTwapToNowResponse,
     },
 };
-use outpost_utils::msg_gen::CosmosProtoMsg;
+use outpost_utils::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    helpers::min_out_after_slippage,
+    msg_gen::CosmosProtoMsg,
+    price_source::{OraclePrice, PriceImpactGuard, PriceSource, SimulatedPrice},
+    swap_venue::SwapVenue,
+};
 
 use crate::errors::OsmosisHelperError;
 
@@ -324,6 +330,8 @@ pub fn generate_known_to_known_swap_and_sim_msg(
     from_asset: &CWCoin,
     to_denom: &str,
     current_time: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<(Uint128, Vec<CosmosProtoMsg>), OsmosisHelperError> {
     generate_swap_and_sim_msg(
         querier,
@@ -333,6 +341,8 @@ pub fn generate_known_to_known_swap_and_sim_msg(
         to_denom.to_string(),
         generate_known_to_known_route(store, pool_routes, &from_asset.denom, to_denom)?,
         current_time,
+        max_slippage,
+        twap_fallback,
     )
 }
 
@@ -345,6 +355,8 @@ pub fn generate_known_to_unknown_swap_and_sim_msg(
     from_asset: &CWCoin,
     to_asset: TargetAsset,
     current_time: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<(Uint128, Vec<CosmosProtoMsg>), OsmosisHelperError> {
     generate_swap_and_sim_msg(
         querier,
@@ -354,6 +366,8 @@ pub fn generate_known_to_unknown_swap_and_sim_msg(
         to_asset.denom.clone(),
         generate_known_to_unknown_route(store, pool_routes, &from_asset.denom, to_asset)?,
         current_time,
+        max_slippage,
+        twap_fallback,
     )
 }
 
@@ -366,6 +380,8 @@ pub fn generate_swap_and_sim_msg(
     to_denom: String,
     route: Vec<SwapAmountInRoute>,
     current_time: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<(Uint128, Vec<CosmosProtoMsg>), OsmosisHelperError> {
     if from_asset.denom.eq(&to_denom) {
         return Ok((from_asset.amount.clone(), vec![]));
@@ -386,12 +402,47 @@ pub fn generate_swap_and_sim_msg(
             from_asset.denom.clone(),
             from_asset.amount,
             current_time,
+            max_slippage,
+            twap_fallback,
         )?,
     )];
 
     Ok((simulation, swap_msgs))
 }
 
+/// What to do when a hop of a route doesn't have a twap.
+/// Pools that are newer than the twap duration won't have one yet
+#[cw_serde]
+#[derive(Default)]
+pub enum TwapFallback {
+    /// Fail the swap if any hop of the route is missing its twap
+    #[default]
+    Fail,
+    /// Use the pool's current spot price for any hop that is missing its twap
+    SpotPrice,
+}
+
+impl TwapFallback {
+    /// Picks the price to use for a hop given the result of its twap query
+    pub fn resolve_price(
+        &self,
+        pool_id: u64,
+        twap: Result<Decimal, OsmosisHelperError>,
+        spot_price: impl FnOnce() -> Result<Decimal, OsmosisHelperError>,
+    ) -> Result<Decimal, OsmosisHelperError> {
+        match (twap, self) {
+            (Ok(price), _) => Ok(price),
+            (Err(_), TwapFallback::SpotPrice) => spot_price(),
+            (Err(err), TwapFallback::Fail) => Err(OsmosisHelperError::TwapUnavailable {
+                pool_id,
+                error: err.to_string(),
+            }),
+        }
+    }
+}
+
+/// Estimates the least that the swap should return based on the twap of the route.
+/// Without a `max_slippage` the swap accepts 1% less than the twap
 pub fn estimate_token_out_min_amount(
     querier: &QuerierWrapper,
     twap_duration_seconds: &u64,
@@ -399,32 +450,94 @@ pub fn estimate_token_out_min_amount(
     denom_in: String,
     amount_in: Uint128,
     current_time: Timestamp,
+    max_slippage: Option<Decimal>,
+    twap_fallback: &TwapFallback,
 ) -> Result<Uint128, OsmosisHelperError> {
-    let twap = TwapQuerier::new(querier);
+    let twap_start = current_time.minus_seconds(twap_duration_seconds.to_owned());
+
+    estimate_route_min_out(
+        route,
+        &denom_in,
+        amount_in,
+        max_slippage,
+        |pool_id, in_denom, out_denom| {
+            hop_twap_price(
+                querier,
+                pool_id,
+                in_denom,
+                out_denom,
+                twap_start,
+                twap_fallback,
+            )
+        },
+    )
+}
 
-    let mut in_denom = denom_in;
-    let mut token_out_min_amount = amount_in;
-    for route_section in route.iter() {
-        let twap_start = current_time.minus_seconds(twap_duration_seconds.to_owned());
-        // get the twap for this section of the multihop route
-        let resp = twap.arithmetic_twap_to_now(
-            route_section.pool_id,
-            in_denom,
-            route_section.token_out_denom.clone(),
+/// Gets the twap for a single section of a multihop route,
+/// falling back to the spot price if the twap fallback allows it
+fn hop_twap_price(
+    querier: &QuerierWrapper,
+    pool_id: u64,
+    in_denom: &str,
+    out_denom: &str,
+    twap_start: Timestamp,
+    twap_fallback: &TwapFallback,
+) -> Result<Decimal, OsmosisHelperError> {
+    let twap_price = TwapQuerier::new(querier)
+        .arithmetic_twap_to_now(
+            pool_id,
+            in_denom.to_string(),
+            out_denom.to_string(),
             Some(shim::Timestamp {
                 seconds: i64::try_from(twap_start.seconds())?,
                 nanos: 0,
             }),
-        )?;
+        )
+        .map_err(OsmosisHelperError::from)
+        .and_then(|resp| Ok(Decimal::from_str(&resp.arithmetic_twap)?));
+
+    twap_fallback.resolve_price(pool_id, twap_price, || {
+        let resp = SpotPriceRequest {
+            pool_id,
+            base_asset_denom: in_denom.to_string(),
+            quote_asset_denom: out_denom.to_string(),
+        }
+        .query(querier)?;
+
+        Ok(Decimal::from_str(&resp.spot_price)?)
+    })
+}
+
+/// Walks the route hop by hop, converting the amount at each hop using the price given by
+/// `hop_price` for the hop's pool, in denom and out denom.
+/// The final amount then has the `max_slippage` taken off of it
+pub fn estimate_route_min_out(
+    route: &[SwapAmountInRoute],
+    denom_in: &str,
+    amount_in: Uint128,
+    max_slippage: Option<Decimal>,
+    mut hop_price: impl FnMut(u64, &str, &str) -> Result<Decimal, OsmosisHelperError>,
+) -> Result<Uint128, OsmosisHelperError> {
+    let mut in_denom = denom_in;
+    let mut token_out_amount = amount_in;
+    for route_section in route.iter() {
+        // each hop swaps whatever the previous hop gave back
+        token_out_amount = token_out_amount
+            * hop_price(
+                route_section.pool_id,
+                in_denom,
+                &route_section.token_out_denom,
+            )?;
 
         // update the in denom for the next section
-        in_denom = route_section.token_out_denom.clone();
-        // calculate the minimum amount of the next token to receive
-        token_out_min_amount = amount_in * Decimal::from_str(&resp.arithmetic_twap)?;
+        in_denom = &route_section.token_out_denom;
     }
 
-    // return 99% of the calculated amount to account for fluctuation in the past minute plus swap fees
-    Ok(token_out_min_amount * Decimal::percent(99u64))
+    // leave room for the slippage to account for fluctuation in the past minute plus swap fees
+    Ok(min_out_after_slippage(
+        token_out_amount,
+        max_slippage.unwrap_or(Decimal::percent(1)),
+    ))
 }
 
 pub fn generate_swap(
@@ -500,3 +613,254 @@ pub fn osmosis_swap_grants(base: GrantBase) -> Vec<GrantRequirement> {
         MsgSwapExactAmountIn::TYPE_URL,
     )]
 }
+
+/// A swap through the poolmanager along a route that has already been resolved,
+/// usually via `generate_known_to_known_route` or `generate_known_to_unknown_route`.
+/// The swap's min out is estimated from the twap of the route
+#[derive(Clone, Debug)]
+pub struct OsmosisVenue {
+    pub route: Vec<SwapAmountInRoute>,
+    pub twap_duration_seconds: u64,
+    pub current_time: Timestamp,
+    pub twap_fallback: TwapFallback,
+}
+
+impl OsmosisVenue {
+    /// Ensures that the route actually ends in the ask denom
+    fn validate_route(&self, ask_denom: &str) -> Result<(), OsmosisHelperError> {
+        validate_route_end(&self.route, ask_denom)
+    }
+}
+
+/// Ensures that the route actually ends in the ask denom
+fn validate_route_end(
+    route: &[SwapAmountInRoute],
+    ask_denom: &str,
+) -> Result<(), OsmosisHelperError> {
+    match route.last() {
+        Some(SwapAmountInRoute {
+            token_out_denom, ..
+        }) if token_out_denom.ne(ask_denom) => Err(OsmosisHelperError::InvalidRouteDenom {
+            denom: ask_denom.to_string(),
+            label: "route does not end in the ask denom".to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+impl SwapVenue for OsmosisVenue {
+    type Error = OsmosisHelperError;
+
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, OsmosisHelperError> {
+        let offer_coin = offer.to_coin()?;
+        let ask_denom = ask.try_denom()?;
+
+        // no swap to do because the offer and ask tokens are the same
+        if offer_coin.denom.eq(&ask_denom) {
+            return Ok(offer.amount);
+        }
+        self.validate_route(&ask_denom)?;
+
+        let (simulation, _) = simulate_swap(querier, &offer_coin, self.route.clone())?;
+
+        Ok(Uint128::from_str(&simulation.token_out_amount)?)
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), OsmosisHelperError> {
+        let ask_denom = ask.try_denom()?;
+        self.validate_route(&ask_denom)?;
+
+        let (simulation, swap_msgs) = generate_swap_and_sim_msg(
+            querier,
+            &self.twap_duration_seconds,
+            sender,
+            &offer.to_coin()?,
+            ask_denom,
+            self.route.clone(),
+            self.current_time,
+            max_slippage,
+            &self.twap_fallback,
+        )?;
+
+        Ok((swap_msgs, simulation))
+    }
+
+    fn grants(&self, base: GrantBase, _offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        osmosis_swap_grants(base)
+    }
+}
+
+/// Prices assets by multiplying together the twaps of every pool along a route
+#[derive(Clone, Debug)]
+pub struct TwapPrice {
+    pub route: Vec<SwapAmountInRoute>,
+    pub twap_duration_seconds: u64,
+    pub current_time: Timestamp,
+    pub twap_fallback: TwapFallback,
+}
+
+impl PriceSource for TwapPrice {
+    type Error = OsmosisHelperError;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, OsmosisHelperError> {
+        let base_denom = base.try_denom()?;
+        let quote_denom = quote.try_denom()?;
+
+        if base_denom.eq(&quote_denom) {
+            return Ok(Decimal::one());
+        }
+        validate_route_end(&self.route, &quote_denom)?;
+
+        let twap_start = self.current_time.minus_seconds(self.twap_duration_seconds);
+
+        let mut in_denom = base_denom.as_str();
+        let mut price = Decimal::one();
+        for SwapAmountInRoute {
+            pool_id,
+            token_out_denom,
+        } in self.route.iter()
+        {
+            price *= hop_twap_price(
+                querier,
+                *pool_id,
+                in_denom,
+                token_out_denom,
+                twap_start,
+                &self.twap_fallback,
+            )?;
+            in_denom = token_out_denom;
+        }
+
+        Ok(price)
+    }
+}
+
+/// Checks the price impact of swapping `offer` along `route` against the route's twap.
+/// Returns the event recording the measured impact, or nothing when there is no swap to do.
+/// Errors if the impact is above the guard's max
+pub fn check_route_price_impact(
+    querier: &QuerierWrapper,
+    guard: &PriceImpactGuard,
+    route: &[SwapAmountInRoute],
+    offer: &CWCoin,
+    twap_duration_seconds: u64,
+    current_time: Timestamp,
+    twap_fallback: TwapFallback,
+) -> Result<Option<Event>, OsmosisHelperError> {
+    let Some(SwapAmountInRoute {
+        token_out_denom, ..
+    }) = route.last()
+    else {
+        return Ok(None);
+    };
+
+    let (simulation, _) = simulate_swap(querier, offer, route.to_vec())?;
+
+    // label the event with the pools so that the thin ones are easy to spot
+    let pools = route
+        .iter()
+        .map(|hop| hop.pool_id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    Ok(Some(guard.check_against(
+        querier,
+        &TwapPrice {
+            route: route.to_vec(),
+            twap_duration_seconds,
+            current_time,
+            twap_fallback,
+        },
+        &format!("osmosis pools {pools}"),
+        &offer.into(),
+        &OutpostAssetInfo::from_denom(token_out_denom),
+        Uint128::from_str(&simulation.token_out_amount)?,
+    )?))
+}
+
+/// How an osmosis outpost prices a given asset.
+/// The twap and simulation sources use the asset's known route to the quote asset
+#[cw_serde]
+pub enum OsmosisPriceSource {
+    /// The twap of the pools along the asset's route
+    Twap,
+    /// A simulated swap of `probe_amount` along the asset's route
+    PoolSimulation { probe_amount: Uint128 },
+    /// An oracle contract that answers `OracleQueryMsg::Price`
+    Oracle(OraclePrice),
+}
+
+impl OsmosisPriceSource {
+    /// Resolves the configured source into something that can be queried.
+    /// `route` is only generated if the source actually needs it
+    pub fn resolve(
+        &self,
+        route: impl FnOnce() -> Result<Vec<SwapAmountInRoute>, OsmosisHelperError>,
+        twap_duration_seconds: u64,
+        current_time: Timestamp,
+        twap_fallback: TwapFallback,
+    ) -> Result<OsmosisPrice, OsmosisHelperError> {
+        Ok(match self {
+            OsmosisPriceSource::Twap => OsmosisPrice::Twap(TwapPrice {
+                route: route()?,
+                twap_duration_seconds,
+                current_time,
+                twap_fallback,
+            }),
+            OsmosisPriceSource::PoolSimulation { probe_amount } => {
+                OsmosisPrice::PoolSimulation(SimulatedPrice {
+                    venue: OsmosisVenue {
+                        route: route()?,
+                        twap_duration_seconds,
+                        current_time,
+                        twap_fallback,
+                    },
+                    probe_amount: *probe_amount,
+                })
+            }
+            OsmosisPriceSource::Oracle(oracle) => OsmosisPrice::Oracle(oracle.clone()),
+        })
+    }
+}
+
+/// A resolved `OsmosisPriceSource`
+#[derive(Clone, Debug)]
+pub enum OsmosisPrice {
+    Twap(TwapPrice),
+    PoolSimulation(SimulatedPrice<OsmosisVenue>),
+    Oracle(OraclePrice),
+}
+
+impl PriceSource for OsmosisPrice {
+    type Error = OsmosisHelperError;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, OsmosisHelperError> {
+        match self {
+            OsmosisPrice::Twap(twap) => twap.price(querier, base, quote),
+            OsmosisPrice::PoolSimulation(simulation) => simulation.price(querier, base, quote),
+            OsmosisPrice::Oracle(oracle) => Ok(oracle.price(querier, base, quote)?),
+        }
+    }
+}
@@ -1,14 +1,29 @@
-use cosmwasm_std::Uint128;
+use std::collections::HashMap;
+
+use cosmwasm_std::{
+    coin,
+    testing::{mock_dependencies, mock_env},
+    Addr, Decimal, QuerierWrapper, StdError, Uint128,
+};
 use osmosis_destinations::{
     comp_prefs::DestProjectSwapRoutes,
     pools::{Denoms, OsmoPools, OsmosisKnownPoolListing, UsdcPools},
 };
 use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use outpost_utils::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    swap_venue::SwapVenue,
+};
 
-use crate::osmosis_swap::{unsafe_generate_known_to_known_route, KnownRoutePools};
+use crate::{
+    errors::OsmosisHelperError,
+    osmosis_swap::{
+        estimate_route_min_out, unsafe_generate_known_to_known_route, KnownRoutePools,
+        OsmosisVenue, TwapFallback,
+    },
+};
 
-#[test]
-fn generate_known_to_known_routes() {
+fn known_routes() -> (Denoms, DestProjectSwapRoutes) {
     let denoms: Denoms = Denoms {
         usdc: "ibc/uusdc".to_string(),
         axlusdc: "ibc/uaxlusdc".to_string(),
@@ -87,6 +102,13 @@ fn generate_known_to_known_routes() {
         usdc_pools,
     };
 
+    (denoms, pools)
+}
+
+#[test]
+fn generate_known_to_known_routes() {
+    let (denoms, pools) = known_routes();
+
     assert_eq!(
         unsafe_generate_known_to_known_route(
             &pools,
@@ -245,3 +267,301 @@ fn generate_known_to_known_routes() {
         "Should generate a known route from axlUSDC to ION via usdc and then osmo"
     );
 }
+
+/// Looks up the price of a hop from a table keyed by pool id, in denom and out denom
+fn table_price(
+    prices: &HashMap<(u64, String, String), Decimal>,
+    pool_id: u64,
+    in_denom: &str,
+    out_denom: &str,
+) -> Result<Decimal, OsmosisHelperError> {
+    prices
+        .get(&(pool_id, in_denom.to_string(), out_denom.to_string()))
+        .copied()
+        .ok_or_else(|| StdError::generic_err(format!("no twap for pool {}", pool_id)).into())
+}
+
+#[test]
+fn estimate_multi_hop_min_out() {
+    let (denoms, pools) = known_routes();
+
+    let prices: HashMap<(u64, String, String), Decimal> = HashMap::from([
+        (
+            (5, denoms.osmo.clone(), denoms.atom.clone()),
+            Decimal::percent(10),
+        ),
+        (
+            (7, denoms.mbrn.clone(), denoms.osmo.clone()),
+            Decimal::percent(200),
+        ),
+        (
+            (6, denoms.osmo.clone(), denoms.whale.clone()),
+            Decimal::percent(50),
+        ),
+        (
+            (3, denoms.mars.clone(), denoms.osmo.clone()),
+            Decimal::percent(25),
+        ),
+        (
+            (4, denoms.osmo.clone(), denoms.usdc.clone()),
+            Decimal::percent(40),
+        ),
+        (
+            (15, denoms.usdc.clone(), denoms.axlusdc.clone()),
+            Decimal::one(),
+        ),
+    ]);
+
+    let osmo_to_atom = unsafe_generate_known_to_known_route(
+        &pools,
+        &denoms,
+        &denoms.osmo,
+        &denoms.atom,
+        KnownRoutePools {
+            from_token_osmo_pool: None,
+            to_token_osmo_pool: Some(5),
+            from_token_usdc_pool: Some(13),
+            to_token_usdc_pool: Some(12),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        estimate_route_min_out(
+            &osmo_to_atom,
+            &denoms.osmo,
+            Uint128::new(1_000_000),
+            None,
+            |pool_id, in_denom, out_denom| table_price(&prices, pool_id, in_denom, out_denom)
+        )
+        .unwrap(),
+        Uint128::new(99_000),
+        "Should take the default 1% off of a single hop"
+    );
+
+    let mbrn_to_whale = unsafe_generate_known_to_known_route(
+        &pools,
+        &denoms,
+        &denoms.mbrn,
+        &denoms.whale,
+        KnownRoutePools {
+            from_token_osmo_pool: Some(7),
+            to_token_osmo_pool: Some(6),
+            from_token_usdc_pool: None,
+            to_token_usdc_pool: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        estimate_route_min_out(
+            &mbrn_to_whale,
+            &denoms.mbrn,
+            Uint128::new(1_000_000),
+            Some(Decimal::percent(2)),
+            |pool_id, in_denom, out_denom| table_price(&prices, pool_id, in_denom, out_denom)
+        )
+        .unwrap(),
+        Uint128::new(980_000),
+        "Should chain the osmo received from the first hop into the second hop"
+    );
+
+    let mut hops = vec![];
+    let mars_to_axlusdc = unsafe_generate_known_to_known_route(
+        &pools,
+        &denoms,
+        &denoms.mars,
+        &denoms.axlusdc,
+        KnownRoutePools {
+            from_token_osmo_pool: Some(3),
+            to_token_osmo_pool: None,
+            from_token_usdc_pool: None,
+            to_token_usdc_pool: Some(15),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        estimate_route_min_out(
+            &mars_to_axlusdc,
+            &denoms.mars,
+            Uint128::new(1_000_000),
+            None,
+            |pool_id, in_denom, out_denom| {
+                hops.push((pool_id, in_denom.to_string(), out_denom.to_string()));
+                table_price(&prices, pool_id, in_denom, out_denom)
+            }
+        )
+        .unwrap(),
+        Uint128::new(99_000),
+        "Should chain the prices of all three hops"
+    );
+    assert_eq!(
+        hops,
+        vec![
+            (3, denoms.mars.clone(), denoms.osmo.clone()),
+            (4, denoms.osmo.clone(), denoms.usdc.clone()),
+            (15, denoms.usdc.clone(), denoms.axlusdc.clone()),
+        ],
+        "Each hop should be priced from the previous hop's out denom"
+    );
+
+    let axlusdc_to_ion = unsafe_generate_known_to_known_route(
+        &pools,
+        &denoms,
+        &denoms.axlusdc,
+        &denoms.ion,
+        KnownRoutePools {
+            from_token_osmo_pool: None,
+            to_token_osmo_pool: Some(2),
+            from_token_usdc_pool: Some(15),
+            to_token_usdc_pool: None,
+        },
+    )
+    .unwrap();
+
+    assert!(
+        estimate_route_min_out(
+            &axlusdc_to_ion,
+            &denoms.axlusdc,
+            Uint128::new(1_000_000),
+            None,
+            |pool_id, in_denom, out_denom| table_price(&prices, pool_id, in_denom, out_denom)
+        )
+        .is_err(),
+        "Should fail when a hop has no price"
+    );
+}
+
+#[test]
+fn twap_fallback_policy() {
+    let (denoms, pools) = known_routes();
+
+    // the ion pool is too new to have a twap but does have a spot price
+    let twaps: HashMap<(u64, String, String), Decimal> = HashMap::from([
+        (
+            (15, denoms.axlusdc.clone(), denoms.usdc.clone()),
+            Decimal::one(),
+        ),
+        (
+            (4, denoms.usdc.clone(), denoms.osmo.clone()),
+            Decimal::percent(250),
+        ),
+    ]);
+    let spot_prices: HashMap<(u64, String, String), Decimal> = HashMap::from([(
+        (2, denoms.osmo.clone(), denoms.ion.clone()),
+        Decimal::permille(1),
+    )]);
+
+    let axlusdc_to_ion = unsafe_generate_known_to_known_route(
+        &pools,
+        &denoms,
+        &denoms.axlusdc,
+        &denoms.ion,
+        KnownRoutePools {
+            from_token_osmo_pool: None,
+            to_token_osmo_pool: Some(2),
+            from_token_usdc_pool: Some(15),
+            to_token_usdc_pool: None,
+        },
+    )
+    .unwrap();
+
+    let estimate = |twap_fallback: TwapFallback| {
+        estimate_route_min_out(
+            &axlusdc_to_ion,
+            &denoms.axlusdc,
+            Uint128::new(1_000_000),
+            Some(Decimal::percent(5)),
+            |pool_id, in_denom, out_denom| {
+                twap_fallback.resolve_price(
+                    pool_id,
+                    table_price(&twaps, pool_id, in_denom, out_denom),
+                    || table_price(&spot_prices, pool_id, in_denom, out_denom),
+                )
+            },
+        )
+    };
+
+    assert_eq!(
+        estimate(TwapFallback::SpotPrice).unwrap(),
+        Uint128::new(2_375),
+        "Should use the spot price for the hop without a twap"
+    );
+
+    assert!(
+        matches!(
+            estimate(TwapFallback::Fail),
+            Err(OsmosisHelperError::TwapUnavailable { pool_id: 2, .. })
+        ),
+        "Should fail when the policy doesn't allow falling back to the spot price"
+    );
+
+    assert_eq!(
+        TwapFallback::default(),
+        TwapFallback::Fail,
+        "Should not fall back to the spot price unless configured to"
+    );
+
+    assert_eq!(
+        TwapFallback::SpotPrice
+            .resolve_price(5, Ok(Decimal::percent(10)), || panic!(
+                "spot price not needed"
+            ))
+            .unwrap(),
+        Decimal::percent(10),
+        "Should prefer the twap when there is one"
+    );
+}
+
+#[test]
+fn osmosis_swap_venue() {
+    let deps = mock_dependencies();
+    let querier = QuerierWrapper::new(&deps.querier);
+    let user = Addr::unchecked("user");
+
+    let venue = OsmosisVenue {
+        route: vec![SwapAmountInRoute {
+            pool_id: 1,
+            token_out_denom: "uion".to_string(),
+        }],
+        twap_duration_seconds: 60,
+        current_time: mock_env().block.time,
+        twap_fallback: TwapFallback::Fail,
+    };
+    let offer = OutpostAsset::from(coin(1_000, "uosmo"));
+
+    assert_eq!(
+        venue
+            .simulate(&querier, &offer, &OutpostAssetInfo::from_denom("uosmo"))
+            .unwrap(),
+        Uint128::new(1_000),
+        "Should not need to swap when the offer and ask are the same"
+    );
+
+    assert!(
+        matches!(
+            venue.swap_msgs(
+                &querier,
+                &user,
+                &offer,
+                &OutpostAssetInfo::from_denom("uatom"),
+                None
+            ),
+            Err(OsmosisHelperError::InvalidRouteDenom { denom, .. }) if denom == "uatom"
+        ),
+        "Should reject asks that the route doesn't end in"
+    );
+
+    assert!(
+        matches!(
+            venue.simulate(
+                &querier,
+                &OutpostAssetInfo::Cw20("osmo1cw20".to_string()).with_amount(1_000u128),
+                &OutpostAssetInfo::from_denom("uion"),
+            ),
+            Err(OsmosisHelperError::OutpostError(_))
+        ),
+        "Should not be able to swap cw20s"
+    );
+}
@@ -4,10 +4,11 @@ use crate::{
 };
 use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as CsdkCoin;
 use cosmwasm_std::{
-    to_json_binary, Addr, Attribute, Coin, Event, QuerierWrapper, Uint128,
+    to_json_binary, Addr, Attribute, Coin, Decimal, Event, QuerierWrapper, Uint128,
 };
 use outpost_utils::{
-    helpers::{csdk_coins, DestProjectMsgs},
+    asset::{OutpostAsset, OutpostAssetInfo},
+    helpers::DestProjectMsgs,
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
 };
 use std::fmt::Display;
@@ -178,12 +179,14 @@ pub fn terraswap_pool_swap_msgs(
     user_addr: &Addr,
     pool_addr: &Addr,
     offer_asset: Asset,
+    max_slippage: Option<Decimal>,
 ) -> DestinationResult {
     Ok(DestProjectMsgs {
         msgs: vec![create_terraswap_pool_swap_msg(
             user_addr,
             offer_asset.clone(),
             pool_addr,
+            max_slippage,
         )?],
         sub_msgs: vec![],
         events: vec![Event::new("terraswap_pool_swap")
@@ -197,6 +200,7 @@ pub fn terraswap_pool_swap_with_sim_msgs(
     user_addr: &Addr,
     pool_addr: &Addr,
     offer_asset: Asset,
+    max_slippage: Option<Decimal>,
 ) -> Result<(Uint128, DestProjectMsgs), SailDestinationError> {
     Ok((
         simulate_pool_swap(querier, &pool_addr.to_string(), &offer_asset)?.return_amount,
@@ -205,6 +209,7 @@ pub fn terraswap_pool_swap_with_sim_msgs(
                 user_addr,
                 offer_asset.clone(),
                 pool_addr,
+                max_slippage,
             )?],
             sub_msgs: vec![],
             events: vec![Event::new("terraswap_pool_swap")
@@ -214,30 +219,32 @@ pub fn terraswap_pool_swap_with_sim_msgs(
     ))
 }
 
-pub fn mint_eris_lsd_msgs(user_addr: &Addr, amount: Asset, eris_addr: &Addr) -> DestinationResult {
+pub fn mint_eris_lsd_msgs(
+    user_addr: &Addr,
+    amount: impl Into<OutpostAsset>,
+    eris_addr: &Addr,
+) -> DestinationResult {
+    let amount: OutpostAsset = amount.into();
+
     Ok(DestProjectMsgs {
-        msgs: match amount.clone() {
-            Asset {
-                info: AssetInfo::NativeToken { denom },
-                amount,
-            } => vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
+        msgs: match &amount.info {
+            OutpostAssetInfo::Cw20(contract_addr) => {
+                vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
+                    contract_addr,
+                    user_addr,
+                    &cw20::Cw20ExecuteMsg::Send {
+                        contract: eris_addr.to_string(),
+                        amount: amount.amount,
+                        msg: to_json_binary(&bond_router::msg::ExecuteMsg::Bond {})?,
+                    },
+                    None,
+                )?)]
+            }
+            _ => vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
                 eris_addr,
                 user_addr,
                 &bond_router::msg::ExecuteMsg::Bond {},
-                Some(csdk_coins(&amount, denom)),
-            )?)],
-            Asset {
-                info: AssetInfo::Token { contract_addr },
-                amount,
-            } => vec![CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
-                contract_addr,
-                user_addr,
-                &cw20::Cw20ExecuteMsg::Send {
-                    contract: eris_addr.to_string(),
-                    amount,
-                    msg: to_json_binary(&bond_router::msg::ExecuteMsg::Bond {})?,
-                },
-                None,
+                Some(vec![amount.to_csdk_coin()?]),
             )?)],
         },
         sub_msgs: vec![],
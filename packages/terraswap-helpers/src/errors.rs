@@ -1,4 +1,5 @@
 use cosmwasm_std::StdError;
+use outpost_utils::errors::OutpostError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +7,9 @@ pub enum TerraswapHelperError {
     #[error("Outpost StdError: {0}")]
     Std(#[from] StdError),
 
+    #[error("Outpost Error: {0}")]
+    OutpostError(#[from] OutpostError),
+
     #[error("Could not simulate swap of {from} to {to}")]
     SwapSimulationError { from: String, to: String },
 }
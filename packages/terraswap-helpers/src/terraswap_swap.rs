@@ -1,13 +1,21 @@
 use cosmos_sdk_proto::cosmos::base::v1beta1::Coin;
-use cosmwasm_std::{to_json_binary, Addr, QuerierWrapper, StdError, Uint128};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_json_binary, Addr, Decimal, QuerierWrapper, StdError, Uint128};
 use cw_grant_spec::grants::{GrantBase, GrantRequirement};
-use outpost_utils::msg_gen::{create_exec_contract_msg, CosmosProtoMsg};
+use outpost_utils::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    helpers::min_out_after_slippage,
+    msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    swap_venue::SwapVenue,
+};
 use white_whale::pool_network::{
     asset::{Asset, AssetInfo},
     pair::{ExecuteMsg as PairExecuteMsg, SimulationResponse},
     router::{ExecuteMsg, SimulateSwapOperationsResponse, SwapOperation},
 };
 
+use crate::errors::TerraswapHelperError;
+
 // /// Queries the Wyndex pool for the amount of `to_denom` that can be received for `from_token`
 // /// IMPORTANT: you must provide the pair contract address for the simulation
 // pub fn simulate_wynd_pool_swap(
@@ -124,6 +132,7 @@ pub fn create_swap_msg(
     offer_amount: Uint128,
     swap_routes: Vec<SwapOperation>,
     multihop_address: String,
+    minimum_receive: Option<Uint128>,
 ) -> Result<Vec<CosmosProtoMsg>, StdError> {
     // // no swap to do because the offer and ask tokens are the same
     // if swap_route.offer_asset_info.eq(&swap_route.ask_asset_info) {
@@ -132,7 +141,7 @@ pub fn create_swap_msg(
 
     let swap_ops = ExecuteMsg::ExecuteSwapOperations {
         operations: swap_routes.clone(),
-        minimum_receive: None,
+        minimum_receive,
         to: None,
     };
 
@@ -174,13 +183,15 @@ pub fn create_swap_msg(
 }
 
 /// Creates a MsgExecuteContract for doing a token swap on terraswap via the multihop router
-/// also returning the simulated resultant token amount
+/// also returning the simulated resultant token amount.
+/// The `max_slippage` is enforced as a minimum receive amount off of the simulation
 pub fn create_terraswap_swap_msg_with_simulation(
     querier: &QuerierWrapper,
     sender: &Addr,
     offer_amount: Uint128,
     swap_routes: Vec<SwapOperation>,
     multihop_address: String,
+    max_slippage: Option<Decimal>,
 ) -> Result<(Vec<CosmosProtoMsg>, Uint128), StdError> {
     // // no swap to do because the offer and ask tokens are the same
     // if offer_asset.eq(&ask_asset_info) {
@@ -199,7 +210,14 @@ pub fn create_terraswap_swap_msg_with_simulation(
         },
     )?;
 
-    let exec = create_swap_msg(sender, offer_amount, swap_routes, multihop_address)?;
+    let exec = create_swap_msg(
+        sender,
+        offer_amount,
+        swap_routes,
+        multihop_address,
+        max_slippage
+            .map(|max_slippage| min_out_after_slippage(simulated_swap.amount, max_slippage)),
+    )?;
 
     Ok((exec, simulated_swap.amount))
 }
@@ -210,6 +228,7 @@ pub fn create_terraswap_pool_swap_msg_with_simulation(
     sender: &Addr,
     offer_asset: Asset,
     pool_address: &Addr,
+    max_slippage: Option<Decimal>,
 ) -> Result<(CosmosProtoMsg, Uint128), StdError> {
     // // no swap to do because the offer and ask tokens are the same
     // if offer_asset.eq(&ask_asset_info) {
@@ -219,7 +238,7 @@ pub fn create_terraswap_pool_swap_msg_with_simulation(
     let simulated_swap: SimulationResponse =
         simulate_pool_swap(querier, &pool_address.to_string(), &offer_asset)?;
 
-    let swap_msg = create_terraswap_pool_swap_msg(sender, offer_asset, pool_address)?;
+    let swap_msg = create_terraswap_pool_swap_msg(sender, offer_asset, pool_address, max_slippage)?;
 
     Ok((swap_msg, simulated_swap.return_amount))
 }
@@ -228,6 +247,7 @@ pub fn create_terraswap_pool_swap_msg(
     sender: &Addr,
     offer_asset: Asset,
     pool_address: &Addr,
+    max_slippage: Option<Decimal>,
 ) -> Result<CosmosProtoMsg, StdError> {
     // // no swap to do because the offer and ask tokens are the same
     // if offer_asset.eq(&ask_asset_info) {
@@ -247,7 +267,7 @@ pub fn create_terraswap_pool_swap_msg(
                 &PairExecuteMsg::Swap {
                     offer_asset,
                     belief_price: None,
-                    max_spread: None,
+                    max_spread: max_slippage,
                     to: None,
                 },
                 Some(vec![Coin {
@@ -272,7 +292,7 @@ pub fn create_terraswap_pool_swap_msg(
                     msg: to_json_binary(&PairExecuteMsg::Swap {
                         offer_asset,
                         belief_price: None,
-                        max_spread: None,
+                        max_spread: max_slippage,
                         to: None,
                     })?,
                 },
@@ -323,3 +343,123 @@ pub fn terraswap_multihop_grant(
         }
     }
 }
+
+/// The ways of swapping on a terraswap style dex such as White Whale
+#[cw_serde]
+pub enum TerraswapVenue {
+    /// Swap directly through a single pool contract
+    Pool(Addr),
+    /// Swap through the multihop router
+    Multihop(Addr),
+}
+
+impl TerraswapVenue {
+    fn swap_operations(offer: &OutpostAssetInfo, ask: &OutpostAssetInfo) -> Vec<SwapOperation> {
+        vec![SwapOperation::TerraSwap {
+            offer_asset_info: offer.clone().into(),
+            ask_asset_info: ask.clone().into(),
+        }]
+    }
+}
+
+impl SwapVenue for TerraswapVenue {
+    type Error = TerraswapHelperError;
+
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, TerraswapHelperError> {
+        // no swap to do because the offer and ask tokens are the same
+        if offer.info.eq(ask) {
+            return Ok(offer.amount);
+        }
+
+        let simulation_error = |_| TerraswapHelperError::SwapSimulationError {
+            from: offer.info.to_string(),
+            to: ask.to_string(),
+        };
+
+        match self {
+            TerraswapVenue::Pool(pool_addr) => {
+                Ok(
+                    simulate_pool_swap(querier, pool_addr.as_str(), &offer.clone().into())
+                        .map_err(simulation_error)?
+                        .return_amount,
+                )
+            }
+            TerraswapVenue::Multihop(multihop_addr) => {
+                let simulation: SimulateSwapOperationsResponse = querier
+                    .query_wasm_smart(
+                        multihop_addr,
+                        &white_whale::pool_network::router::QueryMsg::SimulateSwapOperations {
+                            offer_amount: offer.amount,
+                            operations: TerraswapVenue::swap_operations(&offer.info, ask),
+                        },
+                    )
+                    .map_err(simulation_error)?;
+
+                Ok(simulation.amount)
+            }
+        }
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), TerraswapHelperError> {
+        // no swap to do because the offer and ask tokens are the same
+        if offer.info.eq(ask) {
+            return Ok((vec![], offer.amount));
+        }
+
+        match self {
+            TerraswapVenue::Pool(pool_addr) => {
+                let (swap_msg, simulation) = create_terraswap_pool_swap_msg_with_simulation(
+                    querier,
+                    sender,
+                    offer.clone().into(),
+                    pool_addr,
+                    max_slippage,
+                )?;
+
+                Ok((vec![swap_msg], simulation))
+            }
+            TerraswapVenue::Multihop(multihop_addr) => {
+                Ok(create_terraswap_swap_msg_with_simulation(
+                    querier,
+                    sender,
+                    offer.amount,
+                    TerraswapVenue::swap_operations(&offer.info, ask),
+                    multihop_addr.to_string(),
+                    max_slippage,
+                )?)
+            }
+        }
+    }
+
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        match (self, offer) {
+            (TerraswapVenue::Multihop(multihop_addr), _) => {
+                terraswap_multihop_grant(base, multihop_addr.clone(), offer.clone().into())
+            }
+            // cw20s are swapped by calling send on the token contract
+            (TerraswapVenue::Pool(_), OutpostAssetInfo::Cw20(contract_addr)) => {
+                terraswap_cw20_multihop_swap_grant(base, Addr::unchecked(contract_addr))
+            }
+            (TerraswapVenue::Pool(pool_addr), native) => {
+                vec![GrantRequirement::default_contract_exec_auth(
+                    base,
+                    pool_addr.clone(),
+                    vec!["swap"],
+                    Some(native.to_string().as_str()),
+                )]
+            }
+        }
+    }
+}
@@ -1,14 +1,20 @@
 use cosmos_sdk_proto::cosmos::{
-    bank::v1beta1::MsgSend, base::v1beta1::Coin as CsdkCoin, staking::v1beta1::MsgDelegate,
+    bank::v1beta1::MsgSend,
+    base::v1beta1::Coin as CsdkCoin,
+    staking::v1beta1::{MsgBeginRedelegate, MsgDelegate},
+};
+use cosmwasm_std::{
+    to_json_binary, Addr, Coin, Decimal, Event, FullDelegation, QuerierWrapper, Uint128,
 };
-use cosmwasm_std::{to_json_binary, Coin, Event, Uint128};
 use outpost_utils::{
-    helpers::DestProjectMsgs,
+    asset::{OutpostAsset, OutpostAssetInfo},
+    comp_prefs::ValidatorSelection,
+    helpers::{calculate_compound_amounts, DestProjectMsgs},
     msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    swap_venue::SwapVenue,
 };
 
 use std::fmt::Display;
-use white_whale::pool_network::asset::{Asset, AssetInfo};
 
 use crate::errors::UniversalDestinationError;
 
@@ -36,35 +42,200 @@ pub fn native_staking_msg(
     })
 }
 
+/// Generates the messages needed to split a delegation across several validators
+pub fn native_multi_staking_msgs(
+    validators: &[ValidatorSelection],
+    delegator_addr: &(impl Into<String> + Display),
+    coin: &Coin,
+) -> DestinationResult {
+    split_delegation(
+        validators,
+        &ValidatorSelection::percentages(validators)?,
+        delegator_addr,
+        coin,
+    )
+}
+
+/// Delegates to each of the validators based on the given percentages
+fn split_delegation(
+    validators: &[ValidatorSelection],
+    percentages: &Vec<Decimal>,
+    delegator_addr: &(impl Into<String> + Display),
+    coin: &Coin,
+) -> DestinationResult {
+    let amounts = calculate_compound_amounts(percentages, &coin.amount)?;
+
+    validators
+        .iter()
+        .zip(amounts)
+        // a validator's share can round down to nothing on small compounds
+        .filter(|(_, amount)| !amount.is_zero())
+        .try_fold(
+            DestProjectMsgs::default(),
+            |mut staking_msgs, (selection, amount)| {
+                let DestProjectMsgs { msgs, events, .. } = native_staking_msg(
+                    &selection.validator_address,
+                    delegator_addr,
+                    &Coin {
+                        denom: coin.denom.clone(),
+                        amount,
+                    },
+                )?;
+                staking_msgs.append_msgs(msgs);
+                staking_msgs.append_events(events);
+
+                Ok(staking_msgs)
+            },
+        )
+}
+
+/// The addresses of the selected validators that are currently in the active set.
+/// Jailed, tombstoned and unbonded validators all drop out of the active set
+pub fn query_active_validators(
+    querier: &QuerierWrapper,
+    validators: &[ValidatorSelection],
+) -> Result<Vec<String>, UniversalDestinationError> {
+    let mut active = vec![];
+
+    for ValidatorSelection {
+        validator_address, ..
+    } in validators
+    {
+        // the validator query only finds validators that are in the active set
+        if querier.query_validator(validator_address)?.is_some() {
+            active.push(validator_address.clone());
+        }
+    }
+
+    Ok(active)
+}
+
+/// Delegates to the validator as long as it's still in the active set.
+/// If it isn't this errors so that the destination's share goes to its fallback instead
+pub fn active_native_staking_msg(
+    querier: &QuerierWrapper,
+    validator_address: &str,
+    delegator_addr: &(impl Into<String> + Display),
+    coin: &Coin,
+) -> DestinationResult {
+    active_multi_staking_msgs(
+        querier,
+        &[ValidatorSelection {
+            validator_address: validator_address.to_string(),
+            percent: Decimal::one(),
+        }],
+        false,
+        delegator_addr,
+        coin,
+    )
+}
+
+/// Splits the delegation across the selected validators that are still in the active set.
+/// The shares of the validators that have dropped out are spread over the remaining ones and
+/// if none of them are left this errors so that the destination's share goes to its fallback.
+/// With `redelegate_inactive` any stake the delegator still has on the inactive validators is
+/// moved to the remaining validator with the largest share
+pub fn active_multi_staking_msgs(
+    querier: &QuerierWrapper,
+    validators: &[ValidatorSelection],
+    redelegate_inactive: bool,
+    delegator_addr: &(impl Into<String> + Display),
+    coin: &Coin,
+) -> DestinationResult {
+    // the selection has to be valid before any of it gets rerouted
+    ValidatorSelection::percentages(validators)?;
+
+    let active_set = query_active_validators(querier, validators)?;
+    let (active, inactive): (Vec<_>, Vec<_>) = validators
+        .iter()
+        .cloned()
+        .partition(|selection| active_set.contains(&selection.validator_address));
+
+    let Some(largest_active) = active.iter().max_by_key(|selection| selection.percent) else {
+        return Err(UniversalDestinationError::NoActiveValidators {
+            validators: inactive
+                .iter()
+                .map(|selection| selection.validator_address.clone())
+                .collect::<Vec<_>>()
+                .join(","),
+        });
+    };
+
+    // each active validator keeps its share relative to the other active validators
+    let active_total = active.iter().fold(Decimal::zero(), |total, selection| {
+        total + selection.percent
+    });
+    let percentages = active
+        .iter()
+        .map(|selection| Decimal::from_ratio(selection.percent.atomics(), active_total.atomics()))
+        .collect::<Vec<_>>();
+
+    let mut staking_msgs = split_delegation(&active, &percentages, delegator_addr, coin)?;
+
+    for ValidatorSelection {
+        validator_address,
+        percent,
+    } in inactive.iter()
+    {
+        staking_msgs.append_events(vec![Event::new("inactive_validator")
+            .add_attribute("validator", validator_address)
+            .add_attribute("percent", percent.to_string())]);
+
+        if !redelegate_inactive {
+            continue;
+        }
+
+        if let Some(FullDelegation { amount, .. }) =
+            querier.query_delegation(delegator_addr.to_string(), validator_address)?
+        {
+            if amount.amount.is_zero() {
+                continue;
+            }
+
+            staking_msgs.append_msgs(vec![CosmosProtoMsg::Redelegate(MsgBeginRedelegate {
+                delegator_address: delegator_addr.to_string(),
+                validator_src_address: validator_address.clone(),
+                validator_dst_address: largest_active.validator_address.clone(),
+                amount: Some(CsdkCoin {
+                    denom: amount.denom.clone(),
+                    amount: amount.amount.to_string(),
+                }),
+            })]);
+            staking_msgs.append_events(vec![Event::new("redelegate")
+                .add_attribute("from_validator", validator_address)
+                .add_attribute("to_validator", largest_active.validator_address.clone())
+                .add_attribute("amount", amount.to_string())]);
+        }
+    }
+
+    Ok(staking_msgs)
+}
+
 // stakes an asset to a dao on daodao
 pub fn daodao_staking_msg<T>(
     dao_name: String,
     staker_address: &T,
     staking_contract_addr: &T,
-    staking_asset: Asset,
+    staking_asset: impl Into<OutpostAsset>,
 ) -> DestinationResult
 where
     T: Into<String> + Display,
 {
-    match staking_asset {
-        Asset {
-            amount,
-            info: AssetInfo::NativeToken { denom },
-        } => daodao_native_staking_msg(
+    let staking_asset: OutpostAsset = staking_asset.into();
+
+    match &staking_asset.info {
+        OutpostAssetInfo::Cw20(contract_addr) => daodao_cw20_staking_msg(
             dao_name,
             staker_address,
+            contract_addr,
             staking_contract_addr,
-            Coin { denom, amount },
+            staking_asset.amount,
         ),
-        Asset {
-            amount,
-            info: AssetInfo::Token { contract_addr },
-        } => daodao_cw20_staking_msg(
+        _ => daodao_native_staking_msg(
             dao_name,
             staker_address,
-            &contract_addr.to_string(),
             staking_contract_addr,
-            amount,
+            staking_asset.to_coin()?,
         ),
     }
 }
@@ -131,24 +302,18 @@ where
 pub fn send_tokens_msgs<T>(
     sender_addr: &T,
     recipient_addr: &T,
-    asset_to_send: Asset,
+    asset_to_send: impl Into<OutpostAsset>,
 ) -> DestinationResult
 where
     T: Into<String> + Display,
 {
+    let asset_to_send: OutpostAsset = asset_to_send.into();
+
     Ok(DestProjectMsgs {
         msgs: vec![match &asset_to_send.info {
-            AssetInfo::NativeToken { denom } => CosmosProtoMsg::Send(MsgSend {
-                amount: vec![CsdkCoin {
-                    denom: denom.clone(),
-                    amount: asset_to_send.amount.into(),
-                }],
-                from_address: sender_addr.to_string(),
-                to_address: recipient_addr.to_string(),
-            }),
-            AssetInfo::Token { contract_addr } => {
+            OutpostAssetInfo::Cw20(cw20_addr) => {
                 CosmosProtoMsg::ExecuteContract(create_exec_contract_msg(
-                    contract_addr.clone(),
+                    cw20_addr.clone(),
                     sender_addr,
                     &cw20::Cw20ExecuteMsg::Transfer {
                         recipient: recipient_addr.to_string(),
@@ -157,6 +322,11 @@ where
                     None,
                 )?)
             }
+            _ => CosmosProtoMsg::Send(MsgSend {
+                amount: vec![asset_to_send.to_csdk_coin()?],
+                from_address: sender_addr.to_string(),
+                to_address: recipient_addr.to_string(),
+            }),
         }],
         sub_msgs: vec![],
         events: vec![Event::new("send_tokens")
@@ -165,3 +335,28 @@ where
             .add_attribute("asset", asset_to_send.info.to_string())],
     })
 }
+
+/// Swaps `offer` into `ask` on any `SwapVenue`.
+/// Also returns the simulated amount of `ask` so the swap can be followed up by other msgs
+pub fn token_swap_msgs<V: SwapVenue>(
+    venue: &V,
+    querier: &QuerierWrapper,
+    sender: &Addr,
+    offer: &OutpostAsset,
+    ask: &OutpostAssetInfo,
+    max_slippage: Option<Decimal>,
+) -> Result<(DestProjectMsgs, Uint128), V::Error> {
+    let (msgs, simulated_amount) = venue.swap_msgs(querier, sender, offer, ask, max_slippage)?;
+
+    Ok((
+        DestProjectMsgs {
+            msgs,
+            sub_msgs: vec![],
+            events: vec![Event::new("token_swap")
+                .add_attribute("offer", offer.to_string())
+                .add_attribute("ask", ask.to_string())
+                .add_attribute("simulated_return", simulated_amount.to_string())],
+        },
+        simulated_amount,
+    ))
+}
@@ -3,7 +3,7 @@ use cw_grant_spec::grants::{
     AuthorizationType, GrantBase, GrantRequirement, StakeAuthorizationPolicy,
     StakeAuthorizationType, StakeAuthorizationValidators,
 };
-use white_whale::pool_network::asset::AssetInfo;
+use outpost_utils::{asset::OutpostAssetInfo, comp_prefs::ValidatorSelection};
 
 pub fn native_staking_grant(
     GrantBase {
@@ -28,9 +28,57 @@ pub fn native_staking_grant(
     }]
 }
 
+/// Grants delegating to every one of the selected validators along with moving stake onto them
+/// if the stake on inactive validators gets redelegated
+pub fn native_multi_staking_grant(
+    base: GrantBase,
+    validators: Vec<ValidatorSelection>,
+    redelegate_inactive: bool,
+) -> Vec<GrantRequirement> {
+    let validators: Vec<String> = validators
+        .into_iter()
+        .map(|validator| validator.validator_address)
+        .collect();
+
+    match redelegate_inactive {
+        true => [
+            native_staking_grant(base.clone(), None, Some(validators.clone())),
+            native_redelegate_grant(base, validators),
+        ]
+        .concat(),
+        false => native_staking_grant(base, None, Some(validators)),
+    }
+}
+
+/// Lets the grantee move the granter's stake to any of the given validators
+pub fn native_redelegate_grant(
+    GrantBase {
+        granter,
+        grantee,
+        expiration,
+    }: GrantBase,
+    validators: Vec<String>,
+) -> Vec<GrantRequirement> {
+    vec![GrantRequirement::GrantSpec {
+        grant_type: AuthorizationType::StakeAuthorization {
+            max_tokens: None,
+            authorization_type: StakeAuthorizationType::Redelegate,
+            // redelegations are checked against the validator that the stake is moving to
+            validators: Some(StakeAuthorizationPolicy::AllowList(
+                StakeAuthorizationValidators {
+                    address: validators,
+                },
+            )),
+        },
+        granter,
+        grantee,
+        expiration,
+    }]
+}
+
 pub fn native_send_token(
     base: GrantBase,
-    denom: AssetInfo,
+    denom: impl Into<OutpostAssetInfo>,
     destination_wallet_address: String,
 ) -> Vec<GrantRequirement> {
     let GrantBase {
@@ -39,23 +87,23 @@ pub fn native_send_token(
         expiration,
     } = base.clone();
 
-    vec![match denom {
-        // if it's a native denom we need a send authorization
-        AssetInfo::NativeToken { denom } => GrantRequirement::GrantSpec {
+    vec![match denom.into() {
+        // if it's a cw20 then we need a contract execution authorization on the cw20 contract
+        OutpostAssetInfo::Cw20(contract_addr) => GrantRequirement::default_contract_exec_auth(
+            base,
+            Addr::unchecked(contract_addr),
+            vec!["transfer"],
+            None,
+        ),
+        // if it's a native, tokenfactory or ibc denom we need a send authorization
+        native => GrantRequirement::GrantSpec {
             grant_type: AuthorizationType::SendAuthorization {
-                spend_limit: Some(vec![coin(u128::MAX, denom)]),
+                spend_limit: Some(vec![coin(u128::MAX, native.to_string())]),
                 allow_list: Some(vec![Addr::unchecked(destination_wallet_address)]),
             },
             granter: granter.clone(),
             grantee: grantee.clone(),
             expiration,
         },
-        // if it's a cw20 then we need a contract execution authorization on the cw20 contract
-        AssetInfo::Token { contract_addr } => GrantRequirement::default_contract_exec_auth(
-            base,
-            Addr::unchecked(contract_addr),
-            vec!["transfer"],
-            None,
-        ),
     }]
 }
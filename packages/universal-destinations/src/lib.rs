@@ -1,3 +1,6 @@
 pub mod dest_project_gen;
 pub mod errors;
 pub mod grants;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,137 @@
+use cosmos_sdk_proto::cosmos::{
+    base::v1beta1::Coin as CsdkCoin,
+    staking::v1beta1::{MsgBeginRedelegate, MsgDelegate},
+};
+use cosmwasm_std::{
+    coin, testing::mock_dependencies, Addr, Decimal, FullDelegation, QuerierWrapper, Validator,
+};
+use outpost_utils::{comp_prefs::ValidatorSelection, msg_gen::CosmosProtoMsg};
+
+use crate::{
+    dest_project_gen::{active_multi_staking_msgs, query_active_validators},
+    errors::UniversalDestinationError,
+};
+
+fn validator(address: &str) -> Validator {
+    Validator {
+        address: address.to_string(),
+        commission: Decimal::percent(5),
+        max_commission: Decimal::percent(20),
+        max_change_rate: Decimal::percent(1),
+    }
+}
+
+fn selection(validator_address: &str, percent: u64) -> ValidatorSelection {
+    ValidatorSelection {
+        validator_address: validator_address.to_string(),
+        percent: Decimal::percent(percent),
+    }
+}
+
+fn delegate_msg(validator_address: &str, amount: u128) -> CosmosProtoMsg {
+    CosmosProtoMsg::Delegate(MsgDelegate {
+        delegator_address: "user".to_string(),
+        validator_address: validator_address.to_string(),
+        amount: Some(CsdkCoin {
+            denom: "ujuno".to_string(),
+            amount: amount.to_string(),
+        }),
+    })
+}
+
+#[test]
+fn test_active_multi_staking_reroutes_inactive_validators() {
+    let mut deps = mock_dependencies();
+    let user = Addr::unchecked("user");
+
+    // the jailed validator has dropped out of the active set but the user is still delegated to it
+    deps.querier.update_staking(
+        "ujuno",
+        &[validator("active_big"), validator("active_small")],
+        &[FullDelegation {
+            delegator: user.clone(),
+            validator: "jailed".to_string(),
+            amount: coin(500, "ujuno"),
+            can_redelegate: coin(500, "ujuno"),
+            accumulated_rewards: vec![],
+        }],
+    );
+    let querier = QuerierWrapper::new(&deps.querier);
+
+    let validators = vec![
+        selection("jailed", 60),
+        selection("active_big", 30),
+        selection("active_small", 10),
+    ];
+
+    assert_eq!(
+        query_active_validators(&querier, &validators).unwrap(),
+        vec!["active_big".to_string(), "active_small".to_string()]
+    );
+
+    // the jailed validator's share is spread over the active ones and its stake follows
+    let staking_msgs =
+        active_multi_staking_msgs(&querier, &validators, true, &user, &coin(1_000, "ujuno"))
+            .unwrap();
+    assert_eq!(
+        staking_msgs.msgs,
+        vec![
+            delegate_msg("active_big", 750),
+            delegate_msg("active_small", 250),
+            CosmosProtoMsg::Redelegate(MsgBeginRedelegate {
+                delegator_address: "user".to_string(),
+                validator_src_address: "jailed".to_string(),
+                validator_dst_address: "active_big".to_string(),
+                amount: Some(CsdkCoin {
+                    denom: "ujuno".to_string(),
+                    amount: "500".to_string(),
+                }),
+            }),
+        ]
+    );
+    assert!(staking_msgs
+        .events
+        .iter()
+        .any(|event| event.ty == "inactive_validator"));
+
+    // without redelegating only the new stake is rerouted
+    let staking_msgs =
+        active_multi_staking_msgs(&querier, &validators, false, &user, &coin(1_000, "ujuno"))
+            .unwrap();
+    assert_eq!(
+        staking_msgs.msgs,
+        vec![
+            delegate_msg("active_big", 750),
+            delegate_msg("active_small", 250)
+        ]
+    );
+
+    // active validators are never redelegated away from
+    let staking_msgs = active_multi_staking_msgs(
+        &querier,
+        &[selection("active_big", 50), selection("active_small", 50)],
+        true,
+        &user,
+        &coin(1_000, "ujuno"),
+    )
+    .unwrap();
+    assert_eq!(
+        staking_msgs.msgs,
+        vec![
+            delegate_msg("active_big", 500),
+            delegate_msg("active_small", 500)
+        ]
+    );
+
+    // with nothing left to stake to the share goes to the destination's fallback
+    assert!(matches!(
+        active_multi_staking_msgs(
+            &querier,
+            &[selection("jailed", 100)],
+            true,
+            &user,
+            &coin(1_000, "ujuno"),
+        ),
+        Err(UniversalDestinationError::NoActiveValidators { .. })
+    ));
+}
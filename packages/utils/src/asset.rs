@@ -0,0 +1,261 @@
+use std::{fmt, str::FromStr};
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Coin, Uint128};
+use osmosis_std::types::cosmos::base::v1beta1::Coin as OsmosisCoin;
+
+use crate::errors::OutpostError;
+
+const TOKEN_FACTORY_PREFIX: &str = "factory/";
+const IBC_PREFIX: &str = "ibc/";
+
+/// Venue agnostic description of an asset the outposts can hold, swap or send.
+/// Converts losslessly to and from the wyndex, white whale, osmosis and cosmwasm representations.
+#[cw_serde]
+pub enum OutpostAssetInfo {
+    /// A plain bank denom such as `ujuno` or `uwhale`
+    Native(String),
+    /// A cw20 token identified by its contract address
+    Cw20(String),
+    /// A tokenfactory denom of the form `factory/{creator}/{subdenom}`
+    TokenFactory { creator: String, subdenom: String },
+    /// An ibc voucher denom of the form `ibc/{hash}`
+    Ibc(String),
+}
+
+impl OutpostAssetInfo {
+    /// Classifies a bank denom as native, tokenfactory or ibc
+    pub fn from_denom(denom: impl Into<String>) -> Self {
+        let denom: String = denom.into();
+
+        if let Some(hash) = denom.strip_prefix(IBC_PREFIX) {
+            return OutpostAssetInfo::Ibc(hash.to_string());
+        }
+
+        if let Some((creator, subdenom)) = denom
+            .strip_prefix(TOKEN_FACTORY_PREFIX)
+            .and_then(|rest| rest.split_once('/'))
+        {
+            return OutpostAssetInfo::TokenFactory {
+                creator: creator.to_string(),
+                subdenom: subdenom.to_string(),
+            };
+        }
+
+        OutpostAssetInfo::Native(denom)
+    }
+
+    /// The bank denom of the asset or `None` if it is a cw20
+    pub fn denom(&self) -> Option<String> {
+        match self {
+            OutpostAssetInfo::Native(denom) => Some(denom.clone()),
+            OutpostAssetInfo::Cw20(_) => None,
+            OutpostAssetInfo::TokenFactory { creator, subdenom } => {
+                Some(format!("{TOKEN_FACTORY_PREFIX}{creator}/{subdenom}"))
+            }
+            OutpostAssetInfo::Ibc(hash) => Some(format!("{IBC_PREFIX}{hash}")),
+        }
+    }
+
+    /// The bank denom of the asset or an error if it is a cw20
+    pub fn try_denom(&self) -> Result<String, OutpostError> {
+        match self {
+            OutpostAssetInfo::Cw20(contract_addr) => {
+                Err(OutpostError::AssetIsNotCoinable(contract_addr.clone()))
+            }
+            _ => Ok(self.to_string()),
+        }
+    }
+
+    pub fn is_cw20(&self) -> bool {
+        matches!(self, OutpostAssetInfo::Cw20(_))
+    }
+
+    /// Pairs the asset info with an amount
+    pub fn with_amount(self, amount: impl Into<Uint128>) -> OutpostAsset {
+        OutpostAsset {
+            info: self,
+            amount: amount.into(),
+        }
+    }
+}
+
+/// Displays the bank denom or, for cw20s, the token contract address
+impl fmt::Display for OutpostAssetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutpostAssetInfo::Cw20(contract_addr) => write!(f, "{contract_addr}"),
+            _ => write!(f, "{}", self.denom().unwrap_or_default()),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct OutpostAsset {
+    pub info: OutpostAssetInfo,
+    pub amount: Uint128,
+}
+
+impl OutpostAsset {
+    pub fn new(info: impl Into<OutpostAssetInfo>, amount: impl Into<Uint128>) -> Self {
+        OutpostAsset {
+            info: info.into(),
+            amount: amount.into(),
+        }
+    }
+
+    /// Converts the asset into a cosmwasm coin or errors if it is a cw20
+    pub fn to_coin(&self) -> Result<Coin, OutpostError> {
+        Ok(Coin {
+            denom: self.info.try_denom()?,
+            amount: self.amount,
+        })
+    }
+
+    /// Converts the asset into a cosmos sdk proto coin or errors if it is a cw20
+    pub fn to_csdk_coin(
+        &self,
+    ) -> Result<cosmos_sdk_proto::cosmos::base::v1beta1::Coin, OutpostError> {
+        Ok(cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
+            denom: self.info.try_denom()?,
+            amount: self.amount.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for OutpostAsset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.info)
+    }
+}
+
+impl From<Coin> for OutpostAsset {
+    fn from(Coin { denom, amount }: Coin) -> Self {
+        OutpostAsset {
+            info: OutpostAssetInfo::from_denom(denom),
+            amount,
+        }
+    }
+}
+
+impl From<&Coin> for OutpostAsset {
+    fn from(coin: &Coin) -> Self {
+        coin.clone().into()
+    }
+}
+
+impl TryFrom<OutpostAsset> for Coin {
+    type Error = OutpostError;
+
+    fn try_from(asset: OutpostAsset) -> Result<Self, Self::Error> {
+        asset.to_coin()
+    }
+}
+
+impl TryFrom<OsmosisCoin> for OutpostAsset {
+    type Error = OutpostError;
+
+    fn try_from(OsmosisCoin { denom, amount }: OsmosisCoin) -> Result<Self, Self::Error> {
+        Ok(OutpostAsset {
+            info: OutpostAssetInfo::from_denom(denom),
+            amount: Uint128::from_str(&amount)?,
+        })
+    }
+}
+
+impl TryFrom<OutpostAsset> for OsmosisCoin {
+    type Error = OutpostError;
+
+    fn try_from(asset: OutpostAsset) -> Result<Self, Self::Error> {
+        Ok(OsmosisCoin {
+            denom: asset.info.try_denom()?,
+            amount: asset.amount.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "wyndex")]
+mod wyndex_conversions {
+    use super::{OutpostAsset, OutpostAssetInfo};
+    use wyndex::asset::{Asset, AssetInfo};
+
+    impl From<AssetInfo> for OutpostAssetInfo {
+        fn from(asset_info: AssetInfo) -> Self {
+            match asset_info {
+                AssetInfo::Native(denom) => OutpostAssetInfo::from_denom(denom),
+                AssetInfo::Token(contract_addr) => OutpostAssetInfo::Cw20(contract_addr),
+            }
+        }
+    }
+
+    impl From<OutpostAssetInfo> for AssetInfo {
+        fn from(asset_info: OutpostAssetInfo) -> Self {
+            match asset_info {
+                OutpostAssetInfo::Cw20(contract_addr) => AssetInfo::Token(contract_addr),
+                native => AssetInfo::Native(native.to_string()),
+            }
+        }
+    }
+
+    impl From<Asset> for OutpostAsset {
+        fn from(Asset { info, amount }: Asset) -> Self {
+            OutpostAsset {
+                info: info.into(),
+                amount,
+            }
+        }
+    }
+
+    impl From<OutpostAsset> for Asset {
+        fn from(OutpostAsset { info, amount }: OutpostAsset) -> Self {
+            Asset {
+                info: info.into(),
+                amount,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "white-whale")]
+mod white_whale_conversions {
+    use super::{OutpostAsset, OutpostAssetInfo};
+    use white_whale::pool_network::asset::{Asset, AssetInfo};
+
+    impl From<AssetInfo> for OutpostAssetInfo {
+        fn from(asset_info: AssetInfo) -> Self {
+            match asset_info {
+                AssetInfo::NativeToken { denom } => OutpostAssetInfo::from_denom(denom),
+                AssetInfo::Token { contract_addr } => OutpostAssetInfo::Cw20(contract_addr),
+            }
+        }
+    }
+
+    impl From<OutpostAssetInfo> for AssetInfo {
+        fn from(asset_info: OutpostAssetInfo) -> Self {
+            match asset_info {
+                OutpostAssetInfo::Cw20(contract_addr) => AssetInfo::Token { contract_addr },
+                native => AssetInfo::NativeToken {
+                    denom: native.to_string(),
+                },
+            }
+        }
+    }
+
+    impl From<Asset> for OutpostAsset {
+        fn from(Asset { info, amount }: Asset) -> Self {
+            OutpostAsset {
+                info: info.into(),
+                amount,
+            }
+        }
+    }
+
+    impl From<OutpostAsset> for Asset {
+        fn from(OutpostAsset { info, amount }: OutpostAsset) -> Self {
+            Asset {
+                info: info.into(),
+                amount,
+            }
+        }
+    }
+}
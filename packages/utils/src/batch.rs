@@ -0,0 +1,142 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Event, ReplyOn, Response, Storage, SubMsg, SubMsgResult, WasmMsg,
+};
+use cw_storage_plus::Item;
+use serde::Serialize;
+
+use crate::errors::OutpostError;
+
+/// Each user in a batch gets its own reply id counting up from here
+pub const BATCH_REPLY_ID_START: u64 = 3_000;
+
+/// The last reply id that can be handed out to a user in a batch
+pub const BATCH_REPLY_ID_END: u64 = 3_999;
+
+/// Checks if the reply id belongs to one of the users in a batch compound
+pub fn is_batch_reply(reply_id: u64) -> bool {
+    (BATCH_REPLY_ID_START..=BATCH_REPLY_ID_END).contains(&reply_id)
+}
+
+#[cw_serde]
+pub struct BatchProgress {
+    /// every user in the batch. a user's reply id is their index plus `BATCH_REPLY_ID_START`
+    pub users: Vec<String>,
+    pub succeeded: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+fn skipped_event(user: &str, reason: &str) -> Event {
+    Event::new("batch_compound_skipped")
+        .add_attribute("user", user)
+        .add_attribute("reason", reason)
+}
+
+/// Wraps up the batch once every user has been heard from
+fn finish_batch(
+    storage: &mut dyn Storage,
+    batch: &Item<BatchProgress>,
+    progress: BatchProgress,
+) -> Result<Option<Event>, OutpostError> {
+    if progress.succeeded.len() + progress.skipped.len() < progress.users.len() {
+        batch.save(storage, &progress)?;
+        return Ok(None);
+    }
+
+    batch.remove(storage);
+
+    Ok(Some(
+        Event::new("batch_compound")
+            .add_attribute("succeeded_count", progress.succeeded.len().to_string())
+            .add_attribute("skipped_count", progress.skipped.len().to_string())
+            .add_attribute("succeeded", progress.succeeded.join(","))
+            .add_attribute("skipped", progress.skipped.join(",")),
+    ))
+}
+
+/// Splits a batch up into one submsg per user that calls back into the outpost so that a
+/// user that fails doesn't revert the whole batch.
+/// Users whose compound msg is an error are skipped without running anything
+pub fn batch_compound_submsgs<M: Serialize>(
+    storage: &mut dyn Storage,
+    batch: &Item<BatchProgress>,
+    outpost_address: &Addr,
+    users: Vec<(String, Result<M, String>)>,
+) -> Result<Response, OutpostError> {
+    let max = BATCH_REPLY_ID_END - BATCH_REPLY_ID_START + 1;
+    if users.len() as u64 > max {
+        return Err(OutpostError::BatchTooLarge {
+            count: users.len(),
+            max,
+        });
+    }
+
+    let mut progress = BatchProgress {
+        users: vec![],
+        succeeded: vec![],
+        skipped: vec![],
+    };
+    let mut resp = Response::default().add_attribute("action", "outpost batch compound");
+
+    for (reply_id, (user, compound_msg)) in (BATCH_REPLY_ID_START..).zip(users) {
+        match compound_msg {
+            Ok(compound_msg) => {
+                resp = resp.add_submessage(SubMsg {
+                    id: reply_id,
+                    msg: WasmMsg::Execute {
+                        contract_addr: outpost_address.to_string(),
+                        msg: to_json_binary(&compound_msg)?,
+                        funds: vec![],
+                    }
+                    .into(),
+                    gas_limit: None,
+                    reply_on: ReplyOn::Always,
+                })
+            }
+            Err(reason) => {
+                resp = resp.add_event(skipped_event(&user, &reason));
+                progress.skipped.push(user.clone());
+            }
+        }
+        progress.users.push(user);
+    }
+
+    Ok(match finish_batch(storage, batch, progress)? {
+        Some(batch_event) => resp.add_event(batch_event),
+        None => resp,
+    })
+}
+
+/// Records how one of the users in the batch went and
+/// emits the batch's summary once every user has finished
+pub fn handle_batch_reply(
+    storage: &mut dyn Storage,
+    batch: &Item<BatchProgress>,
+    reply_id: u64,
+    result: SubMsgResult,
+) -> Result<Response, OutpostError> {
+    let Some(mut progress) = batch.may_load(storage)? else {
+        return Ok(Response::default());
+    };
+    let Some(user) = progress
+        .users
+        .get((reply_id - BATCH_REPLY_ID_START) as usize)
+        .cloned()
+    else {
+        return Ok(Response::default());
+    };
+
+    let mut resp = Response::default();
+    match result {
+        SubMsgResult::Ok(_) => progress.succeeded.push(user),
+        SubMsgResult::Err(reason) => {
+            resp = resp.add_event(skipped_event(&user, &reason));
+            progress.skipped.push(user);
+        }
+    }
+
+    Ok(match finish_batch(storage, batch, progress)? {
+        Some(batch_event) => resp.add_event(batch_event),
+        None => resp,
+    })
+}
@@ -1,11 +1,21 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Api, Decimal};
+use cosmwasm_std::{Addr, Api, Decimal, Uint128};
 
-use crate::errors::OutpostError;
+use crate::{errors::OutpostError, helpers::calculate_compound_amounts};
 
 #[cw_serde]
 pub struct CompoundPrefs<DestProjects> {
+    /// destinations that split whatever is left once the absolute and capped destinations
+    /// have been paid, by percentage
     pub relative: Vec<DestinationAction<DestProjects>>,
+    /// destinations that get an exact amount of the rewards every run before anything else is
+    /// split up. here the action's amount is the amount of the reward token in its base units
+    #[serde(default)]
+    pub absolute: Vec<DestinationAction<DestProjects>>,
+    /// destinations that get a percentage of what's left after the absolute destinations but
+    /// never more than their cap. anything over the cap is split between the relative destinations
+    #[serde(default)]
+    pub capped: Vec<CappedDestinationAction<DestProjects>>,
 }
 
 #[cw_serde]
@@ -13,8 +23,290 @@ pub struct DestinationAction<DestProjects> {
     pub destination: DestProjects,
     /// the percentage of the rewards that should be sent to this destination
     /// this is a number with 18 decimal places
-    /// for example "250000000000000000" is 25%
+    /// for example "250000000000000000" is 25%.
+    /// absolute destinations use this as the exact amount of the reward token instead
     pub amount: u128,
+    /// where this destination's share should go instead if the destination fails.
+    /// if none is given the outpost's default fallback is used, if it has one
+    pub fallback: Option<DestProjects>,
+    /// the most slippage that this destination's swaps will accept, for example "0.01" is 1%.
+    /// if none is given the slippage set for the whole compound is used
+    pub max_slippage: Option<Decimal>,
+    /// the least of the reward token that's worth sending to this destination.
+    /// a smaller share is rolled into the destination with the largest share instead
+    pub min_amount: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct CappedDestinationAction<DestProjects> {
+    /// the destination along with the percentage of the rewards that it should get
+    pub action: DestinationAction<DestProjects>,
+    /// the most of the reward token that this destination can get in a single run
+    pub cap: Uint128,
+}
+
+/// How a destination's share of the rewards is worked out
+#[cw_serde]
+#[derive(Copy)]
+pub enum Allocation {
+    /// a percentage of whatever is left once the absolute and capped destinations are paid
+    Relative(Decimal),
+    /// an exact amount that's taken out of the rewards before anything else
+    Absolute(Uint128),
+    /// a percentage of what's left after the absolute destinations, up to the cap
+    Capped { percent: Decimal, cap: Uint128 },
+}
+
+impl From<Decimal> for Allocation {
+    fn from(percent: Decimal) -> Self {
+        Allocation::Relative(percent)
+    }
+}
+
+impl<DestProjects> CompoundPrefs<DestProjects> {
+    /// Every destination action in the order that the rewards are allocated:
+    /// the absolute destinations first, then the capped ones and then the relative ones
+    pub fn actions(&self) -> impl Iterator<Item = &DestinationAction<DestProjects>> {
+        self.absolute
+            .iter()
+            .chain(self.capped.iter().map(|capped| &capped.action))
+            .chain(self.relative.iter())
+    }
+
+    /// How each destination's share is worked out, in the same order as `actions`
+    pub fn allocations(&self) -> Result<Vec<Allocation>, OutpostError> {
+        let percent = |amount: u128| {
+            Decimal::from_atomics(amount, 18)
+                .map_err(|_| OutpostError::PrefsToPercentagesFailure(amount))
+        };
+
+        self.absolute
+            .iter()
+            .map(|action| Ok(Allocation::Absolute(Uint128::new(action.amount))))
+            .chain(self.capped.iter().map(|capped| {
+                Ok(Allocation::Capped {
+                    percent: percent(capped.action.amount)?,
+                    cap: capped.cap,
+                })
+            }))
+            .chain(
+                self.relative
+                    .iter()
+                    .map(|action| percent(action.amount).map(Allocation::Relative)),
+            )
+            .collect()
+    }
+
+    /// Splits the rewards between the destinations.
+    /// Each destination action is paired with the amount that it should compound.
+    /// Destinations whose share falls below their minimum are left out and their share
+    /// goes to the relative destination with the largest share
+    pub fn split_rewards(
+        self,
+        total_amount: &Uint128,
+    ) -> Result<Vec<(Uint128, DestinationAction<DestProjects>)>, OutpostError> {
+        let amounts = calculate_compound_amounts(&self.allocations()?, total_amount)?;
+        let relative_start = self.absolute.len() + self.capped.len();
+
+        Ok(roll_up_dust(
+            amounts
+                .into_iter()
+                .zip(
+                    self.absolute
+                        .into_iter()
+                        .chain(self.capped.into_iter().map(|capped| capped.action))
+                        .chain(self.relative),
+                )
+                .collect(),
+            relative_start,
+        ))
+    }
+
+    /// Every destination that the prefs could end up compounding into, including the fallbacks
+    pub fn destinations(&self) -> impl Iterator<Item = &DestProjects> {
+        self.actions()
+            .flat_map(|action| std::iter::once(&action.destination).chain(action.fallback.iter()))
+    }
+
+    /// Updates every destination action, whichever kind of allocation it has
+    fn try_map_actions(
+        self,
+        mut f: impl FnMut(
+            DestinationAction<DestProjects>,
+        ) -> Result<DestinationAction<DestProjects>, OutpostError>,
+    ) -> Result<Self, OutpostError> {
+        Ok(CompoundPrefs {
+            relative: self
+                .relative
+                .into_iter()
+                .map(&mut f)
+                .collect::<Result<_, _>>()?,
+            absolute: self
+                .absolute
+                .into_iter()
+                .map(&mut f)
+                .collect::<Result<_, _>>()?,
+            capped: self
+                .capped
+                .into_iter()
+                .map(|CappedDestinationAction { action, cap }| {
+                    Ok(CappedDestinationAction {
+                        action: f(action)?,
+                        cap,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl<DestProjects: Clone> CompoundPrefs<DestProjects> {
+    /// Fills in the given default fallback for every destination that doesn't have one of its own
+    pub fn with_default_fallback(self, default_fallback: Option<DestProjects>) -> Self {
+        let fill_in = |action: DestinationAction<DestProjects>| DestinationAction {
+            fallback: action.fallback.or_else(|| default_fallback.clone()),
+            ..action
+        };
+
+        CompoundPrefs {
+            relative: self.relative.into_iter().map(fill_in).collect(),
+            absolute: self.absolute.into_iter().map(fill_in).collect(),
+            capped: self
+                .capped
+                .into_iter()
+                .map(
+                    |CappedDestinationAction { action, cap }| CappedDestinationAction {
+                        action: fill_in(action),
+                        cap,
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    /// Fills in the given slippage tolerance for every destination that doesn't have one of its own
+    pub fn with_default_slippage(
+        self,
+        max_slippage: Option<Decimal>,
+    ) -> Result<Self, OutpostError> {
+        self.try_map_actions(|action| match action.max_slippage.or(max_slippage) {
+            Some(max_slippage) if max_slippage >= Decimal::one() => {
+                Err(OutpostError::InvalidMaxSlippage { max_slippage })
+            }
+            max_slippage => Ok(DestinationAction {
+                max_slippage,
+                ..action
+            }),
+        })
+    }
+}
+
+/// Moves the shares that are below their destination's minimum over to the largest relative share
+/// so that tiny amounts don't end up in swaps that would fail.
+/// The relative shares are the ones from `relative_start` onwards. The absolute and capped
+/// destinations never take on dust, so without any relative destinations it stays with the user
+fn roll_up_dust<DestProjects>(
+    allocated: Vec<(Uint128, DestinationAction<DestProjects>)>,
+    relative_start: usize,
+) -> Vec<(Uint128, DestinationAction<DestProjects>)> {
+    let largest = allocated
+        .iter()
+        .enumerate()
+        .skip(relative_start)
+        .max_by(|(i, (a, _)), (j, (b, _))| a.cmp(b).then(j.cmp(i)))
+        .map(|(i, _)| i);
+
+    let is_dust = |i: usize, (amount, action): &(Uint128, DestinationAction<DestProjects>)| {
+        Some(i) != largest && matches!(action.min_amount, Some(min_amount) if *amount < min_amount)
+    };
+
+    let dust = allocated
+        .iter()
+        .enumerate()
+        .filter(|(i, allocation)| is_dust(*i, allocation))
+        .fold(Uint128::zero(), |acc, (_, (amount, _))| acc + *amount);
+
+    allocated
+        .into_iter()
+        .enumerate()
+        .filter(|(i, allocation)| !is_dust(*i, allocation))
+        .map(|(i, (amount, action))| match Some(i) == largest {
+            true => (amount + dust, action),
+            false => (amount, action),
+        })
+        .collect()
+}
+
+/// A destination that converts its share into an intermediate asset and then splits
+/// whatever the conversion returns between a nested set of prefs.
+/// The nested destinations run as part of this one so their own fallbacks aren't used,
+/// if any of them fail the whole share goes to this destination's fallback instead
+#[cw_serde]
+pub struct CompositeDestination<DestProjects, Conversion> {
+    /// how the share gets converted into the intermediate asset
+    pub convert_to: Conversion,
+    /// the prefs that the intermediate asset is compounded with.
+    /// these destinations are handed the intermediate asset rather than the reward token
+    pub then: CompoundPrefs<DestProjects>,
+}
+
+/// Destinations that can hold a nested set of prefs of their own
+pub trait NestedDestinations: Sized {
+    /// The denom of the intermediate asset along with the prefs that it's compounded with
+    /// if this is a composite destination
+    fn nested_prefs(&self) -> Option<(String, &CompoundPrefs<Self>)>;
+
+    /// Whether the destination can only be handed the outpost's native token, such as staking
+    fn native_only(&self) -> bool {
+        false
+    }
+}
+
+impl<DestProjects: NestedDestinations> CompoundPrefs<DestProjects> {
+    /// Every destination that the prefs could end up compounding into, including the fallbacks
+    /// and everything nested in composite destinations.
+    /// Each destination is paired with the denom that it's handed, starting from `denom`
+    pub fn nested_destinations(&self, denom: &str) -> std::vec::IntoIter<(String, &DestProjects)> {
+        self.destinations()
+            .flat_map(|destination| {
+                let nested = destination
+                    .nested_prefs()
+                    .map(|(intermediate_denom, prefs)| {
+                        prefs.nested_destinations(&intermediate_denom)
+                    })
+                    .into_iter()
+                    .flatten();
+
+                std::iter::once((denom.to_string(), destination)).chain(nested)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Errors if a composite destination would hand anything other than `native_denom`
+    /// to a destination that only accepts the native token
+    pub fn check_native_only(&self, native_denom: &str) -> Result<(), OutpostError> {
+        match self
+            .nested_destinations(native_denom)
+            .find(|(denom, destination)| denom.ne(native_denom) && destination.native_only())
+        {
+            Some((denom, _)) => Err(OutpostError::NativeOnlyDestination {
+                denom,
+                native_denom: native_denom.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The least that the rewards have to be worth for a compound to go ahead
+#[cw_serde]
+pub enum MinCompound {
+    /// an amount of the reward token in its base units
+    Native(Uint128),
+    /// an amount of USDC in its base units.
+    /// the rewards are valued through the outpost's USDC pools
+    Usdc(Uint128),
 }
 
 #[cw_serde]
@@ -23,6 +315,32 @@ pub struct ValidatorSelection {
     pub percent: Decimal,
 }
 
+impl ValidatorSelection {
+    /// The percent of the stake that each of the selected validators should get.
+    /// Every validator needs a nonzero percent and together they have to cover all of the stake
+    pub fn percentages(selections: &[ValidatorSelection]) -> Result<Vec<Decimal>, OutpostError> {
+        let percentages = selections
+            .iter()
+            .map(
+                |ValidatorSelection { percent, .. }| match percent.is_zero() {
+                    true => Err(OutpostError::ZeroPrefs),
+                    false => Ok(*percent),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sum = percentages
+            .iter()
+            .try_fold(Decimal::zero(), |acc, pct| acc.checked_add(*pct))?;
+
+        if sum.ne(&Decimal::one()) {
+            return Err(OutpostError::InvalidValidatorSelection { sum });
+        }
+
+        Ok(percentages)
+    }
+}
+
 #[cw_serde]
 pub struct TakeRate {
     pub max_tax_fee: Decimal,
@@ -40,4 +358,17 @@ impl TakeRate {
             take_rate_addr: api.addr_validate(take_rate_address)?,
         })
     }
+
+    /// The fee to charge the user, defaulting to the max tax fee.
+    /// Errors if the fee is above the max tax fee
+    pub fn tax_fee(&self, fee: Option<Decimal>) -> Result<Decimal, OutpostError> {
+        match fee {
+            Some(fee) if fee > self.max_tax_fee => Err(OutpostError::TaxFeeAboveMax {
+                fee,
+                max_tax_fee: self.max_tax_fee,
+            }),
+            Some(fee) => Ok(fee),
+            None => Ok(self.max_tax_fee),
+        }
+    }
 }
@@ -1,5 +1,5 @@
 use cosmos_sdk_proto::prost;
-use cosmwasm_std::{Decimal, StdError};
+use cosmwasm_std::{Decimal, StdError, Timestamp, Uint128, Uint64};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -28,6 +28,12 @@ pub enum OutpostError {
     #[error("Could not encode msg as any: {0}")]
     EncodeError(#[from] prost::EncodeError),
 
+    #[error("Could not decode msg from any: {0}")]
+    DecodeError(#[from] prost::DecodeError),
+
+    #[error("Unknown proto msg type: {0}")]
+    UnknownProtoMsg(String),
+
     #[error("Compound arithemtic overflow: {0}")]
     OverflowError(#[from] cosmwasm_std::OverflowError),
 
@@ -39,4 +45,101 @@ pub enum OutpostError {
 
     #[error("Invalid asset: {denom} for project: {project}")]
     InvalidAsset { denom: String, project: String },
+
+    #[error("Comp prefs contract address has not been set")]
+    CompPrefsAddressNotSet,
+
+    #[error("No stored comp prefs found. User: {user_address}, Strategy Id: {strat_id}")]
+    NoStoredCompPrefs {
+        user_address: String,
+        strat_id: Uint64,
+    },
+
+    #[error("Stored comp prefs are inactive. User: {user_address}, Strategy Id: {strat_id}")]
+    InactiveStoredCompPrefs {
+        user_address: String,
+        strat_id: Uint64,
+    },
+
+    #[error("Stored comp prefs belong to a different outpost: {outpost_address}")]
+    StoredCompPrefsOutpostMismatch { outpost_address: String },
+
+    #[error("Stored strategy settings are for a different user: {user_address}")]
+    StoredCompPrefsUserMismatch { user_address: String },
+
+    #[error("{user_address} cannot be compounded again until {next_eligible}")]
+    CompoundTooSoon {
+        user_address: String,
+        next_eligible: Timestamp,
+    },
+
+    #[error("Cannot compound into {count} destinations. The max is {max}")]
+    TooManyDestinations { count: usize, max: u64 },
+
+    #[error("Tax fee of {fee} is above the max tax fee of {max_tax_fee}")]
+    TaxFeeAboveMax { fee: Decimal, max_tax_fee: Decimal },
+
+    #[error("Cannot compound {count} users in one batch. The max is {max}")]
+    BatchTooLarge { count: usize, max: u64 },
+
+    #[error("Max slippage of {max_slippage} must be less than 1")]
+    InvalidMaxSlippage { max_slippage: Decimal },
+
+    #[error("Validator percents must sum to 1. {sum}")]
+    InvalidValidatorSelection { sum: Decimal },
+
+    #[error("Fee of {fee} in the fee schedule is above the max of {max_fee}")]
+    InvalidFeeSchedule { fee: Decimal, max_fee: Decimal },
+
+    #[error("Outpost does not have a fee schedule")]
+    FeeScheduleNotSet,
+
+    #[error("Fee of {fee} is above the accepted max of {max_fee}")]
+    FeeAboveAccepted { fee: Decimal, max_fee: Decimal },
+
+    #[error("{0} has not agreed to the outpost's fee")]
+    FeeNotAgreed(String),
+
+    #[error("Tax fee of {tax_fee} does not match the user's fee of {fee}")]
+    TaxFeeMismatch { tax_fee: Decimal, fee: Decimal },
+
+    #[error(
+        "Composite destinations can't hand {denom} to destinations that only accept {native_denom}"
+    )]
+    NativeOnlyDestination { denom: String, native_denom: String },
+
+    #[error("Referral share must not be more than 1. {0}")]
+    InvalidReferralShare(Decimal),
+
+    #[error("{0} is not a registered referrer")]
+    UnregisteredReferrer(String),
+
+    #[error("Invalid prefs: Capped quantities must not sum to more than 1. {sum}")]
+    InvalidCappedQtys { sum: Decimal },
+
+    #[error(
+        "Rewards of {available} can't cover the {required} needed by the absolute destinations"
+    )]
+    UnderfundedCompound {
+        required: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Could not convert asset to coin: token addr- {0}")]
+    AssetIsNotCoinable(String),
+
+    #[error("None of the swap venues can swap {from} to {to}")]
+    NoSwapVenue { from: String, to: String },
+
+    #[error("Simulated prices need a nonzero probe amount")]
+    ZeroPriceProbe,
+
+    #[error("Price impact of {impact} swapping {offer} to {ask} on {venue} is above the max of {max_impact}")]
+    PriceImpactTooHigh {
+        venue: String,
+        offer: String,
+        ask: String,
+        impact: Decimal,
+        max_impact: Decimal,
+    },
 }
@@ -0,0 +1,333 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_string, Addr, Coin, Decimal, Env, Event, ReplyOn, Response, Storage, SubMsg,
+    SubMsgResult,
+};
+use cw_storage_plus::Map;
+use serde::Serialize;
+
+use crate::{
+    comp_prefs::{CompoundPrefs, DestinationAction},
+    errors::OutpostError,
+    helpers::DestProjectMsgs,
+    msg_gen::{create_exec_msg, CosmosProtoMsg, StoredProtoMsg},
+    pipeline::PIPELINE_SWAP_REPLY_ID,
+    stats::{DestinationLabel, StatsStore},
+};
+
+/// Each destination gets its own reply id counting up from here so that a failed destination
+/// can be told apart from the others
+pub const FALLBACK_REPLY_ID_START: u64 = 2_000;
+
+/// The last reply id that can be handed out to a destination
+pub const FALLBACK_REPLY_ID_END: u64 = 2_999;
+
+/// Checks if the reply id belongs to one of the compound's destinations
+pub fn is_fallback_reply(reply_id: u64) -> bool {
+    (FALLBACK_REPLY_ID_START..=FALLBACK_REPLY_ID_END).contains(&reply_id)
+}
+
+#[cw_serde]
+pub struct PendingFallback {
+    pub user: Addr,
+    /// the destination that the share was meant for
+    pub destination: String,
+    /// the destination that the share gets rerouted to if the original one fails
+    pub fallback: Option<String>,
+    /// the labels that the destination's and the fallback's stats are kept under
+    pub label: String,
+    pub fallback_label: Option<String>,
+    /// the share of the rewards that was allocated to the destination
+    pub amount: Coin,
+    /// the msgs that send the share to the fallback destination.
+    /// these are empty once the fallback has been used up
+    pub fallback_msgs: Vec<StoredProtoMsg>,
+    /// set once the share has been sent to the fallback instead
+    pub rerouted: bool,
+}
+
+impl PendingFallback {
+    /// The label of the destination that the share is currently going to
+    pub fn current_label(&self) -> &str {
+        match (self.rerouted, &self.fallback_label) {
+            (true, Some(fallback_label)) => fallback_label,
+            _ => &self.label,
+        }
+    }
+}
+
+/// Prefs that send the whole amount to a single destination
+fn whole_share<D>(destination: D, max_slippage: Option<Decimal>) -> CompoundPrefs<D> {
+    CompoundPrefs {
+        relative: vec![DestinationAction {
+            destination,
+            amount: 1_000_000_000_000_000_000,
+            fallback: None,
+            max_slippage,
+            min_amount: None,
+        }],
+        absolute: vec![],
+        capped: vec![],
+    }
+}
+
+fn fallback_event(fallback: &PendingFallback, error: &str) -> Event {
+    Event::new("destination_fallback")
+        .add_attribute("user", fallback.user.to_string())
+        .add_attribute("destination", fallback.destination.clone())
+        .add_attribute("fallback", fallback.fallback.clone().unwrap_or_default())
+        .add_attribute("amount", fallback.amount.to_string())
+        .add_attribute("error", error)
+}
+
+fn failed_event(fallback: &PendingFallback, error: &str) -> Event {
+    Event::new("destination_failed")
+        .add_attribute("user", fallback.user.to_string())
+        .add_attribute("destination", fallback.destination.clone())
+        .add_attribute("amount", fallback.amount.to_string())
+        .add_attribute("error", error)
+}
+
+/// Generates the msgs for each destination on its own and moves them into their own submsg
+/// so that a destination that fails gets rerouted to its fallback instead of failing the
+/// whole compound. Every destination replies so that its fallback is cleared out once it's
+/// done. A destination whose msgs can't even be generated is rerouted right away.
+/// Returns the destinations' msgs along with the fallbacks that have to be saved for the replies
+pub fn isolate_destinations<D, E>(
+    user: &Addr,
+    total_rewards: &Coin,
+    comp_prefs: &CompoundPrefs<D>,
+    mut gen_msgs: impl FnMut(Coin, CompoundPrefs<D>) -> Result<Vec<DestProjectMsgs>, E>,
+) -> Result<(Vec<DestProjectMsgs>, Vec<(u64, PendingFallback)>), E>
+where
+    D: Clone + Serialize + DestinationLabel,
+    E: From<OutpostError> + ToString,
+{
+    let max = FALLBACK_REPLY_ID_END - FALLBACK_REPLY_ID_START + 1;
+    let count = comp_prefs.actions().count();
+    if count as u64 > max {
+        return Err(OutpostError::TooManyDestinations { count, max }.into());
+    }
+
+    let allocated = comp_prefs.clone().split_rewards(&total_rewards.amount)?;
+
+    let mut isolated = vec![];
+    let mut fallbacks = vec![];
+
+    for (
+        reply_id,
+        (
+            amount,
+            DestinationAction {
+                destination,
+                fallback,
+                max_slippage,
+                ..
+            },
+        ),
+    ) in (FALLBACK_REPLY_ID_START..).zip(allocated.iter())
+    {
+        let amount = Coin {
+            denom: total_rewards.denom.clone(),
+            amount: *amount,
+        };
+
+        // if the fallback can't be generated either then the share just stays with the user
+        let fallback_msgs = match fallback {
+            Some(fallback) => {
+                gen_msgs(amount.clone(), whole_share(fallback.clone(), *max_slippage))
+                    .map(|msgs| DestProjectMsgs::inline(msgs).msgs)
+                    .unwrap_or_default()
+            }
+            None => vec![],
+        };
+
+        let mut pending = PendingFallback {
+            user: user.clone(),
+            destination: to_json_string(destination).map_err(OutpostError::from)?,
+            fallback: fallback
+                .as_ref()
+                .map(to_json_string)
+                .transpose()
+                .map_err(OutpostError::from)?,
+            label: destination.label().to_string(),
+            fallback_label: fallback
+                .as_ref()
+                .map(|fallback| fallback.label().to_string()),
+            amount: amount.clone(),
+            fallback_msgs: fallback_msgs
+                .iter()
+                .map(StoredProtoMsg::try_from)
+                .collect::<Result<_, _>>()?,
+            rerouted: false,
+        };
+
+        match gen_msgs(amount, whole_share(destination.clone(), *max_slippage)) {
+            Ok(dest_msgs) => {
+                let mut dest_msgs =
+                    dest_msgs
+                        .into_iter()
+                        .fold(DestProjectMsgs::default(), |mut acc, msgs| {
+                            acc.append_msgs(msgs.msgs);
+                            acc.append_submsgs(msgs.sub_msgs);
+                            acc.append_events(msgs.events);
+                            acc
+                        });
+
+                if dest_msgs
+                    .sub_msgs
+                    .iter()
+                    .any(|(id, _, _)| *id == PIPELINE_SWAP_REPLY_ID)
+                {
+                    // pipelined destinations are run by the pipeline so this just marks
+                    // which fallback the pipeline should use
+                    dest_msgs.append_submsgs(vec![(reply_id, vec![], ReplyOn::Always)]);
+                } else if dest_msgs.msgs.is_empty() {
+                    // there's nothing to broadcast so there won't be a reply to clear out the fallback
+                    isolated.push(dest_msgs);
+                    continue;
+                } else {
+                    let msgs = std::mem::take(&mut dest_msgs.msgs);
+                    dest_msgs.prepend_submsgs(vec![(reply_id, msgs, ReplyOn::Always)]);
+                }
+
+                isolated.push(dest_msgs);
+            }
+            Err(err) => {
+                let error = err.to_string();
+
+                if fallback_msgs.is_empty() {
+                    isolated.push(DestProjectMsgs {
+                        events: vec![failed_event(&pending, &error)],
+                        ..DestProjectMsgs::default()
+                    });
+                    continue;
+                }
+
+                isolated.push(DestProjectMsgs {
+                    sub_msgs: vec![(reply_id, fallback_msgs, ReplyOn::Always)],
+                    events: vec![fallback_event(&pending, &error)],
+                    ..DestProjectMsgs::default()
+                });
+                // the fallback is already being used so if it fails the share stays with the user
+                pending.fallback_msgs = vec![];
+                pending.rerouted = true;
+            }
+        }
+
+        fallbacks.push((reply_id, pending));
+    }
+
+    Ok((isolated, fallbacks))
+}
+
+/// Saves the fallbacks so that the reply handler can find them if a destination fails
+pub fn save_pending_fallbacks(
+    storage: &mut dyn Storage,
+    pending: &Map<u64, PendingFallback>,
+    fallbacks: Vec<(u64, PendingFallback)>,
+) -> Result<(), OutpostError> {
+    for (reply_id, fallback) in fallbacks {
+        pending.save(storage, reply_id, &fallback)?;
+    }
+
+    Ok(())
+}
+
+/// Reports a destination that failed for good. Whatever it was meant to use stays with the user
+/// so it's taken back out of the stats
+pub fn fail_destination(
+    storage: &mut dyn Storage,
+    reply_id: u64,
+    error: String,
+    pending: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+) -> Result<Response, OutpostError> {
+    let Some(fallback) = pending.may_load(storage, reply_id)? else {
+        return Ok(Response::default());
+    };
+    pending.remove(storage, reply_id);
+    stats.reroute(
+        storage,
+        &fallback.user,
+        fallback.current_label(),
+        None,
+        &fallback.amount,
+    )?;
+
+    Ok(Response::default().add_event(failed_event(&fallback, &error)))
+}
+
+/// Reroutes the share of a destination that failed to its fallback.
+/// If there's no fallback, or the fallback has failed as well, the share is left with the user
+pub fn handle_fallback_reply(
+    storage: &mut dyn Storage,
+    env: &Env,
+    reply_id: u64,
+    error: String,
+    pending: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+) -> Result<Response, OutpostError> {
+    let Some(fallback) = pending.may_load(storage, reply_id)? else {
+        return Ok(Response::default());
+    };
+
+    if fallback.fallback_msgs.is_empty() {
+        return fail_destination(storage, reply_id, error, pending, stats);
+    }
+
+    let fallback_msgs = fallback
+        .fallback_msgs
+        .iter()
+        .map(CosmosProtoMsg::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // if the fallback fails as well then the share just stays with the user
+    pending.save(
+        storage,
+        reply_id,
+        &PendingFallback {
+            fallback_msgs: vec![],
+            rerouted: true,
+            ..fallback.clone()
+        },
+    )?;
+    stats.reroute(
+        storage,
+        &fallback.user,
+        &fallback.label,
+        fallback.fallback_label.as_deref(),
+        &fallback.amount,
+    )?;
+
+    Ok(Response::default()
+        .add_event(fallback_event(&fallback, &error))
+        .add_submessage(SubMsg {
+            id: reply_id,
+            msg: create_exec_msg(&env.contract.address, fallback_msgs)?,
+            gas_limit: None,
+            reply_on: ReplyOn::Always,
+        }))
+}
+
+/// Handles the reply of one of the compound's destinations.
+/// A destination that went through no longer needs its fallback and a failed one is rerouted to it
+pub fn handle_destination_reply(
+    storage: &mut dyn Storage,
+    env: &Env,
+    reply_id: u64,
+    result: SubMsgResult,
+    pending: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+) -> Result<Response, OutpostError> {
+    match result {
+        SubMsgResult::Ok(_) => {
+            pending.remove(storage, reply_id);
+
+            Ok(Response::default())
+        }
+        SubMsgResult::Err(error) => {
+            handle_fallback_reply(storage, env, reply_id, error, pending, stats)
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Decimal, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map};
+
+use crate::errors::OutpostError;
+
+/// A discounted fee for users that have automated at least `min_volume` through the outpost
+#[cw_serde]
+pub struct FeeTier {
+    /// measured in the base units of the outpost's compounding token
+    pub min_volume: Uint128,
+    pub fee: Decimal,
+}
+
+/// The fees an outpost charges its users
+#[cw_serde]
+pub struct FeeSchedule {
+    /// the fee for users that haven't reached any of the tiers
+    pub default_fee: Decimal,
+    #[serde(default)]
+    pub tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+    /// Checks that none of the schedule's fees are above `max_fee`
+    pub fn validate(&self, max_fee: Decimal) -> Result<(), OutpostError> {
+        match std::iter::once(self.default_fee)
+            .chain(self.tiers.iter().map(|FeeTier { fee, .. }| *fee))
+            .find(|fee| fee.gt(&max_fee))
+        {
+            Some(fee) => Err(OutpostError::InvalidFeeSchedule { fee, max_fee }),
+            None => Ok(()),
+        }
+    }
+
+    /// The fee for a user that has automated `volume` so far.
+    /// Users always get the cheapest fee of the tiers they have reached
+    pub fn fee_for_volume(&self, volume: Uint128) -> Decimal {
+        self.tiers
+            .iter()
+            .filter(|FeeTier { min_volume, .. }| volume.ge(min_volume))
+            .map(|FeeTier { fee, .. }| *fee)
+            .fold(self.default_fee, Decimal::min)
+    }
+}
+
+/// Where an outpost keeps its fee schedule along with everything needed to pick a user's fee
+pub struct FeeStore<'a> {
+    pub schedule: Item<'a, FeeSchedule>,
+    /// fees for specific users that replace the schedule, such as fee free partner wallets
+    pub overrides: Map<'a, &'a Addr, Decimal>,
+    /// the fee each user accepted when they granted the outpost. they're never charged more than this
+    pub agreed: Map<'a, &'a Addr, Decimal>,
+    /// the total amount each user has automated, used to place them in a tier
+    pub volume: Map<'a, &'a Addr, Uint128>,
+}
+
+impl<'a> FeeStore<'a> {
+    pub const fn new(
+        schedule_key: &'a str,
+        overrides_namespace: &'a str,
+        agreed_namespace: &'a str,
+        volume_namespace: &'a str,
+    ) -> Self {
+        FeeStore {
+            schedule: Item::new(schedule_key),
+            overrides: Map::new(overrides_namespace),
+            agreed: Map::new(agreed_namespace),
+            volume: Map::new(volume_namespace),
+        }
+    }
+
+    /// Replaces the outpost's fee schedule or removes it.
+    /// Errors if any of the schedule's fees are above `max_fee`
+    pub fn update_schedule(
+        &self,
+        storage: &mut dyn Storage,
+        schedule: Option<FeeSchedule>,
+        max_fee: Decimal,
+    ) -> Result<(), OutpostError> {
+        match schedule {
+            Some(schedule) => {
+                schedule.validate(max_fee)?;
+                Ok(self.schedule.save(storage, &schedule)?)
+            }
+            None => {
+                self.schedule.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the user's override or removes it. Errors if the fee is above `max_fee`
+    pub fn update_override(
+        &self,
+        storage: &mut dyn Storage,
+        user: &Addr,
+        fee: Option<Decimal>,
+        max_fee: Decimal,
+    ) -> Result<(), OutpostError> {
+        match fee {
+            Some(fee) if fee.gt(&max_fee) => Err(OutpostError::InvalidFeeSchedule { fee, max_fee }),
+            Some(fee) => Ok(self.overrides.save(storage, user, &fee)?),
+            None => {
+                self.overrides.remove(storage, user);
+                Ok(())
+            }
+        }
+    }
+
+    /// The fee the user would be charged today ignoring what they have agreed to.
+    /// `None` if the outpost doesn't have a fee schedule and the user doesn't have an override
+    pub fn quote(&self, storage: &dyn Storage, user: &Addr) -> StdResult<Option<Decimal>> {
+        if let Some(fee) = self.overrides.may_load(storage, user)? {
+            return Ok(Some(fee));
+        }
+
+        let volume = self.volume.may_load(storage, user)?.unwrap_or_default();
+
+        Ok(self
+            .schedule
+            .may_load(storage)?
+            .map(|schedule| schedule.fee_for_volume(volume)))
+    }
+
+    /// The fee to charge the user when they're compounded.
+    /// Once the outpost quotes the user a fee they have to have agreed to one and are charged
+    /// their quote but never more than what they agreed to. The caller's `tax_fee` has to match it.
+    /// Otherwise the caller's fee is charged, defaulting to `max_fee` and never above it
+    /// or the fee the user agreed to
+    pub fn fee(
+        &self,
+        storage: &dyn Storage,
+        user: &Addr,
+        tax_fee: Option<Decimal>,
+        max_fee: Decimal,
+    ) -> Result<Decimal, OutpostError> {
+        let agreed = self.agreed.may_load(storage, user)?;
+
+        let Some(quote) = self.quote(storage, user)? else {
+            return cap_fee(
+                tax_fee,
+                agreed.map_or(max_fee, |agreed| agreed.min(max_fee)),
+            );
+        };
+
+        let fee = agreed
+            .map(|agreed| quote.min(agreed))
+            .ok_or_else(|| OutpostError::FeeNotAgreed(user.to_string()))?;
+
+        match tax_fee {
+            Some(tax_fee) if tax_fee.ne(&fee) => Err(OutpostError::TaxFeeMismatch { tax_fee, fee }),
+            _ => Ok(fee),
+        }
+    }
+
+    /// The most the user's grants need to allow for. This is their quote once the outpost quotes
+    /// them a fee since that is what they agree to alongside their grants.
+    /// Otherwise it's the caller's fee, defaulting to `max_fee` and never above it
+    pub fn grant_fee(
+        &self,
+        storage: &dyn Storage,
+        user: &Addr,
+        tax_fee: Option<Decimal>,
+        max_fee: Decimal,
+    ) -> Result<Decimal, OutpostError> {
+        match self.quote(storage, user)? {
+            Some(quote) => Ok(quote),
+            None => cap_fee(tax_fee, max_fee),
+        }
+    }
+
+    /// Freezes the user's current quote as the most they can be charged.
+    /// Errors if the quote is above `max_fee`, the most the user is willing to pay
+    pub fn agree(
+        &self,
+        storage: &mut dyn Storage,
+        user: &Addr,
+        max_fee: Decimal,
+    ) -> Result<Decimal, OutpostError> {
+        let fee = self
+            .quote(storage, user)?
+            .ok_or(OutpostError::FeeScheduleNotSet)?;
+
+        if fee.gt(&max_fee) {
+            return Err(OutpostError::FeeAboveAccepted { fee, max_fee });
+        }
+
+        self.agreed.save(storage, user, &fee)?;
+
+        Ok(fee)
+    }
+
+    /// Adds to the user's volume so that they can move up the tiers
+    pub fn record_volume(
+        &self,
+        storage: &mut dyn Storage,
+        user: &Addr,
+        amount: Uint128,
+    ) -> StdResult<Uint128> {
+        self.volume.update(storage, user, |volume| -> StdResult<_> {
+            Ok(volume.unwrap_or_default() + amount)
+        })
+    }
+}
+
+/// The caller's fee or `max_fee` if they didn't give one. Errors if it's above `max_fee`
+fn cap_fee(tax_fee: Option<Decimal>, max_fee: Decimal) -> Result<Decimal, OutpostError> {
+    match tax_fee {
+        Some(fee) if fee.gt(&max_fee) => Err(OutpostError::TaxFeeAboveMax {
+            fee,
+            max_tax_fee: max_fee,
+        }),
+        Some(fee) => Ok(fee),
+        None => Ok(max_fee),
+    }
+}
@@ -1,12 +1,18 @@
 use cosmos_sdk_proto::cosmos::{bank::v1beta1::MsgSend, base::v1beta1::Coin as CsdkCoin};
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Coin, Decimal, Deps, Event, ReplyOn, Timestamp, Uint128};
-use cw_storage_plus::Item;
+use cosmwasm_std::{
+    Addr, Coin, Decimal, Deps, DepsMut, Event, ReplyOn, Response, Timestamp, Uint128, Uint64,
+};
+use cw_storage_plus::{Item, Map};
 
 use crate::{
-    comp_prefs::{CompoundPrefs, DestinationAction},
+    comp_prefs::{Allocation, CompoundPrefs, DestinationAction, MinCompound},
     errors::OutpostError,
-    msg_gen::CosmosProtoMsg,
+    fallback::PendingFallback,
+    msg_gen::{CosmosProtoMsg, DecodedProtoMsg},
+    pipeline::PIPELINE_SWAP_REPLY_ID,
+    queries::query_user_compounding_frequency,
+    referrals::Referral,
 };
 
 #[cw_serde]
@@ -25,6 +31,174 @@ impl CompoundingFrequency {
     pub fn iteration_count(&self, current_time: Timestamp, end_timestamp: Timestamp) -> u64 {
         (end_timestamp.seconds() - current_time.seconds()) / (self.clone() as u64)
     }
+
+    /// The earliest time that the next compound is allowed to happen
+    pub fn next_compound_at(&self, last_compounded: Timestamp) -> Timestamp {
+        last_compounded.plus_seconds(self.clone() as u64)
+    }
+}
+
+impl From<ymos_comp_prefs::state::CompoundingFrequency> for CompoundingFrequency {
+    fn from(frequency: ymos_comp_prefs::state::CompoundingFrequency) -> Self {
+        use ymos_comp_prefs::state::CompoundingFrequency as StoredFrequency;
+
+        match frequency {
+            StoredFrequency::Hourly => CompoundingFrequency::Hourly,
+            StoredFrequency::TwoTimesADay => CompoundingFrequency::TwiceDaily,
+            StoredFrequency::Daily => CompoundingFrequency::Daily,
+            StoredFrequency::Weekly => CompoundingFrequency::Weekly,
+            StoredFrequency::Monthly => CompoundingFrequency::Monthly,
+            StoredFrequency::Quarterly => CompoundingFrequency::Quarterly,
+        }
+    }
+}
+
+#[cw_serde]
+pub struct CompoundEligibilityResponse {
+    /// Whether or not the user could be compounded right now
+    pub eligible: bool,
+    /// The frequency that the user's compounds are limited to
+    pub frequency: CompoundingFrequency,
+    pub last_compounded: Option<Timestamp>,
+    /// The earliest time the user can next be compounded
+    pub next_eligible: Timestamp,
+    /// Explanation of why a compound would be rejected
+    pub reason: Option<String>,
+}
+
+/// Determines whether the user can be compounded given the last time they were compounded.
+/// The frequency comes from the stored strategy being compounded
+/// or falls back to the default frequency for compounds that aren't from a stored strategy
+pub fn compound_eligibility<'a>(
+    deps: Deps,
+    outpost_address: &Addr,
+    user_address: &'a Addr,
+    strat_id: Option<Uint64>,
+    current_time: Timestamp,
+    last_compounded: &Map<'a, &'a Addr, Timestamp>,
+    comp_prefs_addr: &Item<Addr>,
+) -> Result<CompoundEligibilityResponse, OutpostError> {
+    let frequency = match (comp_prefs_addr.may_load(deps.storage)?, strat_id) {
+        (Some(comp_prefs_addr), Some(strat_id)) => query_user_compounding_frequency(
+            &deps.querier,
+            &comp_prefs_addr,
+            outpost_address,
+            user_address,
+            strat_id,
+            &current_time,
+        )?
+        .unwrap_or_default(),
+        _ => CompoundingFrequency::default(),
+    };
+
+    let last_compounded = last_compounded.may_load(deps.storage, user_address)?;
+
+    let next_eligible = last_compounded
+        .map(|last_compounded| frequency.next_compound_at(last_compounded))
+        .unwrap_or(current_time);
+
+    let eligible = next_eligible.le(&current_time);
+
+    Ok(CompoundEligibilityResponse {
+        eligible,
+        reason: (!eligible).then(|| {
+            format!(
+                "Last compounded at {} and the {:?} frequency does not allow another compound until {}",
+                last_compounded.unwrap_or_default(),
+                frequency,
+                next_eligible
+            )
+        }),
+        frequency,
+        last_compounded,
+        next_eligible,
+    })
+}
+
+/// Rejects the compound if the user was compounded more recently than the strategy's frequency allows
+/// and otherwise records the current time as the user's latest compound.
+/// Users compounding themselves are not limited
+pub fn enforce_compound_frequency<'a>(
+    deps: DepsMut,
+    sender: &Addr,
+    outpost_address: &Addr,
+    user_address: &'a Addr,
+    strat_id: Option<Uint64>,
+    current_time: Timestamp,
+    last_compounded: &Map<'a, &'a Addr, Timestamp>,
+    comp_prefs_addr: &Item<Addr>,
+) -> Result<(), OutpostError> {
+    if sender.ne(user_address) {
+        let eligibility = compound_eligibility(
+            deps.as_ref(),
+            outpost_address,
+            user_address,
+            strat_id,
+            current_time,
+            last_compounded,
+            comp_prefs_addr,
+        )?;
+
+        if !eligibility.eligible {
+            return Err(OutpostError::CompoundTooSoon {
+                user_address: user_address.to_string(),
+                next_eligible: eligibility.next_eligible,
+            });
+        }
+    }
+
+    last_compounded.save(deps.storage, user_address, &current_time)?;
+
+    Ok(())
+}
+
+/// The event type that outposts emit when a compound is skipped for being below the minimum
+pub const COMPOUND_SKIPPED_EVENT: &str = "compound_skipped";
+
+/// Checks the rewards against the user's minimum compound. If the rewards fall short this
+/// returns the event explaining why the compound was skipped.
+/// `usdc_value` is only queried when the minimum is set in USDC
+pub fn below_min_compound<E>(
+    user: &Addr,
+    rewards: &Coin,
+    min_compound: &Option<MinCompound>,
+    usdc_value: impl FnOnce(&Coin) -> Result<Uint128, E>,
+) -> Result<Option<Event>, E> {
+    let (value, minimum) = match min_compound {
+        None => return Ok(None),
+        Some(MinCompound::Native(minimum)) => {
+            (rewards.clone(), Coin::new(minimum.u128(), &rewards.denom))
+        }
+        // there's nothing to value so there's no need to query the pools
+        Some(MinCompound::Usdc(minimum)) if rewards.amount.is_zero() => {
+            (Coin::new(0, "usdc"), Coin::new(minimum.u128(), "usdc"))
+        }
+        Some(MinCompound::Usdc(minimum)) => (
+            Coin::new(usdc_value(rewards)?.u128(), "usdc"),
+            Coin::new(minimum.u128(), "usdc"),
+        ),
+    };
+
+    if value.amount >= minimum.amount {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        Event::new(COMPOUND_SKIPPED_EVENT)
+            .add_attribute("user", user.to_string())
+            .add_attribute("rewards", rewards.to_string())
+            .add_attribute("value", value.to_string())
+            .add_attribute("min_compound", minimum.to_string())
+            .add_attribute("reason", "rewards are below the minimum compound"),
+    ))
+}
+
+/// Whether the compound's response is for a compound that was skipped by `below_min_compound`
+pub fn compound_was_skipped(response: &Response) -> bool {
+    response
+        .events
+        .iter()
+        .any(|event| event.ty.eq(COMPOUND_SKIPPED_EVENT))
 }
 
 /// sums the coins in a vec given denom name youre looking for
@@ -37,39 +211,107 @@ pub fn sum_coins(denom: &String, coins: &[Coin]) -> Coin {
         })
 }
 
-/// calculates the amounts that should be sent to each destination project
-pub fn calculate_compound_amounts(
-    percentages: &Vec<Decimal>,
+/// calculates the amounts that should be sent to each destination project.
+/// the absolute allocations are taken out first, then the capped ones get their percentage
+/// of what's left up to their cap and the relative ones split whatever remains after that.
+/// the last relative allocation picks up anything lost to rounding
+pub fn calculate_compound_amounts<A: Clone + Into<Allocation>>(
+    allocations: &[A],
     total_amount: &Uint128,
 ) -> Result<Vec<Uint128>, OutpostError> {
-    let mut remaining = *total_amount;
+    let allocations: Vec<Allocation> = allocations.iter().cloned().map(Into::into).collect();
+
+    let required =
+        allocations
+            .iter()
+            .try_fold(Uint128::zero(), |acc, allocation| match allocation {
+                Allocation::Absolute(amount) => acc.checked_add(*amount),
+                _ => Ok(acc),
+            })?;
+    let after_absolute =
+        total_amount
+            .checked_sub(required)
+            .map_err(|_| OutpostError::UnderfundedCompound {
+                required,
+                available: *total_amount,
+            })?;
+
+    let share = |amount: Uint128, pct: Decimal| -> Result<Uint128, OutpostError> {
+        Ok(Decimal::new(amount).checked_mul(pct)?.atomics())
+    };
+
+    let capped_total = allocations
+        .iter()
+        .try_fold(Uint128::zero(), |acc, allocation| match allocation {
+            Allocation::Capped { percent, cap } => {
+                Ok(acc.checked_add(share(after_absolute, *percent)?.min(*cap))?)
+            }
+            _ => Ok::<_, OutpostError>(acc),
+        })?;
+    let relative_pool = after_absolute.checked_sub(capped_total)?;
+    let mut remaining = relative_pool;
+    let last_relative = allocations
+        .iter()
+        .rposition(|allocation| matches!(allocation, Allocation::Relative(_)));
+
     let mut amounts = vec![];
-    for (i, pct) in percentages.iter().enumerate() {
-        if (i + 1) == percentages.len() {
-            amounts.push(remaining);
-            break;
-        }
-        let pct_amount = Decimal::new(*total_amount).checked_mul(*pct)?.atomics();
-        amounts.push(pct_amount);
-        remaining = remaining.checked_sub(pct_amount)?;
+    for (i, allocation) in allocations.iter().enumerate() {
+        let amount = match allocation {
+            Allocation::Absolute(amount) => *amount,
+            Allocation::Capped { percent, cap } => share(after_absolute, *percent)?.min(*cap),
+            Allocation::Relative(_) if Some(i) == last_relative => remaining,
+            Allocation::Relative(pct) => {
+                let pct_amount = share(relative_pool, *pct)?;
+                remaining = remaining.checked_sub(pct_amount)?;
+                pct_amount
+            }
+        };
+        amounts.push(amount);
     }
 
     Ok(amounts)
 }
 
-/// checks that the prefs are both summing to 1 and that they are all positive and nonzero
+/// the least that a swap expecting `expected_amount` should accept given the slippage tolerance
+pub fn min_out_after_slippage(expected_amount: Uint128, max_slippage: Decimal) -> Uint128 {
+    expected_amount * Decimal::one().saturating_sub(max_slippage)
+}
+
+/// sums up the percentages making sure that they are all positive and nonzero
+fn sum_percentages(amounts: impl Iterator<Item = u128>) -> Result<Decimal, OutpostError> {
+    amounts.try_fold(Decimal::zero(), |acc, x| {
+        match Decimal::from_atomics(x, 18) {
+            Ok(x) if x.gt(&Decimal::zero()) => Ok(acc + x),
+            _ => Err(OutpostError::ZeroPrefs),
+        }
+    })
+}
+
+/// checks that the relative prefs are both summing to 1 and that they are all positive and nonzero.
+/// the relative prefs can only be left out if there are absolute or capped destinations instead,
+/// in which case anything that isn't allocated stays with the user
 pub fn prefs_sum_to_one<D>(comp_prefs: &CompoundPrefs<D>) -> Result<bool, OutpostError> {
-    let total_pref_amounts =
-        comp_prefs
-            .relative
-            .iter()
-            .map(|x| x.amount)
-            .try_fold(Decimal::zero(), |acc, x| {
-                match (acc, Decimal::from_atomics(x, 18)) {
-                    (acc, Ok(x)) if x.gt(&Decimal::zero()) => Ok(acc + x),
-                    _ => Err(OutpostError::ZeroPrefs),
-                }
-            })?;
+    if comp_prefs.absolute.iter().any(|action| action.amount == 0)
+        || comp_prefs.capped.iter().any(|capped| capped.cap.is_zero())
+    {
+        return Err(OutpostError::ZeroPrefs);
+    }
+
+    let total_capped_amounts =
+        sum_percentages(comp_prefs.capped.iter().map(|capped| capped.action.amount))?;
+    if total_capped_amounts > Decimal::one() {
+        return Err(OutpostError::InvalidCappedQtys {
+            sum: total_capped_amounts,
+        });
+    }
+
+    if comp_prefs.relative.is_empty()
+        && !(comp_prefs.absolute.is_empty() && comp_prefs.capped.is_empty())
+    {
+        return Ok(true);
+    }
+
+    let total_pref_amounts = sum_percentages(comp_prefs.relative.iter().map(|x| x.amount))?;
 
     match total_pref_amounts == Decimal::one() {
         true => Ok(true),
@@ -123,6 +365,8 @@ pub fn is_authorized_compounder(
 pub struct TaxSplitResult {
     pub remaining_rewards: Coin,
     pub tax_amount: Coin,
+    /// the part of `tax_amount` that was sent to the user's referrer
+    pub referral_amount: Coin,
     pub claim_and_tax_msgs: Vec<CosmosProtoMsg>,
 }
 
@@ -162,21 +406,234 @@ impl DestProjectMsgs {
     pub fn append_events(&mut self, events: Vec<Event>) {
         self.events.extend(events);
     }
+
+    /// Holds this destination's msgs back until the swap msgs have run so that they can be
+    /// rebuilt with the amount that the swap actually returned rather than its simulation
+    pub fn run_after_swap(&mut self, swap_msgs: Vec<CosmosProtoMsg>) {
+        self.prepend_submsgs(vec![(PIPELINE_SWAP_REPLY_ID, swap_msgs, ReplyOn::Success)]);
+    }
+
+    /// Folds a destination's msgs into a single list where the pipelined swaps simply run
+    /// before the msgs that use their output
+    pub fn inline(dest_msgs: Vec<DestProjectMsgs>) -> DestProjectMsgs {
+        dest_msgs
+            .into_iter()
+            .fold(DestProjectMsgs::default(), |mut acc, dest_msgs| {
+                let (swaps, sub_msgs): (Vec<_>, Vec<_>) = dest_msgs
+                    .sub_msgs
+                    .into_iter()
+                    .partition(|(id, _, _)| *id == PIPELINE_SWAP_REPLY_ID);
+
+                acc.append_msgs(swaps.into_iter().flat_map(|(_, msgs, _)| msgs).collect());
+                acc.append_msgs(dest_msgs.msgs);
+                acc.append_msgs(sub_msgs.into_iter().flat_map(|(_, msgs, _)| msgs).collect());
+                acc.append_events(dest_msgs.events);
+                acc
+            })
+    }
+
+    /// Combines the msgs that convert a composite destination's share into its intermediate
+    /// asset with the msgs of its nested destinations. The nested msgs are sized from the least
+    /// that the conversion can return so they're simply run right after it
+    pub fn composite(conversion_msgs: Vec<CosmosProtoMsg>, nested: Vec<DestProjectMsgs>) -> Self {
+        let mut composite = DestProjectMsgs::inline(nested);
+        composite.prepend_msgs(conversion_msgs);
+        composite
+    }
+}
+
+#[cw_serde]
+pub struct DestinationSimulation<D> {
+    pub destination: D,
+    /// the portion of the rewards that is allocated to this destination
+    pub amount: Coin,
+    /// the largest amount of each token other than the reward token that the
+    /// destination's msgs move. since those msgs are sized from swap simulations
+    /// this is the estimated output of the destination's swaps.
+    /// if the destination doesn't swap this will just be the allocated amount
+    pub estimated_outputs: Vec<Coin>,
+    pub msgs: Vec<DecodedProtoMsg>,
+}
+
+#[cw_serde]
+pub struct SimulateCompoundResponse<D> {
+    /// the rewards that the user is due to receive before any tax is taken
+    pub total_rewards: Coin,
+    /// the tax that would be taken by the outpost
+    pub tax: Vec<Coin>,
+    pub destinations: Vec<DestinationSimulation<D>>,
+    /// every msg that would be broadcast on behalf of the user.
+    /// the claim and tax msgs come first followed by each destination's msgs
+    pub msgs: Vec<DecodedProtoMsg>,
+    /// set when the rewards are below the user's min compound,
+    /// in which case nothing would be claimed or compounded
+    #[serde(default)]
+    pub skipped: Option<Event>,
+}
+
+/// Everything a compound would do for a user, worked out without saving anything so that
+/// a simulation goes through exactly the same steps as the compound itself
+pub struct CompoundPlan<D> {
+    /// the rewards that the user is due to receive before any tax is taken
+    pub total_rewards: Coin,
+    pub tax: Vec<Coin>,
+    /// the rewards left to split between the destinations once the tax is taken
+    pub compounded: Coin,
+    /// the user's prefs along with the outpost's default fallback
+    pub comp_prefs: CompoundPrefs<D>,
+    pub claim_msgs: Vec<CosmosProtoMsg>,
+    /// each destination's isolated msgs in the same order as the destinations in the comp prefs
+    pub dest_msgs: Vec<DestProjectMsgs>,
+    pub fallbacks: Vec<(u64, PendingFallback)>,
+    /// set when the rewards are below the user's min compound so there's nothing to compound
+    pub skipped: Option<Event>,
+}
+
+impl<D> CompoundPlan<D> {
+    /// A plan that leaves the rewards to build up for a later compound
+    pub fn skipped(total_rewards: Coin, comp_prefs: CompoundPrefs<D>, skipped: Event) -> Self {
+        CompoundPlan {
+            compounded: Coin::new(0, &total_rewards.denom),
+            total_rewards,
+            tax: vec![],
+            comp_prefs,
+            claim_msgs: vec![],
+            dest_msgs: vec![],
+            fallbacks: vec![],
+            skipped: Some(skipped),
+        }
+    }
+
+    /// Previews the plan without broadcasting anything
+    pub fn simulate(self) -> Result<SimulateCompoundResponse<D>, OutpostError> {
+        if let Some(skipped) = self.skipped {
+            return Ok(SimulateCompoundResponse {
+                total_rewards: self.total_rewards,
+                tax: vec![],
+                destinations: vec![],
+                msgs: vec![],
+                skipped: Some(skipped),
+            });
+        }
+
+        simulate_compound_response(
+            self.total_rewards,
+            self.tax,
+            self.compounded,
+            self.comp_prefs,
+            self.claim_msgs,
+            self.dest_msgs,
+        )
+    }
+}
+
+/// Estimates the tokens that a destination will end up with based on its decoded msgs
+fn estimate_destination_outputs(
+    reward_denom: &str,
+    allocated: &Coin,
+    msgs: &[DecodedProtoMsg],
+) -> Vec<Coin> {
+    let outputs = msgs
+        .iter()
+        .flat_map(|msg| msg.funds.iter())
+        .filter(|coin| coin.denom != reward_denom)
+        .fold(Vec::<Coin>::new(), |mut outputs, coin| {
+            match outputs.iter_mut().find(|output| output.denom == coin.denom) {
+                Some(output) => output.amount = output.amount.max(coin.amount),
+                None => outputs.push(coin.clone()),
+            }
+            outputs
+        });
+
+    match (outputs.is_empty(), msgs.is_empty()) {
+        (true, false) => vec![allocated.clone()],
+        _ => outputs,
+    }
+}
+
+/// Builds a compound simulation from the msgs that a compound would have generated.
+/// `compounded` is the amount that gets split between the destinations and `dest_msgs`
+/// must be in the same order as the destinations in the comp prefs
+pub fn simulate_compound_response<D>(
+    total_rewards: Coin,
+    tax: Vec<Coin>,
+    compounded: Coin,
+    comp_prefs: CompoundPrefs<D>,
+    claim_msgs: Vec<CosmosProtoMsg>,
+    dest_msgs: Vec<DestProjectMsgs>,
+) -> Result<SimulateCompoundResponse<D>, OutpostError> {
+    let allocated = comp_prefs.split_rewards(&compounded.amount)?;
+
+    let mut msgs = claim_msgs
+        .iter()
+        .map(DecodedProtoMsg::try_from)
+        .collect::<Result<Vec<_>, OutpostError>>()?;
+
+    let destinations = allocated
+        .into_iter()
+        .zip(dest_msgs)
+        .map(
+            |((amount, DestinationAction { destination, .. }), dest_msgs)| {
+                let (pipeline_swaps, sub_msgs): (Vec<_>, Vec<_>) = dest_msgs
+                    .sub_msgs
+                    .iter()
+                    .partition(|(id, _, _)| *id == PIPELINE_SWAP_REPLY_ID);
+
+                // pipelined swaps run before the rest of the destination's msgs
+                let dest_decoded = pipeline_swaps
+                    .into_iter()
+                    .flat_map(|(_, msgs, _)| msgs)
+                    .chain(dest_msgs.msgs.iter())
+                    .chain(sub_msgs.into_iter().flat_map(|(_, msgs, _)| msgs))
+                    .map(DecodedProtoMsg::try_from)
+                    .collect::<Result<Vec<_>, OutpostError>>()?;
+
+                let allocated = Coin {
+                    denom: compounded.denom.clone(),
+                    amount,
+                };
+
+                Ok(DestinationSimulation {
+                    destination,
+                    estimated_outputs: estimate_destination_outputs(
+                        &compounded.denom,
+                        &allocated,
+                        &dest_decoded,
+                    ),
+                    amount: allocated,
+                    msgs: dest_decoded,
+                })
+            },
+        )
+        .collect::<Result<Vec<_>, OutpostError>>()?;
+
+    msgs.extend(destinations.iter().flat_map(|dest| dest.msgs.clone()));
+
+    Ok(SimulateCompoundResponse {
+        total_rewards,
+        tax,
+        destinations,
+        msgs,
+        skipped: None,
+    })
 }
 
 /// Calculates the tax split for a given token amount and tax rate and the
 /// send message to move the tax amount to the tax address. Note that
-/// the tax will be in addition to the base token amount
+/// the tax will be in addition to the base token amount.
+/// If the user has a referrer their share of the tax is sent to them directly
 pub fn calc_additional_tax_split(
     token: &Coin,
     tax: Decimal,
     sender: String,
     tax_addr: String,
+    referral: Option<&Referral>,
 ) -> TaxSplitResult {
     if tax.is_zero() {
         return TaxSplitResult {
             remaining_rewards: token.clone(),
             tax_amount: Coin::new(0, token.denom.clone()),
+            referral_amount: Coin::new(0, token.denom.clone()),
             claim_and_tax_msgs: vec![],
         };
     }
@@ -184,14 +641,8 @@ pub fn calc_additional_tax_split(
     let tax_amount = token.amount.mul_ceil(tax);
     let remaining_rewards = token.amount;
 
-    let tax_store_msg = CosmosProtoMsg::Send(MsgSend {
-        from_address: sender.to_string(),
-        to_address: tax_addr.to_string(),
-        amount: vec![cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
-            denom: token.denom.clone(),
-            amount: tax_amount.to_string(),
-        }],
-    });
+    let (tax_store_msgs, referral_amount) =
+        tax_store_msgs(&token.denom, tax_amount, &sender, &tax_addr, referral);
 
     TaxSplitResult {
         remaining_rewards: Coin {
@@ -202,22 +653,29 @@ pub fn calc_additional_tax_split(
             denom: token.denom.clone(),
             amount: tax_amount,
         },
-        claim_and_tax_msgs: vec![tax_store_msg],
+        referral_amount: Coin {
+            denom: token.denom.clone(),
+            amount: referral_amount,
+        },
+        claim_and_tax_msgs: tax_store_msgs,
     }
 }
 
 /// Calculates the tax split for a given token amount and tax rate.
-/// Note that the tax will be taken out of the base token amount
+/// Note that the tax will be taken out of the base token amount.
+/// If the user has a referrer their share of the tax is sent to them directly
 pub fn calc_tax_split(
     token: &Coin,
     tax: Decimal,
     sender: &Addr,
     tax_addr: &Addr,
+    referral: Option<&Referral>,
 ) -> TaxSplitResult {
     if tax.is_zero() {
         return TaxSplitResult {
             remaining_rewards: token.clone(),
             tax_amount: Coin::new(0, token.denom.clone()),
+            referral_amount: Coin::new(0, token.denom.clone()),
             claim_and_tax_msgs: vec![],
         };
     }
@@ -225,14 +683,13 @@ pub fn calc_tax_split(
     let tax_amount = token.amount.mul_ceil(tax);
     let remaining_rewards = token.amount.checked_sub(tax_amount).unwrap_or_default();
 
-    let tax_store_msg = CosmosProtoMsg::Send(MsgSend {
-        from_address: sender.to_string(),
-        to_address: tax_addr.to_string(),
-        amount: vec![cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
-            denom: token.denom.clone(),
-            amount: tax_amount.to_string(),
-        }],
-    });
+    let (tax_store_msgs, referral_amount) = tax_store_msgs(
+        &token.denom,
+        tax_amount,
+        sender.as_str(),
+        tax_addr.as_str(),
+        referral,
+    );
 
     TaxSplitResult {
         remaining_rewards: Coin {
@@ -243,14 +700,55 @@ pub fn calc_tax_split(
             denom: token.denom.clone(),
             amount: tax_amount,
         },
-        claim_and_tax_msgs: vec![tax_store_msg],
+        referral_amount: Coin {
+            denom: token.denom.clone(),
+            amount: referral_amount,
+        },
+        claim_and_tax_msgs: tax_store_msgs,
     }
 }
 
+/// The sends that move the tax to the tax address and the referrer's share of it to the referrer.
+/// The referrer's share is rounded down so any dust stays with the tax address.
+/// Also returns the amount sent to the referrer
+fn tax_store_msgs(
+    denom: &str,
+    tax_amount: Uint128,
+    sender: &str,
+    tax_addr: &str,
+    referral: Option<&Referral>,
+) -> (Vec<CosmosProtoMsg>, Uint128) {
+    let referral_amount = referral
+        .map(|Referral { share, .. }| tax_amount.mul_floor(*share).min(tax_amount))
+        .unwrap_or_default();
+
+    let sends = [
+        Some((tax_addr.to_string(), tax_amount - referral_amount)),
+        referral.map(|Referral { referrer, .. }| (referrer.to_string(), referral_amount)),
+    ];
+
+    let msgs = sends
+        .into_iter()
+        .flatten()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(to_address, amount)| {
+            CosmosProtoMsg::Send(MsgSend {
+                from_address: sender.to_string(),
+                to_address,
+                amount: csdk_coins(&amount, denom),
+            })
+        })
+        .collect();
+
+    (msgs, referral_amount)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct RewardSplit {
     pub user_rewards: Uint128,
     pub tax_amount: Uint128,
+    /// the part of `tax_amount` that was paid to the user's referrer
+    pub referral_amount: Uint128,
     pub claim_msgs: Vec<CosmosProtoMsg>,
 }
 
@@ -1,8 +1,17 @@
+pub mod asset;
+pub mod batch;
 pub mod comp_prefs;
 pub mod errors;
+pub mod fallback;
+pub mod fee_schedule;
 pub mod helpers;
 pub mod msg_gen;
+pub mod pipeline;
+pub mod price_source;
 pub mod queries;
+pub mod referrals;
+pub mod stats;
+pub mod swap_venue;
 
 #[cfg(test)]
 mod tests;
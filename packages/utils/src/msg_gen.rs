@@ -1,14 +1,19 @@
 use cosmos_sdk_proto::cosmos::authz::v1beta1::{GenericAuthorization, Grant, MsgGrant};
 use cosmos_sdk_proto::cosmos::bank::v1beta1::MsgSend;
 use cosmos_sdk_proto::cosmos::distribution::v1beta1::MsgWithdrawDelegatorReward;
-use cosmos_sdk_proto::cosmos::staking::v1beta1::MsgDelegate;
+use cosmos_sdk_proto::cosmos::staking::v1beta1::{MsgBeginRedelegate, MsgDelegate};
 use cosmos_sdk_proto::cosmos::{authz::v1beta1::MsgExec, base::v1beta1::Coin};
 use cosmos_sdk_proto::cosmwasm::wasm::v1::MsgExecuteContract;
 use cosmos_sdk_proto::prost::EncodeError;
 use cosmos_sdk_proto::traits::{Message, MessageExt};
 use cosmos_sdk_proto::Any;
 
-use cosmwasm_std::{to_json_binary, Addr, Binary, CosmosMsg, StdError};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_json, to_json_binary, Addr, Binary, CosmosMsg, StdError, StdResult, Uint128, Uint64,
+    WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
 
 use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgCreatePosition;
 use osmosis_std::types::osmosis::gamm::v1beta1::MsgJoinSwapExternAmountIn;
@@ -17,6 +22,9 @@ use osmosis_std::types::osmosis::poolmanager::v1beta1::{
     MsgSwapExactAmountIn, MsgSwapExactAmountOut,
 };
 use serde::Serialize;
+use std::str::FromStr;
+
+use crate::errors::OutpostError;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CosmosProtoMsg {
@@ -24,6 +32,7 @@ pub enum CosmosProtoMsg {
     WithdrawDelegatorReward(MsgWithdrawDelegatorReward),
     Delegate(MsgDelegate),
     AllianceDelegate(MsgDelegate),
+    Redelegate(MsgBeginRedelegate),
     ExecuteContract(MsgExecuteContract),
     Exec(MsgExec),
     OsmosisSwapExactAmountIn(MsgSwapExactAmountIn),
@@ -39,6 +48,7 @@ impl TryFrom<&CosmosProtoMsg> for Any {
             CosmosProtoMsg::Send(msg) => Any::from_msg(msg),
             CosmosProtoMsg::WithdrawDelegatorReward(msg) => Any::from_msg(msg),
             CosmosProtoMsg::Delegate(msg) => Any::from_msg(msg),
+            CosmosProtoMsg::Redelegate(msg) => Any::from_msg(msg),
             CosmosProtoMsg::AllianceDelegate(msg) => Ok(Any {
                 type_url: "/alliance.alliance.MsgDelegate".to_string(),
                 value: msg.clone().encode_to_vec(),
@@ -90,6 +100,81 @@ impl TryFrom<&CosmosProtoMsg> for Any {
     type Error = EncodeError;
 }
 
+impl TryFrom<&Any> for CosmosProtoMsg {
+    fn try_from(any: &Any) -> Result<Self, Self::Error> {
+        let value = any.value.as_slice();
+
+        match any.type_url.as_str() {
+            "/cosmos.bank.v1beta1.MsgSend" => Ok(CosmosProtoMsg::Send(MsgSend::decode(value)?)),
+            "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward" => Ok(
+                CosmosProtoMsg::WithdrawDelegatorReward(MsgWithdrawDelegatorReward::decode(value)?),
+            ),
+            "/cosmos.staking.v1beta1.MsgDelegate" => {
+                Ok(CosmosProtoMsg::Delegate(MsgDelegate::decode(value)?))
+            }
+            "/alliance.alliance.MsgDelegate" => Ok(CosmosProtoMsg::AllianceDelegate(
+                MsgDelegate::decode(value)?,
+            )),
+            "/cosmos.staking.v1beta1.MsgBeginRedelegate" => Ok(CosmosProtoMsg::Redelegate(
+                MsgBeginRedelegate::decode(value)?,
+            )),
+            "/cosmwasm.wasm.v1.MsgExecuteContract" => Ok(CosmosProtoMsg::ExecuteContract(
+                MsgExecuteContract::decode(value)?,
+            )),
+            "/cosmos.authz.v1beta1.MsgExec" => Ok(CosmosProtoMsg::Exec(MsgExec::decode(value)?)),
+            MsgSwapExactAmountIn::TYPE_URL => Ok(CosmosProtoMsg::OsmosisSwapExactAmountIn(
+                MsgSwapExactAmountIn::try_from(Binary::from(value))?,
+            )),
+            MsgSwapExactAmountOut::TYPE_URL => Ok(CosmosProtoMsg::OsmosisSwapExactAmountOut(
+                MsgSwapExactAmountOut::try_from(Binary::from(value))?,
+            )),
+            MsgJoinSwapExternAmountIn::TYPE_URL => Ok(CosmosProtoMsg::OsmosisSingleSidedJoinPool(
+                MsgJoinSwapExternAmountIn::try_from(Binary::from(value))?,
+            )),
+            MsgLockTokens::TYPE_URL => Ok(CosmosProtoMsg::OsmosisLockTokens(
+                MsgLockTokens::try_from(Binary::from(value))?,
+            )),
+            MsgCreatePosition::TYPE_URL => Ok(CosmosProtoMsg::OsomsisCLJoinPool(
+                MsgCreatePosition::try_from(Binary::from(value))?,
+            )),
+            type_url => Err(OutpostError::UnknownProtoMsg(type_url.to_string())),
+        }
+    }
+
+    type Error = OutpostError;
+}
+
+/// A CosmosProtoMsg that has been encoded so that it can be kept in contract state
+#[cw_serde]
+pub struct StoredProtoMsg {
+    pub type_url: String,
+    pub value: Binary,
+}
+
+impl TryFrom<&CosmosProtoMsg> for StoredProtoMsg {
+    fn try_from(msg: &CosmosProtoMsg) -> Result<Self, Self::Error> {
+        let Any { type_url, value } = msg.try_into()?;
+
+        Ok(StoredProtoMsg {
+            type_url,
+            value: Binary::from(value),
+        })
+    }
+
+    type Error = OutpostError;
+}
+
+impl TryFrom<&StoredProtoMsg> for CosmosProtoMsg {
+    fn try_from(stored: &StoredProtoMsg) -> Result<Self, Self::Error> {
+        CosmosProtoMsg::try_from(&Any {
+            type_url: stored.type_url.clone(),
+            value: stored.value.to_vec(),
+        })
+    }
+
+    type Error = OutpostError;
+}
+
 /// Creates a MsgExecuteContract message
 pub fn create_exec_contract_msg<T, N>(
     contract_addr: impl Into<String>,
@@ -196,3 +281,149 @@ pub fn create_alliance_delegate_msg(
         ),
     }
 }
+
+/// Generates the message that lets the comp prefs contract know that a user's
+/// strategy was just run so that its next run can be scheduled
+pub fn create_advance_schedule_msg(
+    comp_prefs_contract: &Addr,
+    strat_id: Uint64,
+    user_address: &str,
+) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: comp_prefs_contract.to_string(),
+        msg: to_json_binary(&ymos_comp_prefs::msg::ExecuteMsg::AdvanceSchedule {
+            strat_id,
+            user_address: user_address.to_string(),
+        })?,
+        funds: vec![],
+    }))
+}
+
+/// A human readable rendering of a CosmosProtoMsg so that simulations can
+/// show exactly what would be broadcast on a user's behalf
+#[cw_serde]
+pub struct DecodedProtoMsg {
+    /// the type url that the msg would be broadcast with
+    pub type_url: String,
+    /// the address that the msg would be sent on behalf of
+    pub sender: String,
+    /// the account, validator, pool or contract on the receiving end of the msg
+    pub target: Option<String>,
+    /// the funds moved by the msg. cw20 amounts use the token's contract address as their denom
+    pub funds: Vec<cosmwasm_std::Coin>,
+    /// the msg body. contract executions are rendered as their json msg
+    pub body: String,
+}
+
+/// converts a list of cosmos sdk proto coins into cosmwasm coins
+fn decode_coins<'a>(
+    coins: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> StdResult<Vec<cosmwasm_std::Coin>> {
+    coins
+        .into_iter()
+        .map(|(denom, amount)| {
+            Ok(cosmwasm_std::Coin {
+                denom: denom.clone(),
+                amount: Uint128::from_str(amount)?,
+            })
+        })
+        .collect()
+}
+
+/// Gets the cw20 amount that is moved by a cw20 execute msg if there is one
+fn decode_cw20_funds(contract: &str, msg: &[u8]) -> Option<cosmwasm_std::Coin> {
+    match from_json::<Cw20ExecuteMsg>(msg).ok()? {
+        Cw20ExecuteMsg::Send { amount, .. }
+        | Cw20ExecuteMsg::Transfer { amount, .. }
+        | Cw20ExecuteMsg::IncreaseAllowance { amount, .. } => Some(cosmwasm_std::Coin {
+            denom: contract.to_string(),
+            amount,
+        }),
+        _ => None,
+    }
+}
+
+impl TryFrom<&CosmosProtoMsg> for DecodedProtoMsg {
+    type Error = OutpostError;
+
+    fn try_from(proto: &CosmosProtoMsg) -> Result<Self, Self::Error> {
+        let type_url = Any::try_from(proto)?.type_url;
+
+        let (sender, target, funds, body) = match proto {
+            CosmosProtoMsg::Send(msg) => (
+                msg.from_address.clone(),
+                Some(msg.to_address.clone()),
+                decode_coins(msg.amount.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::WithdrawDelegatorReward(msg) => (
+                msg.delegator_address.clone(),
+                Some(msg.validator_address.clone()),
+                vec![],
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::Delegate(msg) | CosmosProtoMsg::AllianceDelegate(msg) => (
+                msg.delegator_address.clone(),
+                Some(msg.validator_address.clone()),
+                decode_coins(msg.amount.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::Redelegate(msg) => (
+                msg.delegator_address.clone(),
+                Some(msg.validator_dst_address.clone()),
+                decode_coins(msg.amount.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::ExecuteContract(msg) => {
+                let mut funds = decode_coins(msg.funds.iter().map(|c| (&c.denom, &c.amount)))?;
+                funds.extend(decode_cw20_funds(&msg.contract, &msg.msg));
+
+                (
+                    msg.sender.clone(),
+                    Some(msg.contract.clone()),
+                    funds,
+                    String::from_utf8_lossy(&msg.msg).to_string(),
+                )
+            }
+            CosmosProtoMsg::Exec(msg) => (msg.grantee.clone(), None, vec![], format!("{:?}", msg)),
+            CosmosProtoMsg::OsmosisSwapExactAmountIn(msg) => (
+                msg.sender.clone(),
+                msg.routes.last().map(|route| route.pool_id.to_string()),
+                decode_coins(msg.token_in.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::OsmosisSwapExactAmountOut(msg) => (
+                msg.sender.clone(),
+                msg.routes.last().map(|route| route.pool_id.to_string()),
+                decode_coins(msg.token_out.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::OsmosisSingleSidedJoinPool(msg) => (
+                msg.sender.clone(),
+                Some(msg.pool_id.to_string()),
+                decode_coins(msg.token_in.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::OsmosisLockTokens(msg) => (
+                msg.owner.clone(),
+                None,
+                decode_coins(msg.coins.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+            CosmosProtoMsg::OsomsisCLJoinPool(msg) => (
+                msg.sender.clone(),
+                Some(msg.pool_id.to_string()),
+                decode_coins(msg.tokens_provided.iter().map(|c| (&c.denom, &c.amount)))?,
+                format!("{:?}", msg),
+            ),
+        };
+
+        Ok(DecodedProtoMsg {
+            type_url,
+            sender,
+            target,
+            funds,
+            body,
+        })
+    }
+}
@@ -0,0 +1,780 @@
+use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as CsdkCoin;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_json, to_json_vec, Addr, Binary, CosmosMsg, Env, Event, QuerierWrapper, ReplyOn, Response,
+    Storage, SubMsg, SubMsgResult, Uint128,
+};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+use cw_storage_plus::{Item, Map};
+use osmosis_std::types::cosmos::base::v1beta1::Coin as OsmosisCoin;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+use crate::{
+    errors::OutpostError,
+    fallback::{fail_destination, handle_fallback_reply, is_fallback_reply, PendingFallback},
+    helpers::DestProjectMsgs,
+    msg_gen::{create_exec_msg, CosmosProtoMsg, StoredProtoMsg},
+    stats::StatsStore,
+};
+
+/// Marks a destination's swap msgs in `DestProjectMsgs::sub_msgs` as a pipeline swap.
+/// Once the pipeline is running this is also the id of the reply that fires when the swap lands
+pub const PIPELINE_SWAP_REPLY_ID: u64 = 1_000;
+
+/// The id of the reply that fires when the pipeline is ready to start its next swap
+pub const PIPELINE_NEXT_STAGE_REPLY_ID: u64 = 1_001;
+
+#[cw_serde]
+pub enum PipelineAsset {
+    Native(String),
+    Cw20(String),
+}
+
+impl PipelineAsset {
+    /// Queries how much of the asset the user currently holds
+    pub fn query_balance(
+        &self,
+        querier: &QuerierWrapper,
+        user: &Addr,
+    ) -> Result<Uint128, OutpostError> {
+        Ok(match self {
+            PipelineAsset::Native(denom) => querier.query_balance(user, denom)?.amount,
+            PipelineAsset::Cw20(token) => {
+                let BalanceResponse { balance } = querier.query_wasm_smart(
+                    token,
+                    &Cw20QueryMsg::Balance {
+                        address: user.to_string(),
+                    },
+                )?;
+
+                balance
+            }
+        })
+    }
+}
+
+#[cw_serde]
+pub struct PipelineStage {
+    pub swap_msgs: Vec<StoredProtoMsg>,
+    /// the msgs that use the swap's output. these were generated with the simulated output
+    /// and are rescaled to the actual output once the swap has landed
+    pub follow_up_msgs: Vec<StoredProtoMsg>,
+    pub output: PipelineAsset,
+    pub estimated_output: Uint128,
+    /// the user's balance of the output asset right before the swap ran
+    pub balance_before: Option<Uint128>,
+    /// the reply id of the destination's fallback. when there is one a failing swap or
+    /// follow up is handled by the fallback instead of failing the whole compound
+    pub fallback_id: Option<u64>,
+}
+
+#[cw_serde]
+pub struct Pipeline {
+    pub user: Addr,
+    /// the stages that still have to run. the first stage is the one currently running
+    pub stages: Vec<PipelineStage>,
+    /// the fallback of the stage whose follow up msgs are currently running
+    pub follow_up_fallback_id: Option<u64>,
+}
+
+/// Gets the assets and amounts that a msg moves out of the user's account
+fn moved_assets(msg: &CosmosProtoMsg) -> Result<Vec<(PipelineAsset, Uint128)>, OutpostError> {
+    let natives = |coins: Vec<&CsdkCoin>| -> Result<Vec<(PipelineAsset, Uint128)>, OutpostError> {
+        coins
+            .into_iter()
+            .map(|coin| {
+                Ok((
+                    PipelineAsset::Native(coin.denom.clone()),
+                    Uint128::from_str(&coin.amount)?,
+                ))
+            })
+            .collect()
+    };
+    let osmosis_natives =
+        |coins: Vec<&OsmosisCoin>| -> Result<Vec<(PipelineAsset, Uint128)>, OutpostError> {
+            coins
+                .into_iter()
+                .map(|coin| {
+                    Ok((
+                        PipelineAsset::Native(coin.denom.clone()),
+                        Uint128::from_str(&coin.amount)?,
+                    ))
+                })
+                .collect()
+        };
+
+    match msg {
+        CosmosProtoMsg::Send(msg) => natives(msg.amount.iter().collect()),
+        CosmosProtoMsg::Delegate(msg) | CosmosProtoMsg::AllianceDelegate(msg) => {
+            natives(msg.amount.iter().collect())
+        }
+        CosmosProtoMsg::ExecuteContract(msg) => {
+            let mut assets = natives(msg.funds.iter().collect())?;
+
+            if let Ok(
+                Cw20ExecuteMsg::Send { amount, .. }
+                | Cw20ExecuteMsg::Transfer { amount, .. }
+                | Cw20ExecuteMsg::IncreaseAllowance { amount, .. },
+            ) = from_json::<Cw20ExecuteMsg>(&msg.msg)
+            {
+                assets.push((PipelineAsset::Cw20(msg.contract.clone()), amount));
+            }
+
+            Ok(assets)
+        }
+        CosmosProtoMsg::OsmosisSwapExactAmountIn(msg) => {
+            osmosis_natives(msg.token_in.iter().collect())
+        }
+        CosmosProtoMsg::OsmosisSingleSidedJoinPool(msg) => {
+            osmosis_natives(msg.token_in.iter().collect())
+        }
+        CosmosProtoMsg::OsmosisLockTokens(msg) => osmosis_natives(msg.coins.iter().collect()),
+        CosmosProtoMsg::OsomsisCLJoinPool(msg) => {
+            osmosis_natives(msg.tokens_provided.iter().collect())
+        }
+        // redelegations move stake that's already delegated rather than the rewards
+        CosmosProtoMsg::WithdrawDelegatorReward(_)
+        | CosmosProtoMsg::Redelegate(_)
+        | CosmosProtoMsg::Exec(_)
+        | CosmosProtoMsg::OsmosisSwapExactAmountOut(_) => Ok(vec![]),
+    }
+}
+
+/// An asset info as the dexes that the destinations swap on spell it out in their json msgs
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonAssetInfo {
+    /// wyndex
+    Native(String),
+    /// terraswap and white whale
+    NativeToken {
+        denom: String,
+    },
+    Token(JsonToken),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonToken {
+    /// wyndex
+    Address(String),
+    /// terraswap and white whale
+    Contract { contract_addr: String },
+}
+
+impl From<JsonAssetInfo> for PipelineAsset {
+    fn from(info: JsonAssetInfo) -> Self {
+        match info {
+            JsonAssetInfo::Native(denom) | JsonAssetInfo::NativeToken { denom } => {
+                PipelineAsset::Native(denom)
+            }
+            JsonAssetInfo::Token(JsonToken::Address(contract_addr))
+            | JsonAssetInfo::Token(JsonToken::Contract { contract_addr }) => {
+                PipelineAsset::Cw20(contract_addr)
+            }
+        }
+    }
+}
+
+/// An asset along with its amount as it shows up in the dexes' json msgs
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonAsset {
+    info: JsonAssetInfo,
+    amount: Uint128,
+}
+
+/// A single hop of a multihop swap
+#[derive(Deserialize)]
+struct JsonSwapOperation {
+    ask_asset_info: JsonAssetInfo,
+}
+
+/// The parts of the dexes' swap msgs that say what the swap ends up with
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonSwapMsg {
+    Swap {
+        ask_asset_info: Option<JsonAssetInfo>,
+    },
+    /// each operation is keyed by the venue that it swaps on
+    ExecuteSwapOperations {
+        operations: Vec<BTreeMap<String, JsonSwapOperation>>,
+    },
+    /// swaps that offer a cw20 are sent to the dex along with the swap msg
+    Send { msg: Binary },
+}
+
+impl JsonSwapMsg {
+    fn ask_asset(self) -> Option<PipelineAsset> {
+        match self {
+            JsonSwapMsg::Swap { ask_asset_info } => ask_asset_info.map(PipelineAsset::from),
+            JsonSwapMsg::ExecuteSwapOperations { operations } => operations
+                .into_iter()
+                .last()
+                .and_then(|operation| operation.into_values().next())
+                .map(|JsonSwapOperation { ask_asset_info }| ask_asset_info.into()),
+            JsonSwapMsg::Send { msg } => from_json::<JsonSwapMsg>(&msg).ok()?.ask_asset(),
+        }
+    }
+}
+
+/// The asset that the destination's swaps end up with when their msgs spell it out
+fn swap_output(swap_msgs: &[CosmosProtoMsg]) -> Option<PipelineAsset> {
+    swap_msgs.last().and_then(|msg| match msg {
+        CosmosProtoMsg::OsmosisSwapExactAmountIn(msg) => msg
+            .routes
+            .last()
+            .map(|route| PipelineAsset::Native(route.token_out_denom.clone())),
+        CosmosProtoMsg::ExecuteContract(msg) => {
+            from_json::<JsonSwapMsg>(&msg.msg).ok()?.ask_asset()
+        }
+        _ => None,
+    })
+}
+
+/// Finds the asset that a destination's follow up msgs are built around along with the amount
+/// the follow up msgs expect of it. This is the asset that the swaps end up with or, for swaps
+/// that don't name what they return, the only asset other than the reward token that the follow
+/// up msgs move. `None` if there isn't exactly one such asset
+fn follow_up_output(
+    reward: &PipelineAsset,
+    swap_msgs: &[CosmosProtoMsg],
+    follow_up_msgs: &[CosmosProtoMsg],
+) -> Result<Option<(PipelineAsset, Uint128)>, OutpostError> {
+    let moved = follow_up_msgs
+        .iter()
+        .map(moved_assets)
+        .collect::<Result<Vec<_>, OutpostError>>()?
+        .into_iter()
+        .flatten()
+        .filter(|(asset, _)| asset.ne(reward))
+        .collect::<Vec<_>>();
+
+    let output = match swap_output(swap_msgs) {
+        Some(output) => output,
+        None => match moved.split_first() {
+            Some(((output, _), rest)) if rest.iter().all(|(asset, _)| asset.eq(output)) => {
+                output.clone()
+            }
+            _ => return Ok(None),
+        },
+    };
+
+    Ok(moved
+        .into_iter()
+        .filter(|(asset, _)| asset.eq(&output))
+        .map(|(_, amount)| amount)
+        .max()
+        .map(|amount| (output, amount)))
+}
+
+/// Just enough of json to walk through a contract msg without knowing its type
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+impl JsonValue {
+    /// Rescales the amount of every asset in the json that matches `asset`
+    fn rescale_assets(
+        &mut self,
+        asset: &PipelineAsset,
+        scale: &impl Fn(Uint128) -> Uint128,
+    ) -> Result<(), OutpostError> {
+        match self {
+            JsonValue::Object(fields) => {
+                // only objects shaped like a dex asset are rescaled, everything else is walked
+                let asset_json = (fields.len() == 2 && fields.contains_key("info"))
+                    .then(|| to_json_vec(fields))
+                    .transpose()?;
+                if let Some(Ok(JsonAsset { info, amount })) =
+                    asset_json.map(|json| from_json::<JsonAsset>(&json))
+                {
+                    if PipelineAsset::from(info).eq(asset) {
+                        fields.insert(
+                            "amount".to_string(),
+                            JsonValue::String(scale(amount).to_string()),
+                        );
+                    }
+                    return Ok(());
+                }
+
+                fields
+                    .values_mut()
+                    .try_for_each(|value| value.rescale_assets(asset, scale))
+            }
+            JsonValue::Array(values) => values
+                .iter_mut()
+                .try_for_each(|value| value.rescale_assets(asset, scale)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rescales every amount of the given asset in the msg from the estimated amount to the actual amount.
+/// Assets that show up in a contract's json msg are rescaled as well so they stay in line with the funds
+pub fn rescale_msg(
+    msg: CosmosProtoMsg,
+    asset: &PipelineAsset,
+    estimated: Uint128,
+    actual: Uint128,
+) -> Result<CosmosProtoMsg, OutpostError> {
+    let scale = |amount: Uint128| -> Uint128 {
+        if estimated.is_zero() {
+            actual
+        } else {
+            amount.multiply_ratio(actual, estimated)
+        }
+    };
+    let scale_str = |amount: &str| -> Result<String, OutpostError> {
+        Ok(scale(Uint128::from_str(amount)?).to_string())
+    };
+    let is_native = |denom: &str| asset.eq(&PipelineAsset::Native(denom.to_string()));
+
+    Ok(match msg {
+        CosmosProtoMsg::Send(mut msg) => {
+            for coin in msg.amount.iter_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+            CosmosProtoMsg::Send(msg)
+        }
+        CosmosProtoMsg::Delegate(mut msg) => {
+            if let Some(coin) = msg.amount.as_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+            CosmosProtoMsg::Delegate(msg)
+        }
+        CosmosProtoMsg::AllianceDelegate(mut msg) => {
+            if let Some(coin) = msg.amount.as_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+            CosmosProtoMsg::AllianceDelegate(msg)
+        }
+        CosmosProtoMsg::ExecuteContract(mut msg) => {
+            for coin in msg.funds.iter_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+
+            if asset.eq(&PipelineAsset::Cw20(msg.contract.clone())) {
+                let cw20_msg = match from_json::<Cw20ExecuteMsg>(&msg.msg) {
+                    Ok(Cw20ExecuteMsg::Send {
+                        contract,
+                        amount,
+                        msg,
+                    }) => Some(Cw20ExecuteMsg::Send {
+                        contract,
+                        amount: scale(amount),
+                        msg,
+                    }),
+                    Ok(Cw20ExecuteMsg::Transfer { recipient, amount }) => {
+                        Some(Cw20ExecuteMsg::Transfer {
+                            recipient,
+                            amount: scale(amount),
+                        })
+                    }
+                    Ok(Cw20ExecuteMsg::IncreaseAllowance {
+                        spender,
+                        amount,
+                        expires,
+                    }) => Some(Cw20ExecuteMsg::IncreaseAllowance {
+                        spender,
+                        amount: scale(amount),
+                        expires,
+                    }),
+                    _ => None,
+                };
+
+                if let Some(cw20_msg) = cw20_msg {
+                    msg.msg = to_json_vec(&cw20_msg)?;
+                }
+            } else if let Ok(mut json) = from_json::<JsonValue>(&msg.msg) {
+                // assets spelled out in the msg, such as a swap's offer asset, have to match the funds
+                json.rescale_assets(asset, &scale)?;
+                msg.msg = to_json_vec(&json)?;
+            }
+
+            CosmosProtoMsg::ExecuteContract(msg)
+        }
+        CosmosProtoMsg::OsmosisSwapExactAmountIn(mut msg) => {
+            if let Some(coin) = msg.token_in.as_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+                msg.token_out_min_amount = scale_str(&msg.token_out_min_amount)?;
+            }
+            CosmosProtoMsg::OsmosisSwapExactAmountIn(msg)
+        }
+        CosmosProtoMsg::OsmosisSingleSidedJoinPool(mut msg) => {
+            if let Some(coin) = msg.token_in.as_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+                msg.share_out_min_amount = scale_str(&msg.share_out_min_amount)?;
+            }
+            CosmosProtoMsg::OsmosisSingleSidedJoinPool(msg)
+        }
+        CosmosProtoMsg::OsmosisLockTokens(mut msg) => {
+            for coin in msg.coins.iter_mut().filter(|coin| is_native(&coin.denom)) {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+            CosmosProtoMsg::OsmosisLockTokens(msg)
+        }
+        CosmosProtoMsg::OsomsisCLJoinPool(mut msg) => {
+            for coin in msg
+                .tokens_provided
+                .iter_mut()
+                .filter(|coin| is_native(&coin.denom))
+            {
+                coin.amount = scale_str(&coin.amount)?;
+            }
+            CosmosProtoMsg::OsomsisCLJoinPool(msg)
+        }
+        msg @ (CosmosProtoMsg::WithdrawDelegatorReward(_)
+        | CosmosProtoMsg::Redelegate(_)
+        | CosmosProtoMsg::Exec(_)
+        | CosmosProtoMsg::OsmosisSwapExactAmountOut(_)) => msg,
+    })
+}
+
+/// Splits the destinations' msgs into the ones that can be broadcast right away and the
+/// swaps whose follow up msgs have to wait until the swap has landed.
+/// The waiting stages are saved so that the reply handler can work through them.
+/// Returns the msgs to broadcast right away and whether or not any stages were saved
+pub fn prepare_pipeline(
+    storage: &mut dyn Storage,
+    pipeline: &Item<Pipeline>,
+    user: &Addr,
+    reward: &PipelineAsset,
+    dest_msgs: Vec<DestProjectMsgs>,
+) -> Result<(DestProjectMsgs, bool), OutpostError> {
+    let mut stages = vec![];
+    let mut combined = DestProjectMsgs::default();
+
+    for DestProjectMsgs {
+        msgs,
+        sub_msgs,
+        events,
+    } in dest_msgs
+    {
+        let (swaps, sub_msgs): (Vec<_>, Vec<_>) = sub_msgs
+            .into_iter()
+            .partition(|(id, _, _)| *id == PIPELINE_SWAP_REPLY_ID);
+        let (fallback_markers, sub_msgs): (Vec<_>, Vec<_>) = sub_msgs
+            .into_iter()
+            .partition(|(id, msgs, _)| is_fallback_reply(*id) && msgs.is_empty());
+        let fallback_id = fallback_markers.first().map(|(id, _, _)| *id);
+        let swap_msgs: Vec<CosmosProtoMsg> =
+            swaps.into_iter().flat_map(|(_, msgs, _)| msgs).collect();
+
+        combined.append_submsgs(sub_msgs);
+        combined.append_events(events);
+
+        if swap_msgs.is_empty() {
+            combined.append_msgs(msgs);
+            continue;
+        }
+
+        match follow_up_output(reward, &swap_msgs, &msgs)? {
+            Some((output, estimated_output)) => stages.push(PipelineStage {
+                swap_msgs: swap_msgs
+                    .iter()
+                    .map(StoredProtoMsg::try_from)
+                    .collect::<Result<_, _>>()?,
+                follow_up_msgs: msgs
+                    .iter()
+                    .map(StoredProtoMsg::try_from)
+                    .collect::<Result<_, _>>()?,
+                output,
+                estimated_output,
+                balance_before: None,
+                fallback_id,
+            }),
+            // if there's nothing to rescale then the swap can just run before its follow up msgs
+            None => match fallback_id {
+                Some(fallback_id) => combined.append_submsgs(vec![(
+                    fallback_id,
+                    [swap_msgs, msgs].concat(),
+                    ReplyOn::Always,
+                )]),
+                None => {
+                    combined.append_msgs(swap_msgs);
+                    combined.append_msgs(msgs);
+                }
+            },
+        }
+    }
+
+    let pipelined = !stages.is_empty();
+    if pipelined {
+        pipeline.save(
+            storage,
+            &Pipeline {
+                user: user.clone(),
+                stages,
+                follow_up_fallback_id: None,
+            },
+        )?;
+    }
+
+    Ok((combined, pipelined))
+}
+
+/// Wraps the compound's msgs in the submsgs that kick off the pipeline once they've run.
+/// `claim_msgs` are the msgs that bring in the rewards and always run first.
+/// If every destination ended up in the pipeline then its first swap is started as soon as the
+/// rewards have been claimed so that the balance snapshot includes them
+pub fn pipeline_start_submsgs(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    pipeline: &Item<Pipeline>,
+    claim_msgs: Vec<CosmosMsg>,
+    msgs: Vec<CosmosProtoMsg>,
+    pipelined: bool,
+) -> Result<Vec<SubMsg>, OutpostError> {
+    let mut claim_msgs: Vec<SubMsg> = claim_msgs.into_iter().map(SubMsg::new).collect();
+
+    let start_msgs = match (pipelined, msgs.is_empty()) {
+        (false, true) => vec![],
+        (false, false) => vec![SubMsg::new(create_exec_msg(&env.contract.address, msgs)?)],
+        (true, true) => match claim_msgs.pop() {
+            Some(SubMsg { msg, .. }) => {
+                vec![SubMsg::reply_on_success(msg, PIPELINE_NEXT_STAGE_REPLY_ID)]
+            }
+            None => start_next_stage(storage, querier, env, pipeline)?.messages,
+        },
+        (true, false) => vec![SubMsg::reply_on_success(
+            create_exec_msg(&env.contract.address, msgs)?,
+            PIPELINE_NEXT_STAGE_REPLY_ID,
+        )],
+    };
+
+    Ok(claim_msgs.into_iter().chain(start_msgs).collect())
+}
+
+/// Snapshots the user's balance and runs the swap of the next stage or
+/// clears out the pipeline if there's nothing left to run.
+/// This only runs from a reply or when nothing has to land before the swap
+/// so the snapshot never misses msgs that are still waiting to run
+fn start_next_stage(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    pipeline_item: &Item<Pipeline>,
+) -> Result<Response, OutpostError> {
+    let Some(mut pipeline) = pipeline_item.may_load(storage)? else {
+        return Ok(Response::default());
+    };
+
+    let Some(stage) = pipeline.stages.first_mut() else {
+        pipeline_item.remove(storage);
+
+        return Ok(Response::default().add_event(
+            Event::new("pipeline_complete").add_attribute("user", pipeline.user.to_string()),
+        ));
+    };
+
+    stage.balance_before = Some(stage.output.query_balance(querier, &pipeline.user)?);
+    let reply_on = stage_reply_on(stage.fallback_id);
+
+    let swap_msgs = stage
+        .swap_msgs
+        .iter()
+        .map(CosmosProtoMsg::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    pipeline_item.save(storage, &pipeline)?;
+
+    Ok(Response::default().add_submessage(SubMsg {
+        id: PIPELINE_SWAP_REPLY_ID,
+        msg: create_exec_msg(&env.contract.address, swap_msgs)?,
+        gas_limit: None,
+        reply_on,
+    }))
+}
+
+/// Compares the user's balance to the snapshot from before the swap and runs
+/// the stage's follow up msgs with the amount that the swap actually returned
+fn finish_swap_stage(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    pipeline_item: &Item<Pipeline>,
+    fallbacks: &Map<u64, PendingFallback>,
+) -> Result<Response, OutpostError> {
+    let mut pipeline = pipeline_item.load(storage)?;
+
+    if pipeline.stages.is_empty() {
+        return start_next_stage(storage, querier, env, pipeline_item);
+    }
+    let PipelineStage {
+        follow_up_msgs,
+        output,
+        estimated_output,
+        balance_before,
+        fallback_id,
+        ..
+    } = pipeline.stages.remove(0);
+    pipeline.follow_up_fallback_id = fallback_id;
+
+    let actual_output = output
+        .query_balance(querier, &pipeline.user)?
+        .saturating_sub(balance_before.unwrap_or_default());
+
+    pipeline_item.save(storage, &pipeline)?;
+
+    let swap_event = Event::new("pipeline_swap")
+        .add_attribute("user", pipeline.user.to_string())
+        .add_attribute("estimated_output", estimated_output.to_string())
+        .add_attribute("actual_output", actual_output.to_string());
+
+    // without any output there's nothing for the follow up msgs to use so move on to the next stage
+    if actual_output.is_zero() || follow_up_msgs.is_empty() {
+        complete_follow_up(storage, pipeline_item, fallbacks)?;
+
+        return Ok(start_next_stage(storage, querier, env, pipeline_item)?.add_event(swap_event));
+    }
+
+    let follow_up_msgs = follow_up_msgs
+        .iter()
+        .map(|msg| {
+            rescale_msg(
+                CosmosProtoMsg::try_from(msg)?,
+                &output,
+                estimated_output,
+                actual_output,
+            )
+        })
+        .collect::<Result<Vec<_>, OutpostError>>()?;
+
+    Ok(Response::default()
+        .add_event(swap_event)
+        .add_submessage(SubMsg {
+            id: PIPELINE_NEXT_STAGE_REPLY_ID,
+            msg: create_exec_msg(&env.contract.address, follow_up_msgs)?,
+            gas_limit: None,
+            reply_on: stage_reply_on(fallback_id),
+        }))
+}
+
+/// Reroutes the share of the stage whose swap failed to its fallback and moves on to the next stage
+fn fail_swap_stage(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    pipeline_item: &Item<Pipeline>,
+    fallbacks: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+    error: String,
+) -> Result<Response, OutpostError> {
+    let Some(mut pipeline) = pipeline_item.may_load(storage)? else {
+        return Ok(Response::default());
+    };
+
+    let fallback_id = if pipeline.stages.is_empty() {
+        None
+    } else {
+        pipeline.stages.remove(0).fallback_id
+    };
+    pipeline_item.save(storage, &pipeline)?;
+
+    let fallback = match fallback_id {
+        Some(fallback_id) => {
+            handle_fallback_reply(storage, env, fallback_id, error, fallbacks, stats)?
+        }
+        None => Response::default(),
+    };
+    let next_stage = start_next_stage(storage, querier, env, pipeline_item)?;
+
+    Ok(fallback
+        .add_submessages(next_stage.messages)
+        .add_events(next_stage.events))
+}
+
+/// Reports the destination whose follow up msgs failed and moves on to the next stage.
+/// The swap has already landed at this point so the swapped funds stay with the user
+fn fail_follow_up(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    pipeline_item: &Item<Pipeline>,
+    fallbacks: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+    error: String,
+) -> Result<Response, OutpostError> {
+    let Some(mut pipeline) = pipeline_item.may_load(storage)? else {
+        return Ok(Response::default());
+    };
+
+    let fallback_id = pipeline.follow_up_fallback_id.take();
+    pipeline_item.save(storage, &pipeline)?;
+
+    let failed = match fallback_id {
+        Some(fallback_id) => fail_destination(storage, fallback_id, error, fallbacks, stats)?,
+        None => Response::default(),
+    };
+    let next_stage = start_next_stage(storage, querier, env, pipeline_item)?;
+
+    Ok(failed
+        .add_submessages(next_stage.messages)
+        .add_events(next_stage.events))
+}
+
+/// Clears out the fallback of the stage whose follow up msgs are done since it's no longer needed
+fn complete_follow_up(
+    storage: &mut dyn Storage,
+    pipeline_item: &Item<Pipeline>,
+    fallbacks: &Map<u64, PendingFallback>,
+) -> Result<(), OutpostError> {
+    let Some(mut pipeline) = pipeline_item.may_load(storage)? else {
+        return Ok(());
+    };
+
+    if let Some(fallback_id) = pipeline.follow_up_fallback_id.take() {
+        fallbacks.remove(storage, fallback_id);
+        pipeline_item.save(storage, &pipeline)?;
+    }
+
+    Ok(())
+}
+
+/// Stages with a fallback need to hear about failures so they can be rerouted
+fn stage_reply_on(fallback_id: Option<u64>) -> ReplyOn {
+    match fallback_id {
+        Some(_) => ReplyOn::Always,
+        None => ReplyOn::Success,
+    }
+}
+
+/// Moves the pipeline along based on which of its replies was received
+pub fn handle_pipeline_reply(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    reply_id: u64,
+    result: SubMsgResult,
+    pipeline: &Item<Pipeline>,
+    fallbacks: &Map<u64, PendingFallback>,
+    stats: &StatsStore,
+) -> Result<Response, OutpostError> {
+    match (reply_id, result) {
+        (PIPELINE_SWAP_REPLY_ID, SubMsgResult::Ok(_)) => {
+            finish_swap_stage(storage, querier, env, pipeline, fallbacks)
+        }
+        (PIPELINE_SWAP_REPLY_ID, SubMsgResult::Err(error)) => {
+            fail_swap_stage(storage, querier, env, pipeline, fallbacks, stats, error)
+        }
+        (_, SubMsgResult::Ok(_)) => {
+            complete_follow_up(storage, pipeline, fallbacks)?;
+            start_next_stage(storage, querier, env, pipeline)
+        }
+        (_, SubMsgResult::Err(error)) => {
+            fail_follow_up(storage, querier, env, pipeline, fallbacks, stats, error)
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Decimal, Event, QuerierWrapper, Uint128};
+
+use crate::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    errors::OutpostError,
+    swap_venue::SwapVenue,
+};
+
+/// Somewhere the outposts can get the price of one asset in terms of another.
+/// Swap guards and usd reporting should both go through the same source so that they agree
+pub trait PriceSource {
+    type Error;
+
+    /// How much of `quote` a single unit of `base` is worth
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, Self::Error>;
+
+    /// The value of `asset` denominated in `quote`
+    fn value(
+        &self,
+        querier: &QuerierWrapper,
+        asset: &OutpostAsset,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Uint128, Self::Error> {
+        // nothing to price when the asset is already in the quote denom
+        if asset.info.eq(quote) {
+            return Ok(asset.amount);
+        }
+
+        Ok(asset.amount * self.price(querier, &asset.info, quote)?)
+    }
+}
+
+#[cw_serde]
+pub enum OracleQueryMsg {
+    /// The price of `base` denominated in `quote`
+    Price {
+        base: OutpostAssetInfo,
+        quote: OutpostAssetInfo,
+    },
+}
+
+#[cw_serde]
+pub struct OraclePriceResponse {
+    pub price: Decimal,
+}
+
+/// Prices assets by querying an oracle contract.
+/// Any contract that answers `OracleQueryMsg::Price` can be used which also makes it easy
+/// to stand in a mock contract in tests
+#[cw_serde]
+pub struct OraclePrice {
+    pub contract: Addr,
+}
+
+impl PriceSource for OraclePrice {
+    type Error = OutpostError;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, OutpostError> {
+        let OraclePriceResponse { price } = querier.query_wasm_smart(
+            &self.contract,
+            &OracleQueryMsg::Price {
+                base: base.clone(),
+                quote: quote.clone(),
+            },
+        )?;
+
+        Ok(price)
+    }
+}
+
+/// Prices assets by simulating a swap of `probe_amount` on a venue.
+/// The probe should be small relative to the pool so that the price impact of the
+/// simulation doesn't skew the price, though the venue's swap fee will always be included
+#[derive(Clone, Debug)]
+pub struct SimulatedPrice<V> {
+    pub venue: V,
+    pub probe_amount: Uint128,
+}
+
+impl<V> PriceSource for SimulatedPrice<V>
+where
+    V: SwapVenue,
+    V::Error: From<OutpostError>,
+{
+    type Error = V::Error;
+
+    fn price(
+        &self,
+        querier: &QuerierWrapper,
+        base: &OutpostAssetInfo,
+        quote: &OutpostAssetInfo,
+    ) -> Result<Decimal, V::Error> {
+        if self.probe_amount.is_zero() {
+            return Err(OutpostError::ZeroPriceProbe.into());
+        }
+
+        let simulation =
+            self.venue
+                .simulate(querier, &base.clone().with_amount(self.probe_amount), quote)?;
+
+        Ok(Decimal::from_ratio(simulation, self.probe_amount))
+    }
+}
+
+/// How far a swap returning `simulated_return` is from swapping `offer_amount` at the reference price.
+/// Swaps that do at least as well as the reference price have no impact
+pub fn price_impact(
+    offer_amount: Uint128,
+    simulated_return: Uint128,
+    reference_price: Decimal,
+) -> Decimal {
+    let reference_return = offer_amount * reference_price;
+
+    if reference_return.is_zero() || simulated_return >= reference_return {
+        return Decimal::zero();
+    }
+
+    Decimal::one() - Decimal::from_ratio(simulated_return, reference_return)
+}
+
+/// Stops swaps into pools too thin to take them.
+/// A swap whose price impact is above `max_impact` errors so that its destination is
+/// rerouted to the fallback, or skipped when there is no fallback
+#[cw_serde]
+pub struct PriceImpactGuard {
+    /// The largest price impact a swap can have, e.g. 0.05 for 5%
+    pub max_impact: Decimal,
+}
+
+impl PriceImpactGuard {
+    /// Compares the simulated return of the swap on `venue` against `reference_price`.
+    /// Returns an event recording the measured impact if it is within the max
+    pub fn check(
+        &self,
+        venue: &str,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        simulated_return: Uint128,
+        reference_price: Decimal,
+    ) -> Result<Event, OutpostError> {
+        let impact = price_impact(offer.amount, simulated_return, reference_price);
+
+        if impact > self.max_impact {
+            return Err(OutpostError::PriceImpactTooHigh {
+                venue: venue.to_string(),
+                offer: offer.to_string(),
+                ask: ask.to_string(),
+                impact,
+                max_impact: self.max_impact,
+            });
+        }
+
+        Ok(Event::new("price_impact")
+            .add_attribute("venue", venue)
+            .add_attribute("offer", offer.to_string())
+            .add_attribute("ask", ask.to_string())
+            .add_attribute("simulated_return", simulated_return)
+            .add_attribute("reference_price", reference_price.to_string())
+            .add_attribute("impact", impact.to_string()))
+    }
+
+    /// Checks the swap against the price given by `reference`, usually a twap
+    pub fn check_against<P>(
+        &self,
+        querier: &QuerierWrapper,
+        reference: &P,
+        venue: &str,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        simulated_return: Uint128,
+    ) -> Result<Event, P::Error>
+    where
+        P: PriceSource,
+        P::Error: From<OutpostError>,
+    {
+        let reference_price = reference.price(querier, &offer.info, ask)?;
+
+        Ok(self.check(venue, offer, ask, simulated_return, reference_price)?)
+    }
+
+    /// Checks the swap against the spot price of `swap_venue`.
+    /// The spot price comes from simulating a hundredth of the swap so that the
+    /// simulation itself barely moves the pool
+    pub fn check_against_spot<V>(
+        &self,
+        querier: &QuerierWrapper,
+        swap_venue: &V,
+        venue: &str,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        simulated_return: Uint128,
+    ) -> Result<Event, V::Error>
+    where
+        V: SwapVenue,
+        V::Error: From<OutpostError>,
+    {
+        self.check_against_probe(venue, offer, ask, simulated_return, |probe_amount| {
+            swap_venue.simulate(querier, &offer.info.clone().with_amount(probe_amount), ask)
+        })
+    }
+
+    /// Checks the swap against the spot price found by running `simulate` on a hundredth of the offer.
+    /// For swaps such as multihop routes that aren't a single `SwapVenue`
+    pub fn check_against_probe<E>(
+        &self,
+        venue: &str,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        simulated_return: Uint128,
+        simulate: impl FnOnce(Uint128) -> Result<Uint128, E>,
+    ) -> Result<Event, E>
+    where
+        E: From<OutpostError>,
+    {
+        let probe_amount = std::cmp::max(offer.amount / Uint128::new(100), Uint128::one());
+        let spot_price = Decimal::from_ratio(simulate(probe_amount)?, probe_amount);
+
+        Ok(self.check(venue, offer, ask, simulated_return, spot_price)?)
+    }
+}
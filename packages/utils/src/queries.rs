@@ -1,6 +1,11 @@
-use cosmwasm_std::{Addr, Coin, FullDelegation, QuerierWrapper};
+use cosmwasm_std::{from_json, Addr, Coin, FullDelegation, QuerierWrapper, Timestamp, Uint64};
+use serde::de::DeserializeOwned;
+use ymos_comp_prefs::{msg::QueryMsg as CompPrefsQueryMsg, state::CompPref};
 
-use crate::{errors::OutpostError, helpers::sum_coins};
+use crate::{
+    errors::OutpostError,
+    helpers::{sum_coins, CompoundingFrequency},
+};
 
 pub struct AllPendingRewards {
     pub rewards: Vec<PendingReward>,
@@ -51,3 +56,72 @@ pub fn query_pending_rewards(
 
     Ok(AllPendingRewards { rewards, total })
 }
+
+/// Loads a user's stored compounding preferences for a strategy from the comp prefs contract
+/// and decodes the strategy settings into the outpost's compound prefs.
+/// Prefs that are missing, cancelled, expired or set for a different outpost are rejected
+pub fn query_stored_comp_prefs<T: DeserializeOwned>(
+    querier: &QuerierWrapper,
+    comp_prefs_contract: &Addr,
+    outpost_address: &Addr,
+    user_address: &str,
+    strat_id: Uint64,
+    current_time: &Timestamp,
+) -> Result<T, OutpostError> {
+    let stored_pref: Option<CompPref> = querier.query_wasm_smart(
+        comp_prefs_contract,
+        &CompPrefsQueryMsg::StrategyPreferencesByUserAndStratId {
+            user_address: user_address.to_string(),
+            strategy_id: strat_id,
+        },
+    )?;
+
+    let stored_pref = stored_pref.ok_or_else(|| OutpostError::NoStoredCompPrefs {
+        user_address: user_address.to_string(),
+        strat_id,
+    })?;
+
+    if !stored_pref.is_active(current_time) {
+        return Err(OutpostError::InactiveStoredCompPrefs {
+            user_address: user_address.to_string(),
+            strat_id,
+        });
+    }
+
+    if stored_pref
+        .user_comp_pref
+        .outpost_address
+        .ne(outpost_address)
+    {
+        return Err(OutpostError::StoredCompPrefsOutpostMismatch {
+            outpost_address: stored_pref.user_comp_pref.outpost_address.to_string(),
+        });
+    }
+
+    Ok(from_json(&stored_pref.user_comp_pref.strategy_settings)?)
+}
+
+/// Looks up the compounding frequency of the user's stored strategy
+/// as long as the strategy is active and set for the given outpost
+pub fn query_user_compounding_frequency(
+    querier: &QuerierWrapper,
+    comp_prefs_contract: &Addr,
+    outpost_address: &Addr,
+    user_address: &Addr,
+    strat_id: Uint64,
+    current_time: &Timestamp,
+) -> Result<Option<CompoundingFrequency>, OutpostError> {
+    let stored_pref: Option<CompPref> = querier.query_wasm_smart(
+        comp_prefs_contract,
+        &CompPrefsQueryMsg::StrategyPreferencesByUserAndStratId {
+            user_address: user_address.to_string(),
+            strategy_id: strat_id,
+        },
+    )?;
+
+    Ok(stored_pref
+        .filter(|pref| {
+            pref.is_active(current_time) && pref.user_comp_pref.outpost_address.eq(outpost_address)
+        })
+        .map(|pref| pref.user_comp_pref.comp_period.into()))
+}
@@ -0,0 +1,148 @@
+use cosmos_sdk_proto::cosmos::bank::v1beta1::MsgSend;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Coin, Decimal, Event, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::Map;
+
+use crate::{errors::OutpostError, helpers::csdk_coins, msg_gen::CosmosProtoMsg};
+
+/// A registered partner frontend that onboarded the user and receives `share` of the tax
+#[cw_serde]
+pub struct Referral {
+    pub referrer: Addr,
+    pub share: Decimal,
+}
+
+impl Referral {
+    /// Splits a fee that is taken by another contract, such as the authzpp withdraw tax grant,
+    /// into the fee that the contract should take for the take rate and the referrer's cut of
+    /// `rewards`. The user is charged `fee` in total either way
+    pub fn split_fee(&self, fee: Decimal, rewards: Uint128) -> (Decimal, Uint128) {
+        let referral_fee = fee * self.share;
+
+        (fee - referral_fee, rewards.mul_floor(referral_fee))
+    }
+
+    /// The send that pays the referrer their cut straight from the user's wallet
+    pub fn payout_msg(&self, sender: &Addr, amount: &Coin) -> Option<CosmosProtoMsg> {
+        if amount.amount.is_zero() {
+            return None;
+        }
+
+        Some(CosmosProtoMsg::Send(MsgSend {
+            from_address: sender.to_string(),
+            to_address: self.referrer.to_string(),
+            amount: csdk_coins(&amount.amount, amount.denom.clone()),
+        }))
+    }
+}
+
+/// Where an outpost keeps the referrers its admin has registered and what they have been paid.
+/// Users can only be referred by a registered referrer so the tax is never sent to an address
+/// of their choosing
+pub struct ReferralStore<'a> {
+    /// each registered referrer and the portion of the tax they receive, for example "0.2" is 20%
+    pub referrers: Map<'a, &'a Addr, Decimal>,
+    /// the running total that each referrer has been paid, keyed by referrer and denom
+    pub earnings: Map<'a, (&'a Addr, &'a str), Uint128>,
+}
+
+impl<'a> ReferralStore<'a> {
+    pub const fn new(referrers_namespace: &'a str, earnings_namespace: &'a str) -> Self {
+        ReferralStore {
+            referrers: Map::new(referrers_namespace),
+            earnings: Map::new(earnings_namespace),
+        }
+    }
+
+    /// Registers the referrer with their share of the tax or removes them if there is no share
+    pub fn update_referrer(
+        &self,
+        storage: &mut dyn Storage,
+        referrer: &Addr,
+        share: Option<Decimal>,
+    ) -> Result<(), OutpostError> {
+        match share {
+            Some(share) if share > Decimal::one() => Err(OutpostError::InvalidReferralShare(share)),
+            Some(share) => Ok(self.referrers.save(storage, referrer, &share)?),
+            None => {
+                self.referrers.remove(storage, referrer);
+                Ok(())
+            }
+        }
+    }
+
+    /// Errors if the user named a referrer that the admin hasn't registered.
+    /// Checked before granting since the referrer ends up in the user's send grant
+    pub fn check_registered(
+        &self,
+        storage: &dyn Storage,
+        referrer: Option<&Addr>,
+    ) -> Result<(), OutpostError> {
+        match referrer {
+            Some(referrer) if !self.referrers.has(storage, referrer) => {
+                Err(OutpostError::UnregisteredReferrer(referrer.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The referral to pay out of the tax. Referrers that were never registered
+    /// or have since been removed are paid nothing and the whole tax goes to the take rate
+    pub fn referral(
+        &self,
+        storage: &dyn Storage,
+        referrer: Option<&Addr>,
+    ) -> StdResult<Option<Referral>> {
+        let Some(referrer) = referrer else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .referrers
+            .may_load(storage, referrer)?
+            .map(|share| Referral {
+                referrer: referrer.clone(),
+                share,
+            }))
+    }
+
+    /// Adds what the referrer was paid to their running total
+    /// and returns the event for it if they were paid anything
+    pub fn record(
+        &self,
+        storage: &mut dyn Storage,
+        referral: Option<&Referral>,
+        amount: &Coin,
+    ) -> StdResult<Vec<Event>> {
+        match referral {
+            Some(Referral { referrer, .. }) if !amount.amount.is_zero() => {
+                self.earnings.update(
+                    storage,
+                    (referrer, amount.denom.as_str()),
+                    |earned| -> StdResult<_> { Ok(earned.unwrap_or_default() + amount.amount) },
+                )?;
+
+                Ok(vec![Event::new("referral")
+                    .add_attribute("referrer", referrer)
+                    .add_attribute("amount", amount.to_string())])
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Everything that has been paid out to the referrer so far
+    pub fn earnings(&self, storage: &dyn Storage, referrer: &Addr) -> StdResult<Vec<Coin>> {
+        self.earnings
+            .prefix(referrer)
+            .range(storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+            .collect()
+    }
+
+    /// All of the registered referrers along with their share of the tax
+    pub fn referrers(&self, storage: &dyn Storage) -> StdResult<Vec<(Addr, Decimal)>> {
+        self.referrers
+            .range(storage, None, None, Order::Ascending)
+            .collect()
+    }
+}
@@ -0,0 +1,211 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Coin, StdResult, Storage, Timestamp};
+use cw_storage_plus::{Item, Map};
+
+use crate::fallback::PendingFallback;
+
+/// The name that a destination's stats are kept under, for example "juno_staking".
+/// Keeps the breakdown to one entry per kind of destination
+pub trait DestinationLabel {
+    fn label(&self) -> &str;
+}
+
+/// Running totals of everything that has been automated for a user or for the whole outpost
+#[cw_serde]
+#[derive(Default)]
+pub struct CompoundStats {
+    /// the total amount compounded into the destinations, after tax
+    pub amount_automated: Vec<Coin>,
+    pub tax_collected: Vec<Coin>,
+    pub run_count: u64,
+    pub last_run: Option<Timestamp>,
+    /// how much has been allocated to each kind of destination
+    pub destinations: Vec<DestinationStats>,
+}
+
+#[cw_serde]
+pub struct DestinationStats {
+    /// the name of the destination, for example "juno_staking"
+    pub destination: String,
+    pub amount: Vec<Coin>,
+}
+
+/// Everything that happened in a single compound
+pub struct CompoundRun {
+    pub time: Timestamp,
+    pub automated: Vec<Coin>,
+    pub tax: Vec<Coin>,
+    /// the amount each destination was allocated, labeled by `DestinationLabel`
+    pub allocations: Vec<(String, Coin)>,
+}
+
+impl CompoundRun {
+    /// The compound as it's being run, built from the destinations that `isolate_destinations`
+    /// kept. Shares that were rerouted up front count towards their fallback and shares that
+    /// stayed with the user aren't counted at all
+    pub fn new(time: Timestamp, tax: Vec<Coin>, fallbacks: &[(u64, PendingFallback)]) -> Self {
+        let mut automated = vec![];
+        let allocations = fallbacks
+            .iter()
+            .map(|(_, fallback)| {
+                add_coin(&mut automated, &fallback.amount);
+                (
+                    fallback.current_label().to_string(),
+                    fallback.amount.clone(),
+                )
+            })
+            .collect();
+
+        CompoundRun {
+            time,
+            automated,
+            tax,
+            allocations,
+        }
+    }
+}
+
+impl CompoundStats {
+    pub fn add_run(
+        &mut self,
+        CompoundRun {
+            time,
+            automated,
+            tax,
+            allocations,
+        }: &CompoundRun,
+    ) {
+        automated
+            .iter()
+            .for_each(|coin| add_coin(&mut self.amount_automated, coin));
+        tax.iter()
+            .for_each(|coin| add_coin(&mut self.tax_collected, coin));
+        self.run_count += 1;
+        self.last_run = Some(*time);
+
+        for (destination, coin) in allocations {
+            self.add_allocation(destination, coin);
+        }
+    }
+
+    /// Moves a share that didn't end up where it was allocated. Without a destination to move it
+    /// to the share stayed with the user so it's no longer counted as automated either
+    pub fn reroute(&mut self, from: &str, to: Option<&str>, coin: &Coin) {
+        if let Some(stats) = self
+            .destinations
+            .iter_mut()
+            .find(|stats| stats.destination.eq(from))
+        {
+            sub_coin(&mut stats.amount, coin);
+        }
+
+        match to {
+            Some(to) => self.add_allocation(to, coin),
+            None => sub_coin(&mut self.amount_automated, coin),
+        }
+    }
+
+    fn add_allocation(&mut self, destination: &str, coin: &Coin) {
+        match self
+            .destinations
+            .iter_mut()
+            .find(|stats| stats.destination.eq(destination))
+        {
+            Some(stats) => add_coin(&mut stats.amount, coin),
+            None => self.destinations.push(DestinationStats {
+                destination: destination.to_string(),
+                amount: vec![coin.clone()],
+            }),
+        }
+    }
+}
+
+/// Adds the coin to the matching denom in `coins`
+fn add_coin(coins: &mut Vec<Coin>, coin: &Coin) {
+    if coin.amount.is_zero() {
+        return;
+    }
+
+    match coins
+        .iter_mut()
+        .find(|existing| existing.denom.eq(&coin.denom))
+    {
+        Some(existing) => existing.amount += coin.amount,
+        None => coins.push(coin.clone()),
+    }
+}
+
+/// Takes the coin off the matching denom in `coins`, dropping the denom once it's used up
+fn sub_coin(coins: &mut Vec<Coin>, coin: &Coin) {
+    if let Some(existing) = coins
+        .iter_mut()
+        .find(|existing| existing.denom.eq(&coin.denom))
+    {
+        existing.amount = existing.amount.saturating_sub(coin.amount);
+    }
+
+    coins.retain(|existing| !existing.amount.is_zero());
+}
+
+/// Where an outpost keeps its per user and outpost wide stats
+pub struct StatsStore<'a> {
+    pub users: Map<'a, &'a Addr, CompoundStats>,
+    pub outpost: Item<'a, CompoundStats>,
+}
+
+impl<'a> StatsStore<'a> {
+    pub const fn new(users_namespace: &'a str, outpost_key: &'a str) -> Self {
+        StatsStore {
+            users: Map::new(users_namespace),
+            outpost: Item::new(outpost_key),
+        }
+    }
+
+    /// Adds the compound to both the user's and the outpost's totals
+    pub fn record(
+        &self,
+        storage: &mut dyn Storage,
+        user: &Addr,
+        run: &CompoundRun,
+    ) -> StdResult<()> {
+        self.users.update(storage, user, |stats| -> StdResult<_> {
+            let mut stats = stats.unwrap_or_default();
+            stats.add_run(run);
+            Ok(stats)
+        })?;
+
+        let mut outpost_stats = self.outpost_stats(storage)?;
+        outpost_stats.add_run(run);
+
+        self.outpost.save(storage, &outpost_stats)
+    }
+
+    /// Moves a share that was rerouted or failed in both the user's and the outpost's totals
+    pub fn reroute(
+        &self,
+        storage: &mut dyn Storage,
+        user: &Addr,
+        from: &str,
+        to: Option<&str>,
+        coin: &Coin,
+    ) -> StdResult<()> {
+        self.users.update(storage, user, |stats| -> StdResult<_> {
+            let mut stats = stats.unwrap_or_default();
+            stats.reroute(from, to, coin);
+            Ok(stats)
+        })?;
+
+        let mut outpost_stats = self.outpost_stats(storage)?;
+        outpost_stats.reroute(from, to, coin);
+
+        self.outpost.save(storage, &outpost_stats)
+    }
+
+    pub fn user_stats(&self, storage: &dyn Storage, user: &Addr) -> StdResult<CompoundStats> {
+        Ok(self.users.may_load(storage, user)?.unwrap_or_default())
+    }
+
+    pub fn outpost_stats(&self, storage: &dyn Storage) -> StdResult<CompoundStats> {
+        Ok(self.outpost.may_load(storage)?.unwrap_or_default())
+    }
+}
@@ -0,0 +1,227 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Decimal, QuerierWrapper, Uint128};
+use cw_grant_spec::grants::{GrantBase, GrantRequirement};
+
+use crate::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    errors::OutpostError,
+    msg_gen::CosmosProtoMsg,
+};
+
+/// A dex that the outposts can swap through.
+/// Each helper crate implements this for its venues so that destination generators
+/// only need to be written once and can be reused across chains.
+pub trait SwapVenue {
+    type Error;
+
+    /// Simulates swapping `offer` into `ask` and returns the amount of `ask` expected back
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, Self::Error>;
+
+    /// Generates the msgs for `sender` to swap `offer` into `ask`.
+    /// Also returns the simulated amount of `ask` so it can be used by subsequent msgs
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), Self::Error>;
+
+    /// The grants needed for the outpost to swap `offer` on the user's behalf
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement>;
+}
+
+/// How a `BestExecutionRouter` should break up large swaps
+#[cw_serde]
+pub struct SplitOrder {
+    /// Swaps smaller than this always go entirely through the single best venue
+    pub min_amount: Uint128,
+    /// How many equal chunks to break a large swap into.
+    /// Each chunk goes to whichever venue gives the best return for it
+    pub parts: u8,
+}
+
+/// Simulates a swap on every one of its venues and executes it on whichever gives the best return.
+/// Venues that can't simulate the swap (for example because they have no pool for it) are skipped
+pub struct BestExecutionRouter<V> {
+    pub venues: Vec<V>,
+    pub split: Option<SplitOrder>,
+}
+
+impl<V> BestExecutionRouter<V>
+where
+    V: SwapVenue,
+    V::Error: From<OutpostError>,
+{
+    pub fn new(venues: Vec<V>, split: Option<SplitOrder>) -> Self {
+        BestExecutionRouter { venues, split }
+    }
+
+    /// The simulated return of the whole swap on each venue that can do it, best first
+    pub fn quotes(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Vec<(&V, Uint128)>, V::Error> {
+        let mut quotes: Vec<(&V, Uint128)> = self
+            .venues
+            .iter()
+            .filter_map(|venue| {
+                venue
+                    .simulate(querier, offer, ask)
+                    .ok()
+                    .filter(|simulation| !simulation.is_zero())
+                    .map(|simulation| (venue, simulation))
+            })
+            .collect();
+
+        if quotes.is_empty() {
+            return Err(OutpostError::NoSwapVenue {
+                from: offer.info.to_string(),
+                to: ask.to_string(),
+            }
+            .into());
+        }
+
+        // stable sort so that ties go to the venue that was configured first
+        quotes.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Ok(quotes)
+    }
+
+    /// Decides how much of the offer each venue should swap
+    pub fn allocate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Vec<(&V, Uint128)>, V::Error> {
+        let quotes = self.quotes(querier, offer, ask)?;
+
+        let parts = match &self.split {
+            Some(SplitOrder { min_amount, parts })
+                if *parts > 1 && quotes.len() > 1 && offer.amount.ge(min_amount) =>
+            {
+                Uint128::from(*parts)
+            }
+            // not worth splitting so the best venue takes the whole swap
+            _ => return Ok(vec![(quotes[0].0, offer.amount)]),
+        };
+
+        let chunk = offer.amount / parts;
+        // the amount allocated to each venue and the simulated return for that amount
+        let mut allocations: Vec<(&V, Uint128, Uint128)> = quotes
+            .iter()
+            .map(|(venue, _)| (*venue, Uint128::zero(), Uint128::zero()))
+            .collect();
+
+        for part in 0..parts.u128() {
+            // the last chunk picks up whatever the division left over
+            let chunk = if part == parts.u128() - 1 {
+                offer.amount - chunk * (parts - Uint128::one())
+            } else {
+                chunk
+            };
+
+            // the chunk goes to the venue with the best marginal return
+            let best = allocations
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (venue, allocated, simulated))| {
+                    venue
+                        .simulate(
+                            querier,
+                            &OutpostAsset {
+                                info: offer.info.clone(),
+                                amount: *allocated + chunk,
+                            },
+                            ask,
+                        )
+                        .ok()
+                        .map(|simulation| (i, simulation, simulation.saturating_sub(*simulated)))
+                })
+                .fold(
+                    None,
+                    |best: Option<(usize, Uint128, Uint128)>, candidate| match best {
+                        Some(best) if best.2 >= candidate.2 => Some(best),
+                        _ => Some(candidate),
+                    },
+                );
+
+            match best {
+                Some((i, simulation, _)) => {
+                    allocations[i].1 += chunk;
+                    allocations[i].2 = simulation;
+                }
+                // none of the venues can take the chunk anymore so the best one gets it
+                None => allocations[0].1 += chunk,
+            }
+        }
+
+        Ok(allocations
+            .into_iter()
+            .filter(|(_, allocated, _)| !allocated.is_zero())
+            .map(|(venue, allocated, _)| (venue, allocated))
+            .collect())
+    }
+}
+
+impl<V> SwapVenue for BestExecutionRouter<V>
+where
+    V: SwapVenue,
+    V::Error: From<OutpostError>,
+{
+    type Error = V::Error;
+
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, V::Error> {
+        Ok(self.quotes(querier, offer, ask)?[0].1)
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), V::Error> {
+        self.allocate(querier, offer, ask)?.into_iter().try_fold(
+            (vec![], Uint128::zero()),
+            |(mut msgs, total_simulated), (venue, amount)| {
+                let (swap_msgs, simulated) = venue.swap_msgs(
+                    querier,
+                    sender,
+                    &OutpostAsset {
+                        info: offer.info.clone(),
+                        amount,
+                    },
+                    ask,
+                    max_slippage,
+                )?;
+                msgs.extend(swap_msgs);
+
+                Ok((msgs, total_simulated + simulated))
+            },
+        )
+    }
+
+    /// Grants for every venue since any of them could end up being used
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        self.venues
+            .iter()
+            .flat_map(|venue| venue.grants(base.clone(), offer))
+            .collect()
+    }
+}
@@ -1,14 +1,60 @@
 use std::str::FromStr;
 
-use cosmos_sdk_proto::cosmos::{bank::v1beta1::MsgSend, base::v1beta1::Coin};
-use cosmwasm_std::{coin, Addr, Decimal, Timestamp, Uint128};
+use cosmos_sdk_proto::{
+    cosmos::{bank::v1beta1::MsgSend, base::v1beta1::Coin, staking::v1beta1::MsgBeginRedelegate},
+    cosmwasm::wasm::v1::MsgExecuteContract,
+    Any,
+};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    coin, from_json,
+    testing::{mock_dependencies, mock_env},
+    to_json_binary, Addr, BankMsg, Binary, ContractResult, CosmosMsg, Decimal, QuerierResult,
+    QuerierWrapper, ReplyOn, StdError, Storage, SubMsg, SubMsgResponse, SubMsgResult, SystemError,
+    SystemResult, Timestamp, Uint128, Uint64, WasmQuery,
+};
+use cw_grant_spec::grants::{GrantBase, GrantRequirement};
+use cw_storage_plus::{Item, Map};
+use osmosis_std::types::{
+    cosmos::base::v1beta1::Coin as OsmosisCoin,
+    osmosis::poolmanager::v1beta1::{MsgSwapExactAmountIn, SwapAmountInRoute},
+};
+use ymos_comp_prefs::{
+    msg::QueryMsg as CompPrefsQueryMsg,
+    state::{CompPref, CompoundingFrequency as StoredFrequency, UserCompPref},
+};
 
 use crate::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    batch::{batch_compound_submsgs, handle_batch_reply, BatchProgress},
+    comp_prefs::{
+        Allocation, CappedDestinationAction, CompositeDestination, CompoundPrefs,
+        DestinationAction, MinCompound, NestedDestinations, TakeRate, ValidatorSelection,
+    },
+    errors::OutpostError,
+    fallback::{
+        handle_destination_reply, handle_fallback_reply, isolate_destinations,
+        save_pending_fallbacks, PendingFallback,
+    },
+    fee_schedule::{FeeSchedule, FeeStore, FeeTier},
     helpers::{
-        calc_additional_tax_split, calc_tax_split, calculate_compound_amounts,
-        CompoundingFrequency, TaxSplitResult,
+        below_min_compound, calc_additional_tax_split, calc_tax_split, calculate_compound_amounts,
+        compound_eligibility, enforce_compound_frequency, min_out_after_slippage, prefs_sum_to_one,
+        simulate_compound_response, CompoundPlan, CompoundingFrequency, DestProjectMsgs,
+        TaxSplitResult,
     },
     msg_gen::CosmosProtoMsg,
+    pipeline::{
+        pipeline_start_submsgs, prepare_pipeline, rescale_msg, Pipeline, PipelineAsset,
+        PIPELINE_NEXT_STAGE_REPLY_ID,
+    },
+    price_source::{
+        price_impact, OraclePrice, OraclePriceResponse, OracleQueryMsg, PriceImpactGuard,
+        PriceSource, SimulatedPrice,
+    },
+    referrals::{Referral, ReferralStore},
+    stats::{CompoundRun, CompoundStats, DestinationLabel, DestinationStats, StatsStore},
+    swap_venue::{BestExecutionRouter, SplitOrder, SwapVenue},
 };
 
 #[test]
@@ -95,6 +141,7 @@ fn test_calc_tax_split() {
         tax_rate,
         sender.clone(),
         receiver.clone(),
+        None,
     );
 
     assert_eq!(
@@ -102,6 +149,7 @@ fn test_calc_tax_split() {
         TaxSplitResult {
             remaining_rewards: coin(100_000_000, "ubtc"),
             tax_amount: coin(1_000_000, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
             claim_and_tax_msgs: vec![CosmosProtoMsg::Send(MsgSend {
                 from_address: sender.clone(),
                 to_address: receiver.clone(),
@@ -119,6 +167,7 @@ fn test_calc_tax_split() {
         Decimal::percent(10),
         sender.clone(),
         receiver.clone(),
+        None,
     );
 
     assert_eq!(
@@ -126,6 +175,7 @@ fn test_calc_tax_split() {
         TaxSplitResult {
             remaining_rewards: coin(5, "ubtc"),
             tax_amount: coin(1, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
             claim_and_tax_msgs: vec![CosmosProtoMsg::Send(MsgSend {
                 from_address: sender.clone(),
                 to_address: receiver,
@@ -150,6 +200,7 @@ fn test_tax_split() {
         tax_rate,
         &sender.clone(),
         &receiver.clone(),
+        None,
     );
 
     assert_eq!(
@@ -157,6 +208,7 @@ fn test_tax_split() {
         TaxSplitResult {
             remaining_rewards: coin(99_000_000, "ubtc"),
             tax_amount: coin(1_000_000, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
             claim_and_tax_msgs: vec![CosmosProtoMsg::Send(MsgSend {
                 from_address: sender.to_string(),
                 to_address: receiver.to_string(),
@@ -174,6 +226,7 @@ fn test_tax_split() {
         Decimal::percent(10),
         &sender.clone(),
         &receiver.clone(),
+        None,
     );
 
     assert_eq!(
@@ -181,6 +234,7 @@ fn test_tax_split() {
         TaxSplitResult {
             remaining_rewards: coin(4, "ubtc"),
             tax_amount: coin(1, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
             claim_and_tax_msgs: vec![CosmosProtoMsg::Send(MsgSend {
                 from_address: sender.to_string(),
                 to_address: receiver.to_string(),
@@ -198,17 +252,201 @@ fn test_tax_split() {
         Decimal::percent(0),
         &sender.clone(),
         &receiver.clone(),
+        None,
     );
     assert_eq!(
         split,
         TaxSplitResult {
             remaining_rewards: coin(100_000_000, "ubtc"),
             tax_amount: coin(0, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
             claim_and_tax_msgs: vec![],
         }
     )
 }
 
+#[test]
+fn test_take_rate_tax_fee() {
+    let deps = mock_dependencies();
+    let take_rate = TakeRate::new(&deps.api, Decimal::percent(5), "take_rate").unwrap();
+
+    // callers can pick a lower fee but never one above the max
+    assert_eq!(take_rate.tax_fee(None).unwrap(), Decimal::percent(5));
+    assert_eq!(
+        take_rate.tax_fee(Some(Decimal::percent(1))).unwrap(),
+        Decimal::percent(1)
+    );
+    assert!(matches!(
+        take_rate.tax_fee(Some(Decimal::percent(6))),
+        Err(OutpostError::TaxFeeAboveMax { fee, .. }) if fee == Decimal::percent(6)
+    ));
+}
+
+#[test]
+fn test_referral_tax_split() {
+    let sender = Addr::unchecked("sender");
+    let receiver = Addr::unchecked("receiver");
+    let referral = Referral {
+        referrer: Addr::unchecked("referrer"),
+        share: Decimal::percent(25),
+    };
+    let send = |to_address: &str, amount: u128| {
+        CosmosProtoMsg::Send(MsgSend {
+            from_address: sender.to_string(),
+            to_address: to_address.to_string(),
+            amount: vec![Coin {
+                denom: "ubtc".to_string(),
+                amount: amount.to_string(),
+            }],
+        })
+    };
+
+    // the referrer's share of the tax comes out of what the taxation addr receives
+    let split = calc_tax_split(
+        &coin(100_000_000, "ubtc"),
+        Decimal::percent(1),
+        &sender,
+        &receiver,
+        Some(&referral),
+    );
+
+    assert_eq!(
+        split,
+        TaxSplitResult {
+            remaining_rewards: coin(99_000_000, "ubtc"),
+            tax_amount: coin(1_000_000, "ubtc"),
+            referral_amount: coin(250_000, "ubtc"),
+            claim_and_tax_msgs: vec![send("receiver", 750_000), send("referrer", 250_000)],
+        }
+    );
+
+    // the referrer's share rounds down so tiny taxes stay with the taxation addr
+    let split = calc_additional_tax_split(
+        &coin(30, "ubtc"),
+        Decimal::percent(10),
+        sender.to_string(),
+        receiver.to_string(),
+        Some(&referral),
+    );
+
+    assert_eq!(
+        split,
+        TaxSplitResult {
+            remaining_rewards: coin(30, "ubtc"),
+            tax_amount: coin(3, "ubtc"),
+            referral_amount: coin(0, "ubtc"),
+            claim_and_tax_msgs: vec![send("receiver", 3)],
+        }
+    );
+
+    // a referrer with the whole take rate is the only one sent anything
+    let split = calc_additional_tax_split(
+        &coin(1_000, "ubtc"),
+        Decimal::percent(10),
+        sender.to_string(),
+        receiver.to_string(),
+        Some(&Referral {
+            share: Decimal::one(),
+            ..referral.clone()
+        }),
+    );
+
+    assert_eq!(split.referral_amount, coin(100, "ubtc"));
+    assert_eq!(split.claim_and_tax_msgs, vec![send("referrer", 100)]);
+
+    // fees taken by another contract leave the referrer's cut to be sent on by the outpost
+    assert_eq!(
+        referral.split_fee(Decimal::percent(4), Uint128::new(1_000_000)),
+        (Decimal::percent(3), Uint128::new(10_000))
+    );
+    assert_eq!(
+        referral.payout_msg(&sender, &coin(10_000, "ubtc")),
+        Some(send("referrer", 10_000))
+    );
+    assert_eq!(referral.payout_msg(&sender, &coin(0, "ubtc")), None);
+}
+
+#[test]
+fn test_referral_store() {
+    let mut deps = mock_dependencies();
+    let store = ReferralStore::new("referrers", "referral_earnings");
+    let referrer = Addr::unchecked("referrer");
+
+    assert!(matches!(
+        store.update_referrer(deps.as_mut().storage, &referrer, Some(Decimal::percent(101))),
+        Err(OutpostError::InvalidReferralShare(share)) if share == Decimal::percent(101)
+    ));
+
+    // users can't name a referrer that the admin hasn't registered
+    assert!(matches!(
+        store.check_registered(deps.as_ref().storage, Some(&referrer)),
+        Err(OutpostError::UnregisteredReferrer(addr)) if addr == "referrer"
+    ));
+    assert_eq!(
+        store
+            .referral(deps.as_ref().storage, Some(&referrer))
+            .unwrap(),
+        None
+    );
+    store.check_registered(deps.as_ref().storage, None).unwrap();
+
+    store
+        .update_referrer(deps.as_mut().storage, &referrer, Some(Decimal::percent(25)))
+        .unwrap();
+    store
+        .check_registered(deps.as_ref().storage, Some(&referrer))
+        .unwrap();
+
+    let referral = store
+        .referral(deps.as_ref().storage, Some(&referrer))
+        .unwrap();
+    assert_eq!(
+        referral,
+        Some(Referral {
+            referrer: referrer.clone(),
+            share: Decimal::percent(25),
+        })
+    );
+    assert_eq!(
+        store.referrers(deps.as_ref().storage).unwrap(),
+        vec![(referrer.clone(), Decimal::percent(25))]
+    );
+
+    // payouts add up per denom and nothing is recorded for an empty payout
+    let events = store
+        .record(deps.as_mut().storage, referral.as_ref(), &coin(100, "ubtc"))
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    store
+        .record(deps.as_mut().storage, referral.as_ref(), &coin(50, "ubtc"))
+        .unwrap();
+    assert_eq!(
+        store
+            .record(deps.as_mut().storage, referral.as_ref(), &coin(0, "ueth"))
+            .unwrap(),
+        vec![]
+    );
+    assert_eq!(
+        store.earnings(deps.as_ref().storage, &referrer).unwrap(),
+        vec![coin(150, "ubtc")]
+    );
+
+    // removed referrers stop being paid but keep what they've earned
+    store
+        .update_referrer(deps.as_mut().storage, &referrer, None)
+        .unwrap();
+    assert_eq!(
+        store
+            .referral(deps.as_ref().storage, Some(&referrer))
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        store.earnings(deps.as_ref().storage, &referrer).unwrap(),
+        vec![coin(150, "ubtc")]
+    );
+}
+
 #[test]
 fn test_compounding_freq_iteration_count() {
     let initial_time = Timestamp::from_seconds(0);
@@ -231,3 +469,1998 @@ fn test_compounding_freq_iteration_count() {
         365
     );
 }
+
+#[test]
+fn test_compound_frequency_enforcement() {
+    const LAST_COMPOUNDED: Map<&Addr, Timestamp> = Map::new("last_compounded");
+    const COMP_PREFS_ADDR: Item<Addr> = Item::new("comp_prefs_addr");
+
+    let mut deps = mock_dependencies();
+    let outpost = Addr::unchecked("outpost");
+    let user = Addr::unchecked("user");
+    let compounder = Addr::unchecked("compounder");
+    let initial_time = Timestamp::from_seconds(1_000_000);
+
+    // first compounds are always allowed
+    let eligibility = compound_eligibility(
+        deps.as_ref(),
+        &outpost,
+        &user,
+        None,
+        initial_time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+    assert!(eligibility.eligible);
+    assert_eq!(eligibility.reason, None);
+
+    enforce_compound_frequency(
+        deps.as_mut(),
+        &compounder,
+        &outpost,
+        &user,
+        None,
+        initial_time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+
+    // without stored prefs the default daily frequency applies
+    let eligibility = compound_eligibility(
+        deps.as_ref(),
+        &outpost,
+        &user,
+        None,
+        initial_time.plus_hours(1),
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+    assert!(!eligibility.eligible);
+    assert!(eligibility.reason.is_some());
+    assert_eq!(eligibility.last_compounded, Some(initial_time));
+    assert_eq!(eligibility.next_eligible, initial_time.plus_days(1));
+
+    assert!(matches!(
+        enforce_compound_frequency(
+            deps.as_mut(),
+            &compounder,
+            &outpost,
+            &user,
+            None,
+            initial_time.plus_hours(1),
+            &LAST_COMPOUNDED,
+            &COMP_PREFS_ADDR,
+        ),
+        Err(OutpostError::CompoundTooSoon { .. })
+    ));
+
+    // users compounding themselves are not limited
+    enforce_compound_frequency(
+        deps.as_mut(),
+        &user,
+        &outpost,
+        &user,
+        None,
+        initial_time.plus_hours(1),
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+
+    enforce_compound_frequency(
+        deps.as_mut(),
+        &compounder,
+        &outpost,
+        &user,
+        None,
+        initial_time.plus_hours(1).plus_days(1),
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_compound_frequency_by_strategy() {
+    const LAST_COMPOUNDED: Map<&Addr, Timestamp> = Map::new("last_compounded");
+    const COMP_PREFS_ADDR: Item<Addr> = Item::new("comp_prefs_addr");
+
+    let mut deps = mock_dependencies();
+    let outpost = Addr::unchecked("outpost");
+    let user = Addr::unchecked("user");
+    let compounder = Addr::unchecked("compounder");
+    let initial_time = Timestamp::from_seconds(1_000_000);
+
+    COMP_PREFS_ADDR
+        .save(deps.as_mut().storage, &Addr::unchecked("comp_prefs"))
+        .unwrap();
+
+    // strategy 1 is weekly on this outpost, strategy 2 is hourly on another outpost
+    deps.querier.update_wasm(move |query| -> QuerierResult {
+        let WasmQuery::Smart { msg, .. } = query else {
+            panic!("unexpected query {:?}", query)
+        };
+        let stored_pref = match from_json(msg).unwrap() {
+            CompPrefsQueryMsg::StrategyPreferencesByUserAndStratId { strategy_id, .. } => {
+                match strategy_id.u64() {
+                    1 => Some(("outpost", StoredFrequency::Weekly)),
+                    2 => Some(("other_outpost", StoredFrequency::Hourly)),
+                    _ => None,
+                }
+            }
+            query => panic!("unexpected comp prefs query {:?}", query),
+        }
+        .map(|(outpost_address, comp_period)| CompPref {
+            user_comp_pref: UserCompPref {
+                outpost_address: Addr::unchecked(outpost_address),
+                strat_id: 1,
+                strategy_settings: Binary::default(),
+                address: Addr::unchecked("user"),
+                comp_period,
+                pub_key: "123".to_string(),
+                expires: initial_time.plus_days(365),
+            },
+            chain_id: "juno-1".to_string(),
+            created_at: Timestamp::default(),
+            updated_at: Timestamp::default(),
+            is_inactive: None,
+        });
+
+        SystemResult::Ok(ContractResult::Ok(to_json_binary(&stored_pref).unwrap()))
+    });
+
+    enforce_compound_frequency(
+        deps.as_mut(),
+        &compounder,
+        &outpost,
+        &user,
+        Some(Uint64::new(1)),
+        initial_time,
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+
+    // the weekly strategy can't be compounded again after two days
+    let eligibility = compound_eligibility(
+        deps.as_ref(),
+        &outpost,
+        &user,
+        Some(Uint64::new(1)),
+        initial_time.plus_days(2),
+        &LAST_COMPOUNDED,
+        &COMP_PREFS_ADDR,
+    )
+    .unwrap();
+    assert!(!eligibility.eligible);
+    assert_eq!(eligibility.frequency, CompoundingFrequency::Weekly);
+    assert_eq!(eligibility.next_eligible, initial_time.plus_days(7));
+
+    // strategies for other outposts, missing strategies and compounds without a strategy use the default
+    for strat_id in [Some(Uint64::new(2)), Some(Uint64::new(3)), None] {
+        let eligibility = compound_eligibility(
+            deps.as_ref(),
+            &outpost,
+            &user,
+            strat_id,
+            initial_time.plus_days(2),
+            &LAST_COMPOUNDED,
+            &COMP_PREFS_ADDR,
+        )
+        .unwrap();
+        assert!(eligibility.eligible);
+        assert_eq!(eligibility.frequency, CompoundingFrequency::Daily);
+    }
+}
+
+#[test]
+fn test_simulate_compound_response() {
+    let prefs = CompoundPrefs {
+        relative: vec![
+            DestinationAction {
+                destination: "stake".to_string(),
+                amount: 750_000_000_000_000_000,
+                fallback: None,
+                max_slippage: None,
+                min_amount: None,
+            },
+            DestinationAction {
+                destination: "send".to_string(),
+                amount: 250_000_000_000_000_000,
+                fallback: None,
+                max_slippage: None,
+                min_amount: None,
+            },
+        ],
+        absolute: vec![],
+        capped: vec![],
+    };
+
+    let send_msg = |to: &str, denom: &str, amount: u128| {
+        CosmosProtoMsg::Send(MsgSend {
+            from_address: "user".to_string(),
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }],
+        })
+    };
+
+    let simulation = simulate_compound_response(
+        coin(1_050, "ujuno"),
+        vec![coin(50, "ujuno")],
+        coin(1_000, "ujuno"),
+        prefs.clone(),
+        vec![send_msg("tax", "ujuno", 50)],
+        vec![
+            DestProjectMsgs {
+                msgs: vec![send_msg("validator", "ujuno", 750)],
+                ..DestProjectMsgs::default()
+            },
+            DestProjectMsgs {
+                msgs: vec![
+                    send_msg("pool", "ujuno", 250),
+                    send_msg("friend", "uusdc", 120),
+                ],
+                ..DestProjectMsgs::default()
+            },
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(simulation.total_rewards, coin(1_050, "ujuno"));
+    assert_eq!(simulation.tax, vec![coin(50, "ujuno")]);
+    assert_eq!(simulation.msgs.len(), 4);
+    assert_eq!(simulation.msgs[0].target, Some("tax".to_string()));
+    assert_eq!(simulation.msgs[0].type_url, "/cosmos.bank.v1beta1.MsgSend");
+
+    assert_eq!(simulation.destinations[0].amount, coin(750, "ujuno"));
+    assert_eq!(
+        simulation.destinations[0].estimated_outputs,
+        vec![coin(750, "ujuno")]
+    );
+    assert_eq!(simulation.destinations[1].amount, coin(250, "ujuno"));
+    assert_eq!(
+        simulation.destinations[1].estimated_outputs,
+        vec![coin(120, "uusdc")]
+    );
+    assert_eq!(simulation.skipped, None);
+
+    // rewards below the min compound aren't claimed so nothing is taxed or compounded
+    let skipped = below_min_compound(
+        &Addr::unchecked("user"),
+        &coin(1_050, "ujuno"),
+        &Some(MinCompound::Native(Uint128::new(2_000))),
+        |_| Ok::<_, OutpostError>(Uint128::zero()),
+    )
+    .unwrap()
+    .unwrap();
+    let simulation = CompoundPlan::skipped(coin(1_050, "ujuno"), prefs, skipped.clone())
+        .simulate()
+        .unwrap();
+
+    assert_eq!(simulation.total_rewards, coin(1_050, "ujuno"));
+    assert_eq!(simulation.tax, vec![]);
+    assert!(simulation.destinations.is_empty());
+    assert!(simulation.msgs.is_empty());
+    assert_eq!(simulation.skipped, Some(skipped));
+}
+
+#[test]
+fn test_prepare_pipeline() {
+    const PIPELINE: Item<Pipeline> = Item::new("pipeline");
+    let mut deps = mock_dependencies();
+    let user = Addr::unchecked("user");
+
+    let send_msg = |to: &str, denom: &str, amount: u128| {
+        CosmosProtoMsg::Send(MsgSend {
+            from_address: "user".to_string(),
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }],
+        })
+    };
+
+    let mut swapped = DestProjectMsgs {
+        msgs: vec![send_msg("friend", "uusdc", 120)],
+        ..DestProjectMsgs::default()
+    };
+    swapped.run_after_swap(vec![send_msg("pool", "ujuno", 250)]);
+
+    let (combined, pipelined) = prepare_pipeline(
+        deps.as_mut().storage,
+        &PIPELINE,
+        &user,
+        &PipelineAsset::Native("ujuno".to_string()),
+        vec![
+            DestProjectMsgs {
+                msgs: vec![send_msg("validator", "ujuno", 750)],
+                ..DestProjectMsgs::default()
+            },
+            swapped,
+        ],
+    )
+    .unwrap();
+
+    // only the msgs that don't depend on a swap are broadcast right away
+    assert!(pipelined);
+    assert_eq!(combined.msgs, vec![send_msg("validator", "ujuno", 750)]);
+    assert!(combined.sub_msgs.is_empty());
+
+    let pipeline = PIPELINE.load(deps.as_ref().storage).unwrap();
+    assert_eq!(pipeline.user, user);
+    assert_eq!(pipeline.stages.len(), 1);
+    assert_eq!(
+        pipeline.stages[0].output,
+        PipelineAsset::Native("uusdc".to_string())
+    );
+    assert_eq!(pipeline.stages[0].estimated_output, Uint128::from(120u128));
+
+    // the follow up msgs get rescaled to what the swap actually returned
+    assert_eq!(
+        rescale_msg(
+            send_msg("friend", "uusdc", 120),
+            &pipeline.stages[0].output,
+            Uint128::from(120u128),
+            Uint128::from(90u128),
+        )
+        .unwrap(),
+        send_msg("friend", "uusdc", 90)
+    );
+
+    // without anything to rescale the swap just runs ahead of its follow up msgs
+    let mut unscalable = DestProjectMsgs {
+        msgs: vec![send_msg("friend", "ujuno", 100)],
+        ..DestProjectMsgs::default()
+    };
+    unscalable.run_after_swap(vec![send_msg("pool", "ujuno", 100)]);
+
+    let (combined, pipelined) = prepare_pipeline(
+        deps.as_mut().storage,
+        &Item::new("other_pipeline"),
+        &user,
+        &PipelineAsset::Native("ujuno".to_string()),
+        vec![unscalable],
+    )
+    .unwrap();
+
+    assert!(!pipelined);
+    assert_eq!(
+        combined.msgs,
+        vec![
+            send_msg("pool", "ujuno", 100),
+            send_msg("friend", "ujuno", 100)
+        ]
+    );
+}
+
+#[test]
+fn test_pipeline_output_and_rescale() {
+    const PIPELINE: Item<Pipeline> = Item::new("pipeline");
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let user = Addr::unchecked("user");
+
+    let coin_of = |denom: &str, amount: u128| Coin {
+        denom: denom.to_string(),
+        amount: amount.to_string(),
+    };
+    let provide_liquidity = |usdc: u128, whale: u128| {
+        CosmosProtoMsg::ExecuteContract(MsgExecuteContract {
+            sender: "user".to_string(),
+            contract: "pool".to_string(),
+            msg: format!(
+                r#"{{"provide_liquidity":{{"assets":[{{"amount":"{usdc}","info":{{"native_token":{{"denom":"uusdc"}}}}}},{{"amount":"{whale}","info":{{"native_token":{{"denom":"uwhale"}}}}}}]}}}}"#
+            )
+            .into_bytes(),
+            funds: vec![coin_of("uusdc", usdc), coin_of("uwhale", whale)],
+        })
+    };
+
+    // the swap names what it returns so the stage follows that asset
+    // even though the follow up msgs move more of something else
+    let mut swapped = DestProjectMsgs {
+        msgs: vec![provide_liquidity(120, 500)],
+        ..DestProjectMsgs::default()
+    };
+    swapped.run_after_swap(vec![CosmosProtoMsg::OsmosisSwapExactAmountIn(
+        MsgSwapExactAmountIn {
+            sender: "user".to_string(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uusdc".to_string(),
+            }],
+            token_in: Some(OsmosisCoin {
+                denom: "ujuno".to_string(),
+                amount: "250".to_string(),
+            }),
+            token_out_min_amount: "100".to_string(),
+        },
+    )]);
+
+    let (_, pipelined) = prepare_pipeline(
+        deps.as_mut().storage,
+        &PIPELINE,
+        &user,
+        &PipelineAsset::Native("ujuno".to_string()),
+        vec![swapped],
+    )
+    .unwrap();
+
+    assert!(pipelined);
+    let stage = PIPELINE
+        .load(deps.as_ref().storage)
+        .unwrap()
+        .stages
+        .remove(0);
+    assert_eq!(stage.output, PipelineAsset::Native("uusdc".to_string()));
+    assert_eq!(stage.estimated_output, Uint128::from(120u128));
+    // the snapshot is only taken once the stage starts
+    assert_eq!(stage.balance_before, None);
+
+    // only the amounts of the swapped asset are rescaled, in the funds and in the json
+    assert_eq!(
+        rescale_msg(
+            provide_liquidity(120, 120),
+            &stage.output,
+            Uint128::from(120u128),
+            Uint128::from(90u128),
+        )
+        .unwrap(),
+        provide_liquidity(90, 120)
+    );
+
+    // the pipeline waits for the rewards to be claimed before it snapshots the balance
+    let withdraw = CosmosMsg::Bank(BankMsg::Send {
+        to_address: "user".to_string(),
+        amount: vec![coin(100, "ujuno")],
+    });
+    let start = pipeline_start_submsgs(
+        &mut deps.storage,
+        &QuerierWrapper::new(&deps.querier),
+        &env,
+        &PIPELINE,
+        vec![withdraw.clone()],
+        vec![],
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(
+        start,
+        vec![SubMsg::reply_on_success(
+            withdraw,
+            PIPELINE_NEXT_STAGE_REPLY_ID
+        )]
+    );
+    assert_eq!(
+        PIPELINE.load(deps.as_ref().storage).unwrap().stages[0].balance_before,
+        None
+    );
+}
+
+impl DestinationLabel for String {
+    fn label(&self) -> &str {
+        self
+    }
+}
+
+#[test]
+fn test_isolate_destinations() {
+    const PENDING_FALLBACKS: Map<u64, PendingFallback> = Map::new("pending_fallbacks");
+    const STATS: StatsStore = StatsStore::new("user_stats", "outpost_stats");
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let user = Addr::unchecked("user");
+
+    let send_msg = |to: &str, amount: u128| {
+        CosmosProtoMsg::Send(MsgSend {
+            from_address: "user".to_string(),
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: "ujuno".to_string(),
+                amount: amount.to_string(),
+            }],
+        })
+    };
+
+    let prefs = CompoundPrefs {
+        relative: vec![
+            DestinationAction {
+                destination: "validator".to_string(),
+                amount: 750_000_000_000_000_000,
+                fallback: Some("friend".to_string()),
+                max_slippage: None,
+                min_amount: None,
+            },
+            DestinationAction {
+                destination: "paused_lsd".to_string(),
+                amount: 250_000_000_000_000_000,
+                fallback: None,
+                max_slippage: None,
+                min_amount: None,
+            },
+        ],
+        absolute: vec![],
+        capped: vec![],
+    }
+    .with_default_fallback(Some("friend".to_string()));
+
+    let (isolated, fallbacks) = isolate_destinations(
+        &user,
+        &coin(1_000, "ujuno"),
+        &prefs,
+        |amount, prefs| -> Result<Vec<DestProjectMsgs>, OutpostError> {
+            let destination = prefs.relative[0].destination.clone();
+            if destination == "paused_lsd" {
+                return Err(OutpostError::InvalidAsset {
+                    denom: amount.denom,
+                    project: destination,
+                });
+            }
+
+            Ok(vec![DestProjectMsgs {
+                msgs: vec![send_msg(&destination, amount.amount.u128())],
+                ..DestProjectMsgs::default()
+            }])
+        },
+    )
+    .unwrap();
+
+    // the working destination runs on its own
+    assert_eq!(
+        isolated[0].sub_msgs,
+        vec![(2_000, vec![send_msg("validator", 750)], ReplyOn::Always)]
+    );
+    assert!(isolated[0].msgs.is_empty());
+
+    // the destination that couldn't be generated went straight to the default fallback
+    assert_eq!(
+        isolated[1].sub_msgs,
+        vec![(2_001, vec![send_msg("friend", 250)], ReplyOn::Always)]
+    );
+    assert_eq!(isolated[1].events[0].ty, "destination_fallback");
+
+    // the stats are kept for where the shares are actually going
+    STATS
+        .record(
+            deps.as_mut().storage,
+            &user,
+            &CompoundRun::new(env.block.time, vec![], &fallbacks),
+        )
+        .unwrap();
+    let destination_stats = |storage: &dyn Storage| {
+        STATS
+            .user_stats(storage, &user)
+            .unwrap()
+            .destinations
+            .into_iter()
+            .map(|stats| (stats.destination, stats.amount))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        destination_stats(deps.as_ref().storage),
+        vec![
+            ("validator".to_string(), vec![coin(750, "ujuno")]),
+            ("friend".to_string(), vec![coin(250, "ujuno")]),
+        ]
+    );
+
+    save_pending_fallbacks(deps.as_mut().storage, &PENDING_FALLBACKS, fallbacks).unwrap();
+
+    // once the fallback that was used up front succeeds it's no longer pending
+    let resp = handle_destination_reply(
+        deps.as_mut().storage,
+        &env,
+        2_001,
+        SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+        }),
+        &PENDING_FALLBACKS,
+        &STATS,
+    )
+    .unwrap();
+    assert!(resp.messages.is_empty());
+    assert!(PENDING_FALLBACKS
+        .may_load(deps.as_ref().storage, 2_001)
+        .unwrap()
+        .is_none());
+
+    // the first failure reroutes the share to the fallback
+    let resp = handle_fallback_reply(
+        deps.as_mut().storage,
+        &env,
+        2_000,
+        "validator is jailed".to_string(),
+        &PENDING_FALLBACKS,
+        &STATS,
+    )
+    .unwrap();
+    assert_eq!(resp.messages.len(), 1);
+    assert_eq!(resp.messages[0].id, 2_000);
+    assert_eq!(resp.messages[0].reply_on, ReplyOn::Always);
+    assert_eq!(resp.events[0].ty, "destination_fallback");
+    assert_eq!(
+        destination_stats(deps.as_ref().storage),
+        vec![
+            ("validator".to_string(), vec![]),
+            ("friend".to_string(), vec![coin(1_000, "ujuno")]),
+        ]
+    );
+
+    // if the fallback fails as well then the share is left with the user
+    let resp = handle_fallback_reply(
+        deps.as_mut().storage,
+        &env,
+        2_000,
+        "friend is not accepting".to_string(),
+        &PENDING_FALLBACKS,
+        &STATS,
+    )
+    .unwrap();
+    assert!(resp.messages.is_empty());
+    assert_eq!(resp.events[0].ty, "destination_failed");
+    assert!(PENDING_FALLBACKS
+        .may_load(deps.as_ref().storage, 2_000)
+        .unwrap()
+        .is_none());
+
+    // the share that stayed with the user is no longer counted as automated
+    assert_eq!(
+        destination_stats(deps.as_ref().storage),
+        vec![
+            ("validator".to_string(), vec![]),
+            ("friend".to_string(), vec![coin(250, "ujuno")]),
+        ]
+    );
+    assert_eq!(
+        STATS
+            .user_stats(deps.as_ref().storage, &user)
+            .unwrap()
+            .amount_automated,
+        vec![coin(250, "ujuno")]
+    );
+}
+
+#[test]
+fn test_batch_compound() {
+    const BATCH_COMPOUND: Item<BatchProgress> = Item::new("batch_compound");
+    let mut deps = mock_dependencies();
+    let outpost = Addr::unchecked("outpost");
+
+    let resp = batch_compound_submsgs(
+        deps.as_mut().storage,
+        &BATCH_COMPOUND,
+        &outpost,
+        vec![
+            ("alice".to_string(), Ok("compound alice")),
+            ("bob".to_string(), Err("unauthorized".to_string())),
+            ("carol".to_string(), Ok("compound carol")),
+        ],
+    )
+    .unwrap();
+
+    // bob is skipped up front and everyone else runs in their own submsg
+    assert_eq!(resp.messages.len(), 2);
+    assert_eq!(resp.messages[0].id, 3_000);
+    assert_eq!(resp.messages[1].id, 3_002);
+    assert_eq!(resp.messages[1].reply_on, ReplyOn::Always);
+    assert_eq!(resp.events[0].ty, "batch_compound_skipped");
+
+    let resp = handle_batch_reply(
+        deps.as_mut().storage,
+        &BATCH_COMPOUND,
+        3_000,
+        SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+        }),
+    )
+    .unwrap();
+    assert!(resp.events.is_empty());
+
+    // the last user to finish wraps up the batch
+    let resp = handle_batch_reply(
+        deps.as_mut().storage,
+        &BATCH_COMPOUND,
+        3_002,
+        SubMsgResult::Err("too soon".to_string()),
+    )
+    .unwrap();
+    assert_eq!(resp.events[0].ty, "batch_compound_skipped");
+    assert_eq!(resp.events[1].ty, "batch_compound");
+    assert_eq!(
+        resp.events[1].attributes[2..],
+        vec![
+            ("succeeded", "alice").into(),
+            ("skipped", "bob,carol").into()
+        ]
+    );
+    assert!(BATCH_COMPOUND
+        .may_load(deps.as_ref().storage)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_default_slippage() {
+    let prefs = CompoundPrefs {
+        relative: vec![
+            DestinationAction {
+                destination: "validator".to_string(),
+                amount: 500_000_000_000_000_000,
+                fallback: None,
+                max_slippage: None,
+                min_amount: None,
+            },
+            DestinationAction {
+                destination: "thin_pool".to_string(),
+                amount: 500_000_000_000_000_000,
+                fallback: None,
+                max_slippage: Some(Decimal::percent(5)),
+                min_amount: None,
+            },
+        ],
+        absolute: vec![],
+        capped: vec![],
+    };
+
+    // the destination's own slippage wins over the compound's
+    let with_slippage = prefs
+        .clone()
+        .with_default_slippage(Some(Decimal::percent(1)))
+        .unwrap();
+    assert_eq!(
+        with_slippage.relative[0].max_slippage,
+        Some(Decimal::percent(1))
+    );
+    assert_eq!(
+        with_slippage.relative[1].max_slippage,
+        Some(Decimal::percent(5))
+    );
+
+    assert!(matches!(
+        prefs.with_default_slippage(Some(Decimal::one())),
+        Err(OutpostError::InvalidMaxSlippage { .. })
+    ));
+
+    assert_eq!(
+        min_out_after_slippage(Uint128::new(1_000), Decimal::percent(3)),
+        Uint128::new(970)
+    );
+}
+
+#[test]
+fn test_validator_selection_percentages() {
+    let selection = |validator_address: &str, percent: u64| ValidatorSelection {
+        validator_address: validator_address.to_string(),
+        percent: Decimal::percent(percent),
+    };
+
+    let percentages = ValidatorSelection::percentages(&[
+        selection("val1", 50),
+        selection("val2", 30),
+        selection("val3", 20),
+    ])
+    .unwrap();
+    assert_eq!(
+        calculate_compound_amounts(&percentages, &Uint128::new(1_001)).unwrap(),
+        vec![Uint128::new(500), Uint128::new(300), Uint128::new(201)]
+    );
+
+    assert!(matches!(
+        ValidatorSelection::percentages(&[selection("val1", 50), selection("val2", 30)]),
+        Err(OutpostError::InvalidValidatorSelection { .. })
+    ));
+    assert!(matches!(
+        ValidatorSelection::percentages(&[]),
+        Err(OutpostError::InvalidValidatorSelection { .. })
+    ));
+    assert!(matches!(
+        ValidatorSelection::percentages(&[selection("val1", 100), selection("val2", 0)]),
+        Err(OutpostError::ZeroPrefs)
+    ));
+}
+
+#[test]
+fn test_redelegate_msg() {
+    let redelegate = CosmosProtoMsg::Redelegate(MsgBeginRedelegate {
+        delegator_address: "user".to_string(),
+        validator_src_address: "jailed_validator".to_string(),
+        validator_dst_address: "validator".to_string(),
+        amount: Some(Coin {
+            denom: "ujuno".to_string(),
+            amount: "1000".to_string(),
+        }),
+    });
+
+    let any = Any::try_from(&redelegate).unwrap();
+    assert_eq!(any.type_url, "/cosmos.staking.v1beta1.MsgBeginRedelegate");
+    assert_eq!(CosmosProtoMsg::try_from(&any).unwrap(), redelegate);
+
+    // the stake being moved isn't part of the rewards so it's never rescaled
+    assert_eq!(
+        rescale_msg(
+            redelegate.clone(),
+            &PipelineAsset::Native("ujuno".to_string()),
+            Uint128::from(100u128),
+            Uint128::from(50u128),
+        )
+        .unwrap(),
+        redelegate
+    );
+}
+
+#[cw_serde]
+enum NestingDestination {
+    Stake { validator: String },
+    Composite(CompositeDestination<NestingDestination, String>),
+}
+
+impl NestedDestinations for NestingDestination {
+    fn nested_prefs(&self) -> Option<(String, &CompoundPrefs<Self>)> {
+        match self {
+            NestingDestination::Composite(CompositeDestination { convert_to, then }) => {
+                Some((convert_to.clone(), then))
+            }
+            NestingDestination::Stake { .. } => None,
+        }
+    }
+
+    fn native_only(&self) -> bool {
+        matches!(self, NestingDestination::Stake { .. })
+    }
+}
+
+#[test]
+fn test_composite_destinations() {
+    let action = |destination: NestingDestination, amount: u128| DestinationAction {
+        destination,
+        amount,
+        fallback: None,
+        max_slippage: None,
+        min_amount: None,
+    };
+    let stake = |validator: &str| NestingDestination::Stake {
+        validator: validator.to_string(),
+    };
+
+    // 60% to ampWHALE and half of that bonded to the market
+    let prefs = CompoundPrefs {
+        relative: vec![
+            action(stake("validator"), 400_000_000_000_000_000),
+            action(
+                NestingDestination::Composite(CompositeDestination {
+                    convert_to: "ampwhale".to_string(),
+                    then: CompoundPrefs {
+                        relative: vec![
+                            action(stake("market"), 500_000_000_000_000_000),
+                            action(
+                                NestingDestination::Composite(CompositeDestination {
+                                    convert_to: "usdc".to_string(),
+                                    then: CompoundPrefs {
+                                        relative: vec![action(
+                                            stake("vault"),
+                                            1_000_000_000_000_000_000,
+                                        )],
+                                        absolute: vec![],
+                                        capped: vec![],
+                                    },
+                                }),
+                                500_000_000_000_000_000,
+                            ),
+                        ],
+                        absolute: vec![],
+                        capped: vec![],
+                    },
+                }),
+                600_000_000_000_000_000,
+            ),
+        ],
+        absolute: vec![],
+        capped: vec![],
+    };
+
+    let nested: Vec<(String, NestingDestination)> = prefs
+        .nested_destinations("uwhale")
+        .map(|(denom, destination)| (denom, destination.clone()))
+        .collect();
+
+    assert_eq!(nested.len(), 5);
+    assert_eq!(nested[0], ("uwhale".to_string(), stake("validator")));
+    assert_eq!(nested[2], ("ampwhale".to_string(), stake("market")));
+    assert_eq!(nested[4], ("usdc".to_string(), stake("vault")));
+
+    // staking can only be handed the native token so it can't follow a conversion
+    assert!(matches!(
+        prefs.check_native_only("uwhale"),
+        Err(OutpostError::NativeOnlyDestination { denom, .. }) if denom == "ampwhale"
+    ));
+    assert!(CompoundPrefs {
+        relative: vec![action(stake("validator"), 1_000_000_000_000_000_000)],
+        absolute: vec![],
+        capped: vec![],
+    }
+    .check_native_only("uwhale")
+    .is_ok());
+
+    let send_msg = |to: &str, denom: &str, amount: u128| {
+        CosmosProtoMsg::Send(MsgSend {
+            from_address: "user".to_string(),
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }],
+        })
+    };
+
+    let mut swapped_nested = DestProjectMsgs {
+        msgs: vec![send_msg("vault", "usdc", 40)],
+        ..DestProjectMsgs::default()
+    };
+    swapped_nested.run_after_swap(vec![send_msg("pool", "ampwhale", 50)]);
+
+    // the nested destinations simply run in order after the conversion
+    let composite = DestProjectMsgs::composite(
+        vec![send_msg("pool", "uwhale", 600)],
+        vec![
+            DestProjectMsgs {
+                msgs: vec![send_msg("market", "ampwhale", 50)],
+                ..DestProjectMsgs::default()
+            },
+            swapped_nested,
+        ],
+    );
+
+    assert!(composite.sub_msgs.is_empty());
+    assert_eq!(
+        composite.msgs,
+        vec![
+            send_msg("pool", "uwhale", 600),
+            send_msg("market", "ampwhale", 50),
+            send_msg("pool", "ampwhale", 50),
+            send_msg("vault", "usdc", 40),
+        ]
+    );
+}
+
+#[test]
+fn test_absolute_and_capped_allocations() {
+    let action = |destination: &str, amount: u128| DestinationAction {
+        destination: destination.to_string(),
+        amount,
+        fallback: None,
+        max_slippage: None,
+        min_amount: None,
+    };
+
+    // exactly 5 sent each run, at most 300 staked and the rest split 70/30
+    let prefs = CompoundPrefs {
+        relative: vec![
+            action("pool", 700_000_000_000_000_000),
+            action("vault", 300_000_000_000_000_000),
+        ],
+        absolute: vec![action("send", 5)],
+        capped: vec![CappedDestinationAction {
+            action: action("stake", 500_000_000_000_000_000),
+            cap: Uint128::new(300),
+        }],
+    };
+    prefs_sum_to_one(&prefs).unwrap();
+
+    let split: Vec<(Uint128, String)> = prefs
+        .clone()
+        .with_default_fallback(Some("wallet".to_string()))
+        .split_rewards(&Uint128::new(1_000))
+        .unwrap()
+        .into_iter()
+        .map(|(amount, action)| {
+            assert_eq!(action.fallback, Some("wallet".to_string()));
+            (amount, action.destination)
+        })
+        .collect();
+    assert_eq!(
+        split,
+        vec![
+            (Uint128::new(5), "send".to_string()),
+            (Uint128::new(300), "stake".to_string()),
+            (Uint128::new(486), "pool".to_string()),
+            (Uint128::new(209), "vault".to_string()),
+        ]
+    );
+
+    // under the cap the capped destination just gets its percentage
+    assert_eq!(
+        prefs
+            .clone()
+            .split_rewards(&Uint128::new(205))
+            .unwrap()
+            .into_iter()
+            .map(|(amount, _)| amount)
+            .collect::<Vec<_>>(),
+        vec![
+            Uint128::new(5),
+            Uint128::new(100),
+            Uint128::new(70),
+            Uint128::new(30)
+        ]
+    );
+
+    assert!(matches!(
+        prefs.split_rewards(&Uint128::new(3)),
+        Err(OutpostError::UnderfundedCompound { required, available })
+            if required == Uint128::new(5) && available == Uint128::new(3)
+    ));
+
+    // the absolute allocations come out first wherever they are in the list
+    assert_eq!(
+        calculate_compound_amounts(
+            &[
+                Allocation::Relative(Decimal::percent(50)),
+                Allocation::Absolute(Uint128::new(100)),
+                Allocation::Relative(Decimal::percent(50)),
+            ],
+            &Uint128::new(301)
+        )
+        .unwrap(),
+        vec![Uint128::new(100), Uint128::new(100), Uint128::new(101)]
+    );
+
+    // absolute destinations alone leave whatever is left over with the user
+    prefs_sum_to_one(&CompoundPrefs {
+        relative: vec![],
+        absolute: vec![action("send", 5)],
+        capped: vec![],
+    })
+    .unwrap();
+    assert!(matches!(
+        prefs_sum_to_one(&CompoundPrefs {
+            relative: vec![],
+            absolute: vec![action("send", 0)],
+            capped: vec![],
+        }),
+        Err(OutpostError::ZeroPrefs)
+    ));
+    assert!(matches!(
+        prefs_sum_to_one(&CompoundPrefs {
+            relative: vec![],
+            absolute: vec![],
+            capped: vec![
+                CappedDestinationAction {
+                    action: action("stake", 600_000_000_000_000_000),
+                    cap: Uint128::new(300),
+                },
+                CappedDestinationAction {
+                    action: action("bond", 600_000_000_000_000_000),
+                    cap: Uint128::new(300),
+                },
+            ],
+        }),
+        Err(OutpostError::InvalidCappedQtys { .. })
+    ));
+}
+
+#[test]
+fn test_min_compound_and_dust() {
+    let user = Addr::unchecked("user");
+    let usdc_value = |rewards: &cosmwasm_std::Coin| -> Result<Uint128, OutpostError> {
+        Ok(rewards.amount * Uint128::new(2))
+    };
+
+    assert!(
+        below_min_compound(&user, &coin(100, "ujuno"), &None, usdc_value)
+            .unwrap()
+            .is_none()
+    );
+    assert!(below_min_compound(
+        &user,
+        &coin(100, "ujuno"),
+        &Some(MinCompound::Native(Uint128::new(100))),
+        usdc_value
+    )
+    .unwrap()
+    .is_none());
+
+    let skipped = below_min_compound(
+        &user,
+        &coin(100, "ujuno"),
+        &Some(MinCompound::Usdc(Uint128::new(250))),
+        usdc_value,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(skipped.ty, "compound_skipped");
+    assert!(skipped
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "value" && attr.value == "200usdc"));
+
+    // empty rewards are never worth compounding and don't need to be valued
+    assert!(below_min_compound(
+        &user,
+        &coin(0, "ujuno"),
+        &Some(MinCompound::Usdc(Uint128::new(1))),
+        |_| -> Result<Uint128, OutpostError> { panic!("the rewards shouldn't be valued") },
+    )
+    .unwrap()
+    .is_some());
+
+    let action = |destination: &str, amount: u128, min_amount: Option<u128>| DestinationAction {
+        destination: destination.to_string(),
+        amount,
+        fallback: None,
+        max_slippage: None,
+        min_amount: min_amount.map(Uint128::new),
+    };
+
+    let prefs = CompoundPrefs {
+        relative: vec![
+            action("stake", 600_000_000_000_000_000, None),
+            action("swap", 300_000_000_000_000_000, Some(500)),
+            action("send", 100_000_000_000_000_000, Some(50)),
+        ],
+        absolute: vec![],
+        capped: vec![],
+    };
+
+    // the swap's share is too small so it goes to the stake instead
+    let split: Vec<(Uint128, String)> = prefs
+        .clone()
+        .split_rewards(&Uint128::new(1_000))
+        .unwrap()
+        .into_iter()
+        .map(|(amount, action)| (amount, action.destination))
+        .collect();
+    assert_eq!(
+        split,
+        vec![
+            (Uint128::new(900), "stake".to_string()),
+            (Uint128::new(100), "send".to_string()),
+        ]
+    );
+
+    // the largest share is never rolled up even if it's below its own minimum
+    let split: Vec<(Uint128, String)> = prefs
+        .clone()
+        .split_rewards(&Uint128::new(100))
+        .unwrap()
+        .into_iter()
+        .map(|(amount, action)| (amount, action.destination))
+        .collect();
+    assert_eq!(split, vec![(Uint128::new(100), "stake".to_string())]);
+
+    // the dust only ever goes to a relative destination even when an absolute one is larger
+    let split: Vec<(Uint128, String)> = CompoundPrefs {
+        absolute: vec![action("pay", 800, None)],
+        ..prefs
+    }
+    .split_rewards(&Uint128::new(1_000))
+    .unwrap()
+    .into_iter()
+    .map(|(amount, action)| (amount, action.destination))
+    .collect();
+    assert_eq!(
+        split,
+        vec![
+            (Uint128::new(800), "pay".to_string()),
+            (Uint128::new(200), "stake".to_string()),
+        ]
+    );
+
+    // without any relative destinations the dust stays with the user
+    let split: Vec<(Uint128, String)> = CompoundPrefs {
+        relative: vec![],
+        absolute: vec![action("pay", 800, None)],
+        capped: vec![CappedDestinationAction {
+            action: action("swap", 500_000_000_000_000_000, Some(500)),
+            cap: Uint128::new(1_000),
+        }],
+    }
+    .split_rewards(&Uint128::new(1_000))
+    .unwrap()
+    .into_iter()
+    .map(|(amount, action)| (amount, action.destination))
+    .collect();
+    assert_eq!(split, vec![(Uint128::new(800), "pay".to_string())]);
+}
+
+#[test]
+fn test_outpost_asset_conversions() {
+    assert_eq!(
+        OutpostAssetInfo::from_denom("ujuno"),
+        OutpostAssetInfo::Native("ujuno".to_string())
+    );
+    assert_eq!(
+        OutpostAssetInfo::from_denom(
+            "ibc/EAC38D55372F38F1AFD68DF7FE9EF762DCF69F26520643CF3F9D292A738D8034"
+        ),
+        OutpostAssetInfo::Ibc(
+            "EAC38D55372F38F1AFD68DF7FE9EF762DCF69F26520643CF3F9D292A738D8034".to_string()
+        )
+    );
+    // subdenoms are allowed to contain slashes
+    assert_eq!(
+        OutpostAssetInfo::from_denom("factory/osmo1creator/sub/denom"),
+        OutpostAssetInfo::TokenFactory {
+            creator: "osmo1creator".to_string(),
+            subdenom: "sub/denom".to_string(),
+        }
+    );
+    // a factory prefix without a subdenom isn't a valid tokenfactory denom
+    assert_eq!(
+        OutpostAssetInfo::from_denom("factory/osmo1creator"),
+        OutpostAssetInfo::Native("factory/osmo1creator".to_string())
+    );
+
+    // every bank denom survives the round trip through a cosmwasm coin and an osmosis coin
+    for denom in [
+        "uwhale",
+        "ibc/EAC38D55372F38F1AFD68DF7FE9EF762DCF69F26520643CF3F9D292A738D8034",
+        "factory/migaloo1creator/ampWHALE",
+        "factory/osmo1creator",
+    ] {
+        let asset = OutpostAsset::from(coin(1_000, denom));
+        assert_eq!(
+            cosmwasm_std::Coin::try_from(asset.clone()).unwrap(),
+            coin(1_000, denom)
+        );
+
+        let osmosis_coin =
+            osmosis_std::types::cosmos::base::v1beta1::Coin::try_from(asset.clone()).unwrap();
+        assert_eq!(osmosis_coin.denom, denom);
+        assert_eq!(osmosis_coin.amount, "1000");
+        assert_eq!(OutpostAsset::try_from(osmosis_coin).unwrap(), asset);
+    }
+
+    // cw20s can't be represented as bank coins
+    let cw20 = OutpostAssetInfo::Cw20("juno1cw20".to_string()).with_amount(1_000u128);
+    assert_eq!(cw20.to_string(), "1000juno1cw20");
+    assert!(matches!(
+        cw20.to_coin(),
+        Err(OutpostError::AssetIsNotCoinable(addr)) if addr == "juno1cw20"
+    ));
+    assert!(osmosis_std::types::cosmos::base::v1beta1::Coin::try_from(cw20).is_err());
+}
+
+/// Constant product pool that quotes without touching the querier
+struct MockPool {
+    pool: &'static str,
+    offer_reserve: u128,
+    ask_reserve: u128,
+}
+
+impl SwapVenue for MockPool {
+    type Error = OutpostError;
+
+    fn simulate(
+        &self,
+        _querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        _ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, OutpostError> {
+        if self.offer_reserve == 0 {
+            return Err(StdError::generic_err(format!("{} has no liquidity", self.pool)).into());
+        }
+
+        Ok(offer
+            .amount
+            .multiply_ratio(self.ask_reserve, self.offer_reserve + offer.amount.u128()))
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        _max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), OutpostError> {
+        Ok((
+            vec![CosmosProtoMsg::Send(MsgSend {
+                from_address: sender.to_string(),
+                to_address: self.pool.to_string(),
+                amount: vec![offer.to_csdk_coin()?],
+            })],
+            self.simulate(querier, offer, ask)?,
+        ))
+    }
+
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        vec![GrantRequirement::default_contract_exec_auth(
+            base,
+            Addr::unchecked(self.pool),
+            vec!["swap"],
+            offer.denom().as_deref(),
+        )]
+    }
+}
+
+#[test]
+fn test_best_execution_router() {
+    let deps = mock_dependencies();
+    let querier = QuerierWrapper::new(&deps.querier);
+    let user = Addr::unchecked("user");
+    let ask = OutpostAssetInfo::from_denom("uusdc");
+
+    let shallow = MockPool {
+        pool: "shallow",
+        offer_reserve: 10_000,
+        ask_reserve: 10_000,
+    };
+    let deep = MockPool {
+        pool: "deep",
+        offer_reserve: 100_000,
+        ask_reserve: 90_000,
+    };
+    let empty = MockPool {
+        pool: "empty",
+        offer_reserve: 0,
+        ask_reserve: 0,
+    };
+
+    let router = BestExecutionRouter::new(vec![shallow, deep], None);
+
+    // small swaps get the better price on the shallow pool
+    assert_eq!(
+        router
+            .simulate(&querier, &coin(1_000, "ujuno").into(), &ask)
+            .unwrap(),
+        Uint128::new(909)
+    );
+
+    // large swaps move the shallow pool too much so the deep pool takes the whole thing
+    let (msgs, simulated) = router
+        .swap_msgs(&querier, &user, &coin(4_000, "ujuno").into(), &ask, None)
+        .unwrap();
+    assert_eq!(simulated, Uint128::new(3_461));
+    assert_eq!(
+        msgs,
+        vec![CosmosProtoMsg::Send(MsgSend {
+            from_address: "user".to_string(),
+            to_address: "deep".to_string(),
+            amount: vec![Coin {
+                denom: "ujuno".to_string(),
+                amount: "4000".to_string(),
+            }],
+        })]
+    );
+
+    // splitting lets the shallow pool take the chunk it prices best
+    let router = BestExecutionRouter {
+        split: Some(SplitOrder {
+            min_amount: Uint128::new(1_000),
+            parts: 4,
+        }),
+        ..router
+    };
+    let (msgs, simulated) = router
+        .swap_msgs(&querier, &user, &coin(4_000, "ujuno").into(), &ask, None)
+        .unwrap();
+    assert_eq!(simulated, Uint128::new(2_621 + 909));
+    assert_eq!(
+        msgs,
+        vec![
+            CosmosProtoMsg::Send(MsgSend {
+                from_address: "user".to_string(),
+                to_address: "deep".to_string(),
+                amount: vec![Coin {
+                    denom: "ujuno".to_string(),
+                    amount: "3000".to_string(),
+                }],
+            }),
+            CosmosProtoMsg::Send(MsgSend {
+                from_address: "user".to_string(),
+                to_address: "shallow".to_string(),
+                amount: vec![Coin {
+                    denom: "ujuno".to_string(),
+                    amount: "1000".to_string(),
+                }],
+            })
+        ]
+    );
+
+    // swaps under the split minimum aren't broken up
+    assert_eq!(
+        router
+            .allocate(&querier, &coin(999, "ujuno").into(), &ask)
+            .unwrap()
+            .into_iter()
+            .map(|(venue, amount)| (venue.pool, amount))
+            .collect::<Vec<_>>(),
+        vec![("shallow", Uint128::new(999))]
+    );
+
+    // every venue needs a grant since any of them could be picked
+    assert_eq!(
+        router
+            .grants(
+                GrantBase {
+                    granter: Addr::unchecked("user"),
+                    grantee: Addr::unchecked("outpost"),
+                    expiration: Timestamp::from_seconds(1),
+                },
+                &OutpostAssetInfo::from_denom("ujuno"),
+            )
+            .len(),
+        2
+    );
+
+    // venues that can't quote are skipped
+    let router =
+        BestExecutionRouter::new(vec![empty, router.venues.into_iter().next().unwrap()], None);
+    assert_eq!(
+        router
+            .simulate(&querier, &coin(1_000, "ujuno").into(), &ask)
+            .unwrap(),
+        Uint128::new(909)
+    );
+
+    // and if none of them can then the swap errors
+    let router = BestExecutionRouter::new(
+        vec![MockPool {
+            pool: "empty",
+            offer_reserve: 0,
+            ask_reserve: 0,
+        }],
+        None,
+    );
+    assert!(matches!(
+        router.simulate(&querier, &coin(1_000, "ujuno").into(), &ask),
+        Err(OutpostError::NoSwapVenue { from, to }) if from == "ujuno" && to == "uusdc"
+    ));
+}
+
+#[test]
+fn test_price_sources() {
+    let mut deps = mock_dependencies();
+    // stands in for an oracle contract that only knows the price of juno in usdc
+    deps.querier.update_wasm(|query| match query {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == "oracle" => {
+            match from_json(msg) {
+                Ok(OracleQueryMsg::Price { base, quote })
+                    if base == OutpostAssetInfo::from_denom("ujuno")
+                        && quote == OutpostAssetInfo::from_denom("uusdc") =>
+                {
+                    SystemResult::Ok(ContractResult::Ok(
+                        to_json_binary(&OraclePriceResponse {
+                            price: Decimal::percent(25),
+                        })
+                        .unwrap(),
+                    ))
+                }
+                _ => SystemResult::Ok(ContractResult::Err("no price for this pair".to_string())),
+            }
+        }
+        _ => SystemResult::Err(SystemError::NoSuchContract {
+            addr: "unknown".to_string(),
+        }),
+    });
+    let querier = QuerierWrapper::new(&deps.querier);
+    let usdc = OutpostAssetInfo::from_denom("uusdc");
+
+    let oracle = OraclePrice {
+        contract: Addr::unchecked("oracle"),
+    };
+    assert_eq!(
+        oracle
+            .price(&querier, &OutpostAssetInfo::from_denom("ujuno"), &usdc)
+            .unwrap(),
+        Decimal::percent(25)
+    );
+    assert_eq!(
+        oracle
+            .value(&querier, &coin(1_000, "ujuno").into(), &usdc)
+            .unwrap(),
+        Uint128::new(250)
+    );
+    // assets that are already in the quote denom don't need to be priced
+    assert_eq!(
+        oracle
+            .value(&querier, &coin(1_000, "uusdc").into(), &usdc)
+            .unwrap(),
+        Uint128::new(1_000)
+    );
+    assert!(oracle
+        .price(&querier, &OutpostAssetInfo::from_denom("uatom"), &usdc)
+        .is_err());
+
+    let simulated = SimulatedPrice {
+        venue: MockPool {
+            pool: "deep",
+            offer_reserve: 100_000,
+            ask_reserve: 90_000,
+        },
+        probe_amount: Uint128::new(100),
+    };
+    assert_eq!(
+        simulated
+            .value(&querier, &coin(1_000, "ujuno").into(), &usdc)
+            .unwrap(),
+        Uint128::new(890)
+    );
+
+    let unprobed = SimulatedPrice {
+        probe_amount: Uint128::zero(),
+        ..simulated
+    };
+    assert!(matches!(
+        unprobed.price(&querier, &OutpostAssetInfo::from_denom("ujuno"), &usdc),
+        Err(OutpostError::ZeroPriceProbe)
+    ));
+}
+
+#[test]
+fn test_price_impact_guard() {
+    assert_eq!(
+        price_impact(Uint128::new(1_000), Uint128::new(900), Decimal::one()),
+        Decimal::percent(10)
+    );
+    assert_eq!(
+        price_impact(Uint128::new(1_000), Uint128::new(450), Decimal::percent(50)),
+        Decimal::percent(10)
+    );
+    // doing better than the reference price isn't an impact
+    assert_eq!(
+        price_impact(Uint128::new(1_000), Uint128::new(1_100), Decimal::one()),
+        Decimal::zero()
+    );
+
+    let deps = mock_dependencies();
+    let querier = QuerierWrapper::new(&deps.querier);
+    let offer: OutpostAsset = coin(1_000, "ujuno").into();
+    let ask = OutpostAssetInfo::from_denom("uusdc");
+
+    let guard = PriceImpactGuard {
+        max_impact: Decimal::percent(5),
+    };
+    let event = guard
+        .check("pair", &offer, &ask, Uint128::new(960), Decimal::one())
+        .unwrap();
+    assert_eq!(event.ty, "price_impact");
+    assert!(event
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "impact" && attr.value == "0.04"));
+    assert!(matches!(
+        guard.check("pair", &offer, &ask, Uint128::new(940), Decimal::one()),
+        Err(OutpostError::PriceImpactTooHigh { venue, impact, .. })
+            if venue == "pair" && impact == Decimal::percent(6)
+    ));
+
+    // swapping 40% of the shallow pool moves its price by far more than 5%
+    let shallow = MockPool {
+        pool: "shallow",
+        offer_reserve: 10_000,
+        ask_reserve: 10_000,
+    };
+    let large_offer: OutpostAsset = coin(4_000, "ujuno").into();
+    let simulated = shallow.simulate(&querier, &large_offer, &ask).unwrap();
+    assert!(matches!(
+        guard.check_against_spot(&querier, &shallow, "shallow", &large_offer, &ask, simulated),
+        Err(OutpostError::PriceImpactTooHigh { .. })
+    ));
+
+    // while a small swap stays close to the spot price
+    let simulated = shallow.simulate(&querier, &offer, &ask).unwrap();
+    assert!(guard
+        .check_against_spot(&querier, &shallow, "shallow", &offer, &ask, simulated)
+        .is_ok());
+
+    // swaps that aren't a single venue are probed through the given simulation
+    let probed =
+        guard.check_against_probe("route", &offer, &ask, Uint128::new(800), |probe_amount| {
+            Ok::<_, OutpostError>(probe_amount)
+        });
+    assert!(matches!(
+        probed,
+        Err(OutpostError::PriceImpactTooHigh { venue, impact, .. })
+            if venue == "route" && impact == Decimal::percent(20)
+    ));
+}
+
+#[test]
+fn test_price_impact_reroute() {
+    let deps = mock_dependencies();
+    let querier = QuerierWrapper::new(&deps.querier);
+    let user = Addr::unchecked("user");
+    let dao_token = OutpostAssetInfo::from_denom("udao");
+    let thin_pool = MockPool {
+        pool: "thin_dao_pool",
+        offer_reserve: 1_000_000,
+        ask_reserve: 1_000_000,
+    };
+    let guard = PriceImpactGuard {
+        max_impact: Decimal::percent(5),
+    };
+
+    let prefs = CompoundPrefs {
+        relative: vec![DestinationAction {
+            destination: "dao_stake".to_string(),
+            amount: 1_000_000_000_000_000_000,
+            fallback: Some("wynd_stake".to_string()),
+            max_slippage: None,
+            min_amount: None,
+        }],
+        absolute: vec![],
+        capped: vec![],
+    };
+
+    // the dao stake swaps through the thin pool as long as the guard lets it
+    let gen_msgs = |amount: cosmwasm_std::Coin,
+                    prefs: CompoundPrefs<String>|
+     -> Result<Vec<DestProjectMsgs>, OutpostError> {
+        let destination = prefs.relative[0].destination.clone();
+        if destination == "wynd_stake" {
+            return Ok(vec![DestProjectMsgs {
+                msgs: vec![CosmosProtoMsg::Send(MsgSend {
+                    from_address: user.to_string(),
+                    to_address: destination,
+                    amount: vec![Coin {
+                        denom: amount.denom,
+                        amount: amount.amount.to_string(),
+                    }],
+                })],
+                ..DestProjectMsgs::default()
+            }]);
+        }
+
+        let offer: OutpostAsset = amount.into();
+        let (swap_msgs, simulated) =
+            thin_pool.swap_msgs(&querier, &user, &offer, &dao_token, None)?;
+        let event = guard.check_against_spot(
+            &querier,
+            &thin_pool,
+            thin_pool.pool,
+            &offer,
+            &dao_token,
+            simulated,
+        )?;
+
+        Ok(vec![DestProjectMsgs {
+            msgs: swap_msgs,
+            events: vec![event],
+            ..DestProjectMsgs::default()
+        }])
+    };
+
+    // a small compound barely moves the pool so it's staked as usual
+    let (isolated, _) =
+        isolate_destinations(&user, &coin(10_000, "ujuno"), &prefs, gen_msgs).unwrap();
+    assert_eq!(isolated[0].sub_msgs[0].1.len(), 1);
+    assert_eq!(isolated[0].events[0].ty, "price_impact");
+
+    // while a large one would move it too far and is rerouted to the fallback instead
+    let (isolated, fallbacks) =
+        isolate_destinations(&user, &coin(400_000, "ujuno"), &prefs, gen_msgs).unwrap();
+    assert_eq!(
+        isolated[0].sub_msgs,
+        vec![(
+            2_000,
+            vec![CosmosProtoMsg::Send(MsgSend {
+                from_address: "user".to_string(),
+                to_address: "wynd_stake".to_string(),
+                amount: vec![Coin {
+                    denom: "ujuno".to_string(),
+                    amount: "400000".to_string(),
+                }],
+            })],
+            ReplyOn::Always
+        )]
+    );
+    assert_eq!(isolated[0].events[0].ty, "destination_fallback");
+    assert!(isolated[0].events[0]
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "error" && attr.value.starts_with("Price impact")));
+    // the fallback is already in use so there's nothing left to fall back to
+    assert!(fallbacks[0].1.fallback_msgs.is_empty());
+}
+
+#[test]
+fn test_fee_schedule() {
+    const FEES: FeeStore =
+        FeeStore::new("fee_schedule", "fee_overrides", "agreed_fees", "fee_volume");
+
+    let mut deps = mock_dependencies();
+    let user = Addr::unchecked("user");
+    let partner = Addr::unchecked("partner");
+
+    let schedule = FeeSchedule {
+        default_fee: Decimal::percent(3),
+        tiers: vec![
+            FeeTier {
+                min_volume: Uint128::new(1_000),
+                fee: Decimal::percent(2),
+            },
+            FeeTier {
+                min_volume: Uint128::new(10_000),
+                fee: Decimal::percent(1),
+            },
+        ],
+    };
+
+    assert!(schedule.validate(Decimal::percent(3)).is_ok());
+    assert!(matches!(
+        schedule.validate(Decimal::percent(2)),
+        Err(OutpostError::InvalidFeeSchedule { fee, .. }) if fee == Decimal::percent(3)
+    ));
+
+    // without a schedule there's nothing to quote or agree to
+    // and the caller's fee is charged as long as it's under the max
+    assert_eq!(
+        FEES.fee(&deps.storage, &user, None, Decimal::percent(5))
+            .unwrap(),
+        Decimal::percent(5)
+    );
+    assert_eq!(
+        FEES.fee(
+            &deps.storage,
+            &user,
+            Some(Decimal::percent(1)),
+            Decimal::percent(5)
+        )
+        .unwrap(),
+        Decimal::percent(1)
+    );
+    assert!(matches!(
+        FEES.fee(&deps.storage, &user, Some(Decimal::percent(6)), Decimal::percent(5)),
+        Err(OutpostError::TaxFeeAboveMax { fee, .. }) if fee == Decimal::percent(6)
+    ));
+    assert!(matches!(
+        FEES.agree(&mut deps.storage, &user, Decimal::percent(5)),
+        Err(OutpostError::FeeScheduleNotSet)
+    ));
+
+    assert!(matches!(
+        FEES.update_schedule(&mut deps.storage, Some(schedule.clone()), Decimal::percent(2)),
+        Err(OutpostError::InvalidFeeSchedule { fee, .. }) if fee == Decimal::percent(3)
+    ));
+    FEES.update_schedule(
+        &mut deps.storage,
+        Some(schedule.clone()),
+        Decimal::percent(5),
+    )
+    .unwrap();
+    assert_eq!(
+        FEES.quote(&deps.storage, &user).unwrap(),
+        Some(Decimal::percent(3))
+    );
+    assert_eq!(
+        FEES.grant_fee(
+            &deps.storage,
+            &user,
+            Some(Decimal::percent(1)),
+            Decimal::percent(5)
+        )
+        .unwrap(),
+        Decimal::percent(3)
+    );
+
+    // users that haven't agreed to the schedule can't be compounded
+    assert!(matches!(
+        FEES.fee(&deps.storage, &user, None, Decimal::percent(5)),
+        Err(OutpostError::FeeNotAgreed(_))
+    ));
+
+    // users can't agree to a fee above what they're willing to pay
+    assert!(matches!(
+        FEES.agree(&mut deps.storage, &user, Decimal::percent(2)),
+        Err(OutpostError::FeeAboveAccepted { fee, .. }) if fee == Decimal::percent(3)
+    ));
+    assert_eq!(
+        FEES.agree(&mut deps.storage, &user, Decimal::percent(5))
+            .unwrap(),
+        Decimal::percent(3)
+    );
+
+    // raising the schedule doesn't raise what the user is charged
+    FEES.update_schedule(
+        &mut deps.storage,
+        Some(FeeSchedule {
+            default_fee: Decimal::percent(4),
+            ..schedule.clone()
+        }),
+        Decimal::percent(5),
+    )
+    .unwrap();
+    assert_eq!(
+        FEES.fee(&deps.storage, &user, None, Decimal::percent(5))
+            .unwrap(),
+        Decimal::percent(3)
+    );
+
+    // and the caller can't pick a different fee once there's a schedule
+    assert!(matches!(
+        FEES.fee(&deps.storage, &user, Some(Decimal::percent(1)), Decimal::percent(5)),
+        Err(OutpostError::TaxFeeMismatch { fee, .. }) if fee == Decimal::percent(3)
+    ));
+    assert_eq!(
+        FEES.fee(
+            &deps.storage,
+            &user,
+            Some(Decimal::percent(3)),
+            Decimal::percent(5)
+        )
+        .unwrap(),
+        Decimal::percent(3)
+    );
+
+    // but reaching a cheaper tier lowers it
+    FEES.record_volume(&mut deps.storage, &user, Uint128::new(600))
+        .unwrap();
+    assert_eq!(
+        FEES.record_volume(&mut deps.storage, &user, Uint128::new(600))
+            .unwrap(),
+        Uint128::new(1_200)
+    );
+    assert_eq!(
+        FEES.fee(&deps.storage, &user, None, Decimal::percent(5))
+            .unwrap(),
+        Decimal::percent(2)
+    );
+    assert_eq!(
+        schedule.fee_for_volume(Uint128::new(10_000)),
+        Decimal::percent(1)
+    );
+
+    // once the schedule is removed the caller still can't charge more than the user agreed to
+    FEES.update_schedule(&mut deps.storage, None, Decimal::percent(5))
+        .unwrap();
+    assert!(matches!(
+        FEES.fee(&deps.storage, &user, None, Decimal::percent(5)),
+        Ok(fee) if fee == Decimal::percent(3)
+    ));
+    assert!(matches!(
+        FEES.fee(
+            &deps.storage,
+            &user,
+            Some(Decimal::percent(4)),
+            Decimal::percent(5)
+        ),
+        Err(OutpostError::TaxFeeAboveMax { .. })
+    ));
+
+    // overrides replace the schedule entirely but can't be above the max either
+    assert!(matches!(
+        FEES.update_override(
+            &mut deps.storage,
+            &partner,
+            Some(Decimal::percent(6)),
+            Decimal::percent(5)
+        ),
+        Err(OutpostError::InvalidFeeSchedule { fee, .. }) if fee == Decimal::percent(6)
+    ));
+    FEES.update_override(
+        &mut deps.storage,
+        &partner,
+        Some(Decimal::zero()),
+        Decimal::percent(5),
+    )
+    .unwrap();
+    assert!(matches!(
+        FEES.fee(&deps.storage, &partner, None, Decimal::percent(5)),
+        Err(OutpostError::FeeNotAgreed(_))
+    ));
+    assert_eq!(
+        FEES.agree(&mut deps.storage, &partner, Decimal::zero())
+            .unwrap(),
+        Decimal::zero()
+    );
+    assert_eq!(
+        FEES.fee(&deps.storage, &partner, None, Decimal::percent(5))
+            .unwrap(),
+        Decimal::zero()
+    );
+}
+
+#[test]
+fn test_compound_stats() {
+    const STATS: StatsStore = StatsStore::new("user_stats", "outpost_stats");
+
+    let mut deps = mock_dependencies();
+    let alice = Addr::unchecked("alice");
+    let bob = Addr::unchecked("bob");
+
+    let run = |time: u64, amount: u128| CompoundRun {
+        time: Timestamp::from_seconds(time),
+        automated: vec![coin(amount, "ujuno")],
+        tax: vec![coin(amount / 100, "ujuno")],
+        allocations: vec![
+            ("juno_staking".to_string(), coin(amount / 4, "ujuno")),
+            ("unstake".to_string(), coin(amount - amount / 4, "ujuno")),
+        ],
+    };
+
+    STATS
+        .record(&mut deps.storage, &alice, &run(10, 1_000))
+        .unwrap();
+    STATS
+        .record(&mut deps.storage, &alice, &run(20, 2_000))
+        .unwrap();
+    STATS
+        .record(&mut deps.storage, &bob, &run(30, 4_000))
+        .unwrap();
+
+    assert_eq!(
+        STATS.user_stats(&deps.storage, &alice).unwrap(),
+        CompoundStats {
+            amount_automated: vec![coin(3_000, "ujuno")],
+            tax_collected: vec![coin(30, "ujuno")],
+            run_count: 2,
+            last_run: Some(Timestamp::from_seconds(20)),
+            destinations: vec![
+                DestinationStats {
+                    destination: "juno_staking".to_string(),
+                    amount: vec![coin(750, "ujuno")],
+                },
+                DestinationStats {
+                    destination: "unstake".to_string(),
+                    amount: vec![coin(2_250, "ujuno")],
+                },
+            ],
+        }
+    );
+
+    let outpost_stats = STATS.outpost_stats(&deps.storage).unwrap();
+    assert_eq!(outpost_stats.amount_automated, vec![coin(7_000, "ujuno")]);
+    assert_eq!(outpost_stats.tax_collected, vec![coin(70, "ujuno")]);
+    assert_eq!(outpost_stats.run_count, 3);
+    assert_eq!(outpost_stats.last_run, Some(Timestamp::from_seconds(30)));
+
+    // a share that fell back moves over to its fallback
+    STATS
+        .reroute(
+            &mut deps.storage,
+            &bob,
+            "unstake",
+            Some("juno_staking"),
+            &coin(1_000, "ujuno"),
+        )
+        .unwrap();
+    // and one that failed for good is no longer counted as automated
+    STATS
+        .reroute(
+            &mut deps.storage,
+            &bob,
+            "unstake",
+            None,
+            &coin(2_000, "ujuno"),
+        )
+        .unwrap();
+
+    let bob_stats = STATS.user_stats(&deps.storage, &bob).unwrap();
+    assert_eq!(bob_stats.amount_automated, vec![coin(2_000, "ujuno")]);
+    assert_eq!(
+        bob_stats.destinations,
+        vec![
+            DestinationStats {
+                destination: "juno_staking".to_string(),
+                amount: vec![coin(2_000, "ujuno")],
+            },
+            DestinationStats {
+                destination: "unstake".to_string(),
+                amount: vec![],
+            },
+        ]
+    );
+    assert_eq!(
+        STATS.outpost_stats(&deps.storage).unwrap().amount_automated,
+        vec![coin(5_000, "ujuno")]
+    );
+
+    // users that haven't been compounded have empty stats
+    assert_eq!(
+        STATS
+            .user_stats(&deps.storage, &Addr::unchecked("carol"))
+            .unwrap(),
+        CompoundStats::default()
+    );
+}
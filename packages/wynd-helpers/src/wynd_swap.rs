@@ -1,10 +1,15 @@
 use cosmos_sdk_proto::{cosmos::base::v1beta1::Coin, cosmwasm::wasm::v1::MsgExecuteContract};
+use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{to_json_binary, Addr, Decimal, QuerierWrapper, StdError, Uint128};
 use cw_grant_spec::grants::{
     AuthorizationType, ContractExecutionAuthorizationFilter, ContractExecutionAuthorizationLimit,
     ContractExecutionSetting, GrantBase, GrantRequirement,
 };
-use outpost_utils::msg_gen::{create_exec_contract_msg, CosmosProtoMsg};
+use outpost_utils::{
+    asset::{OutpostAsset, OutpostAssetInfo},
+    msg_gen::{create_exec_contract_msg, CosmosProtoMsg},
+    swap_venue::SwapVenue,
+};
 use wyndex::{
     asset::{Asset, AssetInfo, AssetValidated},
     pair::SimulationResponse,
@@ -35,6 +40,7 @@ pub fn wynd_pair_swap_msg(
     offer_asset: Asset,
     ask_asset: AssetInfo,
     pair_contract_address: &str,
+    max_slippage: Option<Decimal>,
 ) -> Result<CosmosProtoMsg, WyndHelperError> {
     let swap_msg = match offer_asset.info.clone() {
         AssetInfo::Native(denom) => {
@@ -45,7 +51,7 @@ pub fn wynd_pair_swap_msg(
                 &wyndex::pair::ExecuteMsg::Swap {
                     offer_asset: offer_asset.clone(),
                     ask_asset_info: Some(ask_asset),
-                    max_spread: None,
+                    max_spread: max_slippage,
                     belief_price: None,
                     to: None,
                     referral_address: None,
@@ -68,7 +74,7 @@ pub fn wynd_pair_swap_msg(
                     msg: to_json_binary(&wyndex::pair::Cw20HookMsg::Swap {
                         ask_asset_info: Some(ask_asset),
                         belief_price: None,
-                        max_spread: None,
+                        max_spread: max_slippage,
                         to: None,
                         referral_address: None,
                         referral_commission: None,
@@ -90,6 +96,7 @@ pub fn simulate_and_swap_wynd_pair(
     pair_contract_address: &str,
     offer_asset: Asset,
     ask_asset: AssetInfo,
+    max_slippage: Option<Decimal>,
 ) -> Result<(CosmosProtoMsg, SimulationResponse), WyndHelperError> {
     let simulation = simulate_wynd_pool_swap(
         querier,
@@ -98,7 +105,13 @@ pub fn simulate_and_swap_wynd_pair(
         ask_asset.to_string(),
     )?;
 
-    let swap_msg = wynd_pair_swap_msg(sender, offer_asset, ask_asset, pair_contract_address)?;
+    let swap_msg = wynd_pair_swap_msg(
+        sender,
+        offer_asset,
+        ask_asset,
+        pair_contract_address,
+        max_slippage,
+    )?;
 
     Ok((swap_msg, simulation))
 }
@@ -139,10 +152,12 @@ pub fn simulate_multiple_swaps(
 }
 
 /// Creates the swap operations for the multihop router
-/// This can be used for simulations and the actual swap
+/// This can be used for simulations and the actual swap.
+/// Without a `max_slippage` the swap accepts a 2% spread
 pub fn create_wyndex_swap_operations(
     offer_asset: AssetInfo,
     ask_asset_info: AssetInfo,
+    max_slippage: Option<Decimal>,
 ) -> wyndex_multi_hop::msg::ExecuteMsg {
     let operations = vec![wyndex_multi_hop::msg::SwapOperation::WyndexSwap {
         offer_asset_info: offer_asset.clone(),
@@ -152,7 +167,7 @@ pub fn create_wyndex_swap_operations(
         operations,
         minimum_receive: None,
         receiver: None,
-        max_spread: Some(Decimal::percent(2)),
+        max_spread: Some(max_slippage.unwrap_or(Decimal::percent(2))),
         referral_address: None,
         referral_commission: None,
     }
@@ -166,6 +181,7 @@ pub fn create_wyndex_swap_msg(
     offer_asset: AssetInfo,
     ask_asset_info: AssetInfo,
     multihop_address: String,
+    max_slippage: Option<Decimal>,
 ) -> Result<Vec<CosmosProtoMsg>, StdError> {
     // no swap to do because the offer and ask tokens are the same
     if offer_asset.eq(&ask_asset_info) {
@@ -173,7 +189,7 @@ pub fn create_wyndex_swap_msg(
     }
 
     // the swap operations to be used by the multihop router
-    let swap_ops = create_wyndex_swap_operations(offer_asset.clone(), ask_asset_info);
+    let swap_ops = create_wyndex_swap_operations(offer_asset.clone(), ask_asset_info, max_slippage);
 
     match offer_asset {
         AssetInfo::Native(offer_denom) => Ok(vec![CosmosProtoMsg::ExecuteContract(
@@ -214,6 +230,7 @@ pub fn create_wyndex_swap_msg_with_simulation(
     ask_asset_info: AssetInfo,
     multihop_address: String,
     swap_operations: Option<wyndex_multi_hop::msg::ExecuteMsg>,
+    max_slippage: Option<Decimal>,
 ) -> Result<(Vec<CosmosProtoMsg>, Uint128), StdError> {
     // no swap to do because the offer and ask tokens are the same
     if offer_asset.eq(&ask_asset_info) {
@@ -225,6 +242,7 @@ pub fn create_wyndex_swap_msg_with_simulation(
     let swap_ops = swap_operations.unwrap_or(create_wyndex_swap_operations(
         offer_asset.clone(),
         ask_asset_info,
+        max_slippage,
     ));
 
     let simulated_swap: wyndex_multi_hop::msg::SimulateSwapOperationsResponse;
@@ -293,6 +311,7 @@ pub fn create_wyndex_swaps_with_sims(
     offer_assets: Vec<AssetValidated>,
     ask_asset: AssetInfo,
     multihop_address: String,
+    max_slippage: Option<Decimal>,
 ) -> Result<SwapSimResponse, StdError> {
     let swaps_and_sims = offer_assets
         .into_iter()
@@ -305,6 +324,7 @@ pub fn create_wyndex_swaps_with_sims(
                 ask_asset.clone(),
                 multihop_address.to_string(),
                 None,
+                max_slippage,
             )
         })
         .collect::<Result<Vec<_>, StdError>>()?;
@@ -457,3 +477,107 @@ pub fn wynd_multihop_swap_grant(
         }
     }
 }
+
+/// The ways of swapping on Wyndex
+#[cw_serde]
+pub enum WyndexVenue {
+    /// Swap directly through a single pair contract
+    Pair(Addr),
+    /// Swap through the multihop router
+    Multihop(Addr),
+}
+
+impl SwapVenue for WyndexVenue {
+    type Error = WyndHelperError;
+
+    fn simulate(
+        &self,
+        querier: &QuerierWrapper,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+    ) -> Result<Uint128, WyndHelperError> {
+        let offer_asset: Asset = offer.clone().into();
+        let ask_asset_info: AssetInfo = ask.clone().into();
+
+        match self {
+            WyndexVenue::Pair(pair_addr) => Ok(simulate_wynd_pool_swap(
+                querier,
+                pair_addr.as_str(),
+                &offer_asset,
+                ask_asset_info.to_string(),
+            )?
+            .return_amount),
+            WyndexVenue::Multihop(multihop_addr) => {
+                // no swap to do because the offer and ask tokens are the same
+                if offer_asset.info.eq(&ask_asset_info) {
+                    return Ok(offer.amount);
+                }
+
+                let simulation: wyndex_multi_hop::msg::SimulateSwapOperationsResponse = querier
+                    .query_wasm_smart(
+                        multihop_addr,
+                        &wyndex_multi_hop::msg::QueryMsg::SimulateSwapOperations {
+                            offer_amount: offer.amount,
+                            operations: vec![wyndex_multi_hop::msg::SwapOperation::WyndexSwap {
+                                offer_asset_info: offer_asset.info.clone(),
+                                ask_asset_info: ask_asset_info.clone(),
+                            }],
+                            referral: false,
+                            referral_commission: None,
+                        },
+                    )
+                    .map_err(|_| WyndHelperError::SwapSimulationError {
+                        from: offer_asset.info.to_string(),
+                        to: ask_asset_info.to_string(),
+                    })?;
+
+                Ok(simulation.amount)
+            }
+        }
+    }
+
+    fn swap_msgs(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        offer: &OutpostAsset,
+        ask: &OutpostAssetInfo,
+        max_slippage: Option<Decimal>,
+    ) -> Result<(Vec<CosmosProtoMsg>, Uint128), WyndHelperError> {
+        match self {
+            WyndexVenue::Pair(pair_addr) => {
+                let (swap_msg, simulation) = simulate_and_swap_wynd_pair(
+                    querier,
+                    sender,
+                    pair_addr.as_str(),
+                    offer.clone().into(),
+                    ask.clone().into(),
+                    max_slippage,
+                )?;
+
+                Ok((vec![swap_msg], simulation.return_amount))
+            }
+            WyndexVenue::Multihop(multihop_addr) => Ok(create_wyndex_swap_msg_with_simulation(
+                querier,
+                sender,
+                offer.amount,
+                offer.info.clone().into(),
+                ask.clone().into(),
+                multihop_addr.to_string(),
+                None,
+                max_slippage,
+            )?),
+        }
+    }
+
+    fn grants(&self, base: GrantBase, offer: &OutpostAssetInfo) -> Vec<GrantRequirement> {
+        match self {
+            WyndexVenue::Pair(pair_addr) => {
+                wynd_pool_swap_grant(base, pair_addr.clone(), offer.clone().into(), None)
+            }
+            WyndexVenue::Multihop(multihop_addr) => {
+                wynd_multihop_swap_grant(base, multihop_addr.clone(), offer.clone().into(), None)
+            }
+        }
+    }
+}